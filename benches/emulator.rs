@@ -0,0 +1,80 @@
+//! Microbenchmarks of the emulator core, to measure changes meant to make it faster. Run with `cargo bench`;
+//! criterion keeps the last results in `target/criterion` and reports how much each benchmark has changed since
+
+use chip8::Chip8;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+/// Instructions run per iteration of the single instruction benchmarks
+const INSTRUCTIONS: u64 = 1000;
+
+/// An emulator running `setup` once and then `body` in a loop forever
+fn looping(setup: &[u16], body: &[u16]) -> Chip8{
+    let start = 0x200 + 2 * setup.len() as u16;
+    let rom: Vec<u8> = setup.iter().chain(body).chain([&(0x1000 | start)])
+        .flat_map(|opcode| opcode.to_be_bytes()).collect();
+    let mut chip8 = Chip8::init(None);
+    chip8.load_rom(&rom).unwrap();
+    for _ in setup{
+        chip8.do_instruction();
+    }
+    chip8
+}
+
+/// A mix of arithmetic, skips, loads and `RND`, like a game's logic between draws
+const MIXED: [u16; 12] = [0x6005, 0x6103, 0x7001, 0x8014, 0x8122, 0x8213, 0x8305, 0x8406, 0x3000, 0x9010, 0xa300, 0xc0ff];
+
+fn dispatch(c: &mut Criterion){
+    let mut group = c.benchmark_group("do_instruction");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    group.bench_function("mixed", |b| {
+        let mut chip8 = looping(&[], &MIXED);
+        b.iter(|| for _ in 0..INSTRUCTIONS { chip8.do_instruction() })
+    });
+    group.finish();
+}
+
+fn draw(c: &mut Criterion){
+    let mut group = c.benchmark_group("draw");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    // the font's 0, drawn at (8, 8) and at (60, 30), where it wraps past the right and bottom edges
+    for (name, x, y) in [("inside", 8, 8), ("wrapping", 60, 30)]{
+        group.bench_function(name, |b| {
+            let mut chip8 = looping(&[0xa000, 0x6000 | x, 0x6100 | y], &[0xd015; 7]);
+            b.iter(|| {
+                for _ in 0..INSTRUCTIONS { chip8.do_instruction() }
+                chip8.clear_display_events();
+            })
+        });
+    }
+    group.finish();
+}
+
+fn dump_load(c: &mut Criterion){
+    let mut group = c.benchmark_group("registers");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    for (name, opcode) in [("dump", 0xff55), ("load", 0xff65)]{
+        group.bench_function(name, |b| {
+            let mut chip8 = looping(&[0xa300], &[opcode; 7]);
+            b.iter(|| for _ in 0..INSTRUCTIONS { chip8.do_instruction() })
+        });
+    }
+    group.finish();
+}
+
+fn frame(c: &mut Criterion){
+    let mut group = c.benchmark_group("run_frame");
+    // from the slowest to the fastest the speed keys allow
+    for speed in [60, Chip8::DEFAULT_SPEED, 6_000, 60_000]{
+        let mut chip8 = looping(&[0xa000], &[&MIXED[..], &[0xd015]].concat());
+        chip8.clock_speed(speed);
+        group.throughput(Throughput::Elements(chip8.cycles_per_frame()));
+        group.bench_with_input(BenchmarkId::from_parameter(speed), &speed, |b, _| b.iter(|| {
+            chip8.run_frame();
+            chip8.clear_display_events();
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, dispatch, draw, dump_load, frame);
+criterion_main!(benches);
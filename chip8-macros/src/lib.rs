@@ -0,0 +1,361 @@
+//! `#[derive(Chip8Instr)]` for the emulator's instruction enum.
+//!
+//! Each variant gives its encoding and its assembly syntax once, and the derive writes
+//! `From<u16>`, `From<Instruction> for u16`, `Display` and `from_mnemonic` from them:
+//!
+//! ```ignore
+//! #[opcode("0xDXYN")]
+//! #[mnemonic("DRW X Y N")]
+//! Draw(Reg, Reg, u8),
+//! ```
+//!
+//! In an opcode, `X` and `Y` are registers, `N`, `NN` and `NNN` are 4, 8 and 12 bit numbers,
+//! `_` is a nibble that is ignored when decoding and written as 0, and hex digits are fixed.
+//! The variant's fields are the opcode's fields in order.
+//! A mnemonic uses the same letters for its operands; every other word is literal.
+//! The first mnemonic is the one displayed, any more are accepted when parsing.
+//! Opcodes that match no variant decode to the variant marked `#[fallback]`
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Ident, LitStr, Variant};
+
+#[proc_macro_derive(Chip8Instr, attributes(opcode, mnemonic, fallback))]
+pub fn derive_chip8_instr(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    derive(&input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+/// An operand encoded in the opcode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field{
+    X,
+    Y,
+    Nibble,
+    Byte,
+    Address
+}
+
+impl Field{
+    fn parse(word: &str) -> Option<Field>{
+        Some(match word {
+            "X" => Field::X,
+            "Y" => Field::Y,
+            "N" => Field::Nibble,
+            "NN" => Field::Byte,
+            "NNN" => Field::Address,
+            _ => return None
+        })
+    }
+
+    fn is_register(self) -> bool{
+        matches!(self, Field::X | Field::Y)
+    }
+
+    /// Read the field out of `opcode`, where its lowest nibble is `shift` bits up
+    fn decode(self, shift: u32) -> TokenStream2{
+        match self {
+            Field::X | Field::Y => quote!(crate::Reg::from_nibble(opcode >> #shift)),
+            Field::Nibble => quote!(((opcode >> #shift) & 0xF) as u8),
+            Field::Byte => quote!(((opcode >> #shift) & 0xFF) as u8),
+            Field::Address => quote!(crate::Addr::from_bits(opcode >> #shift))
+        }
+    }
+
+    fn encode(self, value: &Ident, shift: u32) -> TokenStream2{
+        match self {
+            Field::Nibble => quote!((u16::from(#value) & 0xF) << #shift),
+            _ => quote!(u16::from(#value) << #shift)
+        }
+    }
+
+    /// The `Operands` method that reads the field from operand `index` of a mnemonic
+    fn operand(self, index: usize) -> TokenStream2{
+        match self {
+            Field::X | Field::Y => quote!(ops.reg(#index)?),
+            Field::Nibble => quote!(ops.nibble(#index)?),
+            Field::Byte => quote!(ops.byte(#index)?),
+            Field::Address => quote!(ops.addr(#index)?)
+        }
+    }
+}
+
+/// A parsed `#[opcode]`: the bits that identify the instruction, and where each field is
+struct Opcode{
+    mask: u16,
+    value: u16,
+    /// Each field and how far its lowest nibble is shifted, in the order of the variant's fields
+    fields: Vec<(Field, u32)>
+}
+
+impl Opcode{
+    fn parse(pattern: &LitStr) -> syn::Result<Opcode>{
+        let text = pattern.value();
+        let digits: Vec<char> = text.strip_prefix("0x").unwrap_or(&text).chars().collect();
+        if digits.len() != 4{
+            return Err(syn::Error::new(pattern.span(), "an opcode is four nibbles, like \"0x8XY4\""));
+        }
+        let mut opcode = Opcode{ mask: 0, value: 0, fields: Vec::new() };
+        let mut index = 0;
+        while index < digits.len(){
+            let c = digits[index];
+            let run = digits[index..].iter().take_while(|&&next| next == c).count();
+            let shift = 4 * (digits.len() - index - run) as u32;
+            match c {
+                '_' => {},
+                'X' | 'Y' | 'N' => {
+                    let word: String = digits[index..index + run].iter().collect();
+                    let field = Field::parse(&word)
+                        .ok_or_else(|| syn::Error::new(pattern.span(), format!("unknown field {word}")))?;
+                    opcode.fields.push((field, shift));
+                },
+                _ => {
+                    let Some(digit) = c.to_digit(16) else {
+                        return Err(syn::Error::new(pattern.span(), format!("unexpected '{c}' in opcode")));
+                    };
+                    // fixed digits are taken one at a time, e.g. the two 0s of 0x00E0
+                    let shift = 4 * (digits.len() - index - 1) as u32;
+                    opcode.mask |= 0xF << shift;
+                    opcode.value |= (digit as u16) << shift;
+                    index += 1;
+                    continue;
+                }
+            }
+            index += run;
+        }
+        Ok(opcode)
+    }
+
+    /// Position of `field` among the variant's fields
+    fn field(&self, field: Field) -> Option<usize>{
+        self.fields.iter().position(|(candidate, _)| *candidate == field)
+    }
+}
+
+/// One word of a mnemonic after the instruction name
+enum Word{
+    Field(Field),
+    /// `I`, `DT`, `V0` and the like
+    Literal(String)
+}
+
+struct Mnemonic{
+    name: String,
+    words: Vec<Word>,
+    span: proc_macro2::Span
+}
+
+impl Mnemonic{
+    fn parse(text: &LitStr) -> syn::Result<Mnemonic>{
+        let value = text.value();
+        let mut words = value.split_whitespace();
+        let name = words.next().ok_or_else(|| syn::Error::new(text.span(), "empty mnemonic"))?;
+        Ok(Mnemonic{
+            name: name.to_ascii_lowercase(),
+            words: words.map(|word| match Field::parse(word) {
+                Some(field) => Word::Field(field),
+                None => Word::Literal(word.to_string())
+            }).collect(),
+            span: text.span()
+        })
+    }
+
+    /// The pattern matching the `Operand` a literal word parses to
+    fn literal_pattern(&self, word: &str) -> syn::Result<TokenStream2>{
+        Ok(match word.to_ascii_uppercase().as_str() {
+            "I" => quote!(Operand::I),
+            "[I]" => quote!(Operand::IndirectI),
+            "DT" => quote!(Operand::DT),
+            "ST" => quote!(Operand::ST),
+            "K" => quote!(Operand::K),
+            "F" => quote!(Operand::F),
+            "B" => quote!(Operand::B),
+            "V0" => quote!(Operand::Reg(crate::Reg::V0)),
+            _ => return Err(syn::Error::new(self.span, format!("unknown operand {word}")))
+        })
+    }
+}
+
+struct Instruction<'a>{
+    name: &'a Ident,
+    variant: &'a Variant,
+    opcode: Opcode,
+    mnemonics: Vec<Mnemonic>,
+    fallback: bool
+}
+
+impl<'a> Instruction<'a>{
+    fn parse(name: &'a Ident, variant: &'a Variant) -> syn::Result<Instruction<'a>>{
+        let mut opcode = None;
+        let mut mnemonics = Vec::new();
+        let mut fallback = false;
+        for attr in variant.attrs.iter(){
+            if attr.path().is_ident("opcode"){
+                opcode = Some(Opcode::parse(&attr.parse_args()?)?);
+            } else if attr.path().is_ident("mnemonic"){
+                mnemonics.push(Mnemonic::parse(&attr.parse_args()?)?);
+            } else if attr.path().is_ident("fallback"){
+                fallback = true;
+            }
+        }
+        let opcode = opcode.ok_or_else(|| syn::Error::new(variant.span(), "missing #[opcode(\"0x....\")]"))?;
+        if mnemonics.is_empty(){
+            return Err(syn::Error::new(variant.span(), "missing #[mnemonic(\"...\")]"));
+        }
+        if opcode.fields.len() != variant.fields.len(){
+            return Err(syn::Error::new(variant.fields.span(),
+                format!("the opcode has {} fields but the variant has {}", opcode.fields.len(), variant.fields.len())));
+        }
+        if fallback && !opcode.fields.is_empty(){
+            return Err(syn::Error::new(variant.span(), "the fallback variant can't have fields"));
+        }
+        Ok(Instruction{ name, variant, opcode, mnemonics, fallback })
+    }
+
+    fn bindings(&self) -> Vec<Ident>{
+        (0..self.opcode.fields.len()).map(|index| format_ident!("f{}", index)).collect()
+    }
+
+    /// `Enum::Variant(f0, f1)`, or `Enum::Variant` if it has no fields
+    fn pattern(&self, values: &[TokenStream2]) -> TokenStream2{
+        let (name, ident) = (self.name, &self.variant.ident);
+        if values.is_empty() {quote!(#name::#ident)} else {quote!(#name::#ident(#(#values),*))}
+    }
+
+    fn encode_arm(&self) -> TokenStream2{
+        let bindings = self.bindings();
+        let pattern = self.pattern(&bindings.iter().map(|binding| quote!(#binding)).collect::<Vec<_>>());
+        let value = self.opcode.value;
+        let fields = self.opcode.fields.iter().zip(bindings.iter()).map(|((field, shift), binding)| field.encode(binding, *shift));
+        quote!(#pattern => #value #(| #fields)*)
+    }
+
+    fn decode_check(&self) -> TokenStream2{
+        let (mask, value) = (self.opcode.mask, self.opcode.value);
+        let construct = self.pattern(&self.opcode.fields.iter().map(|(field, shift)| field.decode(*shift)).collect::<Vec<_>>());
+        quote!(if opcode & #mask == #value { return #construct; })
+    }
+
+    fn display_arm(&self) -> syn::Result<TokenStream2>{
+        let mnemonic = &self.mnemonics[0];
+        let bindings = self.bindings();
+        let mut format = mnemonic.name.to_ascii_uppercase();
+        let mut args = Vec::new();
+        for word in mnemonic.words.iter(){
+            format.push(' ');
+            match word {
+                Word::Literal(text) => format.push_str(text),
+                Word::Field(field) => {
+                    let index = self.opcode.field(*field).ok_or_else(||
+                        syn::Error::new(mnemonic.span, format!("{field:?} isn't part of the opcode")))?;
+                    format.push_str(if field.is_register() {"V{}"} else {"{}"});
+                    args.push(&bindings[index]);
+                }
+            }
+        }
+        let pattern = self.pattern(&bindings.iter().map(|binding| quote!(#binding)).collect::<Vec<_>>());
+        Ok(quote!(#pattern => write!(f, #format #(, #args)*)))
+    }
+
+    /// One match arm per mnemonic, with how specific it is so that literal operands are tried first,
+    /// then numbers, then registers, which also accept bare numbers
+    fn parse_arms(&self) -> syn::Result<Vec<((usize, usize), TokenStream2)>>{
+        self.mnemonics.iter().map(|mnemonic| {
+            let name = &mnemonic.name;
+            let mut patterns = Vec::new();
+            let mut values = vec![None; self.opcode.fields.len()];
+            let (mut literals, mut numbers) = (0, 0);
+            for (index, word) in mnemonic.words.iter().enumerate(){
+                patterns.push(match word {
+                    Word::Literal(text) => {
+                        literals += 1;
+                        mnemonic.literal_pattern(text)?
+                    },
+                    Word::Field(field) => {
+                        // a field that isn't in the opcode is accepted and ignored, like the Vy of SHR Vx Vy
+                        if let Some(position) = self.opcode.field(*field){
+                            values[position] = Some(field.operand(index));
+                        }
+                        if field.is_register() {quote!(_)} else {
+                            numbers += 1;
+                            quote!(Operand::Number(_))
+                        }
+                    }
+                });
+            }
+            let values = values.into_iter().collect::<Option<Vec<_>>>()
+                .ok_or_else(|| syn::Error::new(mnemonic.span, "every field of the opcode needs an operand"))?;
+            let construct = self.pattern(&values);
+            Ok(((literals, numbers), quote!((#name, [#(#patterns),*]) => #construct)))
+        }).collect()
+    }
+}
+
+fn derive(input: &DeriveInput) -> syn::Result<TokenStream2>{
+    let Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new(input.span(), "Chip8Instr can only be derived for an enum"));
+    };
+    let name = &input.ident;
+    let instructions = data.variants.iter().map(|variant| Instruction::parse(name, variant)).collect::<syn::Result<Vec<_>>>()?;
+    let fallbacks: Vec<_> = instructions.iter().filter(|instruction| instruction.fallback).collect();
+    let [fallback] = fallbacks[..] else {
+        return Err(syn::Error::new(input.span(), "exactly one variant should be marked #[fallback]"));
+    };
+    let fallback = fallback.pattern(&[]);
+
+    let encode_arms = instructions.iter().map(Instruction::encode_arm);
+    let decode_checks = instructions.iter().filter(|instruction| !instruction.fallback).map(Instruction::decode_check);
+    let display_arms = instructions.iter().map(Instruction::display_arm).collect::<syn::Result<Vec<_>>>()?;
+    let mut parse_arms = Vec::new();
+    for instruction in instructions.iter(){
+        parse_arms.extend(instruction.parse_arms()?);
+    }
+    // stable, so that otherwise arms keep the order of the variants
+    parse_arms.sort_by_key(|((literals, numbers), _)| std::cmp::Reverse((*literals, *numbers)));
+    let parse_arms = parse_arms.into_iter().map(|(_, arm)| arm);
+    let mut names: Vec<_> = instructions.iter()
+        .flat_map(|instruction| instruction.mnemonics.iter().map(|mnemonic| mnemonic.name.clone()))
+        .collect();
+    names.sort();
+    names.dedup();
+
+    Ok(quote!{
+        impl From<#name> for u16 {
+            fn from(value: #name) -> Self {
+                match value {
+                    #(#encode_arms),*
+                }
+            }
+        }
+
+        impl From<u16> for #name {
+            fn from(opcode: u16) -> Self {
+                #(#decode_checks)*
+                #fallback
+            }
+        }
+
+        impl std::fmt::Display for #name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match *self {
+                    #(#display_arms),*
+                }
+            }
+        }
+
+        impl #name {
+            /// Parse one instruction in the syntax it is displayed in, e.g. `LD VA 0x1f` or `drw v0, v1, 5`.
+            /// Numbers can be decimal, hex (`0x1f`), binary (`0b101`) or a character constant (`'A'`)
+            pub fn from_mnemonic(mnemonic: &str) -> Result<Self, crate::errors::Diagnostic> {
+                use crate::instructions::Operand;
+                let (name_token, ops) = crate::instructions::Operands::split(mnemonic)?;
+                let name = name_token.text.to_ascii_lowercase();
+                Ok(match (name.as_str(), &ops.operands[..]) {
+                    #(#parse_arms,)*
+                    (#(#names)|*, _) => return Err(ops.invalid(name_token)),
+                    _ => return Err(ops.unknown(name_token))
+                })
+            }
+        }
+    })
+}
@@ -0,0 +1,54 @@
+use std::fmt::Display;
+
+pub use chip8::errors::{Diagnostic, Severity, Span};
+
+/// The diagnostic for a pest parse error, pointing at the text pest complained about
+#[cfg(feature = "assembler")]
+pub fn from_pest<R: pest::RuleType>(error: pest::error::Error<R>) -> Diagnostic{
+    let span = match error.line_col {
+        pest::error::LineColLocation::Pos((line, column)) => Span::point(line, column),
+        pest::error::LineColLocation::Span((line, column), (end_line, end_column)) =>
+            Span::new(line, column, end_line, end_column)
+    };
+    Diagnostic::error(error.variant.message()).with_span(span, error.line())
+}
+
+/// Everything wrong with a source file, one diagnostic per problem
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Diagnostics{
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic>{
+        self.0.iter()
+    }
+
+    /// Every diagnostic rendered for a terminal, naming `path` as the source file
+    pub fn with_path(&self, path: &str) -> String{
+        self.iter().map(|diagnostic| diagnostic.render(Some(path))).collect::<Vec<_>>().join("\n\n")
+    }
+
+    /// The diagnostics as a JSON array, for editors and other tools
+    pub fn to_json(&self) -> String{
+        format!("[{}]", self.iter().map(Diagnostic::to_json).collect::<Vec<_>>().join(","))
+    }
+}
+
+impl Display for Diagnostics{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, diagnostic) in self.iter().enumerate(){
+            if index > 0{
+                writeln!(f)?;
+            }
+            write!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+impl From<Diagnostic> for Diagnostics{
+    fn from(diagnostic: Diagnostic) -> Self {
+        Self(vec![diagnostic])
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::BTreeSet;
+
+use chip8::{cfg::Cfg, coverage::{self, Coverage}, symbols::Symbols, Addr, Instruction};
+
+/// How `disassemble` lays out its output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassembleOptions{
+    /// Address the first byte is loaded at, and where execution starts
+    pub origin: Addr,
+    /// Start each line with its address. The listing only assembles again without them
+    pub addresses: bool,
+    /// How a run of the ROM used its bytes, from the emulator's `--coverage`. Instructions that ran
+    /// are code even where jumps can't be followed, and bytes only read or written are data.
+    /// The listing then marks the instructions that never ran
+    pub coverage: Option<Coverage>
+}
+
+impl Default for DisassembleOptions{
+    fn default() -> Self {
+        Self {
+            origin: Addr::ROM_START,
+            addresses: true,
+            coverage: None
+        }
+    }
+}
+
+/// Most bytes on one `bytes` line
+const BYTES_PER_LINE: usize = 8;
+
+/// The instruction at `offset`, unless the word there isn't one that assembles back to the same bytes,
+/// e.g. an unknown opcode or one with bits the instruction ignores
+fn decode(bytes: &[u8], offset: usize) -> Option<Instruction>{
+    let word = u16::from_be_bytes([*bytes.get(offset)?, *bytes.get(offset + 1)?]);
+    let instruction = Instruction::from(word);
+    (instruction != Instruction::Nop && u16::from(instruction) == word).then_some(instruction)
+}
+
+/// Offsets of the instructions that can be reached from the start of `bytes`, or from any that ran in `coverage`,
+/// following jumps, calls and skips. Anything else is data, as is anything `coverage` saw used only as data
+fn reachable(bytes: &[u8], origin: Addr, coverage: Option<&Coverage>) -> BTreeSet<usize>{
+    let is_data = |offset: usize| coverage.is_some_and(|coverage|
+        (offset..offset + chip8::INSTRUCTION_SIZE).any(|offset| coverage.is_data(origin.index() + offset)));
+    let entries = [0].into_iter()
+        .chain(coverage.into_iter().flat_map(Coverage::instruction_starts).filter_map(|addr| addr.checked_sub(origin.index())));
+    // an all-zero or unknown word is where the code runs into data
+    Cfg::new(bytes, origin, entries, |offset| !is_data(offset) && decode(bytes, offset).is_some()).instructions().collect()
+}
+
+/// Labels for every address in the ROM that code jumps to, calls or points I at,
+/// apart from ones in the middle of an instruction
+fn labels(bytes: &[u8], origin: Addr, starts: &BTreeSet<usize>) -> Symbols{
+    let in_rom = |addr: Addr| addr.index().checked_sub(origin.index())
+        .is_some_and(|offset| offset < bytes.len() && !(offset > 0 && starts.contains(&(offset - 1))));
+    let instructions: Vec<_> = starts.iter().filter_map(|&offset| decode(bytes, offset)).collect();
+    let mut symbols = Symbols::default();
+    // subroutines are named first, so that they keep their name if they are also jumped to
+    for (prefix, targets) in [
+        ("sub", instructions.iter().filter_map(|instruction| match instruction {
+            Instruction::Call(addr) => Some(*addr),
+            _ => None
+        }).collect::<Vec<_>>()),
+        ("label", instructions.iter().filter_map(|instruction| match instruction {
+            Instruction::Jump(addr) | Instruction::JumpOffset(addr) => Some(*addr),
+            _ => None
+        }).collect()),
+        ("data", instructions.iter().filter_map(|instruction| match instruction {
+            Instruction::SetMemPtr(addr) => Some(*addr),
+            _ => None
+        }).collect())
+    ]{
+        for addr in targets.into_iter().filter(|addr| in_rom(*addr)){
+            if symbols.name(addr).is_none(){
+                symbols.insert(&format!("{}_{:03x}", prefix, u16::from(addr)), addr);
+            }
+        }
+    }
+    symbols
+}
+
+/// A listing of `bytes`: the code reachable from the start, with labels for the places it jumps to,
+/// calls or loads into I, and everything else as `bytes` lines
+pub fn disassemble(bytes: &[u8], options: &DisassembleOptions) -> String{
+    let starts = reachable(bytes, options.origin, options.coverage.as_ref());
+    let symbols = labels(bytes, options.origin, &starts);
+    let ran = |offset: usize| options.coverage.as_ref()
+        .map(|coverage| coverage.get(options.origin.index() + offset) & coverage::EXECUTED != 0);
+    let mut lines = Vec::new();
+    if options.coverage.is_some(){
+        let count = starts.iter().filter(|offset| ran(**offset) == Some(true)).count();
+        lines.push(format!("# {} of {} instructions ran", count, starts.len()));
+    }
+    let mut offset = 0;
+    while offset < bytes.len(){
+        let Some(addr) = options.origin.offset(offset as isize) else {break};
+        if let Some(label) = symbols.name(addr){
+            lines.push(format!("{}:", label));
+        }
+        let instruction = starts.contains(&offset).then(|| decode(bytes, offset)).flatten();
+        let (line, len) = if let Some(instruction) = instruction{
+            let note = if ran(offset) == Some(false) {" # never ran"} else {""};
+            (format!("{};{}", symbols.instruction(instruction), note), chip8::INSTRUCTION_SIZE)
+        } else {
+            // data runs up to the next instruction or label, a line at a time
+            let len = (offset..bytes.len()).take(BYTES_PER_LINE)
+                .take_while(|&end| end == offset || !starts.contains(&end)
+                    && options.origin.offset(end as isize).and_then(|addr| symbols.name(addr)).is_none())
+                .count();
+            let data: Vec<_> = bytes[offset..offset + len].iter().map(|byte| format!("{:#04x}", byte)).collect();
+            (format!("bytes {}", data.join(" ")), len)
+        };
+        if options.addresses{
+            lines.push(format!("{:#05x}: {}", u16::from(addr), line));
+        } else {
+            lines.push(line);
+        }
+        offset += len;
+    }
+    lines.iter().map(|line| format!("{line}\n")).collect()
+}
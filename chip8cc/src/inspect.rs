@@ -0,0 +1,220 @@
+use std::{collections::{BTreeMap, BTreeSet}, fmt::Display};
+
+use chip8::{cfg::{self, Edge}, errors::json_string, extensions::Extension, rom_inspect::{self, Variant}, Addr, Chip8, Instruction};
+
+/// The keypad as it's laid out on a COSMAC VIP
+const KEYPAD: [[u8; 4]; 4] = [[0x1, 0x2, 0x3, 0xc], [0x4, 0x5, 0x6, 0xd], [0x7, 0x8, 0x9, 0xe], [0xa, 0x0, 0xb, 0xf]];
+
+/// What a ROM's code needs from the emulator, worked out by following it from 0x200 without running it.
+/// Addresses are where the code first does each thing
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RomInfo{
+    pub size: usize,
+    /// The later variant the ROM is written for, with the first instruction from it
+    pub variant: Option<(Variant, Addr, u16)>,
+    pub extensions: BTreeMap<Extension, Addr>,
+    /// Where SUPER-CHIP's 128x64 display is switched on
+    pub high_resolution: Option<Addr>,
+    /// Where the sound timer is set, if the ROM makes any sound
+    pub sound: Option<Addr>,
+    pub bcd: Option<Addr>,
+    pub reg_dump: Option<Addr>,
+    pub reg_load: Option<Addr>,
+    /// How deep subroutine calls can go, or `None` if a subroutine can end up calling itself
+    pub stack_depth: Option<usize>,
+    /// Keys checked by `SKP` and `SKNP`, and where. A key is only known if it's loaded
+    /// into the register by the instruction just before
+    pub keys: BTreeMap<u8, BTreeSet<Addr>>,
+    /// Key checks where the key is worked out some other way
+    pub computed_keys: BTreeSet<Addr>,
+    /// Where the code stops for a key press
+    pub waits_for_key: BTreeSet<Addr>
+}
+
+impl RomInfo{
+    /// The display the ROM draws to
+    pub fn display_size(&self) -> (usize, usize){
+        if self.high_resolution.is_some() {(128, 64)} else {(64, 32)}
+    }
+
+    /// Emulator settings the ROM probably needs
+    pub fn hints(&self) -> Vec<String>{
+        let mut hints = Vec::new();
+        if let Some((variant, _, _)) = self.variant{
+            hints.push(format!("it needs {variant}, which the emulator can't run"));
+        }
+        match self.stack_depth {
+            None => hints.push("a subroutine can call itself, so the stack may overflow".to_string()),
+            Some(depth) if depth > Chip8::DEFAULT_STACK_DEPTH =>
+                hints.push(format!("calls go {depth} deep, run with --stack-depth {depth}")),
+            Some(_) => {}
+        }
+        if self.reg_dump.is_some() || self.bcd.is_some(){
+            hints.push("it stores registers in memory, try --quirk protect-low-memory if the digits it draws get garbled".to_string());
+        }
+        if !self.waits_for_key.is_empty(){
+            hints.push("it waits for keys, try --quirk wait-for-release if one press counts twice".to_string());
+        }
+        hints
+    }
+
+    /// The report as a JSON object, for building a ROM database
+    pub fn to_json(&self) -> String{
+        let addr = |addr: Option<Addr>| addr.map_or("null".to_string(), |addr| u16::from(addr).to_string());
+        let addrs = |addrs: &BTreeSet<Addr>| addrs.iter().map(|addr| u16::from(*addr).to_string()).collect::<Vec<_>>().join(",");
+        let variant = self.variant.map_or("null".to_string(), |(variant, at, opcode)|
+            format!("{{\"name\":{},\"address\":{},\"opcode\":{}}}", json_string(&variant.to_string()), u16::from(at), opcode));
+        let extensions = self.extensions.iter()
+            .map(|(extension, at)| format!("{}:{}", json_string(&extension.to_string()), u16::from(*at)))
+            .collect::<Vec<_>>().join(",");
+        let keys = self.keys.iter()
+            .map(|(key, at)| format!("\"{:x}\":[{}]", key, addrs(at)))
+            .collect::<Vec<_>>().join(",");
+        let (width, height) = self.display_size();
+        let hints = self.hints().iter().map(|hint| json_string(hint)).collect::<Vec<_>>().join(",");
+        format!("{{\"size\":{},\"variant\":{},\"extensions\":{{{}}},\"display\":{{\"width\":{},\"height\":{},\"high_resolution\":{}}},\
+            \"sound\":{},\"bcd\":{},\"reg_dump\":{},\"reg_load\":{},\"stack_depth\":{},\"keys\":{{{}}},\"computed_keys\":[{}],\
+            \"waits_for_key\":[{}],\"hints\":[{}]}}",
+            self.size, variant, extensions, width, height, addr(self.high_resolution), addr(self.sound), addr(self.bcd),
+            addr(self.reg_dump), addr(self.reg_load), self.stack_depth.map_or("null".to_string(), |depth| depth.to_string()),
+            keys, addrs(&self.computed_keys), addrs(&self.waits_for_key), hints)
+    }
+}
+
+impl Display for RomInfo{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let at = |addr: Option<Addr>| addr.map_or("no".to_string(), |addr| format!("yes, at {addr}"));
+        let list = |addrs: &BTreeSet<Addr>| addrs.iter().map(Addr::to_string).collect::<Vec<_>>().join(" ");
+        writeln!(f, "{} bytes", self.size)?;
+        match self.variant {
+            Some((variant, addr, opcode)) => writeln!(f, "variant: {variant} ({opcode:04x} at {addr})")?,
+            None => writeln!(f, "variant: CHIP-8")?
+        }
+        for (extension, addr) in self.extensions.iter(){
+            writeln!(f, "extension: {extension}, at {addr}")?;
+        }
+        let (width, height) = self.display_size();
+        writeln!(f, "display: {width}x{height}")?;
+        writeln!(f, "sound: {}", at(self.sound))?;
+        writeln!(f, "BCD: {}", at(self.bcd))?;
+        writeln!(f, "register dump: {}", at(self.reg_dump))?;
+        writeln!(f, "register load: {}", at(self.reg_load))?;
+        match self.stack_depth {
+            Some(depth) => writeln!(f, "stack depth: {depth}")?,
+            None => writeln!(f, "stack depth: unbounded, a subroutine can call itself")?
+        }
+        writeln!(f, "keys:")?;
+        for row in KEYPAD{
+            let row: Vec<_> = row.iter()
+                .map(|key| if self.keys.contains_key(key) {format!("{key:X}")} else {".".to_string()})
+                .collect();
+            writeln!(f, "  {}", row.join(" "))?;
+        }
+        for (key, addrs) in self.keys.iter(){
+            writeln!(f, "  {key:X}: {}", list(addrs))?;
+        }
+        if !self.computed_keys.is_empty(){
+            writeln!(f, "  computed: {}", list(&self.computed_keys))?;
+        }
+        if !self.waits_for_key.is_empty(){
+            writeln!(f, "waits for a key: {}", list(&self.waits_for_key))?;
+        }
+        for hint in self.hints(){
+            writeln!(f, "hint: {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A subroutine's code, without following its calls
+struct Routine{
+    offsets: BTreeSet<usize>,
+    calls: BTreeSet<usize>
+}
+
+fn routine(rom: &[u8], entry: usize) -> Routine{
+    let mut routine = Routine{ offsets: BTreeSet::new(), calls: BTreeSet::new() };
+    let mut pending = vec![entry];
+    while let Some(offset) = pending.pop(){
+        if offset + 1 >= rom.len() || !routine.offsets.insert(offset){
+            continue;
+        }
+        let opcode = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+        for edge in cfg::edges(opcode, offset, Addr::ROM_START){
+            match edge {
+                Edge::Call(entry) => {routine.calls.insert(entry);},
+                _ => pending.push(edge.target())
+            }
+        }
+    }
+    routine
+}
+
+/// How deep calls from the routine at `entry` go, or `None` if they can come back round to one already being called
+fn depth(routines: &BTreeMap<usize, Routine>, entry: usize, calling: &mut Vec<usize>, depths: &mut BTreeMap<usize, Option<usize>>) -> Option<usize>{
+    if calling.contains(&entry){
+        return None;
+    }
+    if let Some(depth) = depths.get(&entry){
+        return *depth;
+    }
+    calling.push(entry);
+    let mut deepest = Some(0);
+    for callee in routines[&entry].calls.iter(){
+        deepest = deepest.zip(depth(routines, *callee, calling, depths)).map(|(deepest, depth)| deepest.max(depth + 1));
+    }
+    calling.pop();
+    depths.insert(entry, deepest);
+    deepest
+}
+
+/// Work out what `rom` needs from the emulator by following its code
+pub fn inspect(rom: &[u8]) -> RomInfo{
+    let inspection = rom_inspect::inspect(rom);
+    let mut routines = BTreeMap::new();
+    let mut pending = vec![0];
+    while let Some(entry) = pending.pop(){
+        if let std::collections::btree_map::Entry::Vacant(slot) = routines.entry(entry){
+            let routine = slot.insert(routine(rom, entry));
+            pending.extend(routine.calls.iter().copied());
+        }
+    }
+    let mut info = RomInfo{
+        size: rom.len(),
+        variant: inspection.needs(),
+        extensions: inspection.extensions,
+        stack_depth: depth(&routines, 0, &mut Vec::new(), &mut BTreeMap::new()),
+        ..Default::default()
+    };
+
+    let offsets: BTreeSet<usize> = routines.values().flat_map(|routine| routine.offsets.iter().copied()).collect();
+    let decode = |offset: usize| Instruction::from(u16::from_be_bytes([rom[offset], rom[offset + 1]]));
+    let first = |found: &mut Option<Addr>, addr: Addr| *found = Some(found.map_or(addr, |found| found.min(addr)));
+    for offset in offsets.iter().copied(){
+        let Some(addr) = Addr::ROM_START.offset(offset as isize) else {continue};
+        if rom[offset..offset + 2] == [0x00, 0xff]{
+            first(&mut info.high_resolution, addr);
+        }
+        match decode(offset) {
+            Instruction::SetSound(_) => first(&mut info.sound, addr),
+            Instruction::BCD(_) => first(&mut info.bcd, addr),
+            Instruction::RegDump(_) => first(&mut info.reg_dump, addr),
+            Instruction::RegLoad(_) => first(&mut info.reg_load, addr),
+            Instruction::WaitForKey(_) => {info.waits_for_key.insert(addr);},
+            Instruction::SkipKeyPressed(x) | Instruction::SkipKeyNotPressed(x) => {
+                let loaded = offset.checked_sub(chip8::INSTRUCTION_SIZE)
+                    .filter(|before| offsets.contains(before))
+                    .and_then(|before| match decode(before) {
+                        Instruction::SetImm(reg, key) if reg == x => Some(key & 0xf),
+                        _ => None
+                    });
+                match loaded {
+                    Some(key) => {info.keys.entry(key).or_default().insert(addr);},
+                    None => {info.computed_keys.insert(addr);}
+                }
+            },
+            _ => {}
+        }
+    }
+    info
+}
@@ -1,49 +1,91 @@
-use std::{collections::HashMap, hash::Hash};
+use std::collections::BTreeMap;
 
-use chip8::Instruction;
-use pest::{error::ErrorVariant, iterators::{Pair, Pairs}, Parser};
+use chip8::{quirks::Quirk, Addr, Instruction, Reg};
+use pest::{error::ErrorVariant, iterators::{Pair, Pairs}, Parser, Span};
 use pest_derive::Parser;
 
 #[derive(Parser)]
 #[grammar = "grammar/labels.pest"]
 pub struct InstructionParser;
 pub type Error = pest::error::Error<Rule>;
-pub type ParseResult<'a> = Result<(Instruction, Option<&'a str>), Error>;
+pub type ParseResult<'a> = Result<(Instruction, Option<Span<'a>>), Error>;
+
+/// What a line of the program assembles to
+enum Item{
+    Instruction(Instruction),
+    /// The bytes of a `bytes` line, e.g. a sprite
+    Bytes(Vec<u8>)
+}
+
+impl Item{
+    fn len(&self) -> usize{
+        match self {
+            Item::Instruction(_) => chip8::INSTRUCTION_SIZE,
+            Item::Bytes(bytes) => bytes.len()
+        }
+    }
+}
 
 pub struct Program<'a>{
-    instructions: Vec<Instruction>,
-    labels: HashMap<&'a str, usize>,
-    references: Vec<Option<&'a str>>
+    items: Vec<Item>,
+    /// Where each label is, in bytes from the start of the program
+    labels: BTreeMap<&'a str, usize>,
+    /// The label each item uses, if any
+    references: Vec<Option<Span<'a>>>
 }
 
 impl<'a> Program<'a> {
-    pub fn fix_references(&mut self){
-        for label in self.references.iter(){
+    /// Size of the program so far, in bytes
+    fn len(&self) -> usize{
+        self.items.iter().map(Item::len).sum()
+    }
+
+    /// Point jumps, calls and `ld i` at the labels they name, for a program loaded at `origin`
+    pub fn fix_references(&mut self, origin: Addr) -> Result<(), Error>{
+        for (index, label) in self.references.iter().enumerate(){
             if let Some(label) = *label{
-                let addr = self.labels[label];
-                let new_addr = addr * chip8::INSTRUCTION_SIZE + 0x200;
-                let new_instruction = match self.instructions[addr] {
-                    Instruction::Call(_) => Instruction::Call(new_addr as u16),
-                    Instruction::Jump(_) => Instruction::Jump(new_addr as u16),
-                    _ => panic!("unexpected instruction: {}", self.instructions[addr])
+                let Some(target) = self.labels.get(label.as_str()) else {
+                    return Err(Error::new_from_span(
+                        ErrorVariant::CustomError { message: format!("undefined label {}", label.as_str()) }, label))
+                };
+                let new_addr = origin.offset(*target as isize).ok_or_else(|| Error::new_from_span(
+                    ErrorVariant::CustomError { message: "label is past the end of memory".to_string() }, label))?;
+                let Item::Instruction(instruction) = &mut self.items[index] else {
+                    panic!("reference from a data line: {}", label.as_str())
+                };
+                *instruction = match *instruction {
+                    Instruction::Call(_) => Instruction::Call(new_addr),
+                    Instruction::Jump(_) => Instruction::Jump(new_addr),
+                    Instruction::JumpOffset(_) => Instruction::JumpOffset(new_addr),
+                    Instruction::SetMemPtr(_) => Instruction::SetMemPtr(new_addr),
+                    _ => panic!("unexpected instruction: {}", instruction)
                 };
-                self.instructions[addr] = new_instruction;
             }
         }
+        Ok(())
+    }
+
+    /// Where each label ends up in a program loaded at `origin`, leaving out any past the end of memory
+    pub fn label_addresses(&self, origin: Addr) -> BTreeMap<String, Addr>{
+        self.labels.iter().filter_map(|(label, target)| {
+            let addr = origin.offset(*target as isize)?;
+            Some((label.to_string(), addr))
+        }).collect()
     }
 
     pub fn compile(&self) -> Vec<u8>{
-        self.instructions.iter()
-            .map(|i| {<Instruction as Into<u16>>::into(*i)}).flat_map(u16::to_be_bytes)
-            .collect()
+        self.items.iter().flat_map(|item| match item {
+            Item::Instruction(i) => <Instruction as Into<u16>>::into(*i).to_be_bytes().to_vec(),
+            Item::Bytes(bytes) => bytes.clone()
+        }).collect()
     }
 }
 
 
-fn register<'a>(name: &Pair<'a, Rule>) -> Result<u8,Error> {
+fn register<'a>(name: &Pair<'a, Rule>) -> Result<Reg,Error> {
     let s  = name.as_str();
-    if let Some(reg) = s.to_ascii_lowercase().strip_prefix('v'){
-        reg.parse().or(Err(Error::new_from_span(
+    if s.to_ascii_lowercase().starts_with('v'){
+        s.parse().or(Err(Error::new_from_span(
             ErrorVariant::CustomError { message: "invalid register name".to_string() }, name.as_span())))
     } else {
         Err(Error::new_from_span(
@@ -58,7 +100,7 @@ fn bad_rule_error<'a, T>(rule: &Pair<'a, Rule>) -> Result<T, Error>{
     )
 }
 
-fn parse_binop<'a>(pair: Pair<'a, Rule>) -> Result<(Instruction, Option<&'a str>), Error>{
+fn parse_binop<'a>(pair: Pair<'a, Rule>) -> Result<(Instruction, Option<Span<'a>>), Error>{
     let rule = pair.as_rule();
     let mut parts = pair.into_inner();
     if matches!(rule, Rule::add){
@@ -68,15 +110,24 @@ fn parse_binop<'a>(pair: Pair<'a, Rule>) -> Result<(Instruction, Option<&'a str>
     }
     let r1 = register(&parts.next().unwrap())?;
     let p2 = &parts.next().unwrap();
+    if matches!(p2.as_rule(), Rule::number){
+        let imm = byte(p2)?;
+        return Ok((match rule {
+            Rule::add => Instruction::AddImm(r1, imm),
+            Rule::se => Instruction::SkipEqImm(r1, imm),
+            Rule::sne => Instruction::SkipNeImm(r1, imm),
+            Rule::rnd => Instruction::Rand(r1, imm),
+            _ => {bad_rule_error(p2)?}
+        }, None))
+    }
     let r2 = match p2.as_rule() {
         Rule::register => register(p2)?,
-        Rule::number => p2.as_str().parse().unwrap(),
         _ => bad_rule_error(p2)?
     };
     Ok((match rule {
-        Rule::add => {if matches!(p2.as_rule(), Rule::number) {Instruction::AddImm(r1, r2)} else {Instruction::AddReg(r1, r2)}},
-        Rule::se => {if matches!(p2.as_rule(), Rule::number) {Instruction::SkipEqImm(r1, r2)} else {Instruction::SkipEqReg(r1, r2)}},
-        Rule::sne => {if matches!(p2.as_rule(), Rule::number) {Instruction::SkipNeImm(r1, r2)} else {Instruction::SkipNeReg(r1, r2)}},
+        Rule::add => {Instruction::AddReg(r1, r2)},
+        Rule::se => {Instruction::SkipEqReg(r1, r2)},
+        Rule::sne => {Instruction::SkipNeReg(r1, r2)},
         Rule::sub => {Instruction::SubReg(r1, r2)}
         Rule::or => {Instruction::OrReg(r1, r2)},
         Rule::and => {Instruction::AndReg(r1, r2)},
@@ -86,28 +137,42 @@ fn parse_binop<'a>(pair: Pair<'a, Rule>) -> Result<(Instruction, Option<&'a str>
     }, None))
 }
 
-fn parse_addr<'a>(addr: Pairs<'a, Rule>) -> (u16, Option<&'a str>){
+fn byte<'a>(number: &Pair<'a, Rule>) -> Result<u8, Error> {
+    let s = number.as_str();
+    let value = match s.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => s.parse()
+    };
+    value.or(Err(Error::new_from_span(
+        ErrorVariant::CustomError { message: "value doesn't fit in a byte".to_string() }, number.as_span())))
+}
+
+fn address<'a>(number: &Pair<'a, Rule>) -> Result<Addr, Error> {
+    number.as_str().parse().or(Err(Error::new_from_span(
+        ErrorVariant::CustomError { message: "address out of range".to_string() }, number.as_span())))
+}
+
+fn parse_addr<'a>(addr: Pairs<'a, Rule>) -> Result<(Addr, Option<Span<'a>>), Error>{
     if let Some(fixed_addr) = addr.find_first_tagged("fixed"){
-        let fixed_addr = fixed_addr.as_str().parse().unwrap();
-        return (fixed_addr, None)
+        return Ok((address(&fixed_addr)?, None))
     }
     if let Some(label) = addr.find_first_tagged("label"){
-        return (0, Some(label.as_str()))
+        return Ok((Addr::default(), Some(label.as_span())))
     }
     unreachable!()
 }
 
-fn parse_call<'a>(mut call: Pairs<'a, Rule>) -> (Instruction, Option<&'a str>){
-    let addr = call.nth(1).unwrap().into_inner();
-    let addr = parse_addr(addr);
-    return (Instruction::Call(addr.0), addr.1)
+fn parse_call<'a>(mut call: Pairs<'a, Rule>) -> Result<(Instruction, Option<Span<'a>>), Error>{
+    let addr = call.next().unwrap().into_inner();
+    let addr = parse_addr(addr)?;
+    return Ok((Instruction::Call(addr.0), addr.1))
 }
 
-fn parse_jump<'a>(jump: Pair<'a, Rule>) -> Result<(Instruction, Option<&'a str>), Error>{
+fn parse_jump<'a>(jump: Pair<'a, Rule>) -> Result<(Instruction, Option<Span<'a>>), Error>{
     let rule = jump.as_rule();
     let mut jump_in = jump.clone().into_inner();
     let addr = jump_in.next().unwrap().into_inner();
-    let addr = parse_addr(addr);
+    let addr = parse_addr(addr)?;
     Ok((match rule {
         Rule::jump => Instruction::Jump(addr.0),
         Rule::jpoff => Instruction::JumpOffset(addr.0),
@@ -115,17 +180,22 @@ fn parse_jump<'a>(jump: Pair<'a, Rule>) -> Result<(Instruction, Option<&'a str>)
     }, addr.1))
 }
 
-fn parse_draw<'a>(mut draw: Pairs<'a, Rule>) -> Result<(Instruction, Option<&'a str>), Error>{
-    let r1 = register(&draw.nth(1).unwrap())?;
-    let r2 = register(&draw.nth(1).unwrap())?;
-    let n = draw.nth(1).unwrap().as_str().parse().unwrap();
+fn parse_draw<'a>(mut draw: Pairs<'a, Rule>) -> Result<(Instruction, Option<Span<'a>>), Error>{
+    let r1 = register(&draw.next().unwrap())?;
+    let r2 = register(&draw.next().unwrap())?;
+    let height = draw.next().unwrap();
+    let n = byte(&height)?;
+    if n > 0xf{
+        return Err(Error::new_from_span(
+            ErrorVariant::CustomError { message: "a sprite is at most 15 rows high".to_string() }, height.as_span()))
+    }
     return Ok((Instruction::Draw(r1, r2, n), None))
 }
 
 
-fn parse_unop<'a>(op: Pair<'a, Rule>) -> Result<(Instruction, Option<&'a str>), Error>{
+fn parse_unop<'a>(op: Pair<'a, Rule>) -> Result<(Instruction, Option<Span<'a>>), Error>{
     let rule = op.as_rule();
-    let reg = register(&op.clone().into_inner().nth(1).unwrap())?;
+    let reg = register(&op.clone().into_inner().next().unwrap())?;
     Ok((match rule {
         Rule::rsh => Instruction::Rsh(reg),
         Rule::lsh => Instruction::Lsh(reg),
@@ -146,33 +216,36 @@ fn parse_load<'a>(load_args: Pair<'a, Rule>) -> ParseResult<'a>{
             Instruction::BCD(register(&arg0)?)
         }
         Rule::ldmem => {
-            Instruction::SetMemPtr(arg0.as_str().parse().unwrap())
+            let (addr, reference) = parse_addr(arg0.into_inner())?;
+            return Ok((Instruction::SetMemPtr(addr), reference))
         }
         Rule::setdelay => {
             Instruction::SetDelay(register(&arg0)?)
         }
         Rule::getdelay => Instruction::GetDelay(register(&arg0)?),
+        Rule::getkey => Instruction::WaitForKey(register(&arg0)?),
         Rule::setsound => Instruction::SetSound(register(&arg0)?),
-        Rule::regdmp => Instruction::SetSound(register(&arg0)?),
+        Rule::regdmp => Instruction::RegDump(register(&arg0)?),
         Rule::regload => Instruction::RegLoad(register(&arg0)?),
         Rule::setreg => {
             let r1 = pair.into_inner().nth(1).unwrap();
             Instruction::SetReg(register(&arg0)?, register(&r1)?)
         }
         Rule::setimm => {
-            let n: u8 = pair.into_inner().nth(1).unwrap().as_str().parse().unwrap();
+            let n = byte(&pair.into_inner().nth(1).unwrap())?;
             Instruction::SetImm(register(&arg0)?, n)
         }
         _ => bad_rule_error(&pair)?
     }, None))
 }
 
-pub fn parse_instruction<'a>(pair: Pair<'a, Rule>) -> Result<(Instruction, Option<&'a str>),Error>{
+pub fn parse_instruction<'a>(pair: Pair<'a, Rule>) -> Result<(Instruction, Option<Span<'a>>),Error>{
     match pair.as_rule(){
         Rule::cls => { Ok((Instruction::ClearScreen, None))}
         Rule::ret => {Ok((Instruction::Ret, None))}
         Rule::nop => { Ok((Instruction::Nop, None))}
-        Rule::call => { Ok(parse_call(pair.into_inner()))}
+        Rule::vsync => { Ok((Instruction::VSync, None))}
+        Rule::call => { parse_call(pair.into_inner())}
         Rule::jump | Rule::jpoff => { Ok(parse_jump(pair)?)}
         Rule::add | Rule::sub | Rule::se | Rule::sne 
             | Rule::or | Rule::and | Rule::xor | Rule::subn
@@ -189,35 +262,37 @@ pub fn parse_program<'a>(file:&'a str)->Result<Program<'_>, Error>{
    match InstructionParser::parse(Rule::file, file){
         Ok(file) => {
             let mut program: Program<'_> = Program{
-                instructions: vec![],
-                labels: HashMap::new(),
+                items: vec![],
+                labels: BTreeMap::new(),
                 references: vec![]
             };
 
             for line in file{
                 match line.as_rule(){
                     Rule::line => {
-                        let label_instr = line.clone().into_inner().next().unwrap();
-                        match label_instr.as_rule(){
-                            Rule::instruction => {
-                                match parse_instruction(label_instr.into_inner().next().unwrap()){
-                                    Ok((instruction, reference)) => {
-                                        program.instructions.push(instruction);
-                                        program.references.push(reference);
-                                    },
-                                    Err(e) => {return Err(e)}
+                        // blank and comment-only lines have nothing inside
+                        for label_instr in line.into_inner(){
+                            match label_instr.as_rule(){
+                                Rule::instruction => {
+                                    let (instruction, reference) = parse_instruction(label_instr.into_inner().next().unwrap())?;
+                                    program.items.push(Item::Instruction(instruction));
+                                    program.references.push(reference);
+                                }
+                                Rule::data => {
+                                    let bytes = label_instr.into_inner().map(|number| byte(&number)).collect::<Result<_, _>>()?;
+                                    program.items.push(Item::Bytes(bytes));
+                                    program.references.push(None);
                                 }
+                                Rule::label => {
+                                    program.labels.insert(label_instr.as_str().strip_suffix(":").unwrap(),
+                                        program.len()
+                                );},
+                                rule =>                     return Err(Error::new_from_pos(
+                                    ErrorVariant::CustomError { message: format!("unexpected rule: {:?}", rule) }, 
+                                    label_instr.as_span().start_pos()))
                             }
-                            Rule::label => {
-                                program.labels.insert(label_instr.as_str().strip_suffix(":").unwrap(),
-                                    program.instructions.len()
-                            );},
-                            rule =>                     return Err(Error::new_from_pos(
-                                ErrorVariant::CustomError { message: format!("unexpected rule: {:?}", rule) }, 
-                                label_instr.as_span().start_pos()))
-        
-                    }
-                },
+                        }
+                    },
                 Rule::EOI => {break;}
                 rule => { return Err(Error::new_from_pos(
                     ErrorVariant::CustomError { message: format!("unexpected rule: {:?}", rule) }, 
@@ -231,11 +306,257 @@ pub fn parse_program<'a>(file:&'a str)->Result<Program<'_>, Error>{
     }
 }
 
+/// How deep macros can call other macros before expansion gives up
+const MAX_MACRO_DEPTH: usize = 16;
+
+/// A macro's parameter names and the lines it expands to
+struct Macro<'a>{
+    params: Vec<&'a str>,
+    body: Vec<&'a str>
+}
+
+/// The constants and macros defined so far, while expanding a file
+#[derive(Default)]
+struct Definitions<'a>{
+    constants: BTreeMap<&'a str, String>,
+    macros: BTreeMap<&'a str, Macro<'a>>
+}
+
+fn custom_error(message: String, span: Span<'_>) -> Error{
+    Error::new_from_span(ErrorVariant::CustomError { message }, span)
+}
+
+/// `text` with each word replaced by `replace`, or `None` if no words were replaced.
+/// Comments are dropped and statements are put back together separated by `;`
+fn substitute<'s>(text: &str, replace: impl Fn(&str) -> Option<&'s str>) -> Option<String>{
+    let code = text.split('#').next().unwrap_or_default();
+    let mut replaced = false;
+    let statements: Vec<String> = code.split(';').map(|statement| {
+        statement.split_whitespace().map(|word| match replace(word) {
+            Some(replacement) => {replaced = true; replacement},
+            None => word
+        }).collect::<Vec<_>>().join(" ")
+    }).filter(|statement| !statement.is_empty()).collect();
+    replaced.then(|| statements.join("; "))
+}
+
+impl<'a> Definitions<'a>{
+    /// `text` with constants replaced and macros expanded, or `None` if it uses neither.
+    /// Errors point at `span`, the line in the source being expanded
+    fn expand(&self, text: &str, span: Span<'a>, depth: usize) -> Result<Option<String>, Error>{
+        let constants = substitute(text, |word| self.constants.get(word).map(String::as_str));
+        let mut expanded = constants.is_some();
+        let text = constants.unwrap_or_else(|| text.to_string());
+        let mut statements = vec![];
+        for statement in text.split('#').next().unwrap_or_default().split(';'){
+            let mut words = statement.split_whitespace();
+            let Some((name, body)) = words.next().and_then(|name| self.macros.get_key_value(name)) else {
+                statements.push(statement.trim().to_string());
+                continue
+            };
+            let args: Vec<_> = words.collect();
+            if args.len() != body.params.len(){
+                return Err(custom_error(
+                    format!("macro {} takes {} arguments, found {}", name, body.params.len(), args.len()), span))
+            }
+            if depth >= MAX_MACRO_DEPTH{
+                return Err(custom_error(format!("macro {} expands too deeply, does it use itself?", name), span))
+            }
+            for line in body.body.iter(){
+                let line = substitute(line, |word| {
+                    body.params.iter().position(|param| *param == word).map(|index| args[index])
+                }).unwrap_or_else(|| line.to_string());
+                statements.push(self.expand(&line, span, depth + 1)?.unwrap_or(line));
+            }
+            expanded = true;
+        }
+        statements.retain(|statement| !statement.is_empty());
+        Ok(expanded.then(|| statements.join("; ")))
+    }
+}
+
+/// The constants that name a register, such as `define PLAYER v3`, and the register each names in the end
+pub fn register_names(source: &str) -> Result<BTreeMap<String, Reg>, Error>{
+    let mut constants: BTreeMap<&str, String> = BTreeMap::new();
+    for line in InstructionParser::parse(Rule::source, source)?.filter(|line| line.as_rule() == Rule::source_line){
+        let inner = line.into_inner().next().unwrap();
+        if inner.as_rule() == Rule::define{
+            let mut parts = inner.into_inner();
+            let name = parts.next().unwrap().as_str();
+            let value = parts.next().unwrap().as_str();
+            let value = constants.get(value).cloned().unwrap_or_else(|| value.to_string());
+            constants.insert(name, value);
+        }
+    }
+    Ok(constants.into_iter().filter_map(|(name, value)| {
+        let reg = value.to_ascii_lowercase().strip_prefix('v')?.parse().ok()?;
+        Some((name.to_string(), reg))
+    }).collect())
+}
+
+/// The quirks set by `%quirk` pragmas, which the emulator should run the ROM with:
+///
+/// ```text
+/// %variant chip8
+/// %quirk wait-for-release on
+/// %quirk display_wait off
+/// ```
+///
+/// A later pragma for the same quirk wins. `chip8` is the only variant so far
+pub fn pragmas(source: &str) -> Result<BTreeMap<Quirk, bool>, Error>{
+    let mut quirks = BTreeMap::new();
+    for line in InstructionParser::parse(Rule::source, source)?.filter(|line| line.as_rule() == Rule::source_line){
+        let span = line.as_span();
+        let inner = line.into_inner().next().unwrap();
+        if inner.as_rule() != Rule::pragma{
+            continue
+        }
+        let mut parts = inner.into_inner().map(|part| part.as_str());
+        let name = parts.next().unwrap();
+        let args: Vec<_> = parts.collect();
+        match (name.to_ascii_lowercase().as_str(), args.as_slice()) {
+            ("variant", [variant]) if variant.eq_ignore_ascii_case("chip8") => {},
+            ("variant", [variant]) => return Err(custom_error(format!("unsupported variant {}, only chip8 is", variant), span)),
+            ("quirk", [quirk, setting]) => {
+                let quirk_name = quirk.to_ascii_lowercase().replace('_', "-");
+                let Some(quirk) = Quirk::ALL.into_iter().find(|known| known.to_string() == quirk_name) else {
+                    let known: Vec<_> = Quirk::ALL.iter().map(|known| known.to_string()).collect();
+                    return Err(custom_error(format!("unknown quirk {}, expected one of {}", quirk, known.join(", ")), span))
+                };
+                let enabled = match setting.to_ascii_lowercase().as_str() {
+                    "on" | "true" => true,
+                    "off" | "false" => false,
+                    _ => return Err(custom_error(format!("expected on or off for quirk {}, found {}", quirk, setting), span))
+                };
+                quirks.insert(quirk, enabled);
+            },
+            ("variant", _) => return Err(custom_error("expected %variant NAME".to_string(), span)),
+            ("quirk", _) => return Err(custom_error("expected %quirk NAME on|off".to_string(), span)),
+            _ => return Err(custom_error(format!("unknown pragma %{}, expected %variant or %quirk", name), span))
+        }
+    }
+    Ok(quirks)
+}
+
+/// Replace `define`d constants and expand macros, ready for `parse_program`.
+/// Everything a line expands to stays on that line, so errors keep their line numbers:
+///
+/// ```text
+/// define SPEED 2
+/// macro move x dx
+/// add x dx
+/// endm
+/// move v0 SPEED;  # add v0 2
+/// ```
+pub fn expand_macros(source: &str) -> Result<String, Error>{
+    let lines = InstructionParser::parse(Rule::source, source)?
+        .filter(|line| line.as_rule() == Rule::source_line);
+    let mut definitions = Definitions::default();
+    // the macro being defined, and where its definition starts
+    let mut defining: Option<(Span<'_>, &str, Macro<'_>)> = None;
+    let mut expanded = Vec::new();
+    for (line, original) in lines.zip(source.split('\n')){
+        let span = line.as_span();
+        let inner = line.into_inner().next().unwrap();
+        let output = match (inner.as_rule(), defining.is_some()) {
+            (Rule::define, true) => return Err(custom_error("constants can't be defined inside a macro".to_string(), span)),
+            (Rule::macro_start, true) => return Err(custom_error("macros can't be defined inside a macro".to_string(), span)),
+            (Rule::pragma, true) => return Err(custom_error("pragmas can't be used inside a macro".to_string(), span)),
+            (Rule::pragma, false) => String::new(),
+            (Rule::define, false) => {
+                let mut parts = inner.into_inner();
+                let name = parts.next().unwrap().as_str();
+                let value = parts.next().unwrap().as_str();
+                let value = definitions.constants.get(value).cloned().unwrap_or_else(|| value.to_string());
+                definitions.constants.insert(name, value);
+                String::new()
+            },
+            (Rule::macro_start, false) => {
+                let mut names = inner.into_inner().map(|symbol| symbol.as_str());
+                let name = names.next().unwrap();
+                if definitions.macros.contains_key(name){
+                    return Err(custom_error(format!("macro {} is already defined", name), span))
+                }
+                defining = Some((span, name, Macro{ params: names.collect(), body: vec![] }));
+                String::new()
+            },
+            (Rule::macro_end, _) => {
+                let Some((_, name, body)) = defining.take() else {
+                    return Err(custom_error("endm without a macro".to_string(), span))
+                };
+                definitions.macros.insert(name, body);
+                String::new()
+            },
+            (_, true) => {
+                let text = inner.as_str();
+                if text.split('#').next().unwrap_or_default().contains(':'){
+                    return Err(custom_error("labels can't be defined inside a macro".to_string(), span))
+                }
+                if let Some((_, _, body)) = defining.as_mut(){
+                    body.body.push(text);
+                }
+                String::new()
+            },
+            (_, false) => definitions.expand(inner.as_str(), span, 0)?.unwrap_or_else(|| original.to_string())
+        };
+        expanded.push(output);
+    }
+    if let Some((span, name, _)) = defining{
+        return Err(custom_error(format!("macro {} is missing its endm", name), span))
+    }
+    Ok(expanded.join("\n"))
+}
+
 #[cfg(test)]
 mod tests {
     use pest::Parser;
 
     
+    #[test]
+    fn test_expand_macros(){
+        let source = "define SPEED 2
+define PLAYER v3
+macro move x dx # move x by dx
+add x dx
+endm
+macro step
+move PLAYER SPEED; se PLAYER 10
+endm
+ld PLAYER 0 # start on the left
+loop:
+step; jp loop;
+";
+        let expanded = super::expand_macros(source).unwrap();
+        assert_eq!(expanded.lines().count(), source.lines().count());
+        assert_eq!(expanded.lines().nth(8), Some("ld v3 0"));
+        assert_eq!(expanded.lines().nth(9), Some("loop:"));
+        assert_eq!(expanded.lines().nth(10), Some("add v3 2; se v3 10; jp loop"));
+        let program = super::parse_program(&expanded).unwrap();
+        assert_eq!(program.items.len(), 4);
+        assert_eq!(program.labels.get("loop"), Some(&2));
+
+        // unchanged lines are left exactly as they were
+        assert_eq!(super::expand_macros("  ld v0 1; # one\n").unwrap(), "  ld v0 1; # one\n");
+
+        assert_eq!(super::expand_macros("%quirk wait-for-release on\nld v0 1\n").unwrap(), "\nld v0 1\n");
+        for source in ["macro m a\nadd v0 a\nendm\nm 1 2\n", "macro m\n%quirk wait-for-release on\nendm\n", "macro m\nm\nendm\nm\n", "macro m\nld v0 1\n",
+            "endm\n", "macro m\nhere:\nendm\n"]{
+            assert!(super::expand_macros(source).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_pragmas(){
+        use chip8::quirks::Quirk;
+        let quirks = super::pragmas("%variant CHIP8\n%quirk wait-for-release on\n%quirk display_wait off # original timing\n%quirk wait-for-release off\ncls\n").unwrap();
+        assert_eq!(quirks, std::collections::BTreeMap::from([(Quirk::DisplayWait, false), (Quirk::WaitForRelease, false)]));
+        for source in ["%variant schip\n", "%quirk shift_vx on\n", "%quirk wait-for-release maybe\n", "%quirk wait-for-release\n", "%org 0x300\n"]{
+            assert!(super::pragmas(source).is_err(), "{}", source);
+        }
+        let error = super::pragmas("cls\n%quirk shift_vx on\n").unwrap_err();
+        assert!(error.to_string().contains("unknown quirk shift_vx, expected one of display-wait"), "{}", error);
+    }
+
     #[test]
     fn test_parse1(){
         let instructions = 
@@ -250,9 +571,9 @@ end:
 jp end;
 "#;
         let program = super::parse_program(&instructions).unwrap();
-        assert_eq!( program.instructions.len(), 6);
+        assert_eq!( program.items.len(), 6);
         assert_eq!(program.labels.get("start"), Some(&0));
-        assert_eq!(program.labels.get("loop"), Some(&2));
-        assert_eq!(program.labels.get("end"), Some(&5));
+        assert_eq!(program.labels.get("loop"), Some(&4));
+        assert_eq!(program.labels.get("end"), Some(&10));
     }
 }
\ No newline at end of file
@@ -1,2 +1,136 @@
+#[cfg(feature = "assembler")]
 pub mod labels;
-pub use labels::parse_program;
\ No newline at end of file
+#[cfg(feature = "assembler")]
+pub use labels::{expand_macros, parse_program, pragmas, register_names};
+pub mod diagnostics;
+pub mod disassemble;
+pub mod inspect;
+pub mod report;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub use diagnostics::{Diagnostic, Diagnostics, Severity, Span};
+pub use disassemble::{disassemble, DisassembleOptions};
+pub use inspect::{inspect, RomInfo};
+pub use report::Report;
+
+use std::collections::BTreeMap;
+
+use chip8::{quirks::Quirk, replay::rom_hash, symbols::{BuildInfo, Location, Symbols, Variable}, Addr, Reg};
+
+/// How `assemble` builds a ROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssembleOptions{
+    /// Address the ROM is loaded at, which labels are resolved against
+    pub origin: Addr
+}
+
+impl Default for AssembleOptions{
+    fn default() -> Self {
+        Self { origin: Addr::ROM_START }
+    }
+}
+
+/// An assembled program. Assembling the same source with the same options always gives the same ROM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rom{
+    origin: Addr,
+    bytes: Vec<u8>,
+    labels: BTreeMap<String, Addr>,
+    /// Constants that name a register, see `register_names`
+    registers: BTreeMap<String, Reg>,
+    /// Quirks the source asks the emulator for with `%quirk`, see `pragmas`
+    quirks: BTreeMap<Quirk, bool>,
+    /// Hash of the source it was assembled from
+    source: u64
+}
+
+impl Rom{
+    /// Address the ROM was assembled to be loaded at
+    pub fn origin(&self) -> Addr{
+        self.origin
+    }
+
+    /// The ROM image, ready for `load_rom`
+    pub fn bytes(&self) -> &[u8]{
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8>{
+        self.bytes
+    }
+
+    /// Every label in the program and the address it was resolved to
+    pub fn labels(&self) -> &BTreeMap<String, Addr>{
+        &self.labels
+    }
+
+    pub fn label(&self, name: &str) -> Option<Addr>{
+        self.labels.get(name).copied()
+    }
+
+    /// The labels as a symbol map, for the emulator's `--symbols`, with the constants that name a register as variables
+    pub fn symbols(&self) -> Symbols{
+        let mut symbols: Symbols = self.labels.iter().map(|(name, addr)| (name.as_str(), *addr)).collect();
+        for (name, reg) in self.registers.iter(){
+            symbols.add_variable(Variable{ name: name.clone(), location: Location::Register(*reg), scope: None });
+        }
+        symbols
+    }
+
+    /// The quirks the source's `%quirk` pragmas ask for. Save them in the ROM's settings file
+    /// (`RomSettings::path_for`) so the emulator switches them on or off when loading the ROM
+    pub fn quirks(&self) -> &BTreeMap<Quirk, bool>{
+        &self.quirks
+    }
+
+    /// The hashes of the ROM and its source and the options it was built with, for telling builds apart.
+    /// Add it to `symbols` with `Symbols::with_build` so the emulator can tell whether they belong to a ROM
+    pub fn build_info(&self) -> BuildInfo{
+        BuildInfo{ rom: rom_hash(&self.bytes), source: self.source, origin: self.origin }
+    }
+
+    /// Sizes, free space and instruction counts, for keeping an eye on the ROM budget
+    pub fn report(&self) -> Report{
+        Report::new(self)
+    }
+}
+
+/// Assemble `source` into a ROM, resolving labels
+#[cfg(feature = "assembler")]
+pub fn assemble(source: &str, options: &AssembleOptions) -> Result<Rom, Diagnostics>{
+    let expanded = expand_macros(source).map_err(diagnostics::from_pest)?;
+    let mut program = parse_program(&expanded).map_err(diagnostics::from_pest)?;
+    program.fix_references(options.origin).map_err(diagnostics::from_pest)?;
+    let bytes = program.compile();
+    let space = usize::from(Addr::MAX) + 1 - usize::from(options.origin);
+    if bytes.len() > space{
+        let last_line = source.lines().last().unwrap_or_default();
+        return Err(Diagnostic::error(format!("program is {} bytes but only {} fit after {}", bytes.len(), space, options.origin))
+            .with_span(Span::whole_line(source.lines().count().max(1), last_line), last_line)
+            .into());
+    }
+    Ok(Rom{
+        origin: options.origin,
+        bytes,
+        labels: program.label_addresses(options.origin),
+        registers: register_names(source).map_err(diagnostics::from_pest)?,
+        quirks: pragmas(source).map_err(diagnostics::from_pest)?,
+        source: rom_hash(source.as_bytes())
+    })
+}
+
+/// Assemble `source` twice and check that both builds are identical, byte for byte and label for label
+#[cfg(feature = "assembler")]
+pub fn verify_reproducible(source: &str, options: &AssembleOptions) -> Result<Rom, Diagnostics>{
+    let first = assemble(source, options)?;
+    let second = assemble(source, options)?;
+    if first != second{
+        let difference = match first.bytes.iter().zip(second.bytes.iter()).position(|(a, b)| a != b) {
+            _ if first.bytes == second.bytes => "in their labels".to_string(),
+            Some(offset) => format!("first at byte {offset:#x}"),
+            None => format!("in length, {} and {} bytes", first.bytes.len(), second.bytes.len())
+        };
+        return Err(Diagnostic::error(format!("two builds of the same source differ {difference}")).into());
+    }
+    Ok(first)
+}
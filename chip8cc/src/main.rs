@@ -1,33 +1,128 @@
-use std::io::{Read, Write};
-use std::result::Result;
+use std::{io::{Read, Write}, path::{Path, PathBuf}};
 
-use chip8::*;
-use chip8cc::parse_program;
-use clap::Parser;
+use chip8::{coverage::Coverage, errors::Diagnostic, settings::RomSettings};
+use chip8cc::{AssembleOptions, DisassembleOptions, Rom};
+use clap::{Parser, Subcommand};
 use clio::*;
-use itertools::{self, Itertools};
 
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args{
-    input: ClioPath,
-    output: ClioPath,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(required = true)]
+    input: Option<ClioPath>,
+    #[arg(required = true)]
+    output: Option<ClioPath>,
     #[arg(short, long,)]
     disassemble: bool,
     #[arg(short,long)]
-    assemble: bool
+    assemble: bool,
+    /// Leave the addresses out of the disassembly, so that it can be assembled again
+    #[arg(long)]
+    no_addresses: bool,
+    /// Tell code from data in the disassembly by a run's RAM coverage, from `chip8 --coverage`.
+    /// Give it more than once to combine several runs
+    #[arg(long, requires = "disassemble")]
+    coverage: Vec<PathBuf>,
+    /// Also write the label addresses to this file, for the emulator's --symbols
+    #[arg(long)]
+    symbols: Option<PathBuf>,
+    /// Print the ROM size, free space, sections and instruction counts
+    #[arg(long)]
+    report: bool,
+    /// Print the report as JSON
+    #[arg(long, requires = "report")]
+    json: bool,
+    /// Assemble twice and fail unless both builds are identical
+    #[arg(long)]
+    verify_reproducible: bool
+}
+
+#[derive(Subcommand)]
+enum Command{
+    /// Report what a ROM needs from the emulator: its variant, sound, stack depth, keys and display
+    Inspect{
+        rom: ClioPath,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool
+    },
+    /// Work with RAM coverage files from `chip8 --coverage`
+    Cov{
+        #[command(subcommand)]
+        command: CovCommand
+    }
+}
+
+#[derive(Subcommand)]
+enum CovCommand{
+    /// Combine coverage from several runs of the same ROM into one file
+    Merge{
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[arg(short, long)]
+        output: PathBuf
+    }
 }
 
 
 fn main(){
     let args = Args::parse();
+    match args.command {
+        Some(Command::Inspect{ rom, json }) => return inspect(rom, json),
+        Some(Command::Cov{ command: CovCommand::Merge{ files, output } }) => return merge_coverage(&files, &output),
+        None => {}
+    }
+    let (Some(input), Some(output)) = (args.input, args.output) else {unreachable!("clap requires both")};
     if args.disassemble{
-        disassemble(args.input, args.output)
+        disassemble(input, output, !args.no_addresses, args.coverage)
     } else {
-        assemble(args.input, args.output)
+        assemble(input, output, args.symbols, args.report.then_some(args.json), args.verify_reproducible)
     }
 }
 
-fn disassemble(input: ClioPath, output: ClioPath){
+fn inspect(rom: ClioPath, json: bool){
+    let name = rom.file_name().map_or("stdin".to_owned(), |name| name.to_string_lossy().into_owned());
+    let mut bytes = Vec::new();
+    if let Err(err) = rom.open().and_then(|mut input| Ok(input.read_to_end(&mut bytes)?)){
+        eprintln!("Could not read {name}: {err}");
+        std::process::exit(1);
+    }
+    let info = chip8cc::inspect(&bytes);
+    if json {println!("{}", info.to_json())} else {print!("{}", info)}
+}
+
+/// Read a coverage file, exiting with the error if it can't be
+fn read_coverage(path: &Path) -> Coverage{
+    let parsed = std::fs::read_to_string(path)
+        .map_err(|err| Diagnostic::error(format!("Could not read the coverage: {err}")))
+        .and_then(|text| Coverage::parse(&text));
+    parsed.unwrap_or_else(|err| {
+        eprintln!("{}", err.render(Some(&path.display().to_string())));
+        std::process::exit(1)
+    })
+}
+
+/// Every coverage file in `paths` combined, or `None` if there are none
+fn combined_coverage(paths: &[PathBuf]) -> Option<Coverage>{
+    paths.iter().map(|path| read_coverage(path)).reduce(|mut combined, coverage| {
+        combined.merge(&coverage);
+        combined
+    })
+}
+
+fn merge_coverage(files: &[PathBuf], output: &Path){
+    let Some(merged) = combined_coverage(files) else {unreachable!("clap requires a file")};
+    if let Err(err) = merged.save(output){
+        eprintln!("Could not write {}: {err}", output.display());
+        std::process::exit(1);
+    }
+    let (executed, read, written) = merged.counts();
+    println!("Merged {} files: {executed} bytes executed, {read} read, {written} written", files.len());
+}
+
+fn disassemble(input: ClioPath, output: ClioPath, addresses: bool, coverage: Vec<PathBuf>){
     let input_name = input.file_name().map_or("stdin".to_owned(), 
     |name| name.to_string_lossy().into_owned());
     let output_name = input.file_name().map_or("stdout".to_owned(), 
@@ -37,19 +132,34 @@ fn disassemble(input: ClioPath, output: ClioPath){
     if input.read_to_end(&mut bytes).is_err(){
         println!("Error reading {}", input_name)
     }
-    // assert!(bytes.len() % 2 == 0);
-    let instrs = bytes.iter().tuples().map(
-        |(upper,lower)| u16::from_be_bytes([*upper, *lower]).into()
-    ).take_while(|i| !matches!(*i,Instruction::Nop)).collect::<Vec<Instruction>>();
-    let mut mnemonics = instrs.iter().map(|i| i.to_string());
+    let coverage = combined_coverage(&coverage);
+    let text = chip8cc::disassemble(&bytes, &DisassembleOptions{ addresses, coverage, ..Default::default() });
     let mut output = output.create().expect(&format!("Could not create file {}", output_name));
-    output.write_all(mnemonics.join(";\n").as_bytes())
+    output.write_all(text.as_bytes())
         .expect(&format!("could not write to file {output_name}"));
 }
 
 
 
-fn assemble(input: ClioPath, output: ClioPath){
+/// Save the quirks from the source's `%quirk` pragmas in the settings file the emulator loads with the ROM at `path`,
+/// warning about any the file had the other way
+fn save_quirks(rom: &Rom, path: &Path){
+    let settings_path = RomSettings::path_for(path);
+    let mut settings = RomSettings::load(path);
+    for (quirk, enabled) in rom.quirks(){
+        if settings.quirks.get(quirk).is_some_and(|old| old != enabled){
+            eprintln!("warning: quirk {} was {} in {}, the source's %quirk turns it {}", quirk,
+                if *enabled {"off"} else {"on"}, settings_path.display(), if *enabled {"on"} else {"off"});
+        }
+        settings.quirks.insert(*quirk, *enabled);
+    }
+    if let Err(err) = settings.save(&settings_path){
+        eprintln!("Could not write quirks to {}: {}", settings_path.display(), err);
+    }
+}
+
+/// `report` is `Some(json)` to print a build report
+fn assemble(input: ClioPath, output: ClioPath, symbols: Option<PathBuf>, report: Option<bool>, verify: bool){
     let input_name = input.file_name().map_or("stdin".to_owned(), 
     |name| name.to_string_lossy().into_owned());
     let output_name = input.file_name().map_or("stdout".to_owned(), 
@@ -57,31 +167,34 @@ fn assemble(input: ClioPath, output: ClioPath){
     if let Ok(ref mut input) = input.open(){
         let mut text = String::new();
         if input.read_to_string(&mut text).is_err(){
-            println!("Could not read file {}", input_name)
+            eprintln!("Could not read file {}", input_name);
+            std::process::exit(1);
         }
-        match to_binary(&text) {
-            Ok(bytes) => {
+        let build = if verify {chip8cc::verify_reproducible} else {chip8cc::assemble};
+        match build(&text, &AssembleOptions::default()) {
+            Ok(rom) => {
                 let mut output = output.create().expect(&format!("Could not create file {}", output_name));
-                output.write_all(&bytes).expect(&format!("Could not write to {}", output_name));
+                output.write_all(rom.bytes()).expect(&format!("Could not write to {}", output_name));
+                if !rom.quirks().is_empty() && !output.is_std(){
+                    save_quirks(&rom, output.path());
+                }
+                if let Some(path) = symbols{
+                    std::fs::write(&path, rom.symbols().with_build(rom.build_info()).to_string())
+                        .expect(&format!("Could not write to {}", path.display()));
+                }
+                match report {
+                    Some(true) => println!("{}", rom.report().to_json()),
+                    Some(false) => print!("{}", rom.report()),
+                    None => {}
+                }
             },
             Err(error) => {
-                let error = error.with_path(&input_name);
-                eprintln!("{}", error)
+                eprintln!("{}", error.with_path(&input_name));
+                std::process::exit(1);
             }
         }
     } else {
         eprintln!("Could not open file {input_name}");
+        std::process::exit(1);
     }
 }
-
-fn to_binary(text: &str) -> Result<Vec<u8>, chip8cc::labels::Error>{
-    match parse_program(text){
-        Ok(program) => {
-            let bytes: Vec<u8> = program.compile();
-            Ok(bytes)}
-        Err(err) => {
-            Err(err)
-        }
-    }
-}
-
@@ -0,0 +1,114 @@
+use std::{collections::{BTreeMap, BTreeSet}, fmt::Display};
+
+use chip8::{errors::json_string, Addr, Instruction, Reg};
+
+use crate::Rom;
+
+/// The code from a label up to the next one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section{
+    /// The label, or `None` for code before the first label
+    pub name: Option<String>,
+    pub start: Addr,
+    pub size: usize
+}
+
+/// How much of the CHIP-8's memory and registers a program uses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report{
+    pub origin: Addr,
+    pub size: usize,
+    /// Bytes left between the end of the program and the end of memory
+    pub free: usize,
+    pub sections: Vec<Section>,
+    /// Registers named by any instruction
+    pub registers: BTreeSet<Reg>,
+    /// How many of each instruction there are, by mnemonic
+    pub instructions: BTreeMap<String, usize>
+}
+
+impl Report{
+    pub fn new(rom: &Rom) -> Self{
+        let origin = rom.origin();
+        let end = origin.index() + rom.bytes().len();
+        let mut starts: Vec<_> = rom.labels().iter()
+            .map(|(name, addr)| (*addr, Some(name.clone())))
+            .collect();
+        starts.sort();
+        if starts.first().is_none_or(|(addr, _)| *addr > origin){
+            starts.insert(0, (origin, None));
+        }
+        let sections = starts.iter().enumerate().map(|(index, (start, name))| {
+            let next = starts.get(index + 1).map_or(end, |(next, _)| next.index());
+            Section{ name: name.clone(), start: *start, size: next.min(end).saturating_sub(start.index()) }
+        }).collect();
+
+        let mut registers = BTreeSet::new();
+        let mut instructions = BTreeMap::new();
+        for word in rom.bytes().chunks_exact(chip8::INSTRUCTION_SIZE){
+            let instruction = Instruction::from(u16::from_be_bytes([word[0], word[1]]));
+            registers.extend(registers_named(instruction));
+            let text = instruction.to_string();
+            let mnemonic = text.split_whitespace().next().unwrap_or_default();
+            *instructions.entry(mnemonic.to_string()).or_insert(0) += 1;
+        }
+        Self{
+            origin,
+            size: rom.bytes().len(),
+            free: (usize::from(Addr::MAX) + 1).saturating_sub(end),
+            sections,
+            registers,
+            instructions
+        }
+    }
+
+    /// The report as a JSON object, for build scripts and editors
+    pub fn to_json(&self) -> String{
+        let sections = self.sections.iter().map(|section| format!(
+            "{{\"name\":{},\"start\":{},\"size\":{}}}",
+            section.name.as_deref().map_or("null".to_string(), json_string), u16::from(section.start), section.size
+        )).collect::<Vec<_>>().join(",");
+        let registers = self.registers.iter().map(|reg| reg.index().to_string()).collect::<Vec<_>>().join(",");
+        let instructions = self.instructions.iter()
+            .map(|(name, count)| format!("{}:{}", json_string(name), count))
+            .collect::<Vec<_>>().join(",");
+        format!("{{\"origin\":{},\"size\":{},\"free\":{},\"sections\":[{}],\"registers\":[{}],\"instructions\":{{{}}}}}",
+            u16::from(self.origin), self.size, self.free, sections, registers, instructions)
+    }
+}
+
+impl Display for Report{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} bytes at {}, {} bytes of memory left", self.size, self.origin, self.free)?;
+        writeln!(f, "sections:")?;
+        for section in self.sections.iter(){
+            writeln!(f, "  {:<16} {} {:>5} bytes", section.name.as_deref().unwrap_or("(start)"), section.start, section.size)?;
+        }
+        let names: Vec<_> = self.registers.iter().map(|reg| format!("V{}", reg)).collect();
+        writeln!(f, "registers: {} ({} unused)", names.join(" "), 16 - self.registers.len())?;
+        writeln!(f, "instructions:")?;
+        for (name, count) in self.instructions.iter(){
+            writeln!(f, "  {:<5} {}", name, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// The registers an instruction names. Dumping or loading up to Vx names V0 to Vx,
+/// but VF being set as a flag doesn't count
+fn registers_named(instruction: Instruction) -> Vec<Reg>{
+    match instruction {
+        Instruction::ClearScreen | Instruction::Ret | Instruction::Nop | Instruction::VSync | Instruction::Jump(_)
+            | Instruction::Call(_) | Instruction::SetMemPtr(_) => vec![],
+        Instruction::JumpOffset(_) => vec![Reg::V0],
+        Instruction::SkipEqImm(x, _) | Instruction::SkipNeImm(x, _) | Instruction::SetImm(x, _) | Instruction::AddImm(x, _)
+            | Instruction::Rand(x, _) | Instruction::Rsh(x) | Instruction::Lsh(x) | Instruction::SkipKeyPressed(x)
+            | Instruction::SkipKeyNotPressed(x) | Instruction::GetDelay(x) | Instruction::WaitForKey(x)
+            | Instruction::SetDelay(x) | Instruction::SetSound(x) | Instruction::AddMemPtr(x) | Instruction::SetChar(x)
+            | Instruction::BCD(x) => vec![x],
+        Instruction::SkipEqReg(x, y) | Instruction::SkipNeReg(x, y) | Instruction::SetReg(x, y) | Instruction::OrReg(x, y)
+            | Instruction::AndReg(x, y) | Instruction::XorReg(x, y) | Instruction::AddReg(x, y) | Instruction::SubReg(x, y)
+            | Instruction::SubFrom(x, y) | Instruction::Draw(x, y, _) => vec![x, y],
+        Instruction::RegDump(x) | Instruction::RegLoad(x) => (0..=x.index() as u8).filter_map(Reg::new).collect()
+    }
+}
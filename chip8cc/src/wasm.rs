@@ -0,0 +1,53 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{AssembleOptions, Diagnostics, DisassembleOptions, Rom};
+
+/// The result of assembling a program from JavaScript:
+/// either the ROM or the diagnostics explaining why there isn't one
+#[wasm_bindgen]
+pub struct Assembly{
+    rom: Option<Rom>,
+    diagnostics: Diagnostics
+}
+
+#[wasm_bindgen]
+impl Assembly{
+    /// Whether the program assembled
+    pub fn ok(&self) -> bool{
+        self.rom.is_some()
+    }
+
+    /// The ROM image, empty if the program didn't assemble
+    pub fn bytes(&self) -> Vec<u8>{
+        self.rom.as_ref().map(|rom| rom.bytes().to_vec()).unwrap_or_default()
+    }
+
+    /// The quirks set by `%quirk` pragmas as a JSON object, e.g. `{"wait-for-release":true}`, for `Chip8Handle::set_quirk`
+    pub fn quirks(&self) -> String{
+        let quirks: Vec<_> = self.rom.iter().flat_map(|rom| rom.quirks())
+            .map(|(quirk, enabled)| format!("\"{}\":{}", quirk, enabled))
+            .collect();
+        format!("{{{}}}", quirks.join(","))
+    }
+
+    /// The diagnostics as a JSON array of objects with `severity`, `message`
+    /// and, where there is one, a `span` of 1-based `line`, `column`, `end_line` and `end_column`
+    pub fn diagnostics(&self) -> String{
+        self.diagnostics.to_json()
+    }
+}
+
+/// Assemble `source` to be loaded at 0x200
+#[wasm_bindgen(js_name = assemble)]
+pub fn assemble_source(source: &str) -> Assembly{
+    match crate::assemble(source, &AssembleOptions::default()) {
+        Ok(rom) => Assembly{ rom: Some(rom), diagnostics: Diagnostics::default() },
+        Err(diagnostics) => Assembly{ rom: None, diagnostics }
+    }
+}
+
+/// A listing of `bytes`, one instruction per line with its address
+#[wasm_bindgen(js_name = disassemble)]
+pub fn disassemble_bytes(bytes: &[u8]) -> String{
+    crate::disassemble(bytes, &DisassembleOptions{ addresses: true, ..Default::default() })
+}
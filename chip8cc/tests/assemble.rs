@@ -0,0 +1,195 @@
+//! The library API: assembling from memory, diagnostics and disassembly
+
+use chip8::Addr;
+use chip8cc::{assemble, disassemble, inspect, verify_reproducible, AssembleOptions, DisassembleOptions, Span};
+
+const PROGRAM: &str = "start:
+ld v0 1;
+loop:
+add v0 1;
+jp loop;
+";
+
+#[test]
+fn test_assemble_labels(){
+    let rom = assemble(PROGRAM, &AssembleOptions::default()).unwrap();
+    assert_eq!(rom.bytes(), [0x60, 0x01, 0x70, 0x01, 0x12, 0x02]);
+    assert_eq!(rom.label("loop"), Addr::new(0x202));
+    assert_eq!(rom.labels().len(), 2);
+    assert_eq!(rom.symbols().to_string(), "0x200 start\n0x202 loop\n");
+
+    let options = AssembleOptions{ origin: Addr::new(0x600).unwrap() };
+    let rom = assemble(PROGRAM, &options).unwrap();
+    assert_eq!(rom.origin(), options.origin);
+    assert_eq!(rom.bytes()[4..], [0x16, 0x02]);
+}
+
+#[test]
+fn test_reproducible(){
+    let rom = verify_reproducible(PROGRAM, &AssembleOptions::default()).unwrap();
+    assert_eq!(rom, assemble(PROGRAM, &AssembleOptions::default()).unwrap());
+    let build = rom.build_info();
+    assert_eq!(build.rom, chip8::replay::rom_hash(rom.bytes()));
+    assert_eq!(build.origin, Addr::ROM_START);
+    // a comment changes the source but not the ROM
+    let commented = assemble(&format!("# counter\n{PROGRAM}"), &AssembleOptions::default()).unwrap();
+    assert_eq!((commented.build_info().rom, commented.build_info().source == build.source), (build.rom, false));
+    let symbols = chip8::symbols::Symbols::parse(&rom.symbols().with_build(build).to_string()).unwrap();
+    assert_eq!(symbols.build(), Some(build));
+    assert!(symbols.matches(rom.bytes()));
+    assert!(verify_reproducible("jp nowhere;", &AssembleOptions::default()).is_err());
+}
+
+#[test]
+fn test_register_variables(){
+    let source = "define SPEED 2\ndefine PLAYER v3\ndefine HERO PLAYER\nstart:\nadd PLAYER SPEED;\n";
+    let rom = assemble(source, &AssembleOptions::default()).unwrap();
+    assert_eq!(rom.bytes(), [0x73, 0x02]);
+    assert_eq!(rom.symbols().to_string(), ".var HERO v3\n.var PLAYER v3\n0x200 start\n");
+}
+
+#[test]
+fn test_data_labels(){
+    let source = "ld i sprite;\ndrw v0 v1 3;\nend:\njp end;\nsprite:\nbytes 0x80 0xc0 224\nnext:\n";
+    let rom = assemble(source, &AssembleOptions::default()).unwrap();
+    assert_eq!(rom.bytes(), [0xA2, 0x06, 0xD0, 0x13, 0x12, 0x04, 0x80, 0xC0, 0xE0]);
+    assert_eq!(rom.label("sprite"), Addr::new(0x206));
+    assert_eq!(rom.label("next"), Addr::new(0x209));
+
+    let errors = assemble("bytes 1 256\n", &AssembleOptions::default()).unwrap_err();
+    assert_eq!(errors.iter().next().unwrap().span, Some(Span::new(1, 9, 1, 12)));
+}
+
+#[test]
+fn test_report(){
+    let rom = assemble("ld v0 1;\nloop:\nadd v0 1;\nld [i] v2;\njp loop;\n", &AssembleOptions::default()).unwrap();
+    let report = rom.report();
+    assert_eq!(report.size, 8);
+    assert_eq!(report.free, 0x1000 - 0x208);
+    assert_eq!(report.sections.len(), 2);
+    assert_eq!(report.sections[0].name, None);
+    assert_eq!((report.sections[1].name.as_deref(), report.sections[1].size), (Some("loop"), 6));
+    assert_eq!(report.instructions.get("LD"), Some(&2));
+    assert!(report.to_string().contains("registers: V0 V1 V2 (13 unused)"), "{}", report);
+    assert!(report.to_json().starts_with(r#"{"origin":512,"size":8,"free":3576,"sections":[{"name":null,"start":512,"size":2}"#),
+        "{}", report.to_json());
+}
+
+#[test]
+fn test_diagnostics(){
+    let errors = assemble("ld v0 1;\njp nowhere;\n", &AssembleOptions::default()).unwrap_err();
+    let error = errors.iter().next().unwrap();
+    assert_eq!(error.span, Some(Span::new(2, 4, 2, 11)));
+    assert_eq!(error.message, "undefined label nowhere");
+    assert_eq!(errors.with_path("main.ch8sm"),
+        "error: undefined label nowhere\n --> main.ch8sm:2:4\n  |\n2 | jp nowhere;\n  |    ^^^^^^^");
+    assert!(errors.to_json().starts_with(r#"[{"severity":"error","message":"undefined label nowhere","span":{"line":2"#));
+
+    let errors = assemble("cls;\nld v0 0x100;\n", &AssembleOptions::default()).unwrap_err();
+    assert_eq!(errors.iter().next().unwrap().span.map(|span| span.line), Some(2));
+
+    // the height is a nibble, so 16 would otherwise wrap round to 0
+    assert_eq!(assemble("drw v0 v1 15;\n", &AssembleOptions::default()).unwrap().bytes(), [0xd0, 0x1f]);
+    let errors = assemble("drw v0 v1 16;\n", &AssembleOptions::default()).unwrap_err();
+    let error = errors.iter().next().unwrap();
+    assert_eq!(error.message, "a sprite is at most 15 rows high");
+    assert_eq!(error.span, Some(Span::new(1, 11, 1, 13)));
+}
+
+#[test]
+fn test_disassemble(){
+    let rom = assemble(PROGRAM, &AssembleOptions::default()).unwrap();
+    let mut bytes = rom.into_bytes();
+    bytes.extend([0, 0, 0x00, 0xe0]);
+    let listing = disassemble(&bytes, &DisassembleOptions::default());
+    assert_eq!(listing, "0x200: LD V0 1;\nlabel_202:\n0x202: ADD V0 1;\n0x204: JP label_202;\n0x206: bytes 0x00 0x00 0x00 0xe0\n");
+
+    // code after data, reached by a call; the sprite is only read through I
+    let source = "cls;\ncall draw;\nend:\njp end;\nsprite:\nbytes 0xf0 0x90 0xf0\ndraw:\nld i sprite;\nse v0 0;\ndrw v0 v1 3;\nret;\n";
+    let rom = assemble(source, &AssembleOptions::default()).unwrap();
+    let options = DisassembleOptions{ addresses: false, ..Default::default() };
+    let listing = disassemble(rom.bytes(), &options);
+    assert_eq!(listing, "CLS;\nCALL sub_209;\nlabel_204:\nJP label_204;\ndata_206:\nbytes 0xf0 0x90 0xf0\n\
+        sub_209:\nLD I data_206;\nSE V0 0;\nDRW V0 V1 3;\nRET;\n");
+    assert_eq!(assemble(&listing, &AssembleOptions::default()).unwrap().bytes(), rom.bytes());
+}
+
+#[test]
+fn test_disassemble_coverage(){
+    // ld v0 2; jp v0 0x204, which lands on 0x206; two bytes read as data; cls; jp 0x208; and code only reached by jp v0
+    let bytes = [0x60, 0x02, 0xb2, 0x04, 0x60, 0x90, 0x00, 0xe0, 0x12, 0x08, 0x00, 0x00, 0x00, 0xee];
+    let listing = disassemble(&bytes, &DisassembleOptions{ addresses: false, ..Default::default() });
+    // without coverage, the data looks like code and the last instruction looks like data
+    assert!(listing.contains("LD V0 144;"), "{listing}");
+    assert!(listing.contains("0x00 0xee"), "{listing}");
+
+    let mut coverage = chip8::coverage::Coverage::default();
+    for pc in [0x200, 0x202, 0x206, 0x208, 0x20c]{
+        coverage.mark_instruction(pc);
+    }
+    coverage.mark(0x204..0x206, chip8::coverage::READ);
+    let options = DisassembleOptions{ addresses: false, coverage: Some(coverage), ..Default::default() };
+    let listing = disassemble(&bytes, &options);
+    assert!(listing.contains("bytes 0x60 0x90\nCLS;\n"), "{listing}");
+    assert!(listing.ends_with("bytes 0x00 0x00\nRET;\n"), "{listing}");
+    assert_eq!(assemble(&listing, &AssembleOptions::default()).unwrap().bytes(), bytes);
+}
+
+#[test]
+fn test_disassemble_merged_coverage(){
+    // se v0 0; jp 0x206; cls; jp 0x206, one run taking each side of the skip
+    let bytes = [0x30, 0x00, 0x12, 0x06, 0x00, 0xe0, 0x12, 0x06];
+    let run = |pcs: &[usize]| {
+        let mut coverage = chip8::coverage::Coverage::default();
+        for pc in pcs{
+            coverage.mark_instruction(*pc);
+        }
+        coverage
+    };
+    let mut coverage = run(&[0x200, 0x202, 0x206]);
+    let options = DisassembleOptions{ addresses: false, coverage: Some(coverage.clone()), ..Default::default() };
+    assert_eq!(disassemble(&bytes, &options), "# 3 of 4 instructions ran\nSE V0 0;\nJP label_206;\nCLS; # never ran\nlabel_206:\nJP label_206;\n");
+
+    coverage.merge(&run(&[0x200, 0x204, 0x206]));
+    let options = DisassembleOptions{ addresses: false, coverage: Some(coverage), ..Default::default() };
+    let listing = disassemble(&bytes, &options);
+    assert!(listing.starts_with("# 4 of 4 instructions ran\n"), "{listing}");
+    assert!(!listing.contains("never ran"), "{listing}");
+    assert_eq!(assemble(&listing, &AssembleOptions::default()).unwrap().bytes(), bytes);
+}
+
+#[test]
+fn test_inspect(){
+    let addr = |addr| Addr::new(addr).unwrap();
+    // ld v0 5; skp v0; call 0x20c; ld v1 k; sknp v1; jp 0x200;
+    // 0x20c: ld st v0; call 0x214; ret; data; 0x214: ld b v0; ld [i] v2; ret
+    let info = inspect(&[0x60, 0x05, 0xe0, 0x9e, 0x22, 0x0c, 0xf1, 0x0a, 0xe1, 0xa1, 0x12, 0x00,
+        0xf0, 0x18, 0x22, 0x14, 0x00, 0xee, 0x00, 0x00, 0xf0, 0x33, 0xf2, 0x55, 0x00, 0xee]);
+    assert_eq!(info.variant, None);
+    assert_eq!(info.display_size(), (64, 32));
+    assert_eq!(info.stack_depth, Some(2));
+    assert_eq!((info.sound, info.bcd, info.reg_dump, info.reg_load), (Some(addr(0x20c)), Some(addr(0x214)), Some(addr(0x216)), None));
+    assert_eq!(info.keys, std::collections::BTreeMap::from([(5, [addr(0x202)].into())]));
+    assert_eq!(info.computed_keys, [addr(0x208)].into());
+    assert_eq!(info.waits_for_key, [addr(0x206)].into());
+    let text = info.to_string();
+    assert!(text.contains("keys:\n  . . . .\n  . 5 . .\n"), "{text}");
+    assert!(text.contains("hint: it stores registers in memory"), "{text}");
+    assert!(info.to_json().contains(r#""stack_depth":2,"keys":{"5":[514]},"computed_keys":[520]"#), "{}", info.to_json());
+
+    // high; call 0x202, which calls itself
+    let info = inspect(&[0x00, 0xff, 0x22, 0x02]);
+    assert_eq!(info.variant.map(|(variant, ..)| variant), Some(chip8::rom_inspect::Variant::SuperChip));
+    assert_eq!(info.display_size(), (128, 64));
+    assert_eq!(info.stack_depth, None);
+    assert!(info.to_string().contains("stack depth: unbounded"));
+}
+
+#[test]
+fn test_quirk_pragmas(){
+    let rom = assemble(&format!("%variant chip8\n%quirk wait-for-release on\n{PROGRAM}"), &AssembleOptions::default()).unwrap();
+    assert_eq!(rom.bytes(), assemble(PROGRAM, &AssembleOptions::default()).unwrap().bytes());
+    assert_eq!(rom.quirks(), &std::collections::BTreeMap::from([(chip8::quirks::Quirk::WaitForRelease, true)]));
+    let error = assemble(&format!("{PROGRAM}%variant schip\n"), &AssembleOptions::default()).unwrap_err();
+    assert_eq!(error.iter().next().and_then(|error| error.span).map(|span| span.line), Some(6));
+}
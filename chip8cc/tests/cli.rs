@@ -0,0 +1,31 @@
+#![cfg(all(feature = "assembler", feature = "cli"))]
+//! The chip8cc binary's exit status, which scripts and CI rely on to notice a failed build
+
+use std::process::{Command, Output};
+
+/// Run chip8cc with `args` on `source`, written to a scratch file, giving the output and the ROM it wrote
+fn chip8cc(name: &str, source: &str, args: &[&str]) -> (Output, Option<Vec<u8>>){
+    let dir = std::env::temp_dir().join(format!("chip8cc-cli-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (input, output) = (dir.join("main.ch8sm"), dir.join("main.ch8"));
+    std::fs::write(&input, source).unwrap();
+    let result = Command::new(env!("CARGO_BIN_EXE_chip8cc")).args(args).arg(&input).arg(&output).output().unwrap();
+    let rom = std::fs::read(&output).ok();
+    std::fs::remove_dir_all(&dir).unwrap();
+    (result, rom)
+}
+
+#[test]
+fn test_exit_status(){
+    let (output, rom) = chip8cc("ok", "ld v0 1;\n", &[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(rom.unwrap(), [0x60, 0x01]);
+
+    let (output, rom) = chip8cc("range", "jp 5000;\n", &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("address out of range"));
+    assert!(rom.is_none());
+
+    let (output, _) = chip8cc("verify", "jp nowhere;\n", &["--verify-reproducible"]);
+    assert_eq!(output.status.code(), Some(1));
+}
@@ -0,0 +1,70 @@
+//! The programs in `examples/`, assembled with the library and run headless.
+//! The display hashes pin down exactly what each program draws, so a change
+//! to the assembler or the emulator that breaks them shows up here
+
+use chip8::HeadlessDriver;
+use chip8cc::AssembleOptions;
+
+/// Assemble `source` and load it into a headless emulator running at `speed`
+fn load(source: &str, speed: u64) -> HeadlessDriver{
+    let rom = chip8cc::assemble(source, &AssembleOptions::default()).unwrap_or_else(|err| panic!("{err}"));
+    let mut driver = HeadlessDriver::new(Some(speed));
+    driver.load_rom(rom.bytes()).unwrap();
+    driver
+}
+
+/// FNV-1a over the display, so the hashes don't change between Rust versions
+fn display_hash(driver: &HeadlessDriver) -> u64{
+    driver.display().iter().fold(0xcbf29ce484222325, |hash, pixel| {
+        (hash ^ *pixel as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The display as text, to show what was drawn when a hash doesn't match
+fn render(driver: &HeadlessDriver) -> String{
+    let display = driver.display();
+    let (columns, rows) = display.dim();
+    (0..rows).map(|y| {
+        (0..columns).map(|x| if display[[x, y]] {'#'} else {'.'}).collect::<String>()
+    }).collect::<Vec<_>>().join("\n")
+}
+
+#[test]
+fn test_paddle(){
+    let mut driver = load(include_str!("../examples/paddle.ch8sm"), 6000);
+    driver.run_frames(40);
+    assert_eq!(display_hash(&driver), 2548315422062804926, "\n{}", render(&driver));
+    // hold 4 to move the paddle down
+    driver.press_key(0x4);
+    driver.run_frames(10);
+    driver.release_key(0x4);
+    assert_eq!(display_hash(&driver), 7790264290902266534, "\n{}", render(&driver));
+}
+
+#[test]
+fn test_keypad(){
+    // fast enough that each redraw finishes within a frame
+    let mut driver = load(include_str!("../examples/keypad.ch8sm"), 60_000);
+    driver.run_frames(2);
+    assert!(driver.display().iter().all(|pixel| !pixel));
+    for key in [0x1, 0xa, 0xf]{
+        driver.press_key(key);
+    }
+    driver.run_frames(2);
+    assert_eq!(display_hash(&driver), 16325642552707552410, "\n{}", render(&driver));
+    driver.release_key(0xa);
+    driver.run_frames(2);
+    assert_eq!(display_hash(&driver), 10256106744958173258, "\n{}", render(&driver));
+}
+
+#[test]
+fn test_metronome(){
+    let mut driver = load(include_str!("../examples/metronome.ch8sm"), 600);
+    driver.run_frames(2);
+    assert!(driver.sound());
+    driver.run_frames(5);
+    assert!(!driver.sound());
+    // three more beats
+    driver.run_frames(90);
+    assert_eq!(display_hash(&driver), 1960943003634623329, "\n{}", render(&driver));
+}
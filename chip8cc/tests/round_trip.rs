@@ -0,0 +1,53 @@
+#![cfg(feature = "assembler")]
+use chip8::Instruction;
+use chip8cc::{assemble, disassemble, AssembleOptions, DisassembleOptions};
+use proptest::prelude::*;
+
+/// Disassemble `bytes` and assemble the listing again
+fn round_trip(bytes: &[u8]) -> Result<Vec<u8>, String>{
+    let listing = disassemble(bytes, &DisassembleOptions{ addresses: false, ..Default::default() });
+    assemble(&listing, &AssembleOptions::default())
+        .map(|rom| rom.into_bytes())
+        .map_err(|errors| format!("{}\n{}", listing, errors.with_path("listing")))
+}
+
+/// Any instruction the assembler can write, other than `nop`
+fn instruction() -> impl Strategy<Value = Instruction>{
+    any::<u16>().prop_map(Instruction::from)
+        .prop_filter("not an instruction", |instruction| *instruction != Instruction::Nop)
+}
+
+#[test]
+fn test_every_opcode(){
+    let mut failures = Vec::new();
+    for opcode in 0..=u16::MAX{
+        let bytes = opcode.to_be_bytes();
+        match round_trip(&bytes) {
+            Ok(assembled) if assembled == bytes => {},
+            Ok(assembled) => failures.push(format!("{opcode:04x}: {assembled:02x?}")),
+            Err(error) => failures.push(format!("{opcode:04x}: {error}"))
+        }
+    }
+    assert!(failures.is_empty(), "{} failures\n{}", failures.len(), failures.iter().take(20).cloned().collect::<Vec<_>>().join("\n"));
+}
+
+#[test]
+fn test_empty(){
+    assert_eq!(disassemble(&[], &DisassembleOptions{ addresses: false, ..Default::default() }), "");
+    assert!(assemble("", &AssembleOptions::default()).unwrap().bytes().is_empty());
+    assert!(round_trip(&[]).unwrap().is_empty());
+}
+
+proptest!{
+    #[test]
+    fn test_any_bytes(bytes in proptest::collection::vec(any::<u8>(), 0..256)){
+        prop_assert_eq!(round_trip(&bytes).map_err(TestCaseError::fail)?, bytes);
+    }
+
+    #[test]
+    fn test_assemble_disassemble_assemble(instructions in proptest::collection::vec(instruction(), 0..128)){
+        let source: String = instructions.iter().map(|instruction| format!("{instruction};\n")).collect();
+        let rom = assemble(&source, &AssembleOptions::default()).map_err(|errors| TestCaseError::fail(errors.with_path("source")))?;
+        prop_assert_eq!(round_trip(rom.bytes()).map_err(TestCaseError::fail)?, rom.bytes());
+    }
+}
@@ -0,0 +1,68 @@
+/// A square wave tone, used for the buzzer so that no sound file is needed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SquareWave{
+    /// Frequency in Hz
+    pub frequency: f32,
+    /// Samples per second
+    pub sample_rate: u32,
+    /// Amplitude, from 0.0 to 1.0
+    pub volume: f32
+}
+
+impl Default for SquareWave{
+    fn default() -> Self {
+        Self{
+            frequency: Self::DEFAULT_FREQUENCY,
+            sample_rate: Self::DEFAULT_SAMPLE_RATE,
+            volume: 0.25
+        }
+    }
+}
+
+impl SquareWave{
+    pub const DEFAULT_FREQUENCY: f32 = 440.0;
+    pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+    /// The first `count` samples of the wave, as signed 16-bit PCM
+    pub fn samples(&self, count: usize) -> Vec<i16>{
+        let amplitude = (self.volume.clamp(0.0, 1.0) * i16::MAX as f32) as i16;
+        let period = self.sample_rate as f32 / self.frequency;
+        (0..count).map(|i| {
+            if (i as f32 % period) < period / 2.0 {amplitude} else {-amplitude}
+        }).collect()
+    }
+
+    /// `seconds` of the wave as a mono 16-bit WAV file
+    pub fn to_wav(&self, seconds: f32) -> Vec<u8>{
+        let samples = self.samples((self.sample_rate as f32 * seconds) as usize);
+        let data_len = (samples.len() * 2) as u32;
+        let mut wav = Vec::with_capacity(44 + data_len as usize);
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes()); // format chunk length
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+        wav.extend_from_slice(&self.sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(self.sample_rate * 2).to_le_bytes()); // bytes per second
+        wav.extend_from_slice(&2u16.to_le_bytes()); // bytes per sample
+        wav.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        for sample in samples{
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+        wav
+    }
+}
+
+
+#[test]
+fn test_square_wave(){
+    let wave = SquareWave{ frequency: 1000.0, sample_rate: 8000, volume: 1.0 };
+    let samples = wave.samples(8);
+    assert_eq!(samples, vec![i16::MAX, i16::MAX, i16::MAX, i16::MAX, -i16::MAX, -i16::MAX, -i16::MAX, -i16::MAX]);
+    let wav = wave.to_wav(0.5);
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(wav.len(), 44 + 4000 * 2);
+}
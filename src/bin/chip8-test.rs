@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+use chip8::test_suite::{parse_seconds, Outcome, TestSuite};
+
+use clap::Parser;
+
+
+/// Run test ROMs headlessly and compare the screen each one ends on with its golden screenshot
+#[derive(Parser)]
+struct Args{
+    /// Suite file listing the ROMs, one per line, e.g. `3-corax+.ch8 frames=120`
+    suite: PathBuf,
+    /// Write the golden screenshot of every ROM that doesn't have one or doesn't match it
+    #[arg(long)]
+    update: bool,
+    /// Print the screen of every ROM that fails
+    #[arg(short, long)]
+    verbose: bool,
+    /// Fail any ROM still running after this many seconds, unless the suite gives it a `timeout` of its own
+    #[arg(long, value_name = "SECS", value_parser = |s: &str| parse_seconds(s).ok_or("expected a number of seconds"))]
+    timeout: Option<std::time::Duration>
+}
+
+fn main() {
+    let args = Args::parse();
+    let mut suite = TestSuite::load(&args.suite).unwrap_or_else(|err| {
+        eprintln!("{}", err.render(Some(&args.suite.display().to_string())));
+        std::process::exit(1)
+    });
+    if let Some(timeout) = args.timeout{
+        suite.set_default_timeout(timeout);
+    }
+    let mut failed = 0;
+    for test in suite.roms.iter(){
+        let (outcome, screenshot) = test.check(args.update);
+        let hash = screenshot.as_ref().map_or(String::new(), |screenshot| format!(" [{:016x}]", screenshot.hash()));
+        match &outcome {
+            Outcome::Pass => println!("pass    {}{}", test.name(), hash),
+            Outcome::Updated => println!("updated {}{} -> {}", test.name(), hash, test.golden.display()),
+            Outcome::Fail(pixels) => println!("FAIL    {}{}: {} pixels differ from {}", test.name(), hash, pixels, test.golden.display()),
+            Outcome::Missing => println!("FAIL    {}{}: no golden screenshot at {}, run with --update to write it",
+                test.name(), hash, test.golden.display()),
+            Outcome::TimedOut(timeout) => println!("TIMEOUT {}{}: {}", test.name(), hash, timeout),
+            Outcome::Error(message) => println!("FAIL    {}: {}", test.name(), message)
+        }
+        if matches!(outcome, Outcome::Fail(_) | Outcome::Missing | Outcome::TimedOut(_) | Outcome::Error(_)){
+            failed += 1;
+            if let (true, Some(screenshot)) = (args.verbose, &screenshot){
+                print!("{screenshot}");
+            }
+        }
+    }
+    println!("{} passed, {} failed", suite.roms.len() - failed, failed);
+    if failed > 0{
+        std::process::exit(1)
+    }
+}
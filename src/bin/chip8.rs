@@ -1,27 +1,455 @@
-use std::io::Read;
-use chip8::{Chip8Driver, EmulatorMode};
+use std::{io::Read, path::PathBuf};
+use chip8::{dap, debug_script::DebugScript, differential, driver::Backend, extensions::Extension, headless::{RunResult, ScriptedPress}, keypad::KeyProfile, quirks::Quirk, recent::RecentRoms, replay::{rom_hash, Replay}, resources::Resources, rom_check, session::Session, settings::RomSettings, symbols::Symbols, test_suite::TestRom, theme::{PixelEffect, Rgb}, timing::TimingMode, trace_filter::TraceFilter, Chip8, Chip8Driver, EmulatorMode, HaltCondition, HeadlessDriver};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use clio::*;
 
 
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args{
-    rom: ClioPath,
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// The ROM to run; with --resume it can be left out to run the one from the session
+    #[arg(required_unless_present = "resume")]
+    rom: Option<ClioPath>,
     #[arg(short, long)]
     speed: Option<u64>,
+    /// Which window to open, of the frontends built in: `raylib`, with the debug panels, or `sdl2`
+    #[arg(long, value_enum, default_value_t = Backend::default())]
+    backend: Backend,
+    /// How many instructions run each frame: `fixed-ipf` uses --speed,
+    /// `original` approximates the COSMAC VIP
+    #[arg(long, value_enum, default_value_t = TimingMode::FixedIpf)]
+    timing: TimingMode,
+    /// Switch on opcodes beyond the original instruction set, e.g. `--extension vsync`
+    #[arg(long = "extension", value_enum)]
+    extensions: Vec<Extension>,
+    /// Halt the CPU on an opcode that isn't an instruction, or on a jump to itself,
+    /// rather than carrying on; can be given more than once
+    #[arg(long, value_enum)]
+    halt_on: Vec<HaltCondition>,
     #[arg(short, long)]
-    debug: bool
+    debug: bool,
+    /// Seconds of history to keep for stepping backwards while paused
+    #[arg(short, long)]
+    rewind: Option<usize>,
+    /// Debugger script to load breakpoints from; F2 exports back to it
+    #[arg(long)]
+    debug_script: Option<PathBuf>,
+    /// Symbol file from `chip8cc --symbols`, to show label names in the debugger
+    /// and set breakpoints by label
+    #[arg(long)]
+    symbols: Option<PathBuf>,
+    /// Record executed instructions from the start; F4 dumps them to this file
+    #[arg(long)]
+    trace: Option<PathBuf>,
+    /// Only trace matching instructions, e.g. `in:game_loop class:draw, reg:vf`.
+    /// Labels come from --symbols
+    #[arg(long)]
+    trace_filter: Option<String>,
+    /// Pause when the window stops updating, e.g. while it is dragged or resized
+    #[arg(long)]
+    pause_on_stall: bool,
+    /// Where F6 writes the ROM with any edits from the memory view,
+    /// `<ROM>.patched.ch8` by default
+    #[arg(long)]
+    patched_rom: Option<PathBuf>,
+    /// Where F7 saves a screenshot, `screenshot.png` by default. A `.png` also gets a `.pbm` copy,
+    /// and any other extension is saved as text like the golden screenshots of chip8-test
+    #[arg(long)]
+    screenshot: Option<PathBuf>,
+    /// Where F8 saves the recording as an animated GIF when it stops, `recording.gif` by default
+    #[arg(long)]
+    recording: Option<PathBuf>,
+    /// Start recording straight away
+    #[arg(long)]
+    record: bool,
+    /// Write how long every frame took to this CSV file on exit, and print a summary
+    #[arg(long)]
+    timing_log: Option<PathBuf>,
+    /// Track which bytes of RAM are executed, read and written, writing the map to this file on exit
+    /// and colouring the memory view by it
+    #[arg(long)]
+    coverage: Option<PathBuf>,
+    /// Record the keys pressed to this `.c8replay` file, to play the run back exactly with --replay.
+    /// With --resume it starts from the session, which is saved beside it as a `.session` file
+    #[arg(long, conflicts_with = "replay")]
+    record_replay: Option<PathBuf>,
+    /// Play back a run recorded with --record-replay, in place of the keyboard. A replay recorded from a session
+    /// needs --resume with the session saved beside it, which is left as it was
+    #[arg(long)]
+    replay: Option<PathBuf>,
+    /// Log sprite collisions and flash the pixels involved; F5 toggles this
+    #[arg(long)]
+    show_collisions: bool,
+    /// On exit, save the ROM, machine state, debug view and breakpoints to --session-file
+    #[arg(long)]
+    save_session: bool,
+    /// Carry on from the session in --session-file, and save it again on exit
+    #[arg(long)]
+    resume: bool,
+    /// Where --save-session and --resume keep the session
+    #[arg(long, default_value = Session::DEFAULT_PATH)]
+    session_file: PathBuf,
+    /// Colour of lit pixels, as #rrggbb
+    #[arg(long)]
+    foreground: Option<Rgb>,
+    /// Colour of unlit pixels, as #rrggbb
+    #[arg(long)]
+    background: Option<Rgb>,
+    /// Draw scanlines or an LCD grid over the display
+    #[arg(long, value_enum)]
+    pixel_effect: Option<PixelEffect>,
+    /// How much brightness switched off pixels keep each frame, from 0 to 0.95,
+    /// to reduce flicker
+    #[arg(long)]
+    ghosting: Option<f32>,
+    /// Ghosting for frames that clear the screen, from 0 to 0.95,
+    /// for games that clear and redraw everything every frame
+    #[arg(long)]
+    clear_fade: Option<f32>,
+    /// While paused, the memory view's diff (`h`) highlights bytes changed in this many steps
+    #[arg(long, default_value_t = 1)]
+    diff_steps: usize,
+    /// How many instructions `[enter]` runs while paused
+    #[arg(long, default_value_t = 1)]
+    step_count: usize,
+    /// Average this many of the latest frames together, from 1 to 8, to reduce flicker
+    #[arg(long)]
+    blend_frames: Option<usize>,
+    /// Load the ROM even if it doesn't look like a CHIP-8 program
+    #[arg(long)]
+    force: bool,
+    /// Which keyboard keys play the keypad; `two-player` moves C D E F to the arrow keys.
+    /// Remembered for the ROM
+    #[arg(long, value_enum)]
+    keymap: Option<KeyProfile>,
+    /// Start with the on-screen keypad shown, for playing by mouse or touch; `k` toggles it
+    #[arg(long)]
+    virtual_keypad: bool,
+    /// Directory of files to use instead of the built-in ones: `font.ttf` and `buzzer.wav`
+    #[arg(long, value_name = "DIR")]
+    resources: Option<PathBuf>,
+    /// Don't update the ROM's play time and launch count this run
+    #[arg(long)]
+    no_stats: bool,
+    /// Most nested subroutine calls before the CPU halts with a stack overflow
+    #[arg(long, default_value_t = Chip8::DEFAULT_STACK_DEPTH)]
+    stack_depth: usize,
+    /// Switch on a behaviour of the original interpreter that some ROMs expect:
+    /// `display-wait`, `wait-for-release` or `protect-low-memory`; can be given more than once
+    #[arg(long = "quirk", value_enum)]
+    quirks: Vec<Quirk>,
+    /// Seed for the random numbers of RND, so that a game plays out the same each run
+    #[arg(long, conflicts_with = "replay")]
+    seed: Option<u64>
+}
+
+#[derive(Subcommand)]
+enum Command{
+    /// Run a ROM without a window and save a picture of the display it ends on,
+    /// e.g. for thumbnails of a ROM library or images for documentation
+    Snapshot(SnapshotArgs),
+    /// Serve the Debug Adapter Protocol on stdin and stdout, for debugging ROMs in VS Code and other editors
+    Dap{
+        /// Wait for the editor to connect to this port instead
+        #[arg(long)]
+        port: Option<u16>
+    },
+    /// Check the decoder and interpreter against a small reference interpreter, on every opcode
+    /// and on random instructions from random states, and print where they disagree
+    Difftest{
+        /// How many random instructions to execute
+        #[arg(long, default_value_t = 20_000)]
+        count: usize,
+        /// Seed for the random states, to repeat a run; a random one is picked and printed otherwise
+        #[arg(long)]
+        seed: Option<u64>
+    }
+}
+
+#[derive(clap::Args)]
+struct SnapshotArgs{
+    rom: PathBuf,
+    /// How many frames to run, at 60 a second
+    #[arg(long, default_value_t = 600)]
+    frames: u64,
+    /// Where to save the picture: a `.png` in the colours of the ROM's settings, a `.pbm`,
+    /// or any other extension as text like the golden screenshots of chip8-test
+    #[arg(long, default_value = "snapshot.png")]
+    out: PathBuf,
+    /// Hold a key down during the run, e.g. `5@60+10` holds key 5 for 10 frames from frame 60;
+    /// can be given more than once
+    #[arg(long = "press", value_name = "KEY@FRAME[+FRAMES]")]
+    presses: Vec<ScriptedPress>,
+    #[arg(short, long)]
+    speed: Option<u64>,
+    /// Seed for the random numbers of RND, so that the picture comes out the same each time
+    #[arg(long, default_value_t = TestRom::DEFAULT_SEED)]
+    seed: u64,
+    #[arg(long = "extension", value_enum)]
+    extensions: Vec<Extension>,
+    /// Switch on a quirk, on top of any in the ROM's settings; can be given more than once
+    #[arg(long = "quirk", value_enum)]
+    quirks: Vec<Quirk>
+}
+
+/// Run `args.rom` headlessly and save the display it ends on
+fn snapshot(args: SnapshotArgs){
+    let rom = std::fs::read(&args.rom).unwrap_or_else(|err| {
+        eprintln!("Failed to read {}: {}", args.rom.display(), err);
+        std::process::exit(1)
+    });
+    let settings = RomSettings::load(&args.rom);
+    let mut driver = HeadlessDriver::new(args.speed);
+    driver.seed_rng(args.seed);
+    for extension in args.extensions{
+        driver.set_extension(extension, true);
+    }
+    for (quirk, enabled) in settings.quirks.iter(){
+        driver.set_quirk(*quirk, *enabled);
+    }
+    for quirk in args.quirks{
+        driver.set_quirk(quirk, true);
+    }
+    if let Err(err) = driver.load_rom(&rom){
+        eprintln!("{}", err.render(Some(&args.rom.display().to_string())));
+        std::process::exit(1)
+    }
+    if let RunResult::Halted(fault) = driver.run_frames_with_presses(args.frames, &args.presses){
+        eprintln!("warning: the CPU halted at {:#05x}: {}", driver.pc(), fault);
+    }
+    if let Err(err) = driver.screenshot().save(&args.out, &settings.style){
+        eprintln!("Could not save {}: {}", args.out.display(), err);
+        std::process::exit(1)
+    }
+    println!("Saved {}", args.out.display());
+}
+
+/// Run the differential tests and exit with an error if anything disagrees
+fn difftest(count: usize, seed: Option<u64>){
+    /// Most mismatches printed in full
+    const SHOWN: usize = 20;
+    let seed = seed.unwrap_or_else(rand::random);
+    let mismatches: Vec<_> = differential::check_decode().into_iter()
+        .chain(differential::check_execute(seed, count))
+        .collect();
+    for mismatch in mismatches.iter().take(SHOWN){
+        println!("{mismatch}");
+    }
+    if mismatches.len() > SHOWN{
+        println!("... and {} more", mismatches.len() - SHOWN);
+    }
+    println!("Decoded every opcode and executed {count} random instructions (--seed {seed}): {} mismatches", mismatches.len());
+    if !mismatches.is_empty(){
+        std::process::exit(1)
+    }
 }
 
 fn main() {
     let args = Args::parse();
-    let rom_name = args.rom.as_os_str().to_string_lossy().into_owned();
-    let mut input = args.rom.open().expect(&format!("No file named {}", rom_name));
+    match args.command {
+        Some(Command::Snapshot(snapshot_args)) => return snapshot(snapshot_args),
+        Some(Command::Difftest{ count, seed }) => return difftest(count, seed),
+        Some(Command::Dap{ port }) => {
+            let served = match port {
+                Some(port) => dap::serve_tcp(port),
+                None => dap::serve_stdio()
+            };
+            if let Err(err) = served{
+                eprintln!("Debug adapter stopped: {err}");
+                std::process::exit(1)
+            }
+            return;
+        },
+        None => {}
+    }
+    let session = args.resume.then(|| Session::load(&args.session_file).unwrap_or_else(|err| {
+        eprintln!("{}", err.render(Some(&args.session_file.display().to_string())));
+        std::process::exit(1)
+    }));
+    let rom = match (args.rom, &session) {
+        (Some(rom), _) => rom,
+        (None, Some(session)) => ClioPath::new(&session.rom).unwrap_or_else(|err| {
+            eprintln!("No file named {}: {}", session.rom.display(), err);
+            std::process::exit(1)
+        }),
+        (None, None) => unreachable!("clap requires a ROM without --resume")
+    };
+    let rom_name = rom.as_os_str().to_string_lossy().into_owned();
+    let rom_path = rom.is_local().then(|| rom.path().to_path_buf());
+    let mut input = rom.open().unwrap_or_else(|err| {
+        eprintln!("No file named {}: {}", rom_name, err);
+        std::process::exit(1)
+    });
     let mut instructions = Vec::new();
     input.read_to_end(&mut instructions).expect(&format!("Failed to read {}", rom_name ));
-    let mut driver = Chip8Driver::new(if args.debug {EmulatorMode::Paused} else {EmulatorMode::Running},
-        args.speed);
-    driver.load_rom(&instructions);
+    let problems = rom_check::check_rom(&instructions);
+    for problem in problems.iter(){
+        eprintln!("{}", problem.render(Some(&rom_name)));
+    }
+    if rom_check::is_fatal(&problems){
+        std::process::exit(1)
+    }
+    if !problems.is_empty() && !args.force{
+        eprintln!("Pass --force to load it anyway");
+        std::process::exit(1)
+    }
+    let resources = args.resources.map(Resources::with_override_dir).unwrap_or_default();
+    let mut driver = Chip8Driver::with_backend(args.backend, if args.debug {EmulatorMode::Paused} else {EmulatorMode::Running},
+        args.speed, &resources);
+    if let Some(rom_path) = &rom_path{
+        driver.set_settings(RomSettings::load(rom_path), RomSettings::path_for(rom_path));
+        if let Some(path) = RecentRoms::default_path(){
+            let mut recent = RecentRoms::load(&path);
+            recent.push(std::fs::canonicalize(rom_path).unwrap_or(rom_path.clone()));
+            if let Err(err) = recent.save(&path){
+                eprintln!("Could not save recent ROMs to {}: {}", path.display(), err);
+            }
+            driver.set_recent_roms(recent, path);
+        }
+    }
+    if let Some(seconds) = args.rewind{
+        driver.set_rewind_depth(seconds * 60);
+    }
+    if let Some(path) = args.symbols{
+        let text = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+            eprintln!("Failed to read {}: {}", path.display(), err);
+            std::process::exit(1)
+        });
+        match Symbols::parse(&text){
+            Ok(symbols) => {
+                if !symbols.matches(&instructions){
+                    eprintln!("{} was built for a different ROM, the labels may be wrong", path.display());
+                }
+                driver.set_symbols(symbols)
+            },
+            Err(err) => eprintln!("{}", err.render(Some(&path.display().to_string())))
+        }
+    }
+    if let Some(path) = args.debug_script{
+        if path.exists(){
+            let text = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+                eprintln!("Failed to read {}: {}", path.display(), err);
+                std::process::exit(1)
+            });
+            match DebugScript::parse_with_symbols(&text, driver.symbols()){
+                Ok(script) => driver.load_debug_script(&script),
+                Err(err) => eprintln!("{}", err.render(Some(&path.display().to_string())))
+            }
+        }
+        driver.set_debug_script_path(path);
+    }
+    if args.foreground.is_some() || args.background.is_some() || args.pixel_effect.is_some() || args.ghosting.is_some()
+        || args.clear_fade.is_some() || args.blend_frames.is_some(){
+        let mut style = driver.display_style();
+        style.foreground = args.foreground.unwrap_or(style.foreground);
+        style.background = args.background.unwrap_or(style.background);
+        style.effect = args.pixel_effect.unwrap_or(style.effect);
+        style.ghosting = args.ghosting.unwrap_or(style.ghosting);
+        style.clear_fade = args.clear_fade.unwrap_or(style.clear_fade);
+        style.blend_frames = args.blend_frames.unwrap_or(style.blend_frames);
+        driver.set_display_style(style);
+    }
+    if let Some(profile) = args.keymap{
+        driver.set_key_profile(profile);
+    }
+    if args.virtual_keypad{
+        driver.toggle_virtual_keypad();
+    }
+    driver.set_diff_steps(args.diff_steps);
+    driver.set_step_count(args.step_count);
+    driver.set_timing(args.timing);
+    for condition in args.halt_on{
+        driver.set_halt_on(condition, true);
+    }
+    for extension in args.extensions{
+        driver.set_extension(extension, true);
+    }
+    driver.set_pause_on_stall(args.pause_on_stall);
+    driver.set_show_collisions(args.show_collisions);
+    driver.set_track_stats(!args.no_stats);
+    driver.set_stack_depth(args.stack_depth);
+    // the ROM's settings may have quirks from its source's %quirk pragmas
+    let rom_quirks = rom_path.as_ref().map(|path| RomSettings::load(path).quirks).unwrap_or_default();
+    for quirk in args.quirks{
+        if rom_quirks.get(&quirk) == Some(&false){
+            eprintln!("warning: --quirk {} overrides the ROM's settings, which turn it off", quirk);
+        }
+        driver.set_quirk(quirk, true);
+    }
+    match (args.patched_rom, &rom_path) {
+        (Some(path), _) => driver.set_patched_rom_path(path),
+        (None, Some(rom_path)) => driver.set_patched_rom_path(rom_path.with_extension("patched.ch8")),
+        (None, None) => {}
+    }
+    if let Some(path) = args.screenshot{
+        driver.set_screenshot_path(path);
+    }
+    if let Some(path) = args.recording{
+        driver.set_recording_path(path);
+    }
+    if let Some(path) = args.timing_log{
+        driver.set_timing_log_path(path);
+    }
+    if let Some(path) = args.coverage{
+        driver.set_coverage_path(path);
+    }
+    if let Some(text) = args.trace_filter{
+        match TraceFilter::parse(&text, driver.symbols()){
+            Ok(filter) => driver.set_trace_filter(filter),
+            Err(err) => {
+                eprintln!("{}", err.render(Some("--trace-filter")));
+                std::process::exit(1)
+            }
+        }
+    }
+    if let Some(path) = args.trace{
+        driver.enable_trace(path);
+    }
+    if let Err(err) = driver.load_rom(&instructions){
+        eprintln!("{}", err.render(Some(&rom_name)));
+        std::process::exit(1)
+    }
+    if let Some(seed) = args.seed{
+        driver.seed_rng(seed);
+    }
+    if let Some(session) = &session{
+        driver.resume(session);
+    }
+    if let Some(path) = args.record_replay{
+        if let Some(session) = &session{
+            let start = path.with_extension("session");
+            if let Err(err) = session.save(&start){
+                eprintln!("Could not save the session the replay starts from to {}: {}", start.display(), err);
+            }
+        }
+        driver.record_replay(path);
+    }
+    let playing = args.replay.is_some();
+    if let Some(path) = args.replay{
+        let replay = Replay::load(&path).unwrap_or_else(|err| {
+            eprintln!("{}", err.render(Some(&path.display().to_string())));
+            std::process::exit(1)
+        });
+        if replay.rom != rom_hash(&instructions){
+            eprintln!("warning: {} was recorded on a different ROM, so it won't play back the same", path.display());
+        }
+        if let Err(err) = driver.play_replay(replay){
+            eprintln!("{}", err.render(Some(&path.display().to_string())));
+            std::process::exit(1)
+        }
+    }
+    if args.record{
+        driver.start_recording();
+    }
+    // a replay's starting session is kept as it was, to play it again
+    if (args.save_session || args.resume) && !playing{
+        match rom_path {
+            Some(rom_path) => driver.enable_session(std::fs::canonicalize(&rom_path).unwrap_or(rom_path), args.session_file),
+            None => eprintln!("Can't save a session for a ROM that isn't a file")
+        }
+    }
     driver.run()
 }
@@ -0,0 +1,335 @@
+use std::fmt::Display;
+
+use crate::{emulator, Addr, Chip8, Reg};
+
+/// An extra condition that must hold for a breakpoint to trigger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition{
+    /// Vx == imm
+    RegisterEq(Reg, u8),
+    /// start <= I < end
+    IndexInRange(usize, usize),
+    /// The next instruction writes to this address
+    MemoryWrite(Addr)
+}
+
+/// What happens when a breakpoint triggers
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Action{
+    /// Stop before the instruction
+    #[default]
+    Pause,
+    /// Log the message with the registers, and carry on
+    Log(String),
+    /// Carry on, only counting the hit
+    Count
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint{
+    /// Only trigger when the program counter is here; `None` checks every instruction
+    pub addr: Option<Addr>,
+    pub condition: Option<Condition>,
+    pub enabled: bool,
+    pub action: Action,
+    /// Number of times this breakpoint has triggered
+    pub hits: usize
+}
+
+/// The set of breakpoints shared by the driver and frontends.
+/// Breakpoints are checked against the instruction that is about to execute
+#[derive(Debug, Default)]
+pub struct Breakpoints{
+    list: Vec<Breakpoint>,
+    /// Lines logged by `Action::Log` breakpoints since the last `take_log`
+    log: Vec<String>
+}
+
+/// Parse a decimal or 0x-prefixed hex number
+pub(crate) fn parse_number(text: &str) -> Option<usize>{
+    if let Some(hex) = text.strip_prefix("0x"){
+        usize::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+fn parse_addr(text: &str) -> Result<Addr, String>{
+    match parse_number(text){
+        Some(addr) => Addr::try_from(addr).map_err(|_| format!("address {addr:#x} is out of range")),
+        None => Err(format!("invalid address {text}"))
+    }
+}
+
+impl Condition{
+    pub fn holds(&self, chip8: &Chip8) -> bool{
+        match *self {
+            Condition::RegisterEq(reg, imm) => chip8.registers.vn[reg.index()] == imm,
+            Condition::IndexInRange(start, end) => (start..end).contains(&chip8.registers.i),
+            Condition::MemoryWrite(addr) => {
+                emulator::written_range(&chip8.memory, &chip8.registers)
+                    .is_some_and(|range| range.contains(&addr.index()))
+            }
+        }
+    }
+
+    /// Parse a condition from its words, e.g. `["v3", "==", "0x1f"]`
+    pub fn parse(words: &[&str]) -> Result<Self, String>{
+        match words {
+            ["i", "in", start, end] => Ok(Condition::IndexInRange(parse_addr(start)?.index(), parse_number(end).ok_or(format!("invalid address {end}"))?)),
+            ["write", addr] => Ok(Condition::MemoryWrite(parse_addr(addr)?)),
+            [reg, "==", imm] => {
+                let reg = reg.strip_prefix('v')
+                    .and_then(|r| u8::from_str_radix(r, 16).ok())
+                    .and_then(Reg::new)
+                    .ok_or(format!("invalid register {reg}"))?;
+                let imm = parse_number(imm)
+                    .and_then(|imm| u8::try_from(imm).ok())
+                    .ok_or(format!("invalid value {imm}"))?;
+                Ok(Condition::RegisterEq(reg, imm))
+            },
+            _ => Err(format!("unknown condition '{}'", words.join(" ")))
+        }
+    }
+}
+
+impl Display for Condition{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::RegisterEq(reg, imm) => write!(f, "v{reg:x} == {imm:#x}"),
+            Condition::IndexInRange(start, end) => write!(f, "i in {start:#x} {end:#x}"),
+            Condition::MemoryWrite(addr) => write!(f, "write {addr:#x}")
+        }
+    }
+}
+
+impl Action{
+    /// Parse an action from the words after `then`, e.g. `["log", "lives", "lost"]`
+    pub fn parse(words: &[&str]) -> Result<Self, String>{
+        match words {
+            ["pause"] => Ok(Action::Pause),
+            ["count"] => Ok(Action::Count),
+            ["log", message @ ..] => Ok(Action::Log(message.join(" "))),
+            _ => Err(format!("unknown action '{}', expected pause, log or count", words.join(" ")))
+        }
+    }
+}
+
+impl Display for Action{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Pause => write!(f, "pause"),
+            Action::Log(message) if message.is_empty() => write!(f, "log"),
+            Action::Log(message) => write!(f, "log {message}"),
+            Action::Count => write!(f, "count")
+        }
+    }
+}
+
+impl Breakpoint{
+    /// An unconditional breakpoint at `addr`
+    pub fn at(addr: Addr) -> Self{
+        Self{
+            addr: Some(addr),
+            condition: None,
+            enabled: true,
+            action: Action::Pause,
+            hits: 0
+        }
+    }
+
+    /// A breakpoint that triggers wherever `condition` holds
+    pub fn when(condition: Condition) -> Self{
+        Self{
+            addr: None,
+            condition: Some(condition),
+            enabled: true,
+            action: Action::Pause,
+            hits: 0
+        }
+    }
+
+    pub fn triggers(&self, chip8: &Chip8) -> bool{
+        self.enabled
+            && self.addr.is_none_or(|addr| addr.index() == chip8.pc())
+            && self.condition.is_none_or(|condition| condition.holds(chip8))
+    }
+
+    /// Parse a breakpoint from its words, in one of the forms
+    /// `[disabled] <addr> [if <condition>] [then <action>]` or `[disabled] if <condition> [then <action>]`
+    pub fn parse(mut words: &[&str]) -> Result<Self, String>{
+        let mut enabled = true;
+        if let ["disabled", rest @ ..] = words{
+            enabled = false;
+            words = rest;
+        }
+        let mut action = Action::Pause;
+        if let Some(then) = words.iter().position(|word| *word == "then"){
+            action = Action::parse(&words[then + 1..])?;
+            words = &words[..then];
+        }
+        let mut breakpoint = match words {
+            [addr] => Self::at(parse_addr(addr)?),
+            [addr, "if", condition @ ..] if *addr != "if" => Self{
+                condition: Some(Condition::parse(condition)?),
+                ..Self::at(parse_addr(addr)?)
+            },
+            ["if", condition @ ..] => Self::when(Condition::parse(condition)?),
+            _ => return Err(format!("invalid breakpoint '{}'", words.join(" ")))
+        };
+        breakpoint.enabled = enabled;
+        breakpoint.action = action;
+        Ok(breakpoint)
+    }
+}
+
+impl Display for Breakpoint{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut words = vec![];
+        if !self.enabled{
+            words.push("disabled".to_string());
+        }
+        if let Some(addr) = self.addr{
+            words.push(format!("{addr:#x}"));
+        }
+        if let Some(condition) = self.condition{
+            words.push(format!("if {condition}"));
+        }
+        if self.action != Action::Pause{
+            words.push(format!("then {}", self.action));
+        }
+        write!(f, "{}", words.join(" "))
+    }
+}
+
+impl Breakpoints{
+
+    pub fn add(&mut self, breakpoint: Breakpoint){
+        self.list.push(breakpoint)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Breakpoint{
+        self.list.remove(index)
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool){
+        if let Some(breakpoint) = self.list.get_mut(index){
+            breakpoint.enabled = enabled;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&Breakpoint>{
+        self.list.iter()
+    }
+
+    /// Add an unconditional breakpoint at `addr`, or remove every breakpoint at `addr` if there are any
+    pub fn toggle(&mut self, addr: Addr){
+        let before = self.list.len();
+        self.list.retain(|breakpoint| breakpoint.addr != Some(addr));
+        if self.list.len() == before{
+            self.add(Breakpoint::at(addr));
+        }
+    }
+
+    /// The breakpoints attached to the instruction at `addr`
+    pub fn at(&self, addr: Addr) -> impl Iterator<Item=&Breakpoint>{
+        self.list.iter().filter(move |breakpoint| breakpoint.addr == Some(addr))
+    }
+
+    /// Check whether execution should pause before the next instruction,
+    /// counting a hit on every breakpoint that triggers and logging for those that log
+    pub fn check(&mut self, chip8: &Chip8) -> bool{
+        let mut pause = false;
+        for breakpoint in self.list.iter_mut(){
+            if breakpoint.triggers(chip8){
+                breakpoint.hits += 1;
+                match &breakpoint.action {
+                    Action::Pause => pause = true,
+                    Action::Log(message) if message.is_empty() => self.log.push(format!("0x{:03x}: {}", chip8.pc(), registers(chip8))),
+                    Action::Log(message) => self.log.push(format!("0x{:03x}: {} {}", chip8.pc(), message, registers(chip8))),
+                    Action::Count => {}
+                }
+            }
+        }
+        pause
+    }
+
+    /// The lines logged since last time, oldest first
+    pub fn take_log(&mut self) -> Vec<String>{
+        std::mem::take(&mut self.log)
+    }
+}
+
+/// The registers as a log line shows them, e.g. `v0=1f v1=00 ... vf=01 i=0x300`
+fn registers(chip8: &Chip8) -> String{
+    let mut words: Vec<_> = chip8.registers.vn.iter().enumerate().map(|(reg, value)| format!("v{reg:x}={value:02x}")).collect();
+    words.push(format!("i={:#05x}", chip8.registers.i));
+    words.join(" ")
+}
+
+
+#[test]
+fn test_breakpoint_conditions(){
+    // ld v3 0x1f; ld i 0x300; ld b v3
+    let mut chip8 = Chip8::init(None);
+    chip8.load_rom(&[0x63, 0x1f, 0xa3, 0x00, 0xf3, 0x33]).unwrap();
+    let mut breakpoints = Breakpoints::default();
+    breakpoints.add(Breakpoint::when(Condition::RegisterEq(Reg(3), 0x1f)));
+    breakpoints.add(Breakpoint::when(Condition::MemoryWrite(Addr(0x302))));
+    assert!(!breakpoints.check(&chip8));
+    chip8.do_instruction();
+    assert!(breakpoints.check(&chip8));
+    chip8.do_instruction();
+    assert!(breakpoints.check(&chip8));
+    let hits: Vec<_> = breakpoints.iter().map(|b| b.hits).collect();
+    assert_eq!(hits, vec![2, 1]);
+    breakpoints.set_enabled(0, false);
+    breakpoints.set_enabled(1, false);
+    assert!(!breakpoints.check(&chip8));
+}
+
+#[test]
+fn test_breakpoint_toggle(){
+    let mut breakpoints = Breakpoints::default();
+    breakpoints.toggle(Addr(0x204));
+    assert_eq!(breakpoints.at(Addr(0x204)).count(), 1);
+    breakpoints.toggle(Addr(0x204));
+    assert_eq!(breakpoints.at(Addr(0x204)).count(), 0);
+}
+
+#[test]
+fn test_breakpoint_parse(){
+    for text in ["0x204", "0x204 if v3 == 0x1f", "if i in 0x300 0x310", "disabled if write 0x3ff"]{
+        let words: Vec<_> = text.split_whitespace().collect();
+        assert_eq!(Breakpoint::parse(&words).unwrap().to_string(), text);
+    }
+    assert!(Breakpoint::parse(&["if", "vg", "==", "1"]).is_err());
+    assert!(Breakpoint::parse(&["0x1000"]).is_err());
+}
+
+#[test]
+fn test_breakpoint_actions(){
+    for text in ["0x204 then count", "0x204 if v3 == 0x1f then log lives lost", "disabled if write 0x3ff then log"]{
+        let words: Vec<_> = text.split_whitespace().collect();
+        assert_eq!(Breakpoint::parse(&words).unwrap().to_string(), text);
+    }
+    assert_eq!(Breakpoint::parse(&["0x204", "then", "pause"]).unwrap().to_string(), "0x204");
+    assert!(Breakpoint::parse(&["0x204", "then", "stop"]).is_err());
+
+    // ld v3 0x1f; jp 0x200
+    let mut chip8 = Chip8::init(None);
+    chip8.load_rom(&[0x63, 0x1f, 0x12, 0x00]).unwrap();
+    let mut breakpoints = Breakpoints::default();
+    breakpoints.add(Breakpoint::parse(&["0x200", "then", "count"]).unwrap());
+    breakpoints.add(Breakpoint::parse(&["0x202", "then", "log", "looping"]).unwrap());
+    for _ in 0..6{
+        assert!(!breakpoints.check(&chip8), "neither action pauses");
+        chip8.do_instruction();
+    }
+    assert_eq!(breakpoints.iter().map(|b| b.hits).collect::<Vec<_>>(), vec![3, 3]);
+    let log = breakpoints.take_log();
+    assert_eq!(log.len(), 3);
+    assert!(log[0].starts_with("0x202: looping v0=00 v1=00 v2=00 v3=1f"), "{}", log[0]);
+    assert!(log[0].ends_with("vf=00 i=0x000"), "{}", log[0]);
+    assert!(breakpoints.take_log().is_empty());
+}
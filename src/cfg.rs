@@ -0,0 +1,172 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{rom_inspect::Variant, Addr, Instruction, INSTRUCTION_SIZE};
+
+/// A way the code can go on from an instruction, to an offset in the ROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Edge{
+    /// The instruction after, including when a skip isn't taken
+    Next(usize),
+    /// The instruction a taken skip lands on
+    Skip(usize),
+    /// A jump's target, or the start of the table `JP V0` jumps into
+    Jump(usize),
+    /// A subroutine's entry. It comes back to the instruction after the call, which is a `Next` edge
+    Call(usize)
+}
+
+impl Edge{
+    pub fn target(&self) -> usize{
+        match *self {
+            Edge::Next(offset) | Edge::Skip(offset) | Edge::Jump(offset) | Edge::Call(offset) => offset
+        }
+    }
+}
+
+/// Where the code can go on to from `opcode` at `offset` in a ROM loaded at `origin`, through jumps, calls and skips.
+/// Targets before the ROM are left out. Instructions from later variants are stepped over;
+/// any other word that isn't an instruction ends the code
+pub fn edges(opcode: u16, offset: usize, origin: Addr) -> Vec<Edge>{
+    let target = |addr: Addr| addr.index().checked_sub(origin.index());
+    let next = offset + INSTRUCTION_SIZE;
+    match Instruction::from(opcode) {
+        // long `LD I` takes the next two bytes as its address
+        _ if opcode == 0xf000 => vec![Edge::Next(offset + size(opcode))],
+        Instruction::Ret => vec![],
+        // SUPER-CHIP's exit
+        _ if opcode == 0x00fd => vec![],
+        Instruction::Jump(addr) | Instruction::JumpOffset(addr) => target(addr).map(Edge::Jump).into_iter().collect(),
+        Instruction::Call(addr) => target(addr).map(Edge::Call).into_iter().chain([Edge::Next(next)]).collect(),
+        Instruction::SkipEqImm(..) | Instruction::SkipNeImm(..) | Instruction::SkipEqReg(..) | Instruction::SkipNeReg(..)
+            | Instruction::SkipKeyPressed(_) | Instruction::SkipKeyNotPressed(_) => vec![Edge::Next(next), Edge::Skip(next + INSTRUCTION_SIZE)],
+        // anything else that isn't an instruction is most likely data, past the end of the code
+        Instruction::Nop if Variant::of(opcode).is_none() => vec![],
+        _ => vec![Edge::Next(next)]
+    }
+}
+
+/// A run of instructions that is only entered at its first and only left after its last
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock{
+    /// Offset of the first instruction
+    pub start: usize,
+    /// Offset just past the last instruction
+    pub end: usize,
+    /// Where the last instruction goes on to, each the start of another block
+    pub edges: Vec<Edge>
+}
+
+/// The code reachable from a ROM's entry points, as basic blocks and the edges between them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cfg{
+    /// Offset of every instruction found, with its edges to other instructions
+    instructions: BTreeMap<usize, Vec<Edge>>,
+    /// By their start
+    blocks: BTreeMap<usize, BasicBlock>
+}
+
+impl Cfg{
+    /// Follow the code of `rom`, loaded at `origin`, from the offsets in `entries`.
+    /// The code ends wherever `is_code` says the word at an offset isn't an instruction,
+    /// and at a word that overlaps an instruction already found.
+    ///
+    /// A block starts at an entry point, at the target of a jump, call or skip,
+    /// and after an instruction that goes anywhere but on to the next
+    pub fn new(rom: &[u8], origin: Addr, entries: impl IntoIterator<Item = usize>, is_code: impl Fn(usize) -> bool) -> Self{
+        let mut instructions = BTreeMap::new();
+        let mut leaders = BTreeSet::new();
+        let mut pending: Vec<usize> = entries.into_iter().collect();
+        leaders.extend(pending.iter().copied());
+        while let Some(offset) = pending.pop(){
+            let overlaps = offset.checked_sub(1).is_some_and(|before| instructions.contains_key(&before))
+                || instructions.contains_key(&(offset + 1));
+            if offset + 1 >= rom.len() || instructions.contains_key(&offset) || overlaps || !is_code(offset){
+                continue;
+            }
+            let edges = edges(opcode(rom, offset), offset, origin);
+            pending.extend(edges.iter().map(Edge::target));
+            if !matches!(edges[..], [Edge::Next(_)]){
+                leaders.extend(edges.iter().map(Edge::target));
+            }
+            instructions.insert(offset, edges);
+        }
+        // edges into data, or past the end, aren't edges between instructions
+        let found: BTreeSet<usize> = instructions.keys().copied().collect();
+        for edges in instructions.values_mut(){
+            edges.retain(|edge| found.contains(&edge.target()));
+        }
+        leaders.retain(|leader| found.contains(leader));
+        let mut blocks = BTreeMap::new();
+        for &start in leaders.iter(){
+            let mut last = start;
+            while let [Edge::Next(next)] = instructions[&last][..] {
+                if leaders.contains(&next){
+                    break;
+                }
+                last = next;
+            }
+            blocks.insert(start, BasicBlock{ start, end: last + size(opcode(rom, last)), edges: instructions[&last].clone() });
+        }
+        Self{ instructions, blocks }
+    }
+
+    /// The blocks in the order they appear in the ROM
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock>{
+        self.blocks.values()
+    }
+
+    /// The block starting at `start`
+    pub fn block(&self, start: usize) -> Option<&BasicBlock>{
+        self.blocks.get(&start)
+    }
+
+    /// Offsets of every instruction found, in order
+    pub fn instructions(&self) -> impl Iterator<Item = usize> + '_{
+        self.instructions.keys().copied()
+    }
+
+    /// Whether an instruction starts at `offset`
+    pub fn contains(&self, offset: usize) -> bool{
+        self.instructions.contains_key(&offset)
+    }
+}
+
+fn opcode(rom: &[u8], offset: usize) -> u16{
+    u16::from_be_bytes([rom[offset], rom[offset + 1]])
+}
+
+/// Bytes the instruction takes up, counting long `LD I`'s address
+fn size(opcode: u16) -> usize{
+    if opcode == 0xf000 {2 * INSTRUCTION_SIZE} else {INSTRUCTION_SIZE}
+}
+
+
+#[test]
+fn test_cfg(){
+    // 0x200: ld v0 1; se v0 1; call 0x20c; jp 0x200
+    // 0x208: data; ret
+    // 0x20c: add v0 1; ret
+    let rom = [0x60, 0x01, 0x30, 0x01, 0x22, 0x0c, 0x12, 0x00, 0x00, 0x00, 0x00, 0xee, 0x70, 0x01, 0x00, 0xee];
+    let cfg = Cfg::new(&rom, Addr::ROM_START, [0], |_| true);
+    assert_eq!(cfg.instructions().collect::<Vec<_>>(), [0, 2, 4, 6, 12, 14]);
+    assert!(!cfg.contains(8) && !cfg.contains(10));
+    let blocks: Vec<_> = cfg.blocks().map(|block| (block.start, block.end, block.edges.clone())).collect();
+    assert_eq!(blocks, [
+        (0, 4, vec![Edge::Next(4), Edge::Skip(6)]),
+        (4, 6, vec![Edge::Call(12), Edge::Next(6)]),
+        (6, 8, vec![Edge::Jump(0)]),
+        (12, 16, vec![])
+    ]);
+
+    // long `ld i 0x206` takes up two words; jp v0 0x206; cls
+    let cfg = Cfg::new(&[0xf0, 0x00, 0x02, 0x06, 0xb2, 0x06, 0x00, 0xe0], Addr::ROM_START, [0], |_| true);
+    let blocks: Vec<_> = cfg.blocks().map(|block| (block.start, block.end, block.edges.clone())).collect();
+    assert_eq!(blocks, [(0, 6, vec![Edge::Jump(6)]), (6, 8, vec![])]);
+    // the code stops where the caller says it does, and at a word that would overlap an instruction
+    let cfg = Cfg::new(&[0x60, 0x01, 0x00, 0xe0, 0x12, 0x00], Addr::ROM_START, [0], |offset| offset != 2);
+    assert_eq!(cfg.instructions().collect::<Vec<_>>(), [0]);
+    let cfg = Cfg::new(&[0x60, 0x01, 0x12, 0x03, 0x00, 0xe0], Addr::ROM_START, [0], |_| true);
+    assert_eq!(cfg.instructions().collect::<Vec<_>>(), [0, 2]);
+    // jumps before the ROM go nowhere
+    assert!(edges(0x1203, 2, Addr::new(0x300).unwrap()).is_empty());
+}
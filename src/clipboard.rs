@@ -0,0 +1,142 @@
+use itertools::Itertools;
+
+use crate::{errors::Diagnostic, memory_view::MemoryView};
+
+/// Somewhere to copy text to and paste it from, so that features using it fail with a message
+/// rather than doing nothing where there is no clipboard
+pub trait Clipboard{
+    fn copy(&mut self, text: &str) -> Result<(), Diagnostic>;
+    fn paste(&mut self) -> Result<String, Diagnostic>;
+}
+
+/// A clipboard that only this program can see, for tests and frontends without a system one
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LocalClipboard{
+    pub text: String
+}
+
+impl Clipboard for LocalClipboard{
+    fn copy(&mut self, text: &str) -> Result<(), Diagnostic> {
+        self.text = text.to_string();
+        Ok(())
+    }
+
+    fn paste(&mut self) -> Result<String, Diagnostic> {
+        Ok(self.text.clone())
+    }
+}
+
+#[cfg(all(feature = "raylib", not(target_arch = "wasm32")))]
+impl Clipboard for raylib::RaylibHandle{
+    fn copy(&mut self, text: &str) -> Result<(), Diagnostic> {
+        self.set_clipboard_text(text).map_err(|_| Diagnostic::error("Can't copy text with a zero byte in it"))
+    }
+
+    fn paste(&mut self) -> Result<String, Diagnostic> {
+        self.get_clipboard_text().map_err(|_| Diagnostic::error("The clipboard doesn't hold text"))
+    }
+}
+
+#[cfg(all(feature = "sdl2", not(target_arch = "wasm32")))]
+impl Clipboard for sdl2::clipboard::ClipboardUtil{
+    fn copy(&mut self, text: &str) -> Result<(), Diagnostic> {
+        self.set_clipboard_text(text).map_err(|err| Diagnostic::error(format!("Could not copy: {err}")))
+    }
+
+    fn paste(&mut self) -> Result<String, Diagnostic> {
+        self.clipboard_text().map_err(|_| Diagnostic::error("The clipboard doesn't hold text"))
+    }
+}
+
+/// The browser's clipboard, which pages can write to but only read from in a paste event.
+/// The browser finishes a copy after `copy` has returned, so how it went is kept for `take_status`.
+/// Reading it back can't be done without waiting, so the page binds no paste key and `paste` always fails
+#[cfg(feature = "wasm")]
+#[derive(Debug, Clone, Default)]
+pub struct WebClipboard{
+    status: std::rc::Rc<std::cell::RefCell<Option<String>>>
+}
+
+#[cfg(feature = "wasm")]
+impl WebClipboard{
+    /// How the last copy went, once the browser has finished it, e.g. `Could not copy: NotAllowedError: ...`.
+    /// Nothing before then, or once it has been taken
+    pub fn take_status(&self) -> Option<String>{
+        self.status.borrow_mut().take()
+    }
+}
+
+/// A JavaScript error as text, such as the `DOMException` a refused copy rejects with
+#[cfg(feature = "wasm")]
+fn js_error(err: wasm_bindgen::JsValue) -> String{
+    use wasm_bindgen::JsCast;
+    err.as_string().unwrap_or_else(|| err.unchecked_into::<js_sys::Object>().to_string().into())
+}
+
+#[cfg(feature = "wasm")]
+impl Clipboard for WebClipboard{
+    fn copy(&mut self, text: &str) -> Result<(), Diagnostic> {
+        use wasm_bindgen::JsCast;
+        let get = |target: &wasm_bindgen::JsValue, name: &str| js_sys::Reflect::get(target, &name.into())
+            .ok().filter(|value| !value.is_undefined());
+        // only pages served over https or from localhost have a clipboard
+        let clipboard = get(&js_sys::global(), "navigator").and_then(|navigator| get(&navigator, "clipboard"))
+            .ok_or_else(|| Diagnostic::error("The browser doesn't allow this page to use the clipboard"))?;
+        let write_text = get(&clipboard, "writeText").and_then(|write| write.dyn_into::<js_sys::Function>().ok())
+            .ok_or_else(|| Diagnostic::error("The browser can't copy text"))?;
+        let written = write_text.call1(&clipboard, &text.into())
+            .map_err(|err| Diagnostic::error(format!("Could not copy: {}", js_error(err))))?
+            .dyn_into::<js_sys::Promise>()
+            .map_err(|_| Diagnostic::error("The browser can't copy text"))?;
+        let status = self.status.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            // refused if the page isn't focused, or the user hasn't allowed it
+            let message = match wasm_bindgen_futures::JsFuture::from(written).await {
+                Ok(_) => "Copied to the clipboard".to_string(),
+                Err(err) => format!("Could not copy: {}", js_error(err))
+            };
+            *status.borrow_mut() = Some(message);
+        });
+        Ok(())
+    }
+
+    fn paste(&mut self) -> Result<String, Diagnostic> {
+        Err(Diagnostic::error("Pasting isn't available in the browser, which only lets the page read the clipboard asynchronously"))
+    }
+}
+
+/// Bytes as hex, a row of the memory view to a line
+pub fn hex_text(bytes: &[u8]) -> String{
+    bytes.chunks(MemoryView::BYTES_PER_ROW)
+        .map(|row| row.iter().map(|byte| format!("{byte:02x}")).join(" "))
+        .map(|line| line + "\n")
+        .collect()
+}
+
+/// Bytes written in hex, separated by spaces, commas or new lines, with or without `0x`
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, Diagnostic>{
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let digits = word.strip_prefix("0x").unwrap_or(word);
+            u8::from_str_radix(digits, 16).ok().filter(|_| digits.len() <= 2)
+                .ok_or_else(|| Diagnostic::error(format!("'{word}' isn't a byte in hex")))
+        })
+        .collect()
+}
+
+
+#[test]
+fn test_hex_round_trip(){
+    let bytes: Vec<u8> = (0..12).map(|byte| byte * 20).collect();
+    let text = hex_text(&bytes);
+    assert_eq!(text, "00 14 28 3c 50 64 78 8c\na0 b4 c8 dc\n");
+    assert_eq!(parse_hex(&text).unwrap(), bytes);
+    assert_eq!(parse_hex("0x60 0x90,\tff").unwrap(), [0x60, 0x90, 0xff]);
+    assert!(parse_hex(" ").unwrap().is_empty());
+    assert_eq!(parse_hex("12 345").unwrap_err().message, "'345' isn't a byte in hex");
+
+    let mut clipboard = LocalClipboard::default();
+    clipboard.copy(&text).unwrap();
+    assert_eq!(clipboard.paste().unwrap(), text);
+}
@@ -0,0 +1,64 @@
+use std::fmt::Display;
+
+use crate::{emulator, Chip8, Instruction};
+
+/// A DRW that switched pixels off, setting VF
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision{
+    /// Address of the DRW
+    pub pc: usize,
+    pub instruction: Instruction,
+    /// (column, row) of every pixel the sprite switched off
+    pub pixels: Vec<(usize, usize)>
+}
+
+impl Collision{
+    /// Execute the next instruction, returning the collision if it was a DRW that caused one
+    pub fn execute(chip8: &mut Chip8, execute: impl FnOnce(&mut Chip8)) -> Option<Collision>{
+        let pc = chip8.pc();
+        let instruction = emulator::get_instruction(&chip8.memory, &chip8.registers);
+        if !matches!(instruction, Instruction::Draw(..)){
+            execute(chip8);
+            return None;
+        }
+        let before = chip8.frame_buffer().clone();
+        execute(chip8);
+        // drawing only flips pixels, so any that went from on to off were collided with
+        let pixels: Vec<_> = before.indexed_iter()
+            .filter(|&(position, lit)| *lit && !chip8.frame_buffer()[position])
+            .map(|(position, _)| position)
+            .collect();
+        (!pixels.is_empty()).then_some(Collision{ pc, instruction, pixels })
+    }
+}
+
+impl Display for Collision{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "0x{:03x}: {} collided at", self.pc, self.instruction)?;
+        for (index, (x, y)) in self.pixels.iter().enumerate(){
+            write!(f, "{}({}, {})", if index == 0 {" "} else {", "}, x, y)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_collision(){
+    let mut chip8 = Chip8::init(None);
+    // draw the 0 glyph at (2, 3) twice, then move it down a row and draw it again
+    let program: Vec<u8> = ["ld v0 2", "ld v1 3", "drw 0 1 5", "drw 0 1 5", "drw 0 1 5", "add v1 1", "drw 0 1 5"].into_iter()
+        .flat_map(|mnemonic| u16::from(Instruction::from_mnemonic(mnemonic).unwrap()).to_be_bytes())
+        .collect();
+    chip8.load_rom(&program).unwrap();
+    let mut collisions = Vec::new();
+    for _ in 0..7{
+        collisions.extend(Collision::execute(&mut chip8, Chip8::do_instruction));
+    }
+    assert_eq!(collisions.len(), 2);
+    assert_eq!(collisions[0].pc, 0x206);
+    // the 0 glyph has 14 pixels lit
+    assert_eq!(collisions[0].pixels.len(), 14);
+    assert!(collisions[0].pixels.contains(&(2, 3)));
+    assert!(collisions[1].to_string().starts_with("0x20c: DRW"), "{}", collisions[1]);
+    assert_eq!(chip8.registers.vn[15], 1);
+}
@@ -0,0 +1,148 @@
+use std::{fmt::Display, ops::Range};
+
+use crate::{errors::Diagnostic, MEMORY_SIZE};
+
+/// Part of an instruction that ran
+pub const EXECUTED: u8 = 1;
+/// Read as data, by `DRW` or `LD Vx [I]`
+pub const READ: u8 = 2;
+/// Written by `LD B Vx` or `LD [I] Vx`
+pub const WRITTEN: u8 = 4;
+/// The first byte of an instruction that ran, as opposed to its second
+pub const INSTRUCTION_START: u8 = 8;
+
+/// Bytes of RAM on each line of the text form
+const BYTES_PER_LINE: usize = 64;
+
+/// How a run has used each byte of RAM, for telling code from sprites and other data when taking a ROM apart.
+/// In text, each line is an address and then a hex digit for each byte from there: the bits of how it was used,
+/// or `.` for a byte never touched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage{
+    bytes: Vec<u8>
+}
+
+impl Default for Coverage{
+    fn default() -> Self {
+        Self { bytes: vec![0; MEMORY_SIZE] }
+    }
+}
+
+impl Coverage{
+    /// Note that the bytes in `range` were used as `access`, ignoring any past the end of memory
+    pub fn mark(&mut self, range: Range<usize>, access: u8){
+        let range = range.start.min(MEMORY_SIZE)..range.end.min(MEMORY_SIZE);
+        for byte in &mut self.bytes[range]{
+            *byte |= access;
+        }
+    }
+
+    /// Note that an instruction ran at `pc`
+    pub fn mark_instruction(&mut self, pc: usize){
+        self.mark(pc..pc + 1, INSTRUCTION_START);
+        self.mark(pc..pc + crate::INSTRUCTION_SIZE, EXECUTED);
+    }
+
+    /// Add the bytes `other` used, for pooling runs that explored different parts of the same ROM
+    pub fn merge(&mut self, other: &Coverage){
+        for (byte, access) in self.bytes.iter_mut().zip(other.bytes.iter()){
+            *byte |= access;
+        }
+    }
+
+    /// How the byte at `addr` was used, as `EXECUTED`, `READ`, `WRITTEN` and `INSTRUCTION_START` bits
+    pub fn get(&self, addr: usize) -> u8{
+        self.bytes.get(addr).copied().unwrap_or_default()
+    }
+
+    /// Whether the byte at `addr` was only ever used as data
+    pub fn is_data(&self, addr: usize) -> bool{
+        let access = self.get(addr);
+        access & EXECUTED == 0 && access & (READ | WRITTEN) != 0
+    }
+
+    /// Addresses where instructions that ran start
+    pub fn instruction_starts(&self) -> impl Iterator<Item = usize> + '_{
+        self.bytes.iter().enumerate().filter(|(_, access)| *access & INSTRUCTION_START != 0).map(|(addr, _)| addr)
+    }
+
+    /// How many bytes were used each way: executed, read and written
+    pub fn counts(&self) -> (usize, usize, usize){
+        let count = |access: u8| self.bytes.iter().filter(|byte| *byte & access != 0).count();
+        (count(EXECUTED), count(READ), count(WRITTEN))
+    }
+
+    /// Read coverage back from its text form. Lines starting with `#` are comments
+    pub fn parse(text: &str) -> Result<Self, Diagnostic>{
+        let mut coverage = Self::default();
+        for (number, line) in text.lines().enumerate(){
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#'){
+                continue;
+            }
+            let error = |message: &str| Diagnostic::error(format!("line {}: {}", number + 1, message));
+            let (addr, digits) = line.split_once(' ').ok_or_else(|| error("expected an address and then the bytes"))?;
+            let addr = crate::breakpoints::parse_number(addr).ok_or_else(|| error("the address isn't a number"))?;
+            for (offset, digit) in digits.trim().chars().enumerate(){
+                let access = match digit {
+                    '.' => 0,
+                    digit => digit.to_digit(16).ok_or_else(|| error(&format!("{digit} isn't a hex digit")))? as u8
+                };
+                let byte = coverage.bytes.get_mut(addr + offset).ok_or_else(|| error("runs past the end of memory"))?;
+                *byte = access;
+            }
+        }
+        Ok(coverage)
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> std::io::Result<()>{
+        std::fs::write(path, self.to_string())
+    }
+}
+
+impl Display for Coverage{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "# RAM coverage: 1 executed, 2 read, 4 written, 8 an instruction starts here, . untouched")?;
+        for (line, bytes) in self.bytes.chunks(BYTES_PER_LINE).enumerate(){
+            let digits: String = bytes.iter().map(|access| match access {
+                0 => '.',
+                access => char::from_digit(*access as u32, 16).unwrap_or('?')
+            }).collect();
+            writeln!(f, "{:#05x} {}", line * BYTES_PER_LINE, digits)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[test]
+fn test_coverage(){
+    let mut coverage = Coverage::default();
+    coverage.mark_instruction(0x200);
+    coverage.mark(0x300..0x305, READ);
+    coverage.mark(0x304..0x306, WRITTEN);
+    coverage.mark(0xffe..0x1002, WRITTEN);
+    assert_eq!(coverage.get(0x200), EXECUTED | INSTRUCTION_START);
+    assert_eq!(coverage.get(0x201), EXECUTED);
+    assert_eq!(coverage.get(0x304), READ | WRITTEN);
+    assert!(coverage.is_data(0x300));
+    assert!(!coverage.is_data(0x201));
+    assert!(!coverage.is_data(0x400));
+    assert_eq!(coverage.instruction_starts().collect::<Vec<_>>(), [0x200]);
+    assert_eq!(coverage.counts(), (2, 5, 4));
+
+    let text = coverage.to_string();
+    assert!(text.contains("\n0x200 91.."), "{text}");
+    assert!(text.contains("\n0x2c0 ...."), "{text}");
+    assert_eq!(Coverage::parse(&text).unwrap(), coverage);
+    assert!(Coverage::parse("0x200 9g").unwrap_err().message.contains("g isn't a hex digit"));
+    assert!(Coverage::parse("0xfff 99").is_err());
+
+    let mut other = Coverage::default();
+    other.mark_instruction(0x202);
+    other.mark(0x300..0x301, WRITTEN);
+    coverage.merge(&other);
+    assert_eq!(coverage.instruction_starts().collect::<Vec<_>>(), [0x200, 0x202]);
+    assert_eq!(coverage.get(0x300), READ | WRITTEN);
+    assert_eq!(coverage.counts(), (4, 5, 5));
+}
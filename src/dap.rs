@@ -0,0 +1,595 @@
+use std::{io::{self, BufRead, BufReader, Read, Write}, net::TcpListener, path::Path, sync::mpsc::{self, Receiver, RecvTimeoutError}, time::Instant};
+
+use serde_json::{json, Value};
+
+use crate::{breakpoints::{parse_number, Action, Breakpoint, Breakpoints, Condition}, headless::RunResult, session::Session, symbols::Symbols, timing::FRAME_LENGTH, Addr, HeadlessDriver, INSTRUCTION_SIZE, MEMORY_SIZE};
+
+/// The CHIP-8 has one CPU, so one thread
+const THREAD_ID: u64 = 1;
+/// `sourceReference` of the disassembly of the ROM, which stands in for its source
+const LISTING: u64 = 1;
+/// `variablesReference`s of the two scopes
+const REGISTERS: u64 = 1;
+const VARIABLES: u64 = 2;
+
+/// Read a Debug Adapter Protocol message: a `Content-Length` header, a blank line and that many bytes of JSON.
+/// `None` at the end of the input
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>>{
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0{
+            return Ok(None);
+        }
+        match line.trim_end().split_once(':') {
+            Some((name, value)) if name.eq_ignore_ascii_case("Content-Length") => length = value.trim().parse().ok(),
+            None if line.trim_end().is_empty() && length.is_some() => break,
+            _ => {}
+        }
+    }
+    let mut body = vec![0; length.unwrap_or_default()];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(io::Error::other)
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()>{
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// Why the program stopped, for the `stopped` event
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Stop{
+    Entry,
+    Step,
+    Breakpoint,
+    Pause,
+    /// The CPU halted, and why
+    Exception(String)
+}
+
+/// The ROM being debugged and what the debugger asked of it
+struct Program{
+    driver: HeadlessDriver,
+    symbols: Symbols,
+    name: String,
+    /// Length of the ROM, which the listing covers
+    rom_len: usize,
+    /// Breakpoints from source lines, function names, instruction addresses and a resumed session.
+    /// Each request replaces one kind, so they are kept apart and combined into `breakpoints`
+    kinds: [Vec<Breakpoint>; 4],
+    breakpoints: Breakpoints
+}
+
+impl Program{
+    const LINES: usize = 0;
+    const FUNCTIONS: usize = 1;
+    const INSTRUCTIONS: usize = 2;
+    const SESSION: usize = 3;
+
+    fn new(name: String, rom: &[u8], symbols: Symbols) -> Result<Self, String>{
+        let mut driver = HeadlessDriver::new(None);
+        driver.load_rom(rom).map_err(|err| err.message)?;
+        Ok(Self{ driver, symbols, name, rom_len: rom.len(), kinds: Default::default(), breakpoints: Breakpoints::default() })
+    }
+
+    fn set_breakpoints(&mut self, kind: usize, breakpoints: Vec<Breakpoint>){
+        self.kinds[kind] = breakpoints;
+        self.breakpoints = Breakpoints::default();
+        for breakpoint in self.kinds.iter().flatten(){
+            self.breakpoints.add(breakpoint.clone());
+        }
+    }
+
+    /// The line of the listing with the instruction at `addr`, if the listing has it
+    fn line(&self, addr: usize) -> Option<usize>{
+        (Addr::ROM_START.index()..Addr::ROM_START.index() + self.rom_len).contains(&addr)
+            .then(|| (addr - Addr::ROM_START.index()) / INSTRUCTION_SIZE + 1)
+    }
+
+    fn addr(&self, line: usize) -> Option<Addr>{
+        let addr = Addr::ROM_START.index() + line.checked_sub(1)? * INSTRUCTION_SIZE;
+        (addr < Addr::ROM_START.index() + self.rom_len).then(|| Addr::try_from(addr).ok()).flatten()
+    }
+
+    /// The ROM disassembled, one instruction a line with any label in front, as in the source
+    fn listing(&self) -> String{
+        let ram = &self.driver.chip8.memory.ram;
+        let end = (Addr::ROM_START.index() + self.rom_len).next_multiple_of(INSTRUCTION_SIZE).min(MEMORY_SIZE);
+        (Addr::ROM_START.index()..end).step_by(INSTRUCTION_SIZE).map(|addr| {
+            let instruction = self.symbols.instruction(u16::from_be_bytes([ram[addr], ram[addr + 1]]).into());
+            let label = Addr::try_from(addr).ok().and_then(|addr| self.symbols.name(addr)).map_or(String::new(), |label| format!("{label}: "));
+            format!("{addr:#05x}  {label}{instruction}\n")
+        }).collect()
+    }
+
+    fn source(&self) -> Value{
+        json!({ "name": format!("{} (disassembly)", self.name), "sourceReference": LISTING })
+    }
+
+    /// A stack frame for the instruction at `addr`, numbered `id`
+    fn frame(&self, id: usize, addr: usize) -> Value{
+        let name = Addr::try_from(addr).map_or(format!("{addr:#05x}"), |addr| self.symbols.location(addr));
+        let mut frame = json!({ "id": id, "name": name, "line": 0, "column": 0, "instructionPointerReference": format!("{addr:#05x}") });
+        if let Some(line) = self.line(addr){
+            frame["source"] = self.source();
+            frame["line"] = json!(line);
+        }
+        frame
+    }
+
+    /// A breakpoint at `addr` from a DAP breakpoint's `condition`, written as in debugger scripts, e.g. `v3 == 0x1f`,
+    /// and its `logMessage`, which makes it log rather than stop
+    fn breakpoint(addr: Addr, request: &Value) -> Result<Breakpoint, String>{
+        let condition = match request["condition"].as_str().map(str::trim) {
+            None | Some("") => None,
+            Some(condition) => Some(Condition::parse(&condition.split_whitespace().collect::<Vec<_>>())?)
+        };
+        let action = match request["logMessage"].as_str() {
+            Some(message) => Action::Log(message.trim().to_string()),
+            None => Action::Pause
+        };
+        Ok(Breakpoint{ condition, action, ..Breakpoint::at(addr) })
+    }
+}
+
+/// A Debug Adapter Protocol server, so that editors such as VS Code can debug ROMs: breakpoints on the lines of
+/// the ROM's disassembly, on labels from a symbol file and on addresses, stepping, registers and variables, and memory.
+/// The ROM runs headlessly at its normal speed, with no window or keys
+pub struct DapServer<W: Write>{
+    out: W,
+    seq: u64,
+    program: Option<Program>,
+    running: bool,
+    stop_on_entry: bool,
+    /// Run one instruction before checking breakpoints again, so continuing from one doesn't stop straight away
+    leaving_breakpoint: bool
+}
+
+impl<W: Write> DapServer<W>{
+    pub fn new(out: W) -> Self{
+        Self{ out, seq: 0, program: None, running: false, stop_on_entry: false, leaving_breakpoint: false }
+    }
+
+    /// Handle requests as they arrive, running the ROM between them while it isn't stopped,
+    /// until the debugger disconnects
+    pub fn serve(mut self, requests: Receiver<Value>) -> io::Result<()>{
+        loop {
+            let request = if self.running {
+                let started = Instant::now();
+                let stop = self.run_frame();
+                self.send_log()?;
+                if let Some(stop) = stop{
+                    self.stopped(stop)?;
+                    continue;
+                }
+                match requests.recv_timeout(FRAME_LENGTH.saturating_sub(started.elapsed())) {
+                    Ok(request) => request,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => return Ok(())
+                }
+            } else {
+                match requests.recv() {
+                    Ok(request) => request,
+                    Err(_) => return Ok(())
+                }
+            };
+            if !self.handle(&request)?{
+                return Ok(());
+            }
+        }
+    }
+
+    /// Run a frame's worth of instructions, or until a breakpoint or fault stops it
+    fn run_frame(&mut self) -> Option<Stop>{
+        let program = self.program.as_mut()?;
+        for _ in 0..program.driver.chip8.cycles_per_frame(){
+            if !std::mem::take(&mut self.leaving_breakpoint) && program.breakpoints.check(&program.driver.chip8){
+                return Some(Stop::Breakpoint);
+            }
+            if let RunResult::Halted(fault) = program.driver.run_cycles(1){
+                return Some(Stop::Exception(fault.to_string()));
+            }
+        }
+        None
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()>{
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.out, &message)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()>{
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    /// Send what logpoints have logged to the debug console
+    fn send_log(&mut self) -> io::Result<()>{
+        let Some(program) = self.program.as_mut() else {return Ok(())};
+        let log = program.breakpoints.take_log();
+        for line in log{
+            self.event("output", json!({ "category": "console", "output": format!("{line}\n") }))?;
+        }
+        Ok(())
+    }
+
+    fn stopped(&mut self, stop: Stop) -> io::Result<()>{
+        self.running = false;
+        self.leaving_breakpoint = true;
+        let (reason, text) = match stop {
+            Stop::Entry => ("entry", None),
+            Stop::Step => ("step", None),
+            Stop::Breakpoint => ("breakpoint", None),
+            Stop::Pause => ("pause", None),
+            Stop::Exception(text) => ("exception", Some(text))
+        };
+        let mut body = json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true });
+        if let Some(text) = text{
+            body["text"] = json!(text);
+        }
+        self.event("stopped", body)
+    }
+
+    /// Answer one request, returning false once the debugger has disconnected
+    pub fn handle(&mut self, request: &Value) -> io::Result<bool>{
+        let command = request["command"].as_str().unwrap_or_default().to_string();
+        let arguments = &request["arguments"];
+        let result = match command.as_str() {
+            "disconnect" | "terminate" => {
+                self.respond(request, Ok(Value::Null))?;
+                if command == "terminate"{
+                    self.event("terminated", json!({}))?;
+                }
+                return Ok(false);
+            },
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsFunctionBreakpoints": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsConditionalBreakpoints": true,
+                "supportsLogPoints": true,
+                "supportsReadMemoryRequest": true,
+                "supportsTerminateRequest": true
+            })),
+            "launch" => self.launch(arguments),
+            "attach" => self.attach(arguments),
+            "configurationDone" => {
+                self.respond(request, Ok(Value::Null))?;
+                match std::mem::take(&mut self.stop_on_entry) {
+                    true => self.stopped(Stop::Entry)?,
+                    false => self.running = true
+                }
+                return Ok(true);
+            },
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "continue" => {
+                self.running = true;
+                Ok(json!({ "allThreadsContinued": true }))
+            },
+            "pause" => {
+                self.respond(request, Ok(Value::Null))?;
+                self.stopped(Stop::Pause)?;
+                return Ok(true);
+            },
+            "next" | "stepIn" | "stepOut" => match self.program.as_mut() {
+                Some(program) => {
+                    let driver = &mut program.driver;
+                    match command.as_str() {
+                        "next" => {driver.step_over();},
+                        "stepIn" => {driver.run_cycles(1);},
+                        _ => {driver.step_out();}
+                    }
+                    let stop = driver.fault().map_or(Stop::Step, |fault| Stop::Exception(fault.to_string()));
+                    self.respond(request, Ok(Value::Null))?;
+                    self.stopped(stop)?;
+                    return Ok(true);
+                },
+                None => Err("no ROM is loaded".to_string())
+            },
+            _ => match self.program.as_mut() {
+                Some(program) => Self::inspect(program, &command, arguments),
+                None => Err(format!("no ROM is loaded for '{command}'"))
+            }
+        };
+        let launched = result.is_ok() && matches!(command.as_str(), "launch" | "attach");
+        self.respond(request, result)?;
+        if launched{
+            self.event("initialized", json!({}))?;
+        }
+        Ok(true)
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()>{
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok()
+        });
+        match result {
+            Ok(Value::Null) => {},
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message)
+        }
+        self.send(response)
+    }
+
+    /// Load the ROM at `program`, with labels from `symbols` if given, ready to run from the start
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String>{
+        let path = arguments["program"].as_str().ok_or("launch needs the path of the ROM as 'program'")?;
+        let rom = std::fs::read(path).map_err(|err| format!("could not read {path}: {err}"))?;
+        let mut program = Program::new(Self::file_name(path), &rom, Self::symbols(arguments)?)?;
+        if let Some(seed) = arguments["seed"].as_u64(){
+            program.driver.seed_rng(seed);
+        }
+        if let Some(speed) = arguments["speed"].as_u64(){
+            program.driver.chip8.clock_speed(speed);
+        }
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        self.program = Some(program);
+        Ok(Value::Null)
+    }
+
+    /// Carry on from a session saved by the emulator's `--save-session`, in `session`, with its breakpoints
+    fn attach(&mut self, arguments: &Value) -> Result<Value, String>{
+        let path = arguments["session"].as_str().ok_or("attach needs the path of a session file as 'session'")?;
+        let session = Session::load(Path::new(path)).map_err(|err| err.render(Some(path)))?;
+        let rom = std::fs::read(&session.rom).map_err(|err| format!("could not read {}: {}", session.rom.display(), err))?;
+        let mut program = Program::new(Self::file_name(&session.rom.to_string_lossy()), &rom, Self::symbols(arguments)?)?;
+        session.restore(&mut program.driver.chip8);
+        program.set_breakpoints(Program::SESSION, session.breakpoints.clone());
+        self.stop_on_entry = session.paused;
+        self.program = Some(program);
+        Ok(Value::Null)
+    }
+
+    fn file_name(path: &str) -> String{
+        Path::new(path).file_name().map_or(path.to_string(), |name| name.to_string_lossy().into_owned())
+    }
+
+    fn symbols(arguments: &Value) -> Result<Symbols, String>{
+        let Some(path) = arguments["symbols"].as_str() else {
+            return Ok(Symbols::default())
+        };
+        let text = std::fs::read_to_string(path).map_err(|err| format!("could not read {path}: {err}"))?;
+        Symbols::parse(&text).map_err(|err| err.render(Some(path)))
+    }
+
+    /// Requests that look at or set up a loaded ROM without running it
+    fn inspect(program: &mut Program, command: &str, arguments: &Value) -> Result<Value, String>{
+        let requests = arguments["breakpoints"].as_array().cloned().unwrap_or_default();
+        match command {
+            "setBreakpoints" => {
+                let mut breakpoints = vec![];
+                let results = requests.iter().map(|request| {
+                    let line = request["line"].as_u64().unwrap_or_default() as usize;
+                    let addr = program.addr(line);
+                    let result = addr.ok_or(format!("line {line} isn't in the ROM")).and_then(|addr| Program::breakpoint(addr, request));
+                    Self::add_breakpoint(&mut breakpoints, result, json!({ "line": line, "source": program.source() }))
+                }).collect::<Vec<_>>();
+                program.set_breakpoints(Program::LINES, breakpoints);
+                Ok(json!({ "breakpoints": results }))
+            },
+            "setFunctionBreakpoints" | "setInstructionBreakpoints" => {
+                let mut breakpoints = vec![];
+                let results = requests.iter().map(|request| {
+                    let addr = match command {
+                        "setFunctionBreakpoints" => {
+                            let name = request["name"].as_str().unwrap_or_default();
+                            program.symbols.addr(name).or_else(|| parse_number(name).and_then(|addr| Addr::try_from(addr).ok()))
+                                .ok_or(format!("no label named '{name}'"))
+                        },
+                        _ => {
+                            let reference = request["instructionReference"].as_str().unwrap_or_default();
+                            let offset = request["offset"].as_i64().unwrap_or_default();
+                            parse_number(reference)
+                                .and_then(|addr| usize::try_from(addr as i64 + offset).ok())
+                                .and_then(|addr| Addr::try_from(addr).ok())
+                                .ok_or(format!("invalid address {reference}"))
+                        }
+                    };
+                    let result = addr.and_then(|addr| Program::breakpoint(addr, request));
+                    let line = result.as_ref().ok().and_then(|breakpoint| program.line(breakpoint.addr?.index()));
+                    let location = match line {
+                        Some(line) => json!({ "line": line, "source": program.source() }),
+                        None => json!({})
+                    };
+                    Self::add_breakpoint(&mut breakpoints, result, location)
+                }).collect::<Vec<_>>();
+                let kind = if command == "setFunctionBreakpoints" {Program::FUNCTIONS} else {Program::INSTRUCTIONS};
+                program.set_breakpoints(kind, breakpoints);
+                Ok(json!({ "breakpoints": results }))
+            },
+            "stackTrace" => {
+                let chip8 = &program.driver.chip8;
+                let frames: Vec<_> = std::iter::once(chip8.pc()).chain(chip8.call_stack().iter().rev().copied())
+                    .enumerate().map(|(id, addr)| program.frame(id, addr)).collect();
+                Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+            },
+            "scopes" => {
+                let mut scopes = vec![json!({ "name": "Registers", "variablesReference": REGISTERS, "expensive": false })];
+                if !program.symbols.variables().is_empty(){
+                    scopes.push(json!({ "name": "Variables", "variablesReference": VARIABLES, "expensive": false }));
+                }
+                Ok(json!({ "scopes": scopes }))
+            },
+            "variables" => {
+                let chip8 = &program.driver.chip8;
+                let registers = &chip8.registers;
+                let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+                let variables: Vec<_> = match arguments["variablesReference"].as_u64() {
+                    Some(REGISTERS) => registers.vn.iter().enumerate()
+                        .map(|(index, value)| variable(format!("v{index:x}"), format!("{value:#04x}")))
+                        .chain([
+                            variable("i".to_string(), format!("{:#05x}", registers.i)),
+                            variable("pc".to_string(), format!("{:#05x}", registers.pc)),
+                            variable("sp".to_string(), format!("{:#x}", registers.sp)),
+                            variable("delay".to_string(), registers.delay.to_string()),
+                            variable("sound".to_string(), registers.sound.to_string())
+                        ]).collect(),
+                    Some(VARIABLES) => program.symbols.live_variables(chip8.pc()).map(|live| {
+                        let value = live.location.value(chip8);
+                        variable(format!("{} ({})", live.name, live.location), format!("{value} {value:#04x}"))
+                    }).collect(),
+                    _ => vec![]
+                };
+                Ok(json!({ "variables": variables }))
+            },
+            "readMemory" => {
+                let reference = arguments["memoryReference"].as_str().unwrap_or_default();
+                let start = parse_number(reference).ok_or(format!("invalid address {reference}"))? as i64
+                    + arguments["offset"].as_i64().unwrap_or_default();
+                let start = usize::try_from(start).unwrap_or(MEMORY_SIZE).min(MEMORY_SIZE);
+                let count = arguments["count"].as_u64().unwrap_or_default() as usize;
+                let end = start.saturating_add(count).min(MEMORY_SIZE);
+                Ok(json!({
+                    "address": format!("{start:#05x}"),
+                    "data": base64(&program.driver.chip8.memory.ram[start..end]),
+                    "unreadableBytes": count - (end - start)
+                }))
+            },
+            "source" => Ok(json!({ "content": program.listing(), "mimeType": "text/x-chip8" })),
+            _ => Err(format!("'{command}' isn't supported"))
+        }
+    }
+
+    /// Add the breakpoint in `result`, if it could be set, and describe it in the response to the editor
+    fn add_breakpoint(breakpoints: &mut Vec<Breakpoint>, result: Result<Breakpoint, String>, mut location: Value) -> Value{
+        match result {
+            Ok(breakpoint) => {
+                if let Some(addr) = breakpoint.addr{
+                    location["instructionReference"] = json!(format!("{addr:#05x}"));
+                }
+                breakpoints.push(breakpoint);
+                location["verified"] = json!(true);
+            },
+            Err(message) => {
+                location["verified"] = json!(false);
+                location["message"] = json!(message);
+            }
+        }
+        location
+    }
+}
+
+/// Standard base64, which `readMemory` sends memory in
+fn base64(bytes: &[u8]) -> String{
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in bytes.chunks(3){
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, byte)| bits | (*byte as u32) << (16 - 8 * index));
+        for index in 0..4{
+            text.push(if index <= chunk.len() {ALPHABET[(bits >> (18 - 6 * index) & 0x3f) as usize] as char} else {'='});
+        }
+    }
+    text
+}
+
+/// Read messages from `input` on a thread of their own, so the ROM can run while waiting for the next one
+fn spawn_reader(input: impl Read + Send + 'static) -> Receiver<Value>{
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(input);
+        while let Ok(Some(message)) = read_message(&mut reader){
+            if sender.send(message).is_err(){
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Serve one debugger over stdin and stdout, as editors run debug adapters
+pub fn serve_stdio() -> io::Result<()>{
+    DapServer::new(io::stdout()).serve(spawn_reader(io::stdin()))
+}
+
+/// Wait for one debugger to connect to `port` on this machine, and serve it
+pub fn serve_tcp(port: u16) -> io::Result<()>{
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("Waiting for a debugger on port {port}");
+    let (stream, _) = listener.accept()?;
+    DapServer::new(stream.try_clone()?).serve(spawn_reader(stream))
+}
+
+
+#[test]
+fn test_dap_messages(){
+    let mut bytes = vec![];
+    write_message(&mut bytes, &json!({ "command": "threads" })).unwrap();
+    assert_eq!(String::from_utf8_lossy(&bytes), "Content-Length: 21\r\n\r\n{\"command\":\"threads\"}");
+    let mut reader = io::Cursor::new([b"X-Other: 1\r\n".to_vec(), bytes].concat());
+    assert_eq!(read_message(&mut reader).unwrap(), Some(json!({ "command": "threads" })));
+    assert_eq!(read_message(&mut reader).unwrap(), None);
+    assert_eq!(base64(b"Man"), "TWFu");
+    assert_eq!(base64(b"Ma"), "TWE=");
+    assert_eq!(base64(&[0xff]), "/w==");
+}
+
+#[test]
+fn test_dap_session(){
+    let dir = std::env::temp_dir().join(format!("chip8-dap-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // start: ld v3 0x1f; call draw; jp start; draw: ld i 0x300; ret
+    std::fs::write(dir.join("game.ch8"), [0x63, 0x1f, 0x22, 0x06, 0x12, 0x00, 0xa3, 0x00, 0x00, 0xee]).unwrap();
+    std::fs::write(dir.join("game.sym"), "0x200 start\n0x206 draw\n.var x v3\n").unwrap();
+    let mut server = DapServer::new(vec![]);
+    let mut seq = 0;
+    let mut request = |server: &mut DapServer<Vec<u8>>, command: &str, arguments: Value| -> Vec<Value> {
+        seq += 1;
+        server.out.clear();
+        assert!(server.handle(&json!({ "seq": seq, "type": "request", "command": command, "arguments": arguments })).unwrap());
+        let mut reader = io::Cursor::new(std::mem::take(&mut server.out));
+        std::iter::from_fn(|| read_message(&mut reader).unwrap()).collect()
+    };
+    assert!(!request(&mut server, "stackTrace", json!({}))[0]["success"].as_bool().unwrap());
+    let messages = request(&mut server, "launch", json!({
+        "program": dir.join("game.ch8"), "symbols": dir.join("game.sym"), "stopOnEntry": true
+    }));
+    assert_eq!(messages[1]["event"], "initialized");
+    let messages = request(&mut server, "setFunctionBreakpoints", json!({ "breakpoints": [{ "name": "draw" }, { "name": "nowhere" }] }));
+    assert_eq!(messages[0]["body"]["breakpoints"][0]["line"], 4);
+    assert_eq!(messages[0]["body"]["breakpoints"][1]["verified"], false);
+    let messages = request(&mut server, "setBreakpoints", json!({ "source": { "sourceReference": LISTING }, "breakpoints": [{ "line": 3, "condition": "v3 == 0x1f" }] }));
+    assert_eq!(messages[0]["body"]["breakpoints"][0]["instructionReference"], "0x204");
+    assert_eq!(request(&mut server, "configurationDone", json!({}))[1]["body"]["reason"], "entry");
+
+    // as `serve` does while running
+    let run = |server: &mut DapServer<Vec<u8>>| -> Option<Stop> {
+        let stop = server.run_frame()?;
+        server.stopped(stop.clone()).unwrap();
+        Some(stop)
+    };
+    assert_eq!(run(&mut server), Some(Stop::Breakpoint));
+    let messages = request(&mut server, "stackTrace", json!({}));
+    let frames = &messages[0]["body"]["stackFrames"];
+    assert_eq!((frames[0]["name"].as_str(), frames[0]["line"].as_u64()), (Some("draw"), Some(4)));
+    assert_eq!(frames[1]["instructionPointerReference"], "0x202");
+    // carrying on leaves draw before checking breakpoints again
+    assert_eq!(run(&mut server), Some(Stop::Breakpoint));
+    assert_eq!(server.program.as_ref().unwrap().driver.pc(), 0x204);
+    assert_eq!(request(&mut server, "stepIn", json!({}))[1]["body"]["reason"], "step");
+    assert_eq!(server.program.as_ref().unwrap().driver.pc(), 0x200);
+
+    let messages = request(&mut server, "variables", json!({ "variablesReference": VARIABLES }));
+    assert_eq!(messages[0]["body"]["variables"][0]["value"], "31 0x1f");
+    let messages = request(&mut server, "readMemory", json!({ "memoryReference": "0x200", "count": 3 }));
+    assert_eq!(messages[0]["body"]["data"], base64(&[0x63, 0x1f, 0x22]));
+    let messages = request(&mut server, "source", json!({ "sourceReference": LISTING }));
+    let listing = messages[0]["body"]["content"].as_str().unwrap();
+    assert!(listing.starts_with("0x200  start: LD V3 31\n0x202  CALL draw\n"), "{listing}");
+
+    // a logpoint carries on, sending its line to the debug console
+    request(&mut server, "setFunctionBreakpoints", json!({ "breakpoints": [] }));
+    request(&mut server, "setBreakpoints", json!({ "source": { "sourceReference": LISTING }, "breakpoints": [{ "line": 3, "logMessage": "looped" }] }));
+    assert_eq!(server.run_frame(), None);
+    server.send_log().unwrap();
+    let mut reader = io::Cursor::new(std::mem::take(&mut server.out));
+    let events: Vec<_> = std::iter::from_fn(|| read_message(&mut reader).unwrap()).collect();
+    assert!(!events.is_empty());
+    assert!(events[0]["body"]["output"].as_str().unwrap().starts_with("0x204: looped v0=00 v1=00 v2=00 v3=1f"), "{}", events[0]);
+    assert!(!server.handle(&json!({ "seq": 99, "command": "disconnect" })).unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
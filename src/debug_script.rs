@@ -0,0 +1,87 @@
+use std::fmt::Display;
+
+use crate::{breakpoints::Breakpoint, errors::{Diagnostic, Span}, symbols::Symbols};
+
+/// A saved debugging setup, stored as a line-oriented text script:
+///
+/// ```text
+/// # comments start with '#'
+/// break 0x20a
+/// break 0x20a if v3 == 0x1f
+/// break if i in 0x300 0x310
+/// break disabled if write 0x3ff
+/// # labels from a symbol file can stand in for addresses
+/// break loop
+/// ```
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DebugScript{
+    pub breakpoints: Vec<Breakpoint>
+}
+
+impl DebugScript{
+    pub fn parse(text: &str) -> Result<Self, Diagnostic>{
+        Self::parse_with_symbols(text, &Symbols::default())
+    }
+
+    /// Parse a script that may use the labels in `symbols` in place of addresses
+    pub fn parse_with_symbols(text: &str, symbols: &Symbols) -> Result<Self, Diagnostic>{
+        let mut script = Self::default();
+        for (lineno, source_line) in text.lines().enumerate(){
+            let error = |message: String| Diagnostic::error(message)
+                .with_span(Span::whole_line(lineno + 1, source_line), source_line);
+            let line = source_line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty(){
+                continue;
+            }
+            let words: Vec<_> = line.split_whitespace()
+                .map(|word| symbols.addr(word).map_or_else(|| word.to_ascii_lowercase(), |addr| addr.to_string()))
+                .collect();
+            let parts: Vec<_> = words.iter().map(String::as_str).collect();
+            match parts[..] {
+                ["break", ref breakpoint @ ..] => {
+                    let breakpoint = Breakpoint::parse(breakpoint)
+                        .map_err(error)?;
+                    script.breakpoints.push(breakpoint);
+                },
+                _ => return Err(error(format!("unknown command '{line}'")))
+            }
+        }
+        Ok(script)
+    }
+}
+
+impl Display for DebugScript{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "# chip8 debugger script")?;
+        for breakpoint in self.breakpoints.iter(){
+            writeln!(f, "break {breakpoint}")?;
+        }
+        Ok(())
+    }
+}
+
+
+#[test]
+fn test_script_round_trip(){
+    let script = DebugScript::parse("break 0x200\nbreak disabled 0x20a if v3 == 0x1f\nbreak if write 0x300").unwrap();
+    assert_eq!(DebugScript::parse(&script.to_string()).unwrap(), script);
+}
+
+#[test]
+fn test_script_parse(){
+    let script = DebugScript::parse("# setup\n\nbreak 0x204 # loop\nbreak 530\n").unwrap();
+    let addrs: Vec<_> = script.breakpoints.iter().map(|b| b.addr).collect();
+    assert_eq!(addrs, vec![crate::Addr::new(0x204), crate::Addr::new(530)]);
+    let error = DebugScript::parse("break 0x204\nwatch\n").unwrap_err();
+    assert_eq!(error.span.map(|span| span.line), Some(2));
+    assert!(DebugScript::parse("break 0x1000").is_err());
+}
+
+#[test]
+fn test_script_symbols(){
+    let symbols = Symbols::parse("0x20a Loop\n0x300 score\n").unwrap();
+    let script = DebugScript::parse_with_symbols("break Loop\nbreak if write score\n", &symbols).unwrap();
+    assert_eq!(script.breakpoints[0].addr, crate::Addr::new(0x20a));
+    assert_eq!(script.to_string(), "# chip8 debugger script\nbreak 0x20a\nbreak if write 0x300\n");
+    assert!(DebugScript::parse("break Loop").is_err());
+}
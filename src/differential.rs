@@ -0,0 +1,328 @@
+use std::fmt::Display;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{Addr, Chip8, Fault, Instruction, Reg, INSTRUCTION_SIZE, MEMORY_SIZE};
+
+/// Decode `opcode` from its nibbles, written apart from the `Chip8Instr` derive so that each can check the other
+pub fn decode(opcode: u16) -> Instruction{
+    let nibbles = [opcode >> 12, opcode >> 8 & 0xf, opcode >> 4 & 0xf, opcode & 0xf];
+    let (x, y) = (Reg(nibbles[1] as u8), Reg(nibbles[2] as u8));
+    let nn = (opcode & 0xff) as u8;
+    let nnn = Addr(opcode & 0xfff);
+    match nibbles {
+        [0x0, 0x0, 0xe, 0x0] => Instruction::ClearScreen,
+        [0x0, 0x0, 0xe, 0xe] => Instruction::Ret,
+        [0x0, 0x0, 0xf, 0x1] => Instruction::VSync,
+        [0x1, ..] => Instruction::Jump(nnn),
+        [0x2, ..] => Instruction::Call(nnn),
+        [0x3, ..] => Instruction::SkipEqImm(x, nn),
+        [0x4, ..] => Instruction::SkipNeImm(x, nn),
+        [0x5, _, _, 0x0] => Instruction::SkipEqReg(x, y),
+        [0x6, ..] => Instruction::SetImm(x, nn),
+        [0x7, ..] => Instruction::AddImm(x, nn),
+        [0x8, _, _, 0x0] => Instruction::SetReg(x, y),
+        [0x8, _, _, 0x1] => Instruction::OrReg(x, y),
+        [0x8, _, _, 0x2] => Instruction::AndReg(x, y),
+        [0x8, _, _, 0x3] => Instruction::XorReg(x, y),
+        [0x8, _, _, 0x4] => Instruction::AddReg(x, y),
+        [0x8, _, _, 0x5] => Instruction::SubReg(x, y),
+        [0x8, _, _, 0x6] => Instruction::Rsh(x),
+        [0x8, _, _, 0x7] => Instruction::SubFrom(x, y),
+        [0x8, _, _, 0xe] => Instruction::Lsh(x),
+        [0x9, _, _, 0x0] => Instruction::SkipNeReg(x, y),
+        [0xa, ..] => Instruction::SetMemPtr(nnn),
+        [0xb, ..] => Instruction::JumpOffset(nnn),
+        [0xc, ..] => Instruction::Rand(x, nn),
+        [0xd, _, _, n] => Instruction::Draw(x, y, n as u8),
+        [0xe, _, 0x9, 0xe] => Instruction::SkipKeyPressed(x),
+        [0xe, _, 0xa, 0x1] => Instruction::SkipKeyNotPressed(x),
+        [0xf, _, 0x0, 0x7] => Instruction::GetDelay(x),
+        [0xf, _, 0x0, 0xa] => Instruction::WaitForKey(x),
+        [0xf, _, 0x1, 0x5] => Instruction::SetDelay(x),
+        [0xf, _, 0x1, 0x8] => Instruction::SetSound(x),
+        [0xf, _, 0x1, 0xe] => Instruction::AddMemPtr(x),
+        [0xf, _, 0x2, 0x9] => Instruction::SetChar(x),
+        [0xf, _, 0x3, 0x3] => Instruction::BCD(x),
+        [0xf, _, 0x5, 0x5] => Instruction::RegDump(x),
+        [0xf, _, 0x6, 0x5] => Instruction::RegLoad(x),
+        _ => Instruction::Nop
+    }
+}
+
+/// The machine as the reference sees it: registers, the call stack and RAM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State{
+    pub v: [u8; 16],
+    pub i: usize,
+    pub pc: usize,
+    /// Addresses of the `CALL`s returned to, innermost last
+    pub stack: Vec<usize>,
+    pub delay: u8,
+    pub sound: u8,
+    pub ram: Vec<u8>
+}
+
+/// A state after one instruction, and the fault that halted it if any
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome{
+    pub state: State,
+    pub fault: Option<Fault>
+}
+
+/// Whether the reference can execute `instruction`. Those that draw, read keys or take random bytes are left out,
+/// since what they do depends on more than registers and RAM
+pub fn executes(instruction: Instruction) -> bool{
+    !matches!(instruction, Instruction::ClearScreen | Instruction::Draw(..) | Instruction::Rand(..)
+        | Instruction::SkipKeyPressed(_) | Instruction::SkipKeyNotPressed(_) | Instruction::WaitForKey(_))
+}
+
+/// Run the instruction at `state.pc` as this emulator defines it: shifts work on Vx alone, VF is written last,
+/// and `LD [I] Vx` and `LD Vx [I]` leave I as it was.
+/// A fault leaves the state as it was
+pub fn execute(state: &State) -> Outcome{
+    let mut next = state.clone();
+    let fault = step(&mut next).err();
+    match fault {
+        Some(fault) => Outcome{ state: state.clone(), fault: Some(fault) },
+        None => Outcome{ state: next, fault: None }
+    }
+}
+
+fn step(state: &mut State) -> Result<(), Fault>{
+    let opcode = u16::from_be_bytes([state.ram[state.pc], state.ram[state.pc + 1]]);
+    let instruction = decode(opcode);
+    let v = state.v;
+    let next = state.pc + INSTRUCTION_SIZE;
+    let skip = |condition: bool| if condition {next + INSTRUCTION_SIZE} else {next};
+    let mut flag = None;
+    state.pc = next;
+    match instruction {
+        Instruction::Nop | Instruction::VSync => {},
+        Instruction::Ret => state.pc = state.stack.pop().ok_or(Fault::StackUnderflow)? + INSTRUCTION_SIZE,
+        Instruction::Jump(addr) => state.pc = addr.index(),
+        Instruction::Call(addr) => {
+            state.stack.push(next - INSTRUCTION_SIZE);
+            state.pc = addr.index();
+        },
+        Instruction::SkipEqImm(x, nn) => state.pc = skip(v[x.index()] == nn),
+        Instruction::SkipNeImm(x, nn) => state.pc = skip(v[x.index()] != nn),
+        Instruction::SkipEqReg(x, y) => state.pc = skip(v[x.index()] == v[y.index()]),
+        Instruction::SkipNeReg(x, y) => state.pc = skip(v[x.index()] != v[y.index()]),
+        Instruction::SetImm(x, nn) => state.v[x.index()] = nn,
+        Instruction::AddImm(x, nn) => state.v[x.index()] = v[x.index()].wrapping_add(nn),
+        Instruction::SetReg(x, y) => state.v[x.index()] = v[y.index()],
+        Instruction::OrReg(x, y) => state.v[x.index()] = v[x.index()] | v[y.index()],
+        Instruction::AndReg(x, y) => state.v[x.index()] = v[x.index()] & v[y.index()],
+        Instruction::XorReg(x, y) => state.v[x.index()] = v[x.index()] ^ v[y.index()],
+        Instruction::AddReg(x, y) => {
+            let sum = v[x.index()] as u16 + v[y.index()] as u16;
+            state.v[x.index()] = sum as u8;
+            flag = Some((sum > 0xff) as u8);
+        },
+        Instruction::SubReg(x, y) => {
+            state.v[x.index()] = v[x.index()].wrapping_sub(v[y.index()]);
+            flag = Some((v[x.index()] >= v[y.index()]) as u8);
+        },
+        Instruction::SubFrom(x, y) => {
+            state.v[x.index()] = v[y.index()].wrapping_sub(v[x.index()]);
+            flag = Some((v[y.index()] >= v[x.index()]) as u8);
+        },
+        Instruction::Rsh(x) => {
+            state.v[x.index()] = v[x.index()] >> 1;
+            flag = Some(v[x.index()] & 1);
+        },
+        Instruction::Lsh(x) => {
+            state.v[x.index()] = v[x.index()] << 1;
+            flag = Some(v[x.index()] >> 7);
+        },
+        Instruction::SetMemPtr(addr) => state.i = addr.index(),
+        Instruction::JumpOffset(addr) => state.pc = addr.index() + v[0] as usize,
+        Instruction::GetDelay(x) => state.v[x.index()] = state.delay,
+        Instruction::SetDelay(x) => state.delay = v[x.index()],
+        Instruction::SetSound(x) => state.sound = v[x.index()],
+        Instruction::AddMemPtr(x) => state.i += v[x.index()] as usize,
+        Instruction::SetChar(x) => {
+            // the font has the 16 hex digits, 5 bytes each, from address 0
+            if v[x.index()] > 0xf{
+                return Err(Fault::InvalidCharacter(v[x.index()]));
+            }
+            state.i = v[x.index()] as usize * 5;
+        },
+        Instruction::BCD(x) => {
+            let digits = [v[x.index()] / 100, v[x.index()] / 10 % 10, v[x.index()] % 10];
+            ram(state, 3)?.copy_from_slice(&digits);
+        },
+        Instruction::RegDump(x) => {
+            let count = x.index() + 1;
+            ram(state, count)?.copy_from_slice(&v[..count]);
+        },
+        Instruction::RegLoad(x) => {
+            let count = x.index() + 1;
+            let loaded = ram(state, count)?.to_vec();
+            state.v[..count].copy_from_slice(&loaded);
+        },
+        Instruction::ClearScreen | Instruction::Draw(..) | Instruction::Rand(..) | Instruction::SkipKeyPressed(_)
+            | Instruction::SkipKeyNotPressed(_) | Instruction::WaitForKey(_) => unreachable!("{instruction} isn't executed by the reference")
+    }
+    if let Some(flag) = flag{
+        state.v[0xf] = flag;
+    }
+    Ok(())
+}
+
+/// The `count` bytes of RAM from I, or a fault if they run off the end
+fn ram(state: &mut State, count: usize) -> Result<&mut [u8], Fault>{
+    state.ram.get_mut(state.i..state.i + count).ok_or(Fault::MemoryOutOfRange)
+}
+
+/// Where this crate and the reference disagree
+#[derive(Debug, Clone)]
+pub enum Mismatch{
+    Decode{ opcode: u16, expected: Instruction, actual: Instruction },
+    Execute(Box<Execution>)
+}
+
+/// An instruction executed from the same state by both, with different outcomes
+#[derive(Debug, Clone)]
+pub struct Execution{
+    pub before: State,
+    pub expected: Outcome,
+    pub actual: Outcome
+}
+
+/// Decode every opcode both ways
+pub fn check_decode() -> Vec<Mismatch>{
+    (0..=u16::MAX).filter_map(|opcode| {
+        let (expected, actual) = (decode(opcode), Instruction::from(opcode));
+        (expected != actual).then_some(Mismatch::Decode{ opcode, expected, actual })
+    }).collect()
+}
+
+/// Execute `count` random opcodes that the reference can execute, from random states, both ways
+pub fn check_execute(seed: u64, count: usize) -> Vec<Mismatch>{
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut mismatches = vec![];
+    for _ in 0..count{
+        let before = random_state(&mut rng);
+        let expected = execute(&before);
+        let actual = run(&before);
+        if expected != actual{
+            mismatches.push(Mismatch::Execute(Box::new(Execution{ before, expected, actual })));
+        }
+    }
+    mismatches
+}
+
+/// A state with random registers, stack and RAM, and an instruction the reference executes at the program counter
+fn random_state(rng: &mut StdRng) -> State{
+    let mut ram = Chip8::init(None).memory.ram.to_vec();
+    rng.fill(&mut ram[Addr::ROM_START.index()..]);
+    let pc = rng.gen_range(Addr::ROM_START.index() / 2..MEMORY_SIZE / 2) * 2;
+    let opcode = loop {
+        let opcode: u16 = rng.gen();
+        if executes(decode(opcode)){
+            break opcode;
+        }
+    };
+    ram[pc..pc + INSTRUCTION_SIZE].copy_from_slice(&opcode.to_be_bytes());
+    let depth = rng.gen_range(0..4);
+    State{
+        v: rng.gen(),
+        // now and then close enough to the end of RAM for loads and stores to run off it
+        i: if rng.gen_ratio(1, 8) {rng.gen_range(MEMORY_SIZE - 16..MEMORY_SIZE)} else {rng.gen_range(0..MEMORY_SIZE)},
+        pc,
+        stack: (0..depth).map(|_| rng.gen_range(Addr::ROM_START.index() / 2..MEMORY_SIZE / 2) * 2).collect(),
+        delay: rng.gen(),
+        sound: rng.gen(),
+        ram
+    }
+}
+
+/// Execute the instruction at `before.pc` on the emulator
+fn run(before: &State) -> Outcome{
+    let mut chip8 = Chip8::init(None);
+    chip8.memory.ram.copy_from_slice(&before.ram);
+    chip8.memory.stack = before.stack.clone();
+    let registers = &mut chip8.registers;
+    (registers.vn, registers.i, registers.pc, registers.delay, registers.sound) = (before.v, before.i, before.pc, before.delay, before.sound);
+    chip8.do_instruction();
+    let registers = &chip8.registers;
+    Outcome{
+        state: State{
+            v: registers.vn,
+            i: registers.i,
+            pc: registers.pc,
+            stack: chip8.memory.stack.clone(),
+            delay: registers.delay,
+            sound: registers.sound,
+            ram: chip8.memory.ram.to_vec()
+        },
+        fault: chip8.fault()
+    }
+}
+
+impl Display for Mismatch{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mismatch::Decode{ opcode, expected, actual } => write!(f, "{opcode:#06x} decodes to {actual}, expected {expected}"),
+            Mismatch::Execute(execution) => {
+                let Execution{ before, expected, actual } = execution.as_ref();
+                let opcode = u16::from_be_bytes([before.ram[before.pc], before.ram[before.pc + 1]]);
+                let registers = before.v.iter().enumerate().map(|(reg, value)| format!("v{reg:x}={value:02x}")).collect::<Vec<_>>();
+                writeln!(f, "{opcode:#06x} {} at {:#05x}", decode(opcode), before.pc)?;
+                writeln!(f, "  from {} i={:#05x} stack={:03x?}", registers.join(" "), before.i, before.stack)?;
+                for difference in differences(expected, actual){
+                    writeln!(f, "  {difference}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// What differs between the reference's outcome and the emulator's, e.g. `vf: expected 01, got 00`
+fn differences(expected: &Outcome, actual: &Outcome) -> Vec<String>{
+    let mut differences = vec![];
+    let mut compare = |name: String, expected: String, actual: String| if expected != actual {
+        differences.push(format!("{name}: expected {expected}, got {actual}"));
+    };
+    let (expected_state, actual_state) = (&expected.state, &actual.state);
+    for reg in 0..16{
+        compare(format!("v{reg:x}"), format!("{:02x}", expected_state.v[reg]), format!("{:02x}", actual_state.v[reg]));
+    }
+    compare("i".into(), format!("{:#05x}", expected_state.i), format!("{:#05x}", actual_state.i));
+    compare("pc".into(), format!("{:#05x}", expected_state.pc), format!("{:#05x}", actual_state.pc));
+    compare("stack".into(), format!("{:03x?}", expected_state.stack), format!("{:03x?}", actual_state.stack));
+    compare("delay".into(), expected_state.delay.to_string(), actual_state.delay.to_string());
+    compare("sound".into(), expected_state.sound.to_string(), actual_state.sound.to_string());
+    for (addr, (expected, actual)) in expected_state.ram.iter().zip(&actual_state.ram).enumerate(){
+        compare(format!("[{addr:#05x}]"), format!("{expected:02x}"), format!("{actual:02x}"));
+    }
+    compare("fault".into(), format!("{:?}", expected.fault), format!("{:?}", actual.fault));
+    differences
+}
+
+
+#[test]
+fn test_differential(){
+    let mismatches = check_decode();
+    assert!(mismatches.is_empty(), "{}", mismatches.iter().take(10).map(Mismatch::to_string).collect::<Vec<_>>().join("\n"));
+    let mismatches = check_execute(1, 5_000);
+    assert!(mismatches.is_empty(), "{}", mismatches.iter().take(10).map(Mismatch::to_string).collect::<Vec<_>>().join("\n"));
+}
+
+#[test]
+fn test_differential_reports(){
+    // the reference sets VF after the sum, so `ADD VF V1` leaves the carry in VF
+    let mut state = random_state(&mut StdRng::seed_from_u64(0));
+    state.ram[state.pc..state.pc + 2].copy_from_slice(&[0x8f, 0x14]);
+    (state.v[0x1], state.v[0xf]) = (0xff, 0x02);
+    let expected = execute(&state);
+    assert_eq!((expected.state.v[0xf], expected.state.pc), (1, state.pc + 2));
+    let mut actual = expected.clone();
+    actual.state.v[0xf] = 0x02;
+    let report = Mismatch::Execute(Box::new(Execution{ before: state, expected, actual })).to_string();
+    assert!(report.starts_with("0x8f14 ADD VF V1 at "), "{report}");
+    assert!(report.ends_with("  vf: expected 01, got 02\n"), "{report}");
+}
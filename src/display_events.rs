@@ -0,0 +1,91 @@
+use std::collections::VecDeque;
+
+use crate::{quirks::Quirk, DISPLAY_COLUMNS, DISPLAY_ROWS};
+
+/// A change the CPU made to the display. Frontends can treat a clear differently from drawing,
+/// e.g. fading out what was wiped instead of blanking it, or streaming only the rows a sprite touched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayEvent{
+    /// `CLS`, or a reset: every pixel switched off
+    Clear,
+    /// A sprite XORed onto the display, 8 pixels wide and `height` rows tall from column `x`, row `y`,
+    /// wrapping around the edges
+    Sprite{ x: usize, y: usize, height: usize, collided: bool },
+    /// A quirk switched on or off, so a frontend can show it or remember it for the ROM
+    QuirkChanged{ quirk: Quirk, enabled: bool }
+}
+
+/// The display events since a frontend last took them, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct DisplayEvents{
+    events: VecDeque<DisplayEvent>
+}
+
+impl DisplayEvents{
+    /// Past this the oldest events are dropped, so a CPU nobody takes events from doesn't grow without limit
+    pub const MAX_EVENTS: usize = 1024;
+
+    pub fn push(&mut self, event: DisplayEvent){
+        if event == DisplayEvent::Clear{
+            // nothing drawn before a clear is left to see, but quirk changes still are
+            self.events.retain(|event| matches!(event, DisplayEvent::QuirkChanged{..}));
+        }
+        if self.events.len() >= Self::MAX_EVENTS{
+            // keeping clears and quirk changes, which frontends still need to know about
+            let oldest = self.events.iter().position(|event| matches!(event, DisplayEvent::Sprite{..})).unwrap_or(0);
+            self.events.remove(oldest);
+        }
+        self.events.push_back(event);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DisplayEvent>{
+        self.events.iter()
+    }
+
+    /// Whether the display was cleared since the events were last taken
+    pub fn cleared(&self) -> bool{
+        self.events.contains(&DisplayEvent::Clear)
+    }
+
+    /// Take out the quirk changes, oldest first, leaving what was drawn
+    pub fn take_quirk_changes(&mut self) -> Vec<(Quirk, bool)>{
+        let mut changes = vec![];
+        self.events.retain(|event| match *event {
+            DisplayEvent::QuirkChanged{ quirk, enabled } => {
+                changes.push((quirk, enabled));
+                false
+            },
+            _ => true
+        });
+        changes
+    }
+
+    pub fn clear(&mut self){
+        self.events.clear();
+    }
+}
+
+impl DisplayEvent{
+    pub fn sprite(x: u8, y: u8, height: u8, collided: bool) -> Self{
+        Self::Sprite{ x: x as usize % DISPLAY_COLUMNS, y: y as usize % DISPLAY_ROWS, height: height as usize, collided }
+    }
+}
+
+
+#[test]
+fn test_display_events(){
+    let mut events = DisplayEvents::default();
+    events.push(DisplayEvent::sprite(70, 2, 5, false));
+    assert_eq!(events.iter().next(), Some(&DisplayEvent::Sprite{ x: 6, y: 2, height: 5, collided: false }));
+    assert!(!events.cleared());
+    events.push(DisplayEvent::Clear);
+    events.push(DisplayEvent::sprite(0, 0, 1, true));
+    assert!(events.cleared());
+    assert_eq!(events.iter().count(), 2);
+    for _ in 0..DisplayEvents::MAX_EVENTS{
+        events.push(DisplayEvent::sprite(0, 0, 1, false));
+    }
+    assert_eq!(events.iter().count(), DisplayEvents::MAX_EVENTS);
+    assert!(events.cleared());
+    assert_eq!(events.iter().nth(1), Some(&DisplayEvent::sprite(0, 0, 1, false)));
+}
@@ -0,0 +1,72 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{errors::{parse_name, Diagnostic}, resources::Resources, Chip8Driver, EmulatorMode};
+
+/// A window the driver can open, of the frontends built in, for choosing one at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Backend{
+    /// raylib's window, with the debug panels
+    #[cfg(feature = "raylib")]
+    #[default]
+    Raylib,
+    /// A plainer SDL2 window, for where raylib doesn't build
+    #[cfg(feature = "sdl2")]
+    #[cfg_attr(not(feature = "raylib"), default)]
+    Sdl2
+}
+
+impl Backend{
+    /// The backends built in
+    pub const ALL: &'static [Backend] = &[
+        #[cfg(feature = "raylib")]
+        Backend::Raylib,
+        #[cfg(feature = "sdl2")]
+        Backend::Sdl2
+    ];
+}
+
+impl Display for Backend{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            #[cfg(feature = "raylib")]
+            Backend::Raylib => write!(f, "raylib"),
+            #[cfg(feature = "sdl2")]
+            Backend::Sdl2 => write!(f, "sdl2")
+        }
+    }
+}
+
+/// Parses the names `--backend` takes, e.g. `sdl2`
+impl FromStr for Backend{
+    type Err = Diagnostic;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_name(Self::ALL, "backend", s)
+    }
+}
+
+/// Open `backend`'s window, load `rom` and run it until the window is closed.
+/// Fails if the ROM doesn't fit in memory. For anything more, see `Chip8Driver::with_backend`
+pub fn run_with_backend(backend: Backend, rom: &[u8], mode: EmulatorMode, speed: Option<u64>) -> Result<(), Diagnostic>{
+    let mut driver = Chip8Driver::with_backend(backend, mode, speed, &Resources::default());
+    driver.load_rom(rom)?;
+    driver.run();
+    Ok(())
+}
+
+
+#[test]
+fn test_backend_names(){
+    let names: Vec<_> = Backend::ALL.iter().map(|backend| backend.to_string()).collect();
+    assert_eq!(names.contains(&"raylib".to_string()), cfg!(feature = "raylib"));
+    assert_eq!(names.contains(&"sdl2".to_string()), cfg!(feature = "sdl2"));
+    // raylib's window, with the debug panels, whenever it is built in
+    #[cfg(feature = "raylib")]
+    assert_eq!(Backend::default(), Backend::Raylib);
+    #[cfg(not(feature = "raylib"))]
+    assert_eq!(Backend::default(), Backend::Sdl2);
+    for backend in Backend::ALL{
+        assert_eq!(backend.to_string().parse(), Ok(*backend));
+    }
+}
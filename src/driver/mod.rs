@@ -0,0 +1,1791 @@
+
+mod backend;
+mod multiplexer;
+mod recorder;
+mod replays;
+mod session_state;
+mod stats;
+
+pub use backend::{run_with_backend, Backend};
+pub(crate) use multiplexer::Multiplexer;
+pub(crate) use recorder::DisplayRecorder;
+pub(crate) use replays::ReplayState;
+pub(crate) use session_state::SessionState;
+pub(crate) use stats::RunStats;
+
+use crate::{breakpoints::Breakpoints, collision::Collision, debug_script::DebugScript, errors::Diagnostic, extensions::Extension, frame_timing::{FrameTiming, TimingLog}, frontend::{Chip8Frontend, InputSource, KeyInput, Renderer}, history::{Granularity, StateHistory}, keypad::{KeyProfile, KEY_COUNT}, quirks::Quirk, recent::RecentRoms, replay::{self, KeyEvent, Replay}, resources::Resources, rom_check, rom_inspect, screenshot::Screenshot, session::Session, settings::{RomSettings, UsageStats}, symbols::Symbols, theme::DisplayStyle, timing::{FrameScheduler, TimingMode, FRAME_LENGTH}, trace::Trace, trace_filter::TraceFilter, emulator, Addr, Chip8, Chip8Driver, CpuState, EmulatorMode, Fault, HaltCondition, Instruction};
+#[cfg(feature = "raylib")]
+use crate::frontend::RaylibDisplay;
+#[cfg(feature = "sdl2")]
+use crate::frontend::Sdl2Display;
+#[cfg(not(any(feature = "raylib", feature = "sdl2")))]
+compile_error!("the `window` feature needs a frontend to open: switch on `raylib` or `sdl2`");
+use std::{path::{Path, PathBuf}, thread::sleep, time::{Duration, Instant}};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+#[cfg_attr(feature="wasm", wasm_bindgen)]
+impl Chip8Driver{
+
+    pub fn new(mode: EmulatorMode, speed: Option<u64>) -> Self{
+        Self::with_resources(mode, speed, &Resources::default())
+    }
+
+    /// Load `rom`, warning in the window if it doesn't look like a CHIP-8 program,
+    /// and switching on any extensions its code uses. Fails if it doesn't fit in memory.
+    /// Check it with `rom_check::check_rom` first to refuse the others too
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Diagnostic>{
+        self.chip8.load_rom(rom)?;
+        let mut warnings: Vec<_> = rom_check::check_rom(rom).into_iter().map(|warning| warning.message).collect();
+        for (extension, addr) in rom_inspect::inspect(rom).extensions{
+            if !self.chip8.has_extension(extension){
+                self.chip8.set_extension(extension, true);
+                warnings.push(format!("Switched on the {extension} extension, the ROM uses it at {addr}"));
+            }
+        }
+        if !warnings.is_empty(){
+            self.frontend.show_message(&warnings.join("\n"));
+        }
+        self.rom = rom.to_vec();
+        self.frontend.set_rom(rom);
+        Ok(())
+    }
+
+    /// Set how many frames (or paused steps) of history are kept for stepping backwards
+    pub fn set_rewind_depth(&mut self, frames: usize){
+        self.history.set_depth(frames);
+    }
+
+
+    pub fn run(&mut self){
+        self.start_stats();
+        let mut last_frame = Instant::now();
+        loop {
+            let elapsed = last_frame.elapsed();
+            last_frame = Instant::now();
+            if self.tick(elapsed){
+                // a recording still going when the window closes is saved rather than lost
+                self.stop_recording();
+                self.stop_replay_recording();
+                self.stats.save_timing_log();
+                self.save_coverage();
+                self.save_stats();
+                self.save_session();
+                break;
+            }
+            match self.mode {
+                EmulatorMode::Paused => sleep(Duration::from_millis(50)),
+                EmulatorMode::Running => sleep(self.scheduler.until_next_frame())
+            }
+        }
+    }
+
+}
+
+impl Chip8Driver{
+    /// Open the default backend's window with the font and sounds from `resources`, e.g. with an override directory
+    pub fn with_resources(mode: EmulatorMode, speed: Option<u64>, resources: &Resources) -> Self{
+        Self::with_backend(Backend::default(), mode, speed, resources)
+    }
+
+    /// Open `backend`'s window with the font and sounds from `resources`
+    pub fn with_backend(backend: Backend, mode: EmulatorMode, speed: Option<u64>, resources: &Resources) -> Self{
+        let frontend: Box<dyn Chip8Frontend> = match backend {
+            #[cfg(feature = "raylib")]
+            Backend::Raylib => Box::new(RaylibDisplay::new(resources)),
+            #[cfg(feature = "sdl2")]
+            Backend::Sdl2 => Box::new(Sdl2Display::new(resources, Sdl2Display::DEFAULT_SCALE))
+        };
+        Self::with_frontend(mode, speed, frontend)
+    }
+
+    /// Frame gaps longer than this are treated as a stalled window
+    pub const MAX_FRAME_GAP: Duration = Duration::from_millis(250);
+    /// How many frames are emulated for each one shown while fast-forwarding
+    pub const TURBO_FRAMES: u32 = 4;
+    /// The slowest and fastest speeds `[-]` and `[=]` go to, in instructions a second
+    pub const MIN_SPEED: u64 = 60;
+    pub const MAX_SPEED: u64 = 60_000;
+
+    /// A driver drawing to `frontend` instead of a window
+    pub(crate) fn with_frontend(mode: EmulatorMode, speed: Option<u64>, frontend: Box<dyn Chip8Frontend>) -> Self{
+        let mut driver = Self { 
+            chip8: Chip8::init(speed),
+            frontend: Multiplexer::new(frontend),
+            mode,
+            history: StateHistory::new(StateHistory::DEFAULT_DEPTH),
+            breakpoints: Breakpoints::default(),
+            debug_script_path: None,
+            settings: RomSettings::default(),
+            settings_path: None,
+            stats: RunStats::default(),
+            display_style: None,
+            diff_steps: 1,
+            step_count: 1,
+            symbols: Symbols::default(),
+            trace: None,
+            trace_path: None,
+            trace_filter: None,
+            show_collisions: false,
+            pause_on_stall: false,
+            rom: Vec::new(),
+            screenshot_path: None,
+            recorder: DisplayRecorder::default(),
+            coverage_path: None,
+            replays: ReplayState::default(),
+            session_state: SessionState::default(),
+            timing: TimingMode::default(),
+            overrun: 0,
+            scheduler: FrameScheduler::default(),
+            virtual_time: Duration::ZERO,
+            panic: None,
+            dropped_writes: Default::default()
+        };
+        if matches!(mode, EmulatorMode::Paused){
+            driver.frontend.toggle_debug();
+        }
+        driver.frontend.set_gamepad_map(&driver.settings.gamepad);
+        driver.frontend.set_key_remap(&driver.settings.key_remap);
+        driver.frontend.set_key_profile(driver.settings.key_profile);
+        driver.frontend.set_display_style(&driver.settings.style);
+        driver.frontend.set_panel_styles(&driver.settings.panels);
+        driver
+    }
+
+    /// Carry out an input that does the same whether running or paused,
+    /// handing back the ones that depend on the mode
+    fn handle_input(&mut self, input: KeyInput) -> Option<KeyInput>{
+        match input {
+            KeyInput::ExportDebugScript => self.export_debug_script(),
+            KeyInput::ToggleTrace => self.toggle_trace(),
+            KeyInput::DumpTrace => self.dump_trace(),
+            KeyInput::ToggleCollisions => self.show_collisions = !self.show_collisions,
+            KeyInput::ExportRom => self.export_rom(),
+            KeyInput::SaveCrashBundle => self.save_crash_bundle(),
+            KeyInput::Screenshot => self.save_screenshot(),
+            KeyInput::ToggleRecording => self.toggle_recording(),
+            KeyInput::PitchUp => self.shift_pitch(1),
+            KeyInput::PitchDown => self.shift_pitch(-1),
+            KeyInput::SpeedUp => self.change_speed(true),
+            KeyInput::SpeedDown => self.change_speed(false),
+            KeyInput::ToggleQuirk(quirk) => self.change_quirk(quirk, !self.chip8.quirks().get(quirk)),
+            KeyInput::Reset => self.reset(),
+            KeyInput::SoftReset => self.soft_reset(),
+            KeyInput::NextKeyProfile => self.next_key_profile(),
+            KeyInput::RemapKeys => self.frontend.toggle_key_remap(),
+            KeyInput::RemapKey(key, host_key) => self.remap_key(key, host_key),
+            KeyInput::OpenRecent(index) => self.open_recent(index),
+            KeyInput::ToggleDebug => self.frontend.toggle_debug(),
+            KeyInput::ToggleGrid => self.frontend.toggle_grid(),
+            KeyInput::ToggleVirtualKeypad => self.frontend.toggle_virtual_keypad(),
+            KeyInput::Copy => self.copy(),
+            KeyInput::Step | KeyInput::StepOver | KeyInput::StepOut | KeyInput::StepTimers | KeyInput::StepFrame | KeyInput::StepBack
+                | KeyInput::Chip8Key(_) | KeyInput::TogglePause | KeyInput::Turbo | KeyInput::Click(_) | KeyInput::Scroll(..)
+                | KeyInput::WriteMemory(..) | KeyInput::Paste => return Some(input)
+        }
+        None
+    }
+
+    /// Handle input and run whatever frames are due after `elapsed` time.
+    /// Returns true once the frontend has been closed
+    pub(crate) fn tick(&mut self, elapsed: Duration) -> bool{
+        match self.mode{
+            EmulatorMode::Paused => {
+                for k in self.frontend.get_inputs(){
+                    let Some(k) = self.handle_input(k) else {continue};
+                    match k {
+                        KeyInput::Step => {
+                            self.interrupt_replay("single steps");
+                            self.step();
+                            if self.update_frontend(true) {return true}
+
+                        },
+                        KeyInput::StepOver => {
+                            self.interrupt_replay("single steps");
+                            self.step_over();
+                            if self.update_frontend(true) {return true}
+                        },
+                        KeyInput::StepOut => {
+                            self.interrupt_replay("single steps");
+                            self.step_out();
+                            if self.update_frontend(true) {return true}
+                        },
+                        KeyInput::StepTimers => {
+                            self.interrupt_replay("timer steps");
+                            self.history.push(&self.chip8, Granularity::Step);
+                            self.chip8.tick_timers();
+                            if self.update_frontend(true) {return true}
+                        },
+                        KeyInput::StepFrame => {
+                            self.history.push(&self.chip8, Granularity::Frame);
+                            self.replay_frame();
+                            self.chip8.tick_timers();
+                            self.run_frame_instructions(false);
+                            self.record_frame();
+                            if self.update_frontend(true) {return true}
+                        },
+                        KeyInput::StepBack => {
+                            self.interrupt_replay("steps back");
+                            if let Some((previous, granularity)) = self.history.pop(){
+                                self.step_back(previous, granularity);
+                            }
+                            if self.update_frontend(true) {return true}
+                        },
+                        KeyInput::Chip8Key(val) => {
+                            self.clear_keys();
+                            self.press_key(val)
+                        }
+                        KeyInput::TogglePause => {
+                            // carry on past a fault rather than straight back into it
+                            self.chip8.resume();
+                            self.mode = EmulatorMode::Running
+                        },
+                        KeyInput::Click(position) => {
+                            self.frontend.on_mouse_click(position, &mut self.breakpoints)
+                        },
+                        KeyInput::Scroll(position,amount ) => {
+                            self.frontend.on_mouse_scroll(position, amount);
+                        },
+                        KeyInput::WriteMemory(addr, value) => self.write_memory(&[(addr, value)]),
+                        KeyInput::Paste => self.paste(),
+                        // nothing to fast-forward while paused, and the rest were handled above
+                        _ => {}
+                    }
+                }
+                self.update_audio();
+                self.update_frontend(false)
+            },
+            EmulatorMode::Running => {
+                let started = Instant::now();
+                // The render loop stops while the window is dragged or resized.
+                // Treat the gap as lost time rather than something to catch up on
+                self.stats.add_play_time(elapsed.min(Self::MAX_FRAME_GAP));
+                let mut frames = if elapsed > Self::MAX_FRAME_GAP{
+                    self.scheduler.reset();
+                    if self.pause_on_stall{
+                        self.mode = EmulatorMode::Paused;
+                        return false;
+                    }
+                    1
+                } else {
+                    self.scheduler.advance(elapsed)
+                };
+                self.clear_keys();
+                let mut turbo = false;
+                for k in self.frontend.get_inputs(){
+                    let Some(k) = self.handle_input(k) else {continue};
+                    match k {
+                        KeyInput::Chip8Key(key) => self.press_key(key),
+                        KeyInput::TogglePause => self.mode = EmulatorMode::Paused,
+                        KeyInput::Turbo => turbo = true,
+                        // memory is only edited while paused
+                        KeyInput::Paste => self.frontend.show_message("Pause to paste into memory"),
+                        // stepping, and clicks and edits in the debugger, wait for a pause
+                        _ => {}
+                    }
+                }
+                if turbo{
+                    frames *= Self::TURBO_FRAMES;
+                }
+                let instructions_before = self.stats.instructions_run();
+                // At the beginning of each frame, we: 
+                // - save a snapshot for stepping backwards
+                // - tick down the delay and sound registers
+                for _ in 0..frames{
+                    if !matches!(self.mode, EmulatorMode::Running){
+                        break;
+                    }
+                    self.emulate_frame();
+                }
+                let emulated = Instant::now();
+                self.update_audio();
+                let quit = self.update_frontend(true);
+                let timing = FrameTiming{
+                    start: self.stats.timing_log().since_start(started),
+                    interval: elapsed,
+                    emulation: emulated - started,
+                    presentation: emulated.elapsed(),
+                    frames_run: frames,
+                    instructions: self.stats.instructions_run() - instructions_before
+                };
+                self.stats.log_frame(timing);
+                quit
+            }
+        }
+    }
+
+    pub const DEFAULT_DEBUG_SCRIPT: &'static str = "chip8.dbg";
+
+    /// Apply the breakpoints from a debugger script
+    pub fn load_debug_script(&mut self, script: &DebugScript){
+        for breakpoint in script.breakpoints.iter(){
+            self.breakpoints.add(breakpoint.clone());
+        }
+    }
+
+    /// The current debugging setup
+    pub fn debug_script(&self) -> DebugScript{
+        DebugScript{
+            breakpoints: self.breakpoints.iter().cloned().collect()
+        }
+    }
+
+    pub fn breakpoints(&mut self) -> &mut Breakpoints{
+        &mut self.breakpoints
+    }
+
+    /// Set the file that the debugger script is exported to
+    pub fn set_debug_script_path(&mut self, path: PathBuf){
+        self.debug_script_path = Some(path);
+    }
+
+    /// Draw the current state, giving the memory view a snapshot to diff against while paused
+    fn update_frontend(&mut self, show_current_instruction: bool) -> bool{
+        let snapshot = match self.mode {
+            EmulatorMode::Paused => self.history.steps_ago(self.diff_steps).map(|previous| &previous.memory.ram[..]),
+            EmulatorMode::Running => None
+        };
+        self.frontend.set_memory_snapshot(snapshot);
+        if self.frontend.is_debug_view(){
+            self.frontend.set_frame_times(&self.stats.timing_log().recent(TimingLog::RECENT));
+        }
+        let closed = self.frontend.update(&self.chip8, &self.breakpoints, show_current_instruction);
+        self.chip8.clear_display_events();
+        closed
+    }
+
+    /// Show `symbols` in place of addresses in the debugger
+    pub fn set_symbols(&mut self, symbols: Symbols){
+        self.frontend.set_symbols(&symbols);
+        self.symbols = symbols;
+    }
+
+    /// Labels loaded with `set_symbols`, e.g. for parsing a debugger script that uses them
+    pub fn symbols(&self) -> &Symbols{
+        &self.symbols
+    }
+
+    /// Diff the memory view against RAM from `steps` steps ago while paused
+    pub fn set_diff_steps(&mut self, steps: usize){
+        self.diff_steps = steps.max(1);
+    }
+
+    /// Make each single step run `count` instructions, stopping early at a breakpoint or when the CPU stops
+    pub fn set_step_count(&mut self, count: usize){
+        self.step_count = count.max(1);
+    }
+
+    /// Use per-ROM `settings`, saving any changes made while running to `path`
+    pub fn set_settings(&mut self, settings: RomSettings, path: PathBuf){
+        self.settings = settings;
+        self.settings_path = Some(path);
+        self.frontend.set_gamepad_map(&self.settings.gamepad);
+        self.frontend.set_key_remap(&self.settings.key_remap);
+        self.frontend.set_key_profile(self.settings.key_profile);
+        self.frontend.set_display_style(&self.settings.style);
+        self.frontend.set_panel_styles(&self.settings.panels);
+        for (quirk, enabled) in self.settings.quirks.iter(){
+            self.chip8.set_quirk(*quirk, *enabled);
+        }
+    }
+
+    /// Whether the ROM's play statistics are updated this run, on top of its `track_stats` setting
+    pub fn set_track_stats(&mut self, track: bool){
+        self.stats.track = track;
+    }
+
+    /// How much the ROM has been played, from its settings
+    pub fn stats(&self) -> UsageStats{
+        self.settings.stats
+    }
+
+    fn tracking_stats(&self) -> bool{
+        self.stats.track && self.settings.track_stats && self.settings_path.is_some()
+    }
+
+    /// Show how much the ROM has been played before, and count this launch
+    fn start_stats(&mut self){
+        if !self.tracking_stats(){
+            return;
+        }
+        let now = UsageStats::now();
+        if let Some(summary) = self.settings.stats.summary(now){
+            self.frontend.show_message(&summary);
+        }
+        self.settings.stats.launches += 1;
+        self.settings.stats.last_played = Some(now);
+        self.save_settings();
+    }
+
+    /// Add the time played this run
+    fn save_stats(&mut self){
+        if !self.tracking_stats(){
+            return;
+        }
+        self.settings.stats.play_time += self.stats.take_play_time();
+        self.save_settings();
+    }
+
+    /// Colours and effects the display is drawn with, from the ROM's settings unless overridden
+    pub fn display_style(&self) -> DisplayStyle{
+        self.display_style.unwrap_or(self.settings.style)
+    }
+
+    /// Draw the display with `style` for this run, without saving it to the ROM's settings
+    pub fn set_display_style(&mut self, style: DisplayStyle){
+        self.display_style = Some(style);
+        self.frontend.set_display_style(&style);
+    }
+
+    fn shift_pitch(&mut self, semitones: i32){
+        self.settings.shift_pitch(semitones);
+        self.save_settings();
+    }
+
+    /// Run about a quarter more or a fifth fewer instructions a second, in whole instructions a frame
+    fn change_speed(&mut self, faster: bool){
+        if !self.timing.uses_speed(){
+            self.frontend.show_message(&format!("--timing {} sets its own speed", self.timing));
+            return;
+        }
+        self.interrupt_replay("speed changes");
+        let per_frame = self.chip8.cycles_per_frame();
+        let per_frame = if faster {(per_frame * 5 / 4).max(per_frame + 1)} else {(per_frame * 4 / 5).min(per_frame.saturating_sub(1))};
+        let speed = (per_frame * 60).clamp(Self::MIN_SPEED, Self::MAX_SPEED);
+        self.chip8.clock_speed(speed);
+        self.frontend.show_message(&format!("Speed: {} instructions a second", speed));
+    }
+
+    fn save_settings(&self){
+        if let Some(path) = &self.settings_path{
+            if let Err(err) = self.settings.save(path){
+                eprintln!("Could not save settings to {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    /// Pause when the window stalls for longer than `MAX_FRAME_GAP`, instead of resuming where it left off
+    pub fn set_pause_on_stall(&mut self, pause: bool){
+        self.pause_on_stall = pause;
+    }
+
+    pub const DEFAULT_TRACE_FILE: &'static str = "chip8.trace";
+
+    /// Start recording executed instructions, dumping them to `path`
+    pub fn enable_trace(&mut self, path: PathBuf){
+        self.trace = Some(Trace::new(Trace::DEFAULT_CAPACITY).with_filter(self.trace_filter.clone()));
+        self.trace_path = Some(path);
+    }
+
+    /// Only trace the instructions that match `filter`, from now on and whenever tracing is switched on
+    pub fn set_trace_filter(&mut self, filter: TraceFilter){
+        if let Some(trace) = self.trace.take(){
+            self.trace = Some(trace.with_filter(Some(filter.clone())));
+        }
+        self.trace_filter = Some(filter);
+    }
+
+    /// Log sprite collisions and have the frontend flash the pixels involved
+    pub fn set_show_collisions(&mut self, show: bool){
+        self.show_collisions = show;
+    }
+
+    /// Whether a breakpoint pauses before the next instruction. Lines logged by breakpoints go to stderr, away from the summaries on stdout
+    fn check_breakpoints(&mut self) -> bool{
+        let pause = self.breakpoints.check(&self.chip8);
+        for line in self.breakpoints.take_log(){
+            eprintln!("{line}");
+        }
+        pause
+    }
+
+    /// Execute the next instruction, recording it if tracing is enabled
+    /// and reporting any collision it causes if those are being shown
+    /// A panic in the emulator halts the CPU with `Fault::Panic` rather than closing the window
+    fn execute(&mut self){
+        self.stats.count_instruction();
+        let trace = &mut self.trace;
+        let show_collisions = self.show_collisions;
+        let mut collision = None;
+        let panic = self.chip8.catch_panic(|chip8| {
+            let mut execute = |chip8: &mut Chip8| match trace {
+                Some(trace) => trace.execute(chip8),
+                None => chip8.do_instruction()
+            };
+            if show_collisions{
+                collision = Collision::execute(chip8, execute);
+            } else {
+                execute(chip8);
+            }
+        });
+        if let Some(collision) = collision{
+            eprintln!("{}", collision);
+            self.frontend.show_collision(&collision);
+        }
+        if panic.is_some(){
+            self.panic = panic;
+        }
+        if let Some(byte) = self.chip8.take_random_byte(){
+            self.replays.record_random(byte);
+        }
+        if let Some((addr, pc)) = self.chip8.take_dropped_write(){
+            if self.dropped_writes.insert(pc){
+                let message = format!("Dropped a write to {:#05x} from {:#05x}: protect-low-memory keeps the font safe", addr, pc);
+                eprintln!("{}", message);
+                self.frontend.show_message(&message);
+            }
+        }
+    }
+
+    /// Most instructions a step over runs while waiting for the subroutine to return
+    const STEP_OVER_LIMIT: usize = 1_000_000;
+
+    /// Execute `step_count` instructions, as one step back
+    fn step(&mut self){
+        self.history.push(&self.chip8, Granularity::Step);
+        for count in 0..self.step_count{
+            if count > 0 && (self.chip8.state() != CpuState::Running || self.check_breakpoints()){
+                break;
+            }
+            self.execute();
+        }
+        self.report_fault();
+    }
+
+    /// Execute one instruction, running the whole subroutine if it is a call,
+    /// until it returns or a breakpoint triggers
+    fn step_over(&mut self){
+        let instruction = emulator::get_instruction(&self.chip8.memory, &self.chip8.registers);
+        self.history.push(&self.chip8, Granularity::Step);
+        self.execute();
+        if matches!(instruction, Instruction::Call(_)){
+            self.run_until_return(self.chip8.call_stack().len(), "stepping over");
+        }
+    }
+
+    /// Run the rest of the current subroutine, until it returns or a breakpoint triggers
+    fn step_out(&mut self){
+        let depth = self.chip8.call_stack().len();
+        if depth == 0{
+            self.frontend.show_message("Not in a subroutine, so there is nothing to step out of");
+            return;
+        }
+        self.history.push(&self.chip8, Granularity::Step);
+        self.run_until_return(depth, "stepping out");
+    }
+
+    /// Run until the call stack is shallower than `depth`, a breakpoint triggers or the CPU stops
+    fn run_until_return(&mut self, depth: usize, stepping: &str){
+        for _ in 0..Self::STEP_OVER_LIMIT{
+            if self.chip8.call_stack().len() < depth || self.chip8.state() != CpuState::Running{
+                self.report_fault();
+                return;
+            }
+            self.execute();
+            if self.check_breakpoints(){
+                return;
+            }
+        }
+        self.frontend.show_message(&format!("Stopped {stepping} after {} instructions, the subroutine hasn't returned", Self::STEP_OVER_LIMIT));
+    }
+
+    /// Go back to `previous`, with the quirks it ran with, saved as the ROM's again like any other quirk change
+    fn step_back(&mut self, previous: Chip8, granularity: Granularity){
+        let quirks = self.chip8.quirks;
+        self.chip8 = previous;
+        let restored = std::mem::replace(&mut self.chip8.quirks, quirks);
+        for quirk in Quirk::ALL{
+            self.change_quirk(quirk, restored.get(quirk));
+        }
+        if granularity == Granularity::Frame{
+            self.frontend.show_message("Stepped back a whole frame");
+        }
+    }
+
+    /// Start the ROM again from the beginning, as it was loaded
+    fn reset(&mut self){
+        self.interrupt_replay("resets");
+        self.history.push(&self.chip8, Granularity::Step);
+        self.chip8.reset();
+        // the ROM fitted when it was first loaded, so this only fails if that went wrong
+        if let Err(err) = self.chip8.load_rom(&self.rom){
+            self.frontend.show_message(&err.message);
+        }
+    }
+
+    /// Start again from 0x200 without reloading the ROM, so memory edits are kept
+    fn soft_reset(&mut self){
+        self.interrupt_replay("resets");
+        self.history.push(&self.chip8, Granularity::Step);
+        self.chip8.soft_reset();
+    }
+
+    /// Emulate one 60 hz frame as a running emulator does, pausing if a breakpoint triggers
+    fn emulate_frame(&mut self){
+        self.history.push(&self.chip8, Granularity::Frame);
+        self.replay_frame();
+        self.chip8.tick_timers();
+        self.run_frame_instructions(true);
+        self.record_frame();
+    }
+
+    /// Emulate `duration` of running straight away, without sleeping, drawing or reading input,
+    /// for tests and scripts. Keys held with `press_key` stay down throughout, and time short of
+    /// a whole frame carries over to the next call. Returns how many frames ran, fewer than were
+    /// due if a breakpoint or fault paused the emulator
+    pub fn advance(&mut self, duration: Duration) -> u32{
+        self.virtual_time += duration;
+        let due = (self.virtual_time.as_nanos() / FRAME_LENGTH.as_nanos()) as u32;
+        self.virtual_time -= FRAME_LENGTH * due;
+        let mode = std::mem::replace(&mut self.mode, EmulatorMode::Running);
+        for frame in 0..due{
+            self.emulate_frame();
+            if matches!(self.mode, EmulatorMode::Paused){
+                return frame + 1;
+            }
+        }
+        self.mode = mode;
+        due
+    }
+
+    /// Run a frame's worth of instructions for the timing mode,
+    /// pausing early if `check_breakpoints` is set and one triggers, or if the CPU halts
+    fn run_frame_instructions(&mut self, check_breakpoints: bool){
+        let mut budget = self.timing.frame_budget(&self.chip8);
+        let owed = self.overrun.min(budget);
+        self.overrun -= owed;
+        budget -= owed;
+        while budget > 0{
+            let instruction = emulator::get_instruction(&self.chip8.memory, &self.chip8.registers);
+            let cost = self.timing.cost(instruction);
+            self.overrun += cost.saturating_sub(budget);
+            budget = budget.saturating_sub(cost);
+            self.execute();
+            if check_breakpoints && self.check_breakpoints(){
+                self.mode = EmulatorMode::Paused;
+                return;
+            }
+            match self.chip8.state() {
+                CpuState::Running => {},
+                CpuState::Halted(_) => {
+                    self.report_fault();
+                    return;
+                },
+                // Nothing else runs until a key is pressed or the next frame starts
+                CpuState::WaitingForKey(_) | CpuState::WaitingForDisplay => return
+            }
+        }
+    }
+
+    /// If the CPU has halted, pause and say why, in the window as well as on stderr
+    fn report_fault(&mut self){
+        if let CpuState::Halted(fault) = self.chip8.state(){
+            let mut report = fault.diagnostic(self.chip8.pc()).render(None);
+            if let (Fault::Panic, Some(message)) = (fault, &self.panic){
+                report = format!("{report}\n{message}\n[F12] saves a crash bundle to report it");
+            }
+            eprintln!("{}", report);
+            self.frontend.show_message(&report);
+            self.mode = EmulatorMode::Paused;
+        }
+    }
+
+    /// Sound the buzzer while the sound timer is running
+    fn update_audio(&mut self){
+        let audio = self.frontend.audio();
+        audio.set_pitch(self.settings.buzzer_pitch);
+        audio.set_buzzer(self.chip8.sound());
+    }
+
+    /// Choose how many instructions run in each frame
+    pub fn set_timing(&mut self, timing: TimingMode){
+        self.timing = timing;
+        self.overrun = 0;
+        let display_wait = self.settings.quirks.get(&Quirk::DisplayWait).copied().unwrap_or(!timing.uses_speed());
+        self.chip8.set_quirk(Quirk::DisplayWait, display_wait);
+    }
+
+    /// Start the random numbers of `RND` from `seed`, so that runs come out the same
+    pub fn seed_rng(&mut self, seed: u64){
+        self.chip8.seed_rng(seed);
+    }
+
+    /// Halt the CPU on `condition` instead of carrying on through it
+    pub fn set_halt_on(&mut self, condition: HaltCondition, enabled: bool){
+        self.chip8.set_halt_on(condition, enabled);
+    }
+
+    /// Halt with a stack overflow when a call would nest deeper than `depth`
+    pub fn set_stack_depth(&mut self, depth: usize){
+        self.chip8.set_stack_depth(depth);
+    }
+
+    /// Switch a quirk on or off for this run, e.g. from the command line
+    pub fn set_quirk(&mut self, quirk: Quirk, enabled: bool){
+        self.chip8.set_quirk(quirk, enabled);
+    }
+
+    /// Switch a quirk on or off while running or paused, e.g. to see which way a misbehaving ROM expects it.
+    /// The change is shown, kept in the ROM's settings for next time, and stops any replay,
+    /// which was recorded with the quirks as they were
+    pub fn change_quirk(&mut self, quirk: Quirk, enabled: bool){
+        if self.chip8.quirks().get(quirk) == enabled{
+            return
+        }
+        self.chip8.set_quirk(quirk, enabled);
+        self.interrupt_replay("quirk changes");
+        self.settings.quirks.insert(quirk, enabled);
+        self.save_settings();
+        self.frontend.show_message(&format!("Quirk {}: {}", quirk, if enabled {"on"} else {"off"}));
+    }
+
+    /// Show or hide the keypad drawn over the display
+    pub fn toggle_virtual_keypad(&mut self){
+        self.frontend.toggle_virtual_keypad();
+    }
+
+    /// Play the keypad with the keyboard keys of `profile`, remembered in the ROM's settings
+    pub fn set_key_profile(&mut self, profile: KeyProfile){
+        self.settings.key_profile = profile;
+        self.save_settings();
+        self.frontend.set_key_profile(profile);
+    }
+
+    fn next_key_profile(&mut self){
+        let profile = self.settings.key_profile.next();
+        self.set_key_profile(profile);
+        let detail = match profile {
+            KeyProfile::Standard => "keypad on 1234/QWER/ASDF/ZXCV",
+            KeyProfile::TwoPlayer => "player 2's C D E F on the arrow keys"
+        };
+        self.frontend.show_message(&format!("Keys: {profile} ({detail})"));
+    }
+
+    /// Give CHIP-8 `key` another key as well as its usual one, by the frontend's name for it,
+    /// or with `None` take it away. Remembered in the ROM's settings
+    pub fn remap_key(&mut self, key: u8, host_key: Option<&str>){
+        let message = match host_key {
+            Some(host_key) => {
+                self.settings.key_remap.insert(key, host_key.to_string());
+                format!("CHIP-8 key {key:X} is also on {host_key}")
+            },
+            None => {
+                self.settings.key_remap.remove(&key);
+                format!("CHIP-8 key {key:X} is only on its usual key")
+            }
+        };
+        self.save_settings();
+        self.frontend.set_key_remap(&self.settings.key_remap);
+        self.frontend.show_message(&message);
+    }
+
+    /// Switch an extension's opcodes on or off
+    pub fn set_extension(&mut self, extension: Extension, enabled: bool){
+        self.chip8.set_extension(extension, enabled);
+    }
+
+    /// Save the session for `rom` to `path` when the emulator is closed
+    pub fn enable_session(&mut self, rom: PathBuf, path: PathBuf){
+        self.session_state.enable_session(rom, path);
+    }
+
+    /// The ROM, machine state, debug view and breakpoints, for picking up where this run left off
+    pub fn session(&self, rom: PathBuf) -> Session{
+        let mut session = Session::new(rom, &self.chip8);
+        session.paused = matches!(self.mode, EmulatorMode::Paused);
+        session.debug_view = self.frontend.is_debug_view();
+        session.breakpoints = self.breakpoints.iter().cloned().collect();
+        session
+    }
+
+    /// Carry on from a saved session. The ROM should already be loaded
+    pub fn resume(&mut self, session: &Session){
+        session.restore(&mut self.chip8);
+        self.mode = if session.paused {EmulatorMode::Paused} else {EmulatorMode::Running};
+        if self.frontend.is_debug_view() != session.debug_view{
+            self.frontend.toggle_debug();
+        }
+        self.breakpoints = Breakpoints::default();
+        for breakpoint in session.breakpoints.iter(){
+            self.breakpoints.add(breakpoint.clone());
+        }
+        self.history.clear();
+    }
+
+    fn save_session(&self){
+        let Some((rom, path)) = self.session_state.session() else {return};
+        if let Err(err) = self.session(rom.to_path_buf()).save(path){
+            eprintln!("Could not save the session to {}: {}", path.display(), err);
+        }
+    }
+
+    fn toggle_trace(&mut self){
+        self.trace = match self.trace {
+            Some(_) => None,
+            None => Some(Trace::new(Trace::DEFAULT_CAPACITY).with_filter(self.trace_filter.clone()))
+        };
+    }
+
+    fn dump_trace(&self){
+        let Some(trace) = &self.trace else {return};
+        let path = self.trace_path.clone().unwrap_or(PathBuf::from(Self::DEFAULT_TRACE_FILE));
+        if let Err(err) = trace.dump(&path, &self.symbols){
+            eprintln!("Could not write trace to {}: {}", path.display(), err);
+        }
+    }
+
+    pub const DEFAULT_PATCHED_ROM: &'static str = "patched.ch8";
+
+    /// Where F6 writes the edited ROM, instead of `patched.ch8`
+    pub fn set_patched_rom_path(&mut self, path: PathBuf){
+        self.session_state.set_patched_rom_path(path);
+    }
+
+    /// The ROM region of RAM, including any edits made while paused
+    pub fn patched_rom(&self) -> Vec<u8>{
+        self.chip8.read_memory(crate::Addr::ROM_START.index(), self.rom.len()).unwrap_or_default().to_vec()
+    }
+
+    /// Keep `recent`, which starts with the ROM running, up to date in the state file at `path` as ROMs are opened
+    pub fn set_recent_roms(&mut self, recent: RecentRoms, path: PathBuf){
+        self.session_state.set_recent_roms(recent, path);
+    }
+
+    /// Open the `index`th ROM before the one running, most recent first
+    fn open_recent(&mut self, index: usize){
+        let paths = self.session_state.recent_paths();
+        match paths.get(index) {
+            Some(path) => self.open_rom(&path.clone()),
+            None if paths.len() > 1 => {
+                let list = paths.iter().enumerate().skip(1)
+                    .map(|(index, path)| format!("{}: {}", index, path.display())).collect::<Vec<_>>();
+                self.frontend.show_message(&format!("No recent ROM {index}. Recent ROMs:\n{}", list.join("\n")));
+            },
+            None => self.frontend.show_message("No other ROMs opened recently")
+        }
+    }
+
+    /// Switch to the ROM at `path` with its own settings, as if the emulator had been started with it
+    pub fn open_rom(&mut self, path: &Path){
+        let rom = match std::fs::read(path) {
+            Ok(rom) => rom,
+            Err(err) => return self.frontend.show_message(&format!("Could not open {}: {}", path.display(), err))
+        };
+        let problems = rom_check::check_rom(&rom);
+        if rom_check::is_fatal(&problems){
+            let problems: Vec<_> = problems.iter().map(|problem| problem.render(Some(&path.display().to_string()))).collect();
+            return self.frontend.show_message(&problems.join("\n"));
+        }
+        self.interrupt_replay("switching ROMs");
+        self.save_stats();
+        // nothing from the last ROM is worth stepping back to
+        self.history.clear();
+        self.dropped_writes.clear();
+        self.chip8.reset();
+        // coverage is of one ROM, so the last one's is saved and tracking starts again
+        self.save_coverage();
+        self.chip8.set_coverage(self.coverage_path.is_some());
+        for quirk in Quirk::ALL{
+            self.chip8.set_quirk(quirk, false);
+        }
+        // reset keeps extensions and loading only switches them on, so one ROM's don't outlive it
+        for extension in Extension::ALL{
+            self.chip8.set_extension(extension, false);
+        }
+        if let Err(err) = self.load_rom(&rom){
+            return self.frontend.show_message(&err.render(Some(&path.display().to_string())));
+        }
+        self.set_settings(RomSettings::load(path), RomSettings::path_for(path));
+        self.start_stats();
+        self.set_symbols(Symbols::default());
+        self.session_state.open(path);
+        self.frontend.show_message(&format!("Opened {}", path.display()));
+    }
+
+    fn export_rom(&self){
+        let path = self.session_state.patched_rom_path();
+        if let Err(err) = std::fs::write(&path, self.patched_rom()){
+            eprintln!("Could not write the ROM to {}: {}", path.display(), err);
+        }
+    }
+
+    pub const DEFAULT_SCREENSHOT: &'static str = "screenshot.png";
+
+    /// Where F7 writes a screenshot, instead of `screenshot.png`.
+    /// A PNG also gets a PBM copy next to it
+    pub fn set_screenshot_path(&mut self, path: PathBuf){
+        self.screenshot_path = Some(path);
+    }
+
+    /// A copy of the display as it is now
+    pub fn screenshot(&self) -> Screenshot{
+        Screenshot::of(self.chip8.frame_buffer())
+    }
+
+    fn save_screenshot(&mut self){
+        let path = self.screenshot_path.clone().unwrap_or(PathBuf::from(Self::DEFAULT_SCREENSHOT));
+        let screenshot = self.screenshot();
+        let mut paths = vec![path.clone()];
+        if path.extension().is_some_and(|extension| extension == "png"){
+            paths.push(path.with_extension("pbm"));
+        }
+        for path in paths.iter(){
+            if let Err(err) = screenshot.save(path, &self.display_style()){
+                eprintln!("Could not write the screenshot to {}: {}", path.display(), err);
+                return;
+            }
+        }
+        self.frontend.show_message(&format!("Saved a screenshot to {}", path.display()));
+    }
+
+    pub const DEFAULT_RECORDING: &'static str = "recording.gif";
+
+    /// Where F8 saves the recording as a GIF when it stops, instead of `recording.gif`
+    pub fn set_recording_path(&mut self, path: PathBuf){
+        self.recorder.set_path(path);
+    }
+
+    /// Record the display every frame until `stop_recording`
+    pub fn start_recording(&mut self){
+        self.recorder.start();
+        self.frontend.show_message("Recording");
+    }
+
+    /// Stop recording and save the GIF
+    pub fn stop_recording(&mut self){
+        let Some(recording) = self.recorder.take() else {return};
+        let path = self.recorder.path();
+        match recording.save(&path, &self.display_style()) {
+            Ok(()) => self.frontend.show_message(&format!("Saved {} frames to {}", recording.len(), path.display())),
+            Err(err) => eprintln!("Could not write the recording to {}: {}", path.display(), err)
+        }
+    }
+
+    pub fn is_recording(&self) -> bool{
+        self.recorder.recording().is_some()
+    }
+
+    fn toggle_recording(&mut self){
+        if self.is_recording(){
+            self.stop_recording();
+        } else {
+            self.start_recording();
+        }
+    }
+
+    fn record_frame(&mut self){
+        // rather than silently dropping the rest of the run
+        if self.recorder.push(Screenshot::of(self.chip8.frame_buffer())){
+            self.stop_recording();
+        }
+    }
+
+    /// Keep the timing of every frame, to write to `path` as CSV on exit along with a summary
+    pub fn set_timing_log_path(&mut self, path: PathBuf){
+        self.stats.set_timing_log_path(path);
+    }
+
+    pub fn timing_log(&self) -> &TimingLog{
+        self.stats.timing_log()
+    }
+
+    /// Track how each byte of RAM is used from now on, writing it to `path` on exit
+    /// and colouring the memory view by it
+    pub fn set_coverage_path(&mut self, path: PathBuf){
+        self.chip8.set_coverage(true);
+        self.coverage_path = Some(path);
+    }
+
+    fn save_coverage(&self){
+        let (Some(path), Some(coverage)) = (&self.coverage_path, self.chip8.coverage()) else {return};
+        if let Err(err) = coverage.save(path){
+            eprintln!("Could not write the coverage to {}: {}", path.display(), err);
+        }
+        let (executed, read, written) = coverage.counts();
+        println!("Coverage: {executed} bytes executed, {read} read, {written} written, saved to {}", path.display());
+    }
+
+    /// Record every key pressed from now on, to save to `path` as a replay on exit.
+    /// Call it straight after `load_rom`, or `resume` to start the replay from a saved session
+    pub fn record_replay(&mut self, path: PathBuf){
+        self.replays.record(Replay::new(&self.rom, &self.chip8, self.timing), path);
+    }
+
+    /// Copy the panel under the mouse to the clipboard
+    fn copy(&mut self){
+        match self.frontend.copy(&self.chip8, &self.breakpoints) {
+            Ok(()) => self.frontend.show_message("Copied to the clipboard"),
+            Err(err) => self.frontend.show_message(&err.message)
+        }
+    }
+
+    /// Write the hex bytes on the clipboard into memory at the byte selected in the memory view
+    fn paste(&mut self){
+        match self.frontend.paste() {
+            Ok(writes) => {
+                if let Some((start, _)) = writes.first(){
+                    self.frontend.show_message(&format!("Pasted {} bytes at {}", writes.len(), start));
+                }
+                self.write_memory(&writes);
+            },
+            Err(err) => self.frontend.show_message(&err.message)
+        }
+    }
+
+    /// Overwrite bytes of RAM, edited in the memory view, as one edit that stepping back undoes
+    fn write_memory(&mut self, writes: &[(Addr, u8)]){
+        if writes.is_empty(){
+            return;
+        }
+        self.interrupt_replay("memory edits");
+        // saved first so that stepping back undoes the edit
+        self.history.push(&self.chip8, Granularity::Step);
+        for (addr, value) in writes{
+            if let Err(err) = self.chip8.write_memory(addr.index(), &[*value]){
+                self.frontend.show_message(&err.message);
+            }
+        }
+    }
+
+    /// Save the replay being recorded, if any
+    pub fn stop_replay_recording(&mut self){
+        let Some((replay, path)) = self.replays.take_recording() else {return};
+        match replay.save(&path) {
+            Ok(()) => self.frontend.show_message(&format!("Saved a replay of {} frames to {}", replay.frames(), path.display())),
+            Err(err) => eprintln!("Could not write the replay to {}: {}", path.display(), err)
+        }
+    }
+
+    /// Play `replay` back in place of the keyboard. Call it straight after `load_rom`, or `resume` for a replay
+    /// recorded from a saved session; it's refused if it started from a different machine state.
+    /// The replay's seed, speed, timing, extensions and quirks replace the current ones
+    pub fn play_replay(&mut self, replay: Replay) -> Result<(), Diagnostic>{
+        if replay.state.is_some_and(|state| state != replay::state_hash(&self.chip8)){
+            return Err(Diagnostic::error("The replay starts from a different machine state. \
+                If it was recorded after resuming a session, resume the session saved with it"));
+        }
+        self.set_timing(replay.timing);
+        replay.apply(&mut self.chip8);
+        self.replays.play(replay);
+        Ok(())
+    }
+
+    pub fn is_playing_replay(&self) -> bool{
+        self.replays.is_playing()
+    }
+
+    /// Stop recording or playing a replay, which can't include `actions`
+    fn interrupt_replay(&mut self, actions: &str){
+        if self.replays.is_recording(){
+            self.stop_replay_recording();
+            self.frontend.show_message(&format!("Stopped recording the replay, {actions} can't be replayed"));
+        }
+        if self.replays.stop_playing(){
+            self.frontend.show_message(&format!("Stopped playing the replay, {actions} can't be replayed"));
+        }
+    }
+
+    /// Hold down a CHIP-8 key until `clear_keys`, unless a replay is playing. Keys past F are ignored
+    pub fn press_key(&mut self, key: u8){
+        if self.replays.is_playing() || key >= KEY_COUNT{
+            return;
+        }
+        self.replays.record_event(KeyEvent::Press(key));
+        self.chip8.press_key(key);
+    }
+
+    /// Release every CHIP-8 key, unless a replay is playing
+    pub fn clear_keys(&mut self){
+        if self.replays.is_playing(){
+            return;
+        }
+        // releasing keys that are already up changes nothing, so it needn't be replayed
+        if self.chip8.keys_down() != 0{
+            self.replays.record_event(KeyEvent::Clear);
+        }
+        self.chip8.clear_keys();
+    }
+
+    /// At the start of each frame, replay the key events before it, or count it in the recording
+    fn replay_frame(&mut self){
+        if self.replays.next_frame(&mut self.chip8){
+            self.frontend.show_message("The replay has finished");
+        }
+    }
+
+    /// Save a crash bundle to a new `chip8-crash-<time>` directory, see `write_crash_bundle`
+    fn save_crash_bundle(&mut self){
+        let dir = PathBuf::from(format!("chip8-crash-{}", UsageStats::now()));
+        match self.write_crash_bundle(&dir) {
+            Ok(()) => self.frontend.show_message(&format!("Saved a crash bundle to {}", dir.display())),
+            Err(err) => {
+                eprintln!("Could not write the crash bundle to {}: {}", dir.display(), err);
+                self.frontend.show_message(&format!("Could not write the crash bundle: {err}"));
+            }
+        }
+    }
+
+    /// Write everything needed to look into a crash to `dir`: what went wrong and where in `crash.txt`,
+    /// the ROM, a session that resumes the machine as it is, the breakpoints and the trace if there is one
+    pub fn write_crash_bundle(&self, dir: &std::path::Path) -> std::io::Result<()>{
+        std::fs::create_dir_all(dir)?;
+        let rom = std::path::absolute(dir.join("rom.ch8"))?;
+        std::fs::write(&rom, &self.rom)?;
+        let instruction = emulator::get_instruction(&self.chip8.memory, &self.chip8.registers);
+        let mut report = format!("chip8 {}\n", env!("CARGO_PKG_VERSION"));
+        report += &format!("pc: {:#05x} {}\n", self.chip8.pc(), self.symbols.instruction(instruction));
+        report += &format!("cpu: {}\n", self.chip8.state());
+        if let Some(message) = &self.panic{
+            report += &format!("panic: {message}\n");
+        }
+        report += &crate::panels::DebugPanels::register_lines(&self.chip8, self.settings.key_profile).join("\n");
+        std::fs::write(dir.join("crash.txt"), report + "\n")?;
+        self.session(rom).save(&dir.join(Session::DEFAULT_PATH))?;
+        std::fs::write(dir.join(Self::DEFAULT_DEBUG_SCRIPT), self.debug_script().to_string())?;
+        if let Some(trace) = &self.trace{
+            trace.dump(&dir.join(Self::DEFAULT_TRACE_FILE), &self.symbols)?;
+        }
+        Ok(())
+    }
+
+    fn export_debug_script(&self){
+        let path = self.debug_script_path.clone().unwrap_or(PathBuf::from(Self::DEFAULT_DEBUG_SCRIPT));
+        if let Err(err) = std::fs::write(&path, self.debug_script().to_string()){
+            eprintln!("Could not write debugger script to {}: {}", path.display(), err);
+        }
+    }
+
+    /// Draw every frame to `renderer` as well as the window, until it reports itself closed
+    pub fn add_mirror(&mut self, renderer: Box<dyn Renderer>){
+        self.frontend.add_mirror(renderer);
+    }
+
+    /// Take key presses and commands from `source` as well as the window
+    pub fn add_input_source(&mut self, source: Box<dyn InputSource>){
+        self.frontend.add_source(source);
+    }
+}
+
+
+#[cfg(test)]
+fn test_driver(mode: EmulatorMode, rom: &[u8]) -> (Chip8Driver, std::rc::Rc<std::cell::RefCell<crate::test_frontend::Recording>>){
+    let (frontend, recording) = crate::test_frontend::TestFrontend::new();
+    let mut driver = Chip8Driver::with_frontend(mode, None, Box::new(frontend));
+    driver.load_rom(rom).unwrap();
+    (driver, recording)
+}
+
+#[test]
+fn test_driver_rom_inspection(){
+    // vsync; jp 0x200
+    let (driver, recording) = test_driver(EmulatorMode::Running, &[0x00, 0xf1, 0x12, 0x00]);
+    assert!(driver.chip8.has_extension(Extension::VSync));
+    assert_eq!(recording.borrow().messages.last().unwrap(), "Switched on the vsync extension, the ROM uses it at 0x200");
+    // hires; jp 0x202
+    let (_, recording) = test_driver(EmulatorMode::Running, &[0x00, 0xff, 0x12, 0x02]);
+    assert!(recording.borrow().messages.last().unwrap().starts_with("This ROM needs SUPER-CHIP"));
+}
+
+#[test]
+fn test_driver_mirrors(){
+    // ld v2 7; jp 0x202
+    let (mut driver, recording) = test_driver(EmulatorMode::Running, &[0x62, 0x07, 0x12, 0x02]);
+    let (mirror, mirrored) = crate::test_frontend::TestFrontend::new();
+    let (source, sourced) = crate::test_frontend::TestFrontend::new();
+    driver.add_mirror(Box::new(mirror));
+    driver.add_input_source(Box::new(source));
+    sourced.borrow_mut().inputs.push_back(vec![KeyInput::TogglePause]);
+    assert!(!driver.tick(FRAME_LENGTH));
+    assert!(matches!(driver.mode, EmulatorMode::Paused));
+    assert_eq!(mirrored.borrow().draws.len(), 1);
+    assert!(sourced.borrow().draws.is_empty());
+    driver.frontend.show_message("hello");
+    assert_eq!(mirrored.borrow().messages, vec!["hello"]);
+
+    // a closed mirror is dropped, but only the main frontend closing stops the emulator
+    mirrored.borrow_mut().closed = true;
+    assert!(!driver.tick(Duration::ZERO));
+    assert!(!driver.tick(Duration::ZERO));
+    assert_eq!(mirrored.borrow().draws.len(), 2);
+    assert_eq!(recording.borrow().draws.len(), 3);
+}
+
+#[test]
+fn test_driver_step(){
+    // ld v2 7; add v2 1; jp 0x204
+    let (mut driver, recording) = test_driver(EmulatorMode::Paused, &[0x62, 0x07, 0x72, 0x01, 0x12, 0x04]);
+    assert!(recording.borrow().debug_mode);
+    recording.borrow_mut().inputs.extend([vec![KeyInput::Step, KeyInput::Step], vec![KeyInput::StepBack]]);
+    assert!(!driver.tick(Duration::ZERO));
+    assert_eq!(driver.chip8.registers.vn[2], 8);
+    assert!(!driver.tick(Duration::ZERO));
+    assert_eq!(driver.chip8.pc(), 0x202);
+    let pcs: Vec<_> = recording.borrow().draws.iter()
+        .filter(|draw| draw.show_current_instruction)
+        .map(|draw| draw.pc)
+        .collect();
+    assert_eq!(pcs, vec![0x202, 0x204, 0x202]);
+}
+
+#[test]
+fn test_driver_step_back_quirks(){
+    // ld v2 7; jp 0x202
+    let (mut driver, recording) = test_driver(EmulatorMode::Paused, &[0x62, 0x07, 0x12, 0x02]);
+    recording.borrow_mut().inputs.extend([vec![KeyInput::Step, KeyInput::ToggleQuirk(Quirk::DisplayWait)], vec![KeyInput::StepBack]]);
+    driver.tick(Duration::ZERO);
+    assert!(driver.chip8.quirks().get(Quirk::DisplayWait));
+    driver.tick(Duration::ZERO);
+    // the quirk goes back with the step it was switched on after
+    assert_eq!((driver.chip8.pc(), driver.chip8.quirks().get(Quirk::DisplayWait)), (0x200, false));
+    assert_eq!(driver.settings.quirks.get(&Quirk::DisplayWait), Some(&false));
+    recording.borrow_mut().inputs.extend([vec![KeyInput::StepFrame], vec![KeyInput::Step], vec![KeyInput::StepBack], vec![KeyInput::StepBack]]);
+    for _ in 0..3{
+        driver.tick(Duration::ZERO);
+    }
+    assert_ne!(recording.borrow().messages.last().unwrap(), "Stepped back a whole frame");
+    driver.tick(Duration::ZERO);
+    assert_eq!(driver.chip8.pc(), 0x200);
+    assert_eq!(recording.borrow().messages.last().unwrap(), "Stepped back a whole frame");
+}
+
+#[test]
+fn test_driver_step_over_and_reset(){
+    // call 0x206; add v2 1; jp 0x204; ld v2 7; add v2 1; ret
+    let (mut driver, recording) = test_driver(EmulatorMode::Paused, &[0x22, 0x06, 0x72, 0x01, 0x12, 0x04, 0x62, 0x07, 0x72, 0x01, 0x00, 0xee]);
+    recording.borrow_mut().inputs.extend([vec![KeyInput::StepOver], vec![KeyInput::StepOver], vec![KeyInput::Reset], vec![KeyInput::StepBack]]);
+    assert!(!driver.tick(Duration::ZERO));
+    assert_eq!((driver.chip8.pc(), driver.chip8.registers.vn[2]), (0x202, 8));
+    assert!(!driver.tick(Duration::ZERO));
+    assert_eq!((driver.chip8.pc(), driver.chip8.registers.vn[2]), (0x204, 9));
+    assert!(!driver.tick(Duration::ZERO));
+    assert_eq!((driver.chip8.pc(), driver.chip8.registers.vn[2]), (0x200, 0));
+    assert_eq!(driver.chip8.read_memory(0x200, 2).unwrap(), &[0x22, 0x06]);
+    assert!(!driver.tick(Duration::ZERO));
+    assert_eq!(driver.chip8.pc(), 0x204);
+
+    // a soft reset keeps a patched ROM, a full reset restores it
+    recording.borrow_mut().inputs.extend([vec![KeyInput::WriteMemory(crate::Addr(0x203), 0x05)], vec![KeyInput::SoftReset], vec![KeyInput::Reset]]);
+    assert!(!driver.tick(Duration::ZERO));
+    assert!(!driver.tick(Duration::ZERO));
+    assert_eq!((driver.chip8.pc(), driver.chip8.registers.vn[2]), (0x200, 0));
+    assert_eq!(driver.chip8.read_memory(0x202, 2).unwrap(), &[0x72, 0x05]);
+    assert!(!driver.tick(Duration::ZERO));
+    assert_eq!(driver.chip8.read_memory(0x202, 2).unwrap(), &[0x72, 0x01]);
+
+    // step out of the subroutine, then from the top level there is nothing to step out of
+    recording.borrow_mut().inputs.extend([vec![KeyInput::Step], vec![KeyInput::Step], vec![KeyInput::StepOut], vec![KeyInput::StepOut]]);
+    for _ in 0..3{
+        assert!(!driver.tick(Duration::ZERO));
+    }
+    assert_eq!((driver.chip8.pc(), driver.chip8.registers.vn[2]), (0x202, 8));
+    assert!(!driver.tick(Duration::ZERO));
+    assert_eq!(recording.borrow().messages.last().unwrap(), "Not in a subroutine, so there is nothing to step out of");
+    driver.set_step_count(3);
+    recording.borrow_mut().inputs.extend([vec![KeyInput::Step], vec![KeyInput::StepBack]]);
+    assert!(!driver.tick(Duration::ZERO));
+    assert_eq!((driver.chip8.pc(), driver.chip8.registers.vn[2]), (0x204, 9));
+    assert!(!driver.tick(Duration::ZERO));
+    assert_eq!(driver.chip8.pc(), 0x202);
+}
+
+#[test]
+fn test_driver_write_memory(){
+    let (mut driver, recording) = test_driver(EmulatorMode::Paused, &[0x62, 0x07]);
+    recording.borrow_mut().inputs.extend([vec![KeyInput::WriteMemory(crate::Addr(0x201), 0x2a)], vec![KeyInput::StepBack]]);
+    driver.tick(Duration::ZERO);
+    assert_eq!(driver.chip8.read_memory(0x200, 2).unwrap(), [0x62, 0x2a]);
+    // stepping back undoes the edit
+    driver.tick(Duration::ZERO);
+    assert_eq!(driver.chip8.read_memory(0x200, 2).unwrap(), [0x62, 0x07]);
+}
+
+#[test]
+fn test_driver_copy_paste(){
+    // ld v0 0xa; ld f v0; drw v1 v1 5
+    let (mut driver, recording) = test_driver(EmulatorMode::Paused, &[0x60, 0x0a, 0xf0, 0x29, 0xd1, 0x15]);
+    recording.borrow_mut().inputs.push_back(vec![KeyInput::StepFrame, KeyInput::Copy]);
+    driver.tick(Duration::ZERO);
+    assert_eq!(recording.borrow().clipboard.text, Screenshot::of(driver.chip8.frame_buffer()).to_string());
+    assert_eq!(recording.borrow().messages.last().unwrap(), "Copied to the clipboard");
+
+    recording.borrow_mut().inputs.push_back(vec![KeyInput::Paste]);
+    driver.tick(Duration::ZERO);
+    assert_eq!(recording.borrow().messages.last().unwrap(), "No byte is selected");
+
+    recording.borrow_mut().clipboard.text = "0x12 0x34\n56".to_string();
+    recording.borrow_mut().paste_at = Some(crate::Addr(0x201));
+    recording.borrow_mut().inputs.extend([vec![KeyInput::Paste], vec![KeyInput::StepBack]]);
+    driver.tick(Duration::ZERO);
+    assert_eq!(driver.chip8.read_memory(0x200, 4).unwrap(), [0x60, 0x12, 0x34, 0x56]);
+    assert_eq!(recording.borrow().messages.last().unwrap(), "Pasted 3 bytes at 0x201");
+    // stepping back undoes the whole paste
+    driver.tick(Duration::ZERO);
+    assert_eq!(driver.chip8.read_memory(0x200, 4).unwrap(), [0x60, 0x0a, 0xf0, 0x29]);
+
+    driver.mode = EmulatorMode::Running;
+    recording.borrow_mut().inputs.push_back(vec![KeyInput::Paste]);
+    driver.tick(Duration::ZERO);
+    assert_eq!(recording.borrow().messages.last().unwrap(), "Pause to paste into memory");
+}
+
+#[test]
+fn test_driver_export_rom(){
+    let (mut driver, recording) = test_driver(EmulatorMode::Paused, &[0x62, 0x07, 0x12, 0x00]);
+    let path = std::env::temp_dir().join(format!("chip8-test-{}.ch8", std::process::id()));
+    driver.set_patched_rom_path(path.clone());
+    recording.borrow_mut().inputs.push_back(vec![KeyInput::WriteMemory(crate::Addr(0x201), 0x09), KeyInput::ExportRom]);
+    driver.tick(Duration::ZERO);
+    assert_eq!(std::fs::read(&path).unwrap(), [0x62, 0x09, 0x12, 0x00]);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_driver_screenshot(){
+    // ld v0 0xa; ld f v0; drw v1 v1 5
+    let (mut driver, recording) = test_driver(EmulatorMode::Paused, &[0x60, 0x0a, 0xf0, 0x29, 0xd1, 0x15]);
+    let path = std::env::temp_dir().join(format!("chip8-test-{}.png", std::process::id()));
+    driver.set_screenshot_path(path.clone());
+    recording.borrow_mut().inputs.push_back(vec![KeyInput::StepFrame, KeyInput::Screenshot]);
+    driver.tick(Duration::ZERO);
+    assert!(std::fs::read(&path).unwrap().starts_with(b"\x89PNG"));
+    let pbm = std::fs::read_to_string(path.with_extension("pbm")).unwrap();
+    assert_eq!(pbm, driver.screenshot().to_pbm());
+    // the top of the 'A' sprite, 0xf0, drawn at (0, 0)
+    assert!(pbm.lines().nth(2).unwrap().starts_with("1 1 1 1 0"));
+    assert_eq!(recording.borrow().messages.len(), 1);
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(path.with_extension("pbm")).unwrap();
+}
+
+#[test]
+fn test_driver_recording(){
+    // ld v0 0xa; ld f v0; drw v1 v1 5; jp 0x206
+    let (mut driver, recording) = test_driver(EmulatorMode::Paused, &[0x60, 0x0a, 0xf0, 0x29, 0xd1, 0x15, 0x12, 0x06]);
+    let path = std::env::temp_dir().join(format!("chip8-test-{}.gif", std::process::id()));
+    driver.set_recording_path(path.clone());
+    recording.borrow_mut().inputs.push_back(vec![KeyInput::ToggleRecording, KeyInput::StepFrame, KeyInput::StepFrame]);
+    driver.tick(Duration::ZERO);
+    assert!(driver.is_recording());
+    assert_eq!(driver.recorder.recording().map(crate::recording::Recording::len), Some(2));
+    recording.borrow_mut().inputs.push_back(vec![KeyInput::ToggleRecording]);
+    driver.tick(Duration::ZERO);
+    assert!(!driver.is_recording());
+    assert!(std::fs::read(&path).unwrap().starts_with(b"GIF89a"));
+    assert_eq!(recording.borrow().messages.last().unwrap(), &format!("Saved 2 frames to {}", path.display()));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_driver_speed(){
+    let (mut driver, recording) = test_driver(EmulatorMode::Running, &[0x12, 0x00]);
+    driver.chip8.clock_speed(600);
+    recording.borrow_mut().inputs.extend([vec![KeyInput::SpeedUp], vec![KeyInput::SpeedDown, KeyInput::SpeedDown], vec![KeyInput::Turbo]]);
+    driver.tick(FRAME_LENGTH);
+    assert_eq!(driver.chip8.speed(), 720);
+    assert_eq!(recording.borrow().messages.last().unwrap(), "Speed: 720 instructions a second");
+    driver.tick(FRAME_LENGTH);
+    assert_eq!(driver.chip8.speed(), 420);
+    driver.tick(FRAME_LENGTH);
+    let timings = driver.timing_log().recent(3);
+    assert_eq!(timings.iter().map(|timing| (timing.frames_run, timing.instructions)).collect::<Vec<_>>(),
+        vec![(1, 12), (1, 7), (Chip8Driver::TURBO_FRAMES, 4 * 7)]);
+
+    for _ in 0..100{
+        driver.change_speed(false);
+    }
+    assert_eq!(driver.chip8.speed(), Chip8Driver::MIN_SPEED);
+    driver.set_timing(TimingMode::Original);
+    driver.change_speed(true);
+    assert_eq!(driver.chip8.speed(), Chip8Driver::MIN_SPEED);
+}
+
+#[test]
+fn test_driver_vip_timing(){
+    // cls; cls; jp 0x200
+    let (mut driver, recording) = test_driver(EmulatorMode::Running, &[0x00, 0xe0, 0x00, 0xe0, 0x12, 0x00]);
+    driver.set_timing(TimingMode::Vip);
+    // the second clear runs past the end of the frame, and the next frame pays for it
+    let mut runs = vec![];
+    for _ in 0..3{
+        driver.tick(FRAME_LENGTH);
+        runs.push(driver.stats.instructions_run());
+    }
+    assert_eq!(runs, vec![2, 5, 7]);
+    driver.change_speed(true);
+    assert_eq!(recording.borrow().messages.last().unwrap(), "--timing vip sets its own speed");
+}
+
+#[test]
+fn test_driver_key_profile(){
+    let (mut driver, recording) = test_driver(EmulatorMode::Running, &[0x12, 0x00]);
+    recording.borrow_mut().inputs.extend([vec![KeyInput::NextKeyProfile], vec![KeyInput::Chip8Key(0xc), KeyInput::Chip8Key(0x4)]]);
+    driver.tick(FRAME_LENGTH);
+    assert_eq!(driver.settings.key_profile, KeyProfile::TwoPlayer);
+    assert_eq!(recording.borrow().messages.last().unwrap(), "Keys: two-player (player 2's C D E F on the arrow keys)");
+    driver.tick(FRAME_LENGTH);
+    let registers = crate::panels::DebugPanels::register_lines(&driver.chip8, driver.settings.key_profile).join("\n");
+    assert!(registers.contains("keys: P1: 4  P2: c"), "{registers}");
+    driver.set_key_profile(KeyProfile::Standard);
+    assert_eq!(driver.settings.key_profile, KeyProfile::Standard);
+}
+
+#[test]
+fn test_driver_key_remap(){
+    let (mut driver, recording) = test_driver(EmulatorMode::Running, &[0x12, 0x00]);
+    let path = std::env::temp_dir().join(format!("chip8-remap-{}.cfg", std::process::id()));
+    driver.set_settings(RomSettings::parse("remap_6 = Y"), path.clone());
+    assert_eq!(recording.borrow().key_remap.get(&6).unwrap(), "Y");
+    recording.borrow_mut().inputs.extend([vec![KeyInput::RemapKeys], vec![KeyInput::RemapKey(0x5, Some("UP"))]]);
+    driver.tick(FRAME_LENGTH);
+    assert!(recording.borrow().remapping);
+    driver.tick(FRAME_LENGTH);
+    assert_eq!(recording.borrow().messages.last().unwrap(), "CHIP-8 key 5 is also on UP");
+    assert_eq!(RomSettings::parse(&std::fs::read_to_string(&path).unwrap()).key_remap.get(&5).unwrap(), "UP");
+    driver.remap_key(0x6, None);
+    assert_eq!(recording.borrow().key_remap, std::collections::BTreeMap::from([(5, "UP".to_string())]));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_driver_virtual_keypad(){
+    let (mut driver, recording) = test_driver(EmulatorMode::Running, &[0x12, 0x00]);
+    recording.borrow_mut().inputs.extend([vec![KeyInput::ToggleVirtualKeypad], vec![KeyInput::TogglePause], vec![KeyInput::ToggleVirtualKeypad]]);
+    driver.tick(FRAME_LENGTH);
+    assert!(recording.borrow().show_virtual_keypad);
+    driver.tick(FRAME_LENGTH);
+    driver.tick(FRAME_LENGTH);
+    assert!(!recording.borrow().show_virtual_keypad, "the keypad can be hidden while paused");
+}
+
+#[test]
+fn test_driver_timing_log(){
+    let (mut driver, _) = test_driver(EmulatorMode::Running, &[0x12, 0x00]);
+    driver.set_timing_log_path(PathBuf::from("unused.csv"));
+    driver.tick(crate::timing::FRAME_LENGTH * 2);
+    driver.tick(crate::timing::FRAME_LENGTH);
+    let timings = driver.timing_log().recent(TimingLog::RECENT);
+    assert_eq!(timings.iter().map(|timing| (timing.interval, timing.frames_run)).collect::<Vec<_>>(),
+        vec![(crate::timing::FRAME_LENGTH * 2, 2), (crate::timing::FRAME_LENGTH, 1)]);
+    assert!(timings[0].start <= timings[1].start);
+}
+
+#[test]
+fn test_driver_coverage(){
+    let dir = std::env::temp_dir().join(format!("chip8-coverage-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // jp 0x200
+    std::fs::write(dir.join("b.ch8"), [0x12, 0x00]).unwrap();
+    // ld i 0x204; ld v0 [i]; jp 0x202
+    let (mut driver, _) = test_driver(EmulatorMode::Running, &[0xa2, 0x04, 0xf0, 0x65, 0x12, 0x02]);
+    driver.set_coverage_path(dir.join("coverage.txt"));
+    driver.tick(FRAME_LENGTH);
+    // the first ROM's coverage is saved on switching to the next
+    driver.open_rom(&dir.join("b.ch8"));
+    let saved = crate::coverage::Coverage::parse(&std::fs::read_to_string(dir.join("coverage.txt")).unwrap()).unwrap();
+    assert_eq!(saved.instruction_starts().collect::<Vec<_>>(), [0x200, 0x202, 0x204]);
+    // the jump is read as data too
+    assert_eq!(saved.get(0x204), crate::coverage::EXECUTED | crate::coverage::INSTRUCTION_START | crate::coverage::READ);
+    assert_eq!(driver.chip8.coverage().unwrap().counts(), (0, 0, 0));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_driver_replay(){
+    // rnd v0 0xff; wait for a key into v1; rnd v2 0xff; add v3 1; jp 0x206
+    let rom = [0xc0, 0xff, 0xf1, 0x0a, 0xc2, 0xff, 0x73, 0x01, 0x12, 0x06];
+    let path = std::env::temp_dir().join(format!("chip8-test-{}.{}", std::process::id(), Replay::EXTENSION));
+    let (mut driver, recording) = test_driver(EmulatorMode::Running, &rom);
+    driver.record_replay(path.clone());
+    driver.tick(crate::timing::FRAME_LENGTH);
+    recording.borrow_mut().inputs.push_back(vec![KeyInput::Chip8Key(0xb)]);
+    driver.tick(crate::timing::FRAME_LENGTH * 2);
+    driver.tick(crate::timing::FRAME_LENGTH);
+    driver.stop_replay_recording();
+    let recorded = driver.chip8.registers.clone();
+
+    let mut replay = Replay::load(&path).unwrap();
+    assert_eq!(replay.frames(), 4);
+    assert_eq!(replay.random(), [recorded.vn[0], recorded.vn[2]]);
+    // the recorded random bytes are played back, whatever the generator would give
+    replay.seed = replay.seed.wrapping_add(1);
+    let (mut driver, recording) = test_driver(EmulatorMode::Running, &rom);
+    driver.play_replay(replay).unwrap();
+    // keys pressed during playback are ignored
+    recording.borrow_mut().inputs.push_back(vec![KeyInput::Chip8Key(0x3)]);
+    for _ in 0..4{
+        driver.tick(crate::timing::FRAME_LENGTH);
+    }
+    assert!(!driver.is_playing_replay());
+    assert_eq!(driver.chip8.registers.vn, recorded.vn);
+    assert_eq!(driver.chip8.registers.vn[1], 0xb);
+    assert_eq!(recording.borrow().messages.last().unwrap(), "The replay has finished");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_driver_replay_from_session(){
+    // add v0 1; ld v1 k; jp 0x200
+    let rom = [0x70, 0x01, 0xf1, 0x0a, 0x12, 0x00];
+    let path = std::env::temp_dir().join(format!("chip8-test-session-{}.{}", std::process::id(), Replay::EXTENSION));
+    let (mut driver, recording) = test_driver(EmulatorMode::Running, &rom);
+    recording.borrow_mut().inputs.extend([vec![KeyInput::Chip8Key(0x2)], vec![]]);
+    for _ in 0..3{
+        driver.tick(crate::timing::FRAME_LENGTH);
+    }
+    let session = driver.session(PathBuf::from("test.ch8"));
+
+    let (mut driver, recording) = test_driver(EmulatorMode::Running, &rom);
+    driver.resume(&session);
+    driver.record_replay(path.clone());
+    recording.borrow_mut().inputs.extend([vec![KeyInput::Chip8Key(0x7)], vec![]]);
+    for _ in 0..3{
+        driver.tick(crate::timing::FRAME_LENGTH);
+    }
+    driver.stop_replay_recording();
+    let recorded = driver.chip8.registers.vn;
+    assert_eq!(recorded[1], 0x7);
+
+    let replay = Replay::load(&path).unwrap();
+    let (mut driver, _) = test_driver(EmulatorMode::Running, &rom);
+    let error = driver.play_replay(replay.clone()).unwrap_err();
+    assert!(error.message.starts_with("The replay starts from a different machine state"), "{}", error.message);
+    driver.resume(&Session::parse(&session.to_string()).unwrap());
+    driver.play_replay(replay).unwrap();
+    for _ in 0..3{
+        driver.tick(crate::timing::FRAME_LENGTH);
+    }
+    assert_eq!(driver.chip8.registers.vn, recorded);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_driver_quirks(){
+    let path = std::env::temp_dir().join(format!("chip8-test-quirks-{}.cfg", std::process::id()));
+    let (mut driver, recording) = test_driver(EmulatorMode::Paused, &[0x12, 0x00]);
+    driver.set_settings(RomSettings::default(), path.clone());
+    driver.set_timing(TimingMode::Original);
+    assert!(driver.chip8.quirks().get(Quirk::DisplayWait));
+    recording.borrow_mut().inputs.extend([vec![KeyInput::ToggleQuirk(Quirk::DisplayWait), KeyInput::ToggleQuirk(Quirk::WaitForRelease)]]);
+    driver.tick(FRAME_LENGTH);
+    assert_eq!(driver.chip8.quirks().enabled().collect::<Vec<_>>(), vec![Quirk::WaitForRelease]);
+    assert_eq!(recording.borrow().messages.last().unwrap(), "Quirk wait-for-release: on");
+    // the ROM's settings remember the change, and it outlasts the timing mode
+    let saved = RomSettings::parse(&std::fs::read_to_string(&path).unwrap());
+    assert_eq!(saved.quirks, std::collections::BTreeMap::from([(Quirk::DisplayWait, false), (Quirk::WaitForRelease, true)]));
+    driver.set_timing(TimingMode::Original);
+    assert!(!driver.chip8.quirks().get(Quirk::DisplayWait));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_driver_crash_bundle(){
+    let dir = std::env::temp_dir().join(format!("chip8-test-crash-{}", std::process::id()));
+    let (mut driver, recording) = test_driver(EmulatorMode::Running, &[0x62, 0x07, 0x12, 0x02]);
+    driver.tick(FRAME_LENGTH);
+    driver.panic = driver.chip8.catch_panic(|_| panic!("index out of bounds"));
+    driver.report_fault();
+    assert!(matches!(driver.mode, EmulatorMode::Paused));
+    assert!(recording.borrow().messages.last().unwrap().contains("index out of bounds\n[F12] saves a crash bundle"));
+
+    driver.write_crash_bundle(&dir).unwrap();
+    let report = std::fs::read_to_string(dir.join("crash.txt")).unwrap();
+    assert!(report.contains("pc: 0x202 JP 0x202\n") && report.contains("panic: index out of bounds\n"), "{report}");
+    assert_eq!(std::fs::read(dir.join("rom.ch8")).unwrap(), [0x62, 0x07, 0x12, 0x02]);
+    let session = Session::load(&dir.join(Session::DEFAULT_PATH)).unwrap();
+    assert_eq!(session.rom, std::path::absolute(dir.join("rom.ch8")).unwrap());
+    let mut chip8 = Chip8::init(None);
+    session.restore(&mut chip8);
+    assert_eq!((chip8.registers.vn[2], chip8.pc()), (7, 0x202));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_driver_stats(){
+    let path = std::env::temp_dir().join(format!("chip8-test-{}.cfg", std::process::id()));
+    let (mut driver, recording) = test_driver(EmulatorMode::Running, &[0x12, 0x00]);
+    let stats = UsageStats{ launches: 1, play_time: std::time::Duration::from_secs(60), last_played: Some(UsageStats::now()) };
+    driver.set_settings(RomSettings{ stats, ..RomSettings::default() }, path.clone());
+    driver.start_stats();
+    assert!(recording.borrow().messages[0].starts_with("Last played less than an hour ago, 1m total"));
+    driver.tick(crate::timing::FRAME_LENGTH * 3);
+    driver.save_stats();
+    let saved = RomSettings::parse(&std::fs::read_to_string(&path).unwrap());
+    assert_eq!(saved.stats.launches, 2);
+    assert!(driver.stats().play_time > stats.play_time);
+    assert_eq!(driver.stats.take_play_time(), std::time::Duration::ZERO);
+
+    driver.set_track_stats(false);
+    driver.start_stats();
+    assert_eq!(RomSettings::parse(&std::fs::read_to_string(&path).unwrap()).stats.launches, 2);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_driver_open_recent(){
+    let dir = std::env::temp_dir().join(format!("chip8-recent-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (pong, state) = (dir.join("pong.ch8"), dir.join("recent"));
+    std::fs::write(&pong, [0x60, 0x2a, 0x12, 0x02]).unwrap();
+    std::fs::write(RomSettings::path_for(&pong), "quirk_wait_for_release = true\n").unwrap();
+    let (mut driver, recording) = test_driver(EmulatorMode::Running, &[0x12, 0x00]);
+    driver.change_quirk(Quirk::DisplayWait, true);
+    driver.set_recent_roms(RecentRoms::parse(&format!("{}\n{}\n", dir.join("current.ch8").display(), pong.display())), state.clone());
+    recording.borrow_mut().inputs.extend([vec![KeyInput::OpenRecent(2)], vec![KeyInput::OpenRecent(1)]]);
+    driver.tick(FRAME_LENGTH);
+    assert!(recording.borrow().messages.last().unwrap().starts_with("No recent ROM 2. Recent ROMs:\n1: "));
+    driver.tick(FRAME_LENGTH);
+    assert_eq!(driver.chip8.registers.vn[0], 0x2a);
+    assert!(driver.chip8.quirks().get(Quirk::WaitForRelease));
+    assert!(!driver.chip8.quirks().get(Quirk::DisplayWait));
+    assert_eq!(RecentRoms::load(&state).paths()[0], pong);
+    // vsync; jp 0x200
+    let vsync = dir.join("vsync.ch8");
+    std::fs::write(&vsync, [0x00, 0xf1, 0x12, 0x00]).unwrap();
+    driver.open_rom(&vsync);
+    assert!(driver.chip8.has_extension(Extension::VSync));
+    driver.open_rom(&pong);
+    assert!(!driver.chip8.has_extension(Extension::VSync));
+    driver.open_rom(&dir.join("missing.ch8"));
+    assert!(recording.borrow().messages.last().unwrap().starts_with("Could not open"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_driver_load_warning(){
+    let (mut driver, recording) = test_driver(EmulatorMode::Paused, &[0x62, 0x07, 0x12, 0x00]);
+    assert!(recording.borrow().messages.is_empty());
+    driver.load_rom(b"PK\x03\x04\x14\x00\x00\x00").unwrap();
+    assert_eq!(recording.borrow().messages, vec!["This looks like a zip archive, not a CHIP-8 ROM".to_string()]);
+}
+
+#[test]
+fn test_driver_memory_snapshot(){
+    // ld i 0x300; ld v0 5; ld [i] v0
+    let (mut driver, recording) = test_driver(EmulatorMode::Paused, &[0xa3, 0x00, 0x60, 0x05, 0xf0, 0x55]);
+    driver.set_diff_steps(2);
+    recording.borrow_mut().inputs.extend([vec![KeyInput::Step, KeyInput::Step], vec![KeyInput::Step]]);
+    driver.tick(Duration::ZERO);
+    assert_eq!(recording.borrow().snapshot.as_ref().map(|ram| ram[0x300]), Some(0));
+    driver.tick(Duration::ZERO);
+    assert_eq!(driver.chip8.read_memory(0x300, 1).unwrap(), [5]);
+    // the snapshot is from before the second step, when 0x300 was still clear
+    let snapshot = recording.borrow().snapshot.clone().unwrap();
+    assert_eq!(snapshot[0x300], 0);
+    assert_eq!(&snapshot[0x200..0x206], driver.chip8.read_memory(0x200, 6).unwrap());
+}
+
+#[test]
+fn test_driver_collisions(){
+    // drw v0 v0 1, four times, with I pointing at the top row of the 0 glyph
+    let (mut driver, recording) = test_driver(EmulatorMode::Paused, &[0xd0, 0x01, 0xd0, 0x01, 0xd0, 0x01, 0xd0, 0x01]);
+    recording.borrow_mut().inputs.extend([
+        vec![KeyInput::Step, KeyInput::Step],
+        vec![KeyInput::ToggleCollisions, KeyInput::Step, KeyInput::Step]
+    ]);
+    // the second draw collides, but collisions aren't shown yet
+    driver.tick(Duration::ZERO);
+    assert!(recording.borrow().collisions.is_empty());
+    driver.tick(Duration::ZERO);
+    let collisions = &recording.borrow().collisions;
+    assert_eq!(collisions.len(), 1);
+    assert_eq!(collisions[0].pc, 0x206);
+    assert_eq!(collisions[0].pixels, [(0, 0), (1, 0), (2, 0), (3, 0)]);
+}
+
+#[test]
+fn test_driver_session(){
+    // ld v0 1; add v0 1; jp 0x202
+    let rom = [0x60, 0x01, 0x70, 0x01, 0x12, 0x02];
+    let (mut driver, recording) = test_driver(EmulatorMode::Paused, &rom);
+    recording.borrow_mut().inputs.extend([vec![KeyInput::Step], vec![KeyInput::Step]]);
+    driver.tick(Duration::ZERO);
+    driver.tick(Duration::ZERO);
+    driver.breakpoints().toggle(crate::Addr::new(0x204).unwrap());
+    let session = driver.session(PathBuf::from("test.ch8"));
+    assert!(session.paused && session.debug_view);
+
+    let (mut resumed, recording) = test_driver(EmulatorMode::Running, &rom);
+    resumed.resume(&Session::parse(&session.to_string()).unwrap());
+    assert_eq!(resumed.chip8.pc(), 0x204);
+    assert_eq!(resumed.chip8.registers.vn[0], 2);
+    assert!(matches!(resumed.mode, EmulatorMode::Paused));
+    assert!(recording.borrow().debug_mode);
+    assert_eq!(resumed.breakpoints().iter().count(), 1);
+}
+
+#[test]
+fn test_driver_advance(){
+    // ld v0 60; ld dt v0; ld v1 k; jp 0x206
+    let (mut driver, recording) = test_driver(EmulatorMode::Paused, &[0x60, 0x3c, 0xf0, 0x15, 0xf1, 0x0a, 0x12, 0x06]);
+    assert_eq!(driver.advance(FRAME_LENGTH * 5 / 2), 2);
+    assert_eq!(driver.chip8.registers.delay, 59);
+    assert_eq!(driver.chip8.state(), CpuState::WaitingForKey(crate::Reg(1)));
+    driver.press_key(0x5);
+    assert_eq!(driver.advance(FRAME_LENGTH / 2), 1);
+    assert_eq!((driver.chip8.registers.delay, driver.chip8.registers.vn[1]), (58, 0x5));
+    assert_eq!(driver.advance(Duration::from_secs(1)), 60);
+    assert_eq!(driver.chip8.registers.delay, 0);
+    assert!(matches!(driver.mode, EmulatorMode::Paused));
+    assert!(recording.borrow().draws.is_empty());
+}
+
+#[test]
+fn test_driver_fault(){
+    // ld v2 7; ret
+    let (mut driver, recording) = test_driver(EmulatorMode::Running, &[0x62, 0x07, 0x00, 0xee]);
+    assert!(!driver.tick(crate::timing::FRAME_LENGTH));
+    assert!(matches!(driver.mode, EmulatorMode::Paused));
+    assert_eq!(driver.chip8.pc(), 0x202);
+    // the load check warns about the same fault first
+    assert_eq!(recording.borrow().messages.last().map(String::as_str), Some("error: returned with an empty call stack\n --> 0x202"));
+
+    // unpausing skips the faulting instruction
+    recording.borrow_mut().inputs.push_back(vec![KeyInput::TogglePause]);
+    assert!(!driver.tick(Duration::ZERO));
+    assert!(matches!(driver.mode, EmulatorMode::Running));
+    assert_eq!((driver.chip8.state(), driver.chip8.pc()), (CpuState::Running, 0x204));
+}
+
+#[test]
+fn test_driver_breakpoint(){
+    // ld v2 7; add v2 1; jp 0x202
+    let (mut driver, _recording) = test_driver(EmulatorMode::Running, &[0x62, 0x07, 0x72, 0x01, 0x12, 0x02]);
+    driver.breakpoints().add(crate::breakpoints::Breakpoint::at(crate::Addr(0x204)));
+    assert!(!driver.tick(crate::timing::FRAME_LENGTH));
+    assert!(matches!(driver.mode, EmulatorMode::Paused));
+    assert_eq!(driver.chip8.pc(), 0x204);
+    assert_eq!(driver.breakpoints().iter().next().unwrap().hits, 1);
+
+    // one that only counts lets it carry on
+    let (mut driver, _recording) = test_driver(EmulatorMode::Running, &[0x62, 0x07, 0x72, 0x01, 0x12, 0x02]);
+    driver.breakpoints().add(crate::breakpoints::Breakpoint::parse(&["0x204", "then", "count"]).unwrap());
+    driver.tick(crate::timing::FRAME_LENGTH);
+    assert!(matches!(driver.mode, EmulatorMode::Running));
+    assert!(driver.breakpoints().iter().next().unwrap().hits > 1);
+}
+
+#[test]
+fn test_driver_pause_and_close(){
+    // ld i <font sprite for 0>; drw v0 v0 5; jp 0x204
+    let (mut driver, recording) = test_driver(EmulatorMode::Running, &[0xf0, 0x29, 0xd0, 0x05, 0x12, 0x04]);
+    assert!(!driver.tick(crate::timing::FRAME_LENGTH));
+    assert!(recording.borrow().draws.last().unwrap().display.iter().any(|pixel| *pixel));
+    recording.borrow_mut().inputs.push_back(vec![KeyInput::TogglePause]);
+    assert!(!driver.tick(crate::timing::FRAME_LENGTH));
+    assert!(matches!(driver.mode, EmulatorMode::Paused));
+    recording.borrow_mut().closed = true;
+    assert!(driver.tick(Duration::ZERO));
+}
+
+#[test]
+fn test_driver_buzzer(){
+    // ld v0 2; ld st v0; jp 0x204
+    let (mut driver, recording) = test_driver(EmulatorMode::Running, &[0x60, 0x02, 0xf0, 0x18, 0x12, 0x04]);
+    assert!(!driver.tick(crate::timing::FRAME_LENGTH));
+    assert!(recording.borrow().buzzer);
+    assert_eq!(recording.borrow().pitch, 1.0);
+    for _ in 0..2{
+        assert!(!driver.tick(crate::timing::FRAME_LENGTH));
+    }
+    assert!(!recording.borrow().buzzer);
+}
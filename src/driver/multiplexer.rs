@@ -0,0 +1,133 @@
+use crate::{breakpoints::Breakpoints, collision::Collision, errors::Diagnostic, frame_timing::FrameTiming, frontend::{Audio, Chip8Frontend, InputSource, KeyInput, Point, Renderer}, keypad::KeyProfile, panel_style::PanelStyles, symbols::Symbols, theme::DisplayStyle, Addr, Chip8};
+
+/// The main frontend, with any mirrors drawn alongside it and any extra input sources merged into its input.
+/// The main frontend alone decides when the emulator quits, and keeps the debug view and the sound
+pub(crate) struct Multiplexer{
+    main: Box<dyn Chip8Frontend>,
+    mirrors: Vec<Box<dyn Renderer>>,
+    sources: Vec<Box<dyn InputSource>>
+}
+
+impl Multiplexer{
+    pub fn new(main: Box<dyn Chip8Frontend>) -> Self{
+        Self { main, mirrors: vec![], sources: vec![] }
+    }
+
+    pub fn add_mirror(&mut self, mirror: Box<dyn Renderer>){
+        self.mirrors.push(mirror);
+    }
+
+    pub fn add_source(&mut self, source: Box<dyn InputSource>){
+        self.sources.push(source);
+    }
+
+    fn each_renderer(&mut self, mut f: impl FnMut(&mut dyn Renderer)){
+        f(self.main.as_mut());
+        for mirror in self.mirrors.iter_mut(){
+            f(mirror.as_mut());
+        }
+    }
+}
+
+impl Renderer for Multiplexer{
+    fn update(&mut self, chip8: &Chip8, breakpoints: &Breakpoints, show_current_instruction: bool) -> bool {
+        self.mirrors.retain_mut(|mirror| !mirror.update(chip8, breakpoints, show_current_instruction));
+        self.main.update(chip8, breakpoints, show_current_instruction)
+    }
+
+    fn set_display_style(&mut self, style: &DisplayStyle) {
+        self.each_renderer(|renderer| renderer.set_display_style(style));
+    }
+
+    fn set_panel_styles(&mut self, styles: &PanelStyles) {
+        self.each_renderer(|renderer| renderer.set_panel_styles(styles));
+    }
+
+    fn set_memory_snapshot(&mut self, snapshot: Option<&[u8]>) {
+        self.each_renderer(|renderer| renderer.set_memory_snapshot(snapshot));
+    }
+
+    fn show_collision(&mut self, collision: &Collision) {
+        self.each_renderer(|renderer| renderer.show_collision(collision));
+    }
+
+    fn set_symbols(&mut self, symbols: &Symbols) {
+        self.each_renderer(|renderer| renderer.set_symbols(symbols));
+    }
+
+    fn set_rom(&mut self, rom: &[u8]) {
+        self.each_renderer(|renderer| renderer.set_rom(rom));
+    }
+
+    fn set_frame_times(&mut self, times: &[FrameTiming]) {
+        self.each_renderer(|renderer| renderer.set_frame_times(times));
+    }
+
+    fn show_message(&mut self, message: &str) {
+        self.each_renderer(|renderer| renderer.show_message(message));
+    }
+}
+
+impl InputSource for Multiplexer{
+    fn get_inputs(&mut self) -> Vec<KeyInput> {
+        let mut inputs = self.main.get_inputs();
+        for source in self.sources.iter_mut(){
+            inputs.extend(source.get_inputs());
+        }
+        inputs
+    }
+}
+
+impl Chip8Frontend for Multiplexer{
+    fn toggle_debug(&mut self) {
+        self.main.toggle_debug();
+    }
+
+    fn is_debug_view(&self) -> bool {
+        self.main.is_debug_view()
+    }
+
+    fn toggle_grid(&mut self) {
+        self.main.toggle_grid();
+    }
+
+    fn toggle_virtual_keypad(&mut self) {
+        self.main.toggle_virtual_keypad();
+    }
+
+    fn on_mouse_scroll(&mut self, position: Point, direction: isize) {
+        self.main.on_mouse_scroll(position, direction);
+    }
+
+    fn on_mouse_click(&mut self, position: Point, breakpoints: &mut Breakpoints) {
+        self.main.on_mouse_click(position, breakpoints);
+    }
+
+    fn audio(&mut self) -> &mut dyn Audio {
+        self.main.audio()
+    }
+
+    fn set_gamepad_map(&mut self, map: &std::collections::BTreeMap<crate::settings::PadButton, u8>) {
+        self.main.set_gamepad_map(map);
+    }
+
+    fn set_key_profile(&mut self, profile: KeyProfile) {
+        self.main.set_key_profile(profile);
+    }
+
+    fn set_key_remap(&mut self, remap: &std::collections::BTreeMap<u8, String>) {
+        self.main.set_key_remap(remap);
+    }
+
+    fn toggle_key_remap(&mut self) {
+        self.main.toggle_key_remap();
+    }
+
+    fn copy(&mut self, chip8: &Chip8, breakpoints: &Breakpoints) -> Result<(), Diagnostic> {
+        self.main.copy(chip8, breakpoints)
+    }
+
+    fn paste(&mut self) -> Result<Vec<(Addr, u8)>, Diagnostic> {
+        self.main.paste()
+    }
+}
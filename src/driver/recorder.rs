@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+
+use crate::{recording::Recording, screenshot::Screenshot, Chip8Driver};
+
+/// The display recorded frame by frame for an animated GIF, and where F8 saves it
+#[derive(Default)]
+pub(crate) struct DisplayRecorder{
+    /// The display of every frame since recording started
+    recording: Option<Recording>,
+    /// Where the recording is saved to when it stops
+    path: Option<PathBuf>
+}
+
+impl DisplayRecorder{
+    pub fn set_path(&mut self, path: PathBuf){
+        self.path = Some(path);
+    }
+
+    /// Where the recording is saved, `recording.gif` unless set
+    pub fn path(&self) -> PathBuf{
+        self.path.clone().unwrap_or(PathBuf::from(Chip8Driver::DEFAULT_RECORDING))
+    }
+
+    /// Start again with no frames
+    pub fn start(&mut self){
+        self.recording = Some(Recording::default());
+    }
+
+    pub fn recording(&self) -> Option<&Recording>{
+        self.recording.as_ref()
+    }
+
+    /// Stop recording, handing back the frames so far
+    pub fn take(&mut self) -> Option<Recording>{
+        self.recording.take()
+    }
+
+    /// Add the next frame while recording. True once the recording is full
+    pub fn push(&mut self, screen: Screenshot) -> bool{
+        let Some(recording) = &mut self.recording else {return false};
+        recording.push(screen);
+        recording.is_full()
+    }
+}
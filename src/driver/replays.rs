@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+
+use crate::{replay::{KeyEvent, Replay}, Chip8};
+
+/// The replay being recorded, and the one being played back in place of the keyboard
+#[derive(Default)]
+pub(crate) struct ReplayState{
+    /// Key events since the ROM was loaded, and where to save them as a replay
+    recording: Option<(Replay, PathBuf)>,
+    /// A replay standing in for the keyboard, and the next frame of it to play
+    playback: Option<(Replay, usize)>
+}
+
+impl ReplayState{
+    /// Record key events into `replay` from now on, to save to `path`
+    pub fn record(&mut self, replay: Replay, path: PathBuf){
+        self.recording = Some((replay, path));
+    }
+
+    pub fn is_recording(&self) -> bool{
+        self.recording.is_some()
+    }
+
+    /// Stop recording, handing back the replay and where to save it
+    pub fn take_recording(&mut self) -> Option<(Replay, PathBuf)>{
+        self.recording.take()
+    }
+
+    /// Add `event` to the replay being recorded, if there is one
+    pub fn record_event(&mut self, event: KeyEvent){
+        if let Some((replay, _)) = &mut self.recording{
+            replay.record(event);
+        }
+    }
+
+    /// Add a random byte the ROM drew to the replay being recorded, if there is one
+    pub fn record_random(&mut self, byte: u8){
+        if let Some((replay, _)) = &mut self.recording{
+            replay.record_random(byte);
+        }
+    }
+
+    /// Play `replay` from its first frame
+    pub fn play(&mut self, replay: Replay){
+        self.playback = Some((replay, 0));
+    }
+
+    pub fn is_playing(&self) -> bool{
+        self.playback.is_some()
+    }
+
+    /// Stop playing, returning whether a replay was playing
+    pub fn stop_playing(&mut self) -> bool{
+        self.playback.take().is_some()
+    }
+
+    /// At the start of each frame, press the keys the replay being played has before it,
+    /// and count the frame in the one being recorded. True when the replay being played has just finished
+    pub fn next_frame(&mut self, chip8: &mut Chip8) -> bool{
+        let mut finished = false;
+        if let Some((replay, frame)) = &mut self.playback{
+            for event in replay.events(*frame){
+                match event {
+                    KeyEvent::Clear => chip8.clear_keys(),
+                    KeyEvent::Press(key) => chip8.press_key(key)
+                }
+            }
+            *frame += 1;
+            finished = *frame >= replay.frames();
+        }
+        if finished{
+            self.playback = None;
+        }
+        if let Some((replay, _)) = &mut self.recording{
+            replay.next_frame();
+        }
+        finished
+    }
+}
+
+
+#[test]
+fn test_replay_state(){
+    let mut chip8 = Chip8::init(None);
+    let mut replays = ReplayState::default();
+    replays.record(Replay::new(&[], &chip8, Default::default()), PathBuf::from("test.replay"));
+    replays.record_event(KeyEvent::Press(5));
+    assert!(!replays.next_frame(&mut chip8));
+    replays.record_event(KeyEvent::Clear);
+    assert!(!replays.next_frame(&mut chip8));
+    let (replay, _) = replays.take_recording().unwrap();
+    assert_eq!(replay.frames(), 2);
+    assert!(!replays.is_recording());
+
+    replays.play(replay);
+    assert!(!replays.next_frame(&mut chip8));
+    assert_eq!(chip8.keys_down(), 1 << 5);
+    assert!(replays.next_frame(&mut chip8));
+    assert_eq!(chip8.keys_down(), 0);
+    assert!(!replays.is_playing());
+}
@@ -0,0 +1,59 @@
+use std::path::{Path, PathBuf};
+
+use crate::{recent::RecentRoms, Chip8Driver};
+
+/// The files kept about the ROM running: the session saved on exit,
+/// the ROMs opened lately and where the edited ROM is written
+#[derive(Default)]
+pub(crate) struct SessionState{
+    /// The ROM being run and where to save the session to on exit
+    session: Option<(PathBuf, PathBuf)>,
+    /// ROMs opened lately, the one running first, and the state file they are kept in
+    recent_roms: Option<(RecentRoms, PathBuf)>,
+    /// Where the edited ROM is written to
+    patched_rom_path: Option<PathBuf>
+}
+
+impl SessionState{
+    pub fn enable_session(&mut self, rom: PathBuf, path: PathBuf){
+        self.session = Some((rom, path));
+    }
+
+    /// The ROM being run and where its session is saved, if it is
+    pub fn session(&self) -> Option<(&Path, &Path)>{
+        self.session.as_ref().map(|(rom, path)| (rom.as_path(), path.as_path()))
+    }
+
+    pub fn set_recent_roms(&mut self, recent: RecentRoms, path: PathBuf){
+        self.recent_roms = Some((recent, path));
+    }
+
+    /// The ROMs opened lately, the one running first
+    pub fn recent_paths(&self) -> &[PathBuf]{
+        self.recent_roms.as_ref().map_or(&[][..], |(recent, _)| recent.paths())
+    }
+
+    pub fn set_patched_rom_path(&mut self, path: PathBuf){
+        self.patched_rom_path = Some(path);
+    }
+
+    /// Where the edited ROM is written, `patched.ch8` unless set
+    pub fn patched_rom_path(&self) -> PathBuf{
+        self.patched_rom_path.clone().unwrap_or(PathBuf::from(Chip8Driver::DEFAULT_PATCHED_ROM))
+    }
+
+    /// Switch to the ROM at `path`: the session is saved for it, its edits go next to it,
+    /// and it goes to the top of the recent ROMs
+    pub fn open(&mut self, path: &Path){
+        self.patched_rom_path = Some(path.with_extension("patched.ch8"));
+        if let Some((session_rom, _)) = &mut self.session{
+            *session_rom = path.to_path_buf();
+        }
+        if let Some((recent, recent_path)) = &mut self.recent_roms{
+            recent.push(path.to_path_buf());
+            if let Err(err) = recent.save(recent_path){
+                eprintln!("Could not save recent ROMs to {}: {}", recent_path.display(), err);
+            }
+        }
+    }
+}
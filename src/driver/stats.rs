@@ -0,0 +1,72 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::frame_timing::{FrameTiming, TimingLog};
+
+/// Measurements of this run: the time played, for the ROM's play statistics,
+/// the instructions executed and how long each frame took
+pub(crate) struct RunStats{
+    /// Whether play statistics are updated this run
+    pub track: bool,
+    /// Time spent running this run, added to the ROM's play statistics on exit
+    play_time: Duration,
+    /// Instructions executed since the ROM was loaded, for measuring the speed
+    instructions_run: u64,
+    /// How long recent frames took, or every frame with `timing_log_path`
+    timing_log: TimingLog,
+    /// Where the timing of every frame is written to on exit
+    timing_log_path: Option<PathBuf>
+}
+
+impl Default for RunStats{
+    fn default() -> Self {
+        Self{
+            track: true,
+            play_time: Duration::ZERO,
+            instructions_run: 0,
+            timing_log: TimingLog::new(Some(TimingLog::RECENT)),
+            timing_log_path: None
+        }
+    }
+}
+
+impl RunStats{
+    pub fn add_play_time(&mut self, time: Duration){
+        self.play_time += time;
+    }
+
+    /// The time played since this was last called
+    pub fn take_play_time(&mut self) -> Duration{
+        std::mem::take(&mut self.play_time)
+    }
+
+    pub fn count_instruction(&mut self){
+        self.instructions_run += 1;
+    }
+
+    pub fn instructions_run(&self) -> u64{
+        self.instructions_run
+    }
+
+    pub fn timing_log(&self) -> &TimingLog{
+        &self.timing_log
+    }
+
+    pub fn log_frame(&mut self, timing: FrameTiming){
+        self.timing_log.push(timing);
+    }
+
+    /// Keep the timing of every frame from now on, to write to `path`
+    pub fn set_timing_log_path(&mut self, path: PathBuf){
+        self.timing_log = TimingLog::new(None);
+        self.timing_log_path = Some(path);
+    }
+
+    /// Write the timing log as CSV, if there is somewhere to, and print its summary
+    pub fn save_timing_log(&self){
+        let Some(path) = &self.timing_log_path else {return};
+        if let Err(err) = self.timing_log.save(path){
+            eprintln!("Could not write the timing log to {}: {}", path.display(), err);
+        }
+        print!("{}", self.timing_log.summary());
+    }
+}
@@ -1,4 +1,9 @@
-use crate::*;
+use std::{cmp::max, collections::BTreeSet, panic::AssertUnwindSafe, str::FromStr};
+
+#[cfg(test)]
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{display_events::{DisplayEvent, DisplayEvents}, errors::{parse_name, Diagnostic}, extensions::Extension, keypad::{KeyState, KEY_COUNT}, quirks::{Quirk, Quirks}, random::{RandomDevice, RandomSource}, *};
 
 /////////////////////////////////////
 /// Memory
@@ -26,7 +31,7 @@ impl Default for Memory {
         let mut mem = Self { 
             ram: [0;4096], 
             display: Display::from_elem([DISPLAY_COLUMNS, DISPLAY_ROWS], false),
-            keys: Default::default(),
+            keypad: Default::default(),
             stack: Default::default()
         };
         mem.ram[0..CHAR_SPRITES.len()].copy_from_slice(&CHAR_SPRITES);
@@ -35,8 +40,14 @@ impl Default for Memory {
 }
 
 impl Memory{
-    pub fn load_rom(&mut self, rom: &[u8]){
+    /// Copy `rom` into RAM at 0x200, or fail without writing anything if it doesn't fit
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Diagnostic>{
+        if rom.len() > crate::rom_check::MAX_ROM_SIZE{
+            return Err(Diagnostic::error(format!("The ROM is {} bytes, but at most {} bytes fit in memory",
+                rom.len(), crate::rom_check::MAX_ROM_SIZE)));
+        }
         self.ram[0x200..0x200 + rom.len()].copy_from_slice(rom);
+        Ok(())
     }
     
     /// Xor a row of 8 pixels starting at (x,y) with the pattern in `byte`,
@@ -72,6 +83,67 @@ fn test_set_row(){
 ///  Registers
 //////////////////////////////
 
+impl std::fmt::Display for CpuState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CpuState::Running => write!(f, "running"),
+            CpuState::WaitingForKey(reg) => write!(f, "waiting for key (V{reg})"),
+            CpuState::WaitingForDisplay => write!(f, "waiting for display"),
+            CpuState::Halted(fault) => write!(f, "halted: {fault}")
+        }
+    }
+}
+
+impl std::fmt::Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fault::StackUnderflow => write!(f, "returned with an empty call stack"),
+            Fault::StackOverflow(depth) => write!(f, "called a subroutine with {depth} calls already on the stack"),
+            Fault::InvalidOpcode(opcode) => write!(f, "{opcode:#06x} is not an instruction"),
+            Fault::SelfJump => write!(f, "jumped to itself, so the program has ended"),
+            Fault::ProtectedWrite(addr) => write!(f, "wrote to {addr:#05x}, below 0x200 where the font is kept"),
+            Fault::MemoryOutOfRange => write!(f, "accessed memory past the end of RAM"),
+            Fault::InvalidKey(key) => write!(f, "{key:#x} is not a key"),
+            Fault::InvalidCharacter(value) => write!(f, "{value:#x} is not a hex digit"),
+            Fault::Panic => write!(f, "the emulator crashed running this instruction; this is a bug in the emulator, not the ROM")
+        }
+    }
+}
+
+impl Fault {
+    /// The fault as a diagnostic, raised by the instruction at `pc`
+    pub fn diagnostic(&self, pc: usize) -> Diagnostic {
+        let diagnostic = Diagnostic::error(self.to_string());
+        match Addr::try_from(pc) {
+            Ok(addr) => diagnostic.at_address(addr),
+            Err(_) => diagnostic
+        }
+    }
+}
+
+impl HaltCondition{
+    pub const ALL: [HaltCondition; 3] = [HaltCondition::InvalidOpcode, HaltCondition::SelfJump, HaltCondition::ProtectedWrite];
+}
+
+impl std::fmt::Display for HaltCondition{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HaltCondition::InvalidOpcode => write!(f, "invalid-opcode"),
+            HaltCondition::SelfJump => write!(f, "self-jump"),
+            HaltCondition::ProtectedWrite => write!(f, "protected-write")
+        }
+    }
+}
+
+/// Parses the names `--halt-on` takes, e.g. `self-jump`
+impl FromStr for HaltCondition{
+    type Err = Diagnostic;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_name(&Self::ALL, "halt condition", s)
+    }
+}
+
 impl Default for Registers {
     fn default() -> Self {
         Self { 
@@ -83,7 +155,7 @@ impl Default for Registers {
             // Call stack starts at 0x1ff and grows down 
             sp: 0x1ff,
             i: Default::default(), 
-            key_flag: Default::default() }
+            state: Default::default() }
     }
 }
 
@@ -94,6 +166,8 @@ impl Default for Registers {
 
 impl Chip8{
     pub const DEFAULT_SPEED: u64 = 500;
+    /// As deep as most interpreters allow; the original COSMAC VIP's allowed 12
+    pub const DEFAULT_STACK_DEPTH: usize = 16;
 
 
     pub fn init(speed: Option<u64>)->Self{
@@ -101,6 +175,14 @@ impl Chip8{
             clock_speed: speed.unwrap_or(Self::DEFAULT_SPEED),
             memory: Memory::default(),
             registers: Registers::default(),
+            stack_depth: Self::DEFAULT_STACK_DEPTH,
+            quirks: Quirks::default(),
+            extensions: BTreeSet::new(),
+            halt_on: BTreeSet::new(),
+            rng: RandomSource::seeded(rand::random()),
+            dropped_write: None,
+            display_events: DisplayEvents::default(),
+            coverage: None
         }
     }
 
@@ -108,18 +190,251 @@ impl Chip8{
         self.clock_speed = speed;
     }
 
-    pub fn load_rom(&mut self, rom: &[u8]) {
-        self.memory.load_rom(rom);
+    /// Instructions per second
+    pub fn speed(&self) -> u64{
+        self.clock_speed
+    }
+
+    /// Restart the random numbers of `RND` from `seed`, so that they come out the same every run
+    pub fn seed_rng(&mut self, seed: u64){
+        self.rng = RandomSource::seeded(seed);
+    }
+
+    /// The seed `RND`'s random numbers were last started from
+    pub fn rng_seed(&self) -> u64{
+        self.rng.seed()
+    }
+
+    /// Have `RND` use `bytes`, in order, before going back to its seeded numbers, e.g. from a replay
+    pub fn play_back_random(&mut self, bytes: std::rc::Rc<[u8]>){
+        self.rng.play_back(bytes);
+    }
+
+    /// The random byte `RND` drew since this was last called, for recording it
+    #[cfg(all(feature = "window", not(target_arch = "wasm32")))]
+    pub(crate) fn take_random_byte(&mut self) -> Option<u8>{
+        self.rng.take_last()
+    }
+
+    /// The first address and the instruction of the last write below 0x200 that the `protect-low-memory` quirk dropped,
+    /// since this was last called
+    pub fn take_dropped_write(&mut self) -> Option<(usize, usize)>{
+        self.dropped_write.take()
+    }
+
+    /// Number of instructions executed between each 60 hz timer tick
+    pub fn cycles_per_frame(&self) -> u64 {
+        max(self.clock_speed / 60, 1)
+    }
+
+    /// Load `rom` at 0x200, see `Memory::load_rom`
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Diagnostic> {
+        self.memory.load_rom(rom)
+    }
+
+    /// Clear memory and registers as if just switched on, keeping the speed, quirks, extensions and seed
+    pub fn reset(&mut self){
+        self.memory = Memory::default();
+        self.registers = Registers::default();
+        self.seed_rng(self.rng.seed());
+        self.display_events.push(DisplayEvent::Clear);
+    }
+
+    /// The clears, sprites and quirk changes since `clear_display_events`, oldest first
+    pub fn display_events(&self) -> &DisplayEvents{
+        &self.display_events
+    }
+
+    /// Forget the display events, once every frontend has seen them
+    pub fn clear_display_events(&mut self){
+        self.display_events.clear();
+    }
+
+    /// Restart from 0x200 with the display, stack, keys and registers cleared, keeping RAM as it is,
+    /// so edits to the program and anything it stored survive, like the reset switch of a real machine
+    pub fn soft_reset(&mut self){
+        let ram = self.memory.ram;
+        self.reset();
+        self.memory.ram = ram;
+    }
+
+    /// Run `step` on the CPU, catching a panic instead of letting it unwind through the driver:
+    /// the CPU halts with `Fault::Panic`, left as it was when the panic happened, and the panic's message is returned
+    pub fn catch_panic(&mut self, step: impl FnOnce(&mut Self)) -> Option<String>{
+        let payload = std::panic::catch_unwind(AssertUnwindSafe(|| step(self))).err()?;
+        let message = payload.downcast_ref::<&str>().map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        self.registers.state = CpuState::Halted(Fault::Panic);
+        Some(message)
     }
 
     pub fn do_instruction(&mut self){
-        if self.registers.key_flag.is_none() {
-            do_instruction(&mut self.memory, &mut self.registers)
+        let (pressed, released) = self.memory.keypad.poll();
+        if let CpuState::WaitingForKey(dest) = self.registers.state{
+            if let Some(key) = self.memory.keypad.wait(pressed, released, self.quirks.get(Quirk::WaitForRelease)){
+                self.registers.vn[dest.index()] = key;
+                self.registers.state = CpuState::Running;
+            }
+        }
+        if self.registers.state != CpuState::Running {
+            return
+        }
+        let instruction = get_instruction(&self.memory, &self.registers);
+        let fault = match instruction {
+            Instruction::Call(_) if self.memory.stack.len() >= self.stack_depth => Some(Fault::StackOverflow(self.memory.stack.len())),
+            Instruction::Nop if self.halts_on(HaltCondition::InvalidOpcode) => {
+                let opcode = get_opcode(&self.memory, &self.registers);
+                (opcode != 0).then_some(Fault::InvalidOpcode(opcode))
+            },
+            Instruction::Jump(addr) if addr.index() == self.registers.pc && self.halts_on(HaltCondition::SelfJump) => Some(Fault::SelfJump),
+            _ => None
+        };
+        let protected = written_range(&self.memory, &self.registers)
+            .filter(|range| self.quirks.get(Quirk::ProtectLowMemory) && range.start < Addr::ROM_START.index())
+            .map(|range| range.start..range.end.min(Addr::ROM_START.index()));
+        let fault = fault.or_else(|| protected.clone()
+            .filter(|_| self.halts_on(HaltCondition::ProtectedWrite))
+            .map(|range| Fault::ProtectedWrite(range.start)));
+        if let Some(fault) = fault{
+            self.registers.state = CpuState::Halted(fault);
+            return
+        }
+        let wait = match instruction {
+            Instruction::Draw(..) => self.quirks.get(Quirk::DisplayWait),
+            Instruction::VSync => self.has_extension(Extension::VSync),
+            _ => false
+        };
+        // read before the draw, which may overwrite a coordinate register with the collision flag
+        let event = match instruction {
+            Instruction::ClearScreen => Some(DisplayEvent::Clear),
+            Instruction::Draw(vx, vy, n) => Some(DisplayEvent::sprite(self.registers.vn[vx.index()], self.registers.vn[vy.index()], n, false)),
+            _ => None
+        };
+        let accessed = self.coverage.is_some()
+            .then(|| (read_range(&self.memory, &self.registers), written_range(&self.memory, &self.registers)));
+        // the rest of the instruction still happens, only the protected bytes are put back
+        let pc = self.registers.pc;
+        let kept = protected.as_ref().map(|range| self.memory.ram[range.clone()].to_vec());
+        do_instruction(&mut self.memory, &mut self.registers, &mut self.rng);
+        if let (Some(coverage), Some((read, written)), false) = (&mut self.coverage, accessed, matches!(self.registers.state, CpuState::Halted(_))){
+            coverage.mark_instruction(pc);
+            coverage.mark(read.unwrap_or_default(), coverage::READ);
+            // dropped writes to protected memory don't count
+            let written = written.map(|range| protected.as_ref().map_or(range.start, |protected| protected.end)..range.end);
+            coverage.mark(written.unwrap_or_default(), coverage::WRITTEN);
+        }
+        if let (Some(range), Some(kept)) = (protected, kept){
+            self.memory.ram[range.clone()].copy_from_slice(&kept);
+            self.dropped_write = Some((range.start, pc));
+        }
+        if wait && self.registers.state == CpuState::Running {
+            self.registers.state = CpuState::WaitingForDisplay;
+        }
+        if let (Some(mut event), false) = (event, matches!(self.registers.state, CpuState::Halted(_))){
+            if let DisplayEvent::Sprite{ collided, .. } = &mut event{
+                *collided = self.registers.vn[15] != 0;
+            }
+            self.display_events.push(event);
+        }
+    }
+
+    /// What the CPU is doing
+    pub fn state(&self) -> CpuState {
+        self.registers.state
+    }
+
+    /// Why the CPU halted, if it has
+    pub fn fault(&self) -> Option<Fault> {
+        match self.registers.state {
+            CpuState::Halted(fault) => Some(fault),
+            _ => None
+        }
+    }
+
+    /// Set what the CPU is doing, e.g. to resume after a fault
+    pub fn set_state(&mut self, state: CpuState) {
+        self.registers.state = state;
+    }
+
+    /// Allow at most `depth` nested calls, halting with a stack overflow on the next
+    pub fn set_stack_depth(&mut self, depth: usize) {
+        self.stack_depth = depth;
+    }
+
+    pub fn stack_depth(&self) -> usize {
+        self.stack_depth
+    }
+
+    /// The quirks switched on
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Switch a quirk on or off, which takes effect from the next instruction, even mid-run.
+    /// A change is reported to frontends as a `DisplayEvent::QuirkChanged`
+    pub fn set_quirk(&mut self, quirk: Quirk, enabled: bool) {
+        if self.quirks.get(quirk) != enabled{
+            self.quirks.set(quirk, enabled);
+            self.display_events.push(DisplayEvent::QuirkChanged{ quirk, enabled });
+        }
+    }
+
+    /// The quirks switched since this was last called, oldest first, taken out of the display events
+    pub fn take_quirk_changes(&mut self) -> Vec<(Quirk, bool)>{
+        self.display_events.take_quirk_changes()
+    }
+
+    /// Start or stop tracking how each byte of RAM is used, forgetting what was tracked before
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.coverage = enabled.then(coverage::Coverage::default);
+    }
+
+    /// How each byte of RAM has been used since tracking started, if it is being tracked
+    pub fn coverage(&self) -> Option<&coverage::Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Switch an extension's opcodes on or off; switched off, they are NOPs
+    pub fn set_extension(&mut self, extension: Extension, enabled: bool) {
+        if enabled {
+            self.extensions.insert(extension);
+        } else {
+            self.extensions.remove(&extension);
+        }
+    }
+
+    pub fn has_extension(&self, extension: Extension) -> bool {
+        self.extensions.contains(&extension)
+    }
+
+    /// Make `condition` halt the CPU, or go back to carrying on through it
+    pub fn set_halt_on(&mut self, condition: HaltCondition, enabled: bool) {
+        if enabled {
+            self.halt_on.insert(condition);
+        } else {
+            self.halt_on.remove(&condition);
         }
-        
     }
 
+    pub fn halts_on(&self, condition: HaltCondition) -> bool {
+        self.halt_on.contains(&condition)
+    }
+
+    /// Carry on after a fault by skipping the instruction that caused it.
+    /// Does nothing unless the CPU has halted
+    pub fn resume(&mut self) {
+        if let CpuState::Halted(_) = self.registers.state {
+            self.registers.state = CpuState::Running;
+            self.registers.pc += INSTRUCTION_SIZE;
+        }
+    }
+
+    /// Start a new 60 hz frame: count down the timers and release a CPU waiting on the display
     pub fn tick_timers(&mut self){
+        if self.registers.state == CpuState::WaitingForDisplay {
+            self.registers.state = CpuState::Running;
+        }
         if self.registers.delay > 0{
             self.registers.delay -= 1;
         }
@@ -128,16 +443,61 @@ impl Chip8{
         }
     }
 
-    pub fn set_key(&mut self, key: u8){
-        self.memory.keys[key as usize] = true;
-        if let Some(dest) = self.registers.key_flag{
-            self.registers.vn[dest] = key;
-            self.registers.key_flag = None;
+    /// Execute one frame: tick the timers, then run a frame's worth of instructions
+    pub fn run_frame(&mut self){
+        self.tick_timers();
+        for _ in 0..self.cycles_per_frame(){
+            self.do_instruction();
+        }
+    }
+
+    /// Hold down `key`. A CPU waiting for a key sees the press before its next instruction.
+    /// Keys past F don't exist, and are ignored
+    pub fn press_key(&mut self, key: u8){
+        if key < KEY_COUNT{
+            self.memory.keypad.press(key);
+        }
+    }
+
+    /// Let go of `key`, ignoring keys past F like `press_key`
+    pub fn release_key(&mut self, key: u8){
+        if key < KEY_COUNT{
+            self.memory.keypad.release(key);
         }
     }
 
     pub fn clear_keys(&mut self){
-        self.memory.keys = [false; 16]
+        self.memory.keypad.clear();
+    }
+
+    /// The keys held down, as a bit for each key
+    pub fn keys_down(&self) -> u16{
+        self.memory.keypad.down()
+    }
+
+    /// Whether `key` has been pressed, held or released since the last instruction.
+    /// Keys past F are always up
+    pub fn key_state(&self, key: u8) -> KeyState{
+        if key < KEY_COUNT {self.memory.keypad.state(key)} else {KeyState::Up}
+    }
+
+    /// The display buffer, indexed like (column, row)
+    pub fn frame_buffer(&self) -> &Array2<bool> {
+        &self.memory.display
+    }
+
+    /// `len` bytes of RAM starting at `addr`, or `None` if they run past the end of memory
+    pub fn read_memory(&self, addr: usize, len: usize) -> Option<&[u8]> {
+        self.memory.ram.get(addr..addr.checked_add(len)?)
+    }
+
+    /// Copy `bytes` into RAM starting at `addr`.
+    /// Nothing is written if they would run past the end of memory
+    pub fn write_memory(&mut self, addr: usize, bytes: &[u8]) -> Result<(), Diagnostic> {
+        let ram = addr.checked_add(bytes.len()).and_then(|end| self.memory.ram.get_mut(addr..end))
+            .ok_or_else(|| Diagnostic::error(format!("{} bytes at {:#05x} run past the end of memory", bytes.len(), addr)))?;
+        ram.copy_from_slice(bytes);
+        Ok(())
     }
 
     pub fn sound(&self) -> bool {
@@ -148,13 +508,40 @@ impl Chip8{
         self.registers.pc
     }
 
+    /// Addresses of the CALL instructions that haven't returned yet, outermost first
+    pub fn call_stack(&self) -> &[usize] {
+        &self.memory.stack
+    }
+
 }
 
-/// Get the current instruction from memory
+/// Get the current instruction from memory, reading past the end of RAM as zeroes
 pub(crate) fn get_instruction(memory: &Memory, registers: &Registers) -> Instruction{
-    let upper = memory.ram[registers.pc];
-    let lower = memory.ram[registers.pc + 1];
-    (((upper as u16) << 8) | (lower as u16)).into()
+    get_opcode(memory, registers).into()
+}
+
+fn get_opcode(memory: &Memory, registers: &Registers) -> u16{
+    let upper = memory.ram.get(registers.pc).copied().unwrap_or_default();
+    let lower = memory.ram.get(registers.pc + 1).copied().unwrap_or_default();
+    ((upper as u16) << 8) | (lower as u16)
+}
+
+/// The range of RAM that the current instruction will read as data, if any
+pub(crate) fn read_range(memory: &Memory, registers: &Registers) -> Option<std::ops::Range<usize>>{
+    match get_instruction(memory, registers) {
+        Instruction::Draw(_, _, n) => Some(registers.i..registers.i + n as usize),
+        Instruction::RegLoad(reg) => Some(registers.i..registers.i + reg.index() + 1),
+        _ => None
+    }
+}
+
+/// The range of RAM that the current instruction will write to, if any
+pub(crate) fn written_range(memory: &Memory, registers: &Registers) -> Option<std::ops::Range<usize>>{
+    match get_instruction(memory, registers) {
+        Instruction::BCD(_) => Some(registers.i..registers.i + 3),
+        Instruction::RegDump(reg) => Some(registers.i..registers.i + reg.index() + 1),
+        _ => None
+    }
 }
 
 
@@ -177,147 +564,174 @@ fn test_overflow_add(){
 
 /// Evaluate a - b, setting a flag if there was no underflow
 fn subtract_with_underflow(a: u8, b:u8) -> (u8, bool){
-    if b > a {
-        (u8::MAX - b + a, false)
-    } else {
-        (a - b, true)
-    }
+    (a.wrapping_sub(b), a >= b)
+}
+
+#[test]
+fn test_underflow_subtract(){
+    assert_eq!(subtract_with_underflow(0, 1), (255, false));
+    assert_eq!(subtract_with_underflow(3, 200), (59, false));
+    assert_eq!(subtract_with_underflow(5, 5), (0, true));
 }
 
 pub const INSTRUCTION_SIZE: usize = 2;
 
-/// Update the state of the emulator according to `instruction`
-pub(crate) fn do_instruction(memory: &mut Memory, registers: &mut Registers){
+/// Update the state of the emulator according to the next instruction,
+/// halting the CPU if the instruction faults
+pub(crate) fn do_instruction(memory: &mut Memory, registers: &mut Registers, rng: &mut impl RandomDevice){
     let instruction = get_instruction(memory, registers);
+    let result = ram_range(registers.pc, INSTRUCTION_SIZE)
+        .and_then(|_| execute(memory, registers, rng, instruction));
+    if let Err(fault) = result{
+        registers.state = CpuState::Halted(fault);
+        return
+    }
+    if !matches!(instruction, Instruction::Jump(_) | Instruction::JumpOffset(_) | Instruction::Call(_) ){
+        registers.pc += INSTRUCTION_SIZE;
+    }
+}
+
+/// The `len` bytes of RAM starting at `start`, or a fault if they run past the end of memory
+fn ram_range(start: usize, len: usize) -> Result<std::ops::Range<usize>, Fault>{
+    if start + len <= MEMORY_SIZE {Ok(start..start + len)} else {Err(Fault::MemoryOutOfRange)}
+}
+
+fn execute(memory: &mut Memory, registers: &mut Registers, rng: &mut impl RandomDevice, instruction: Instruction) -> Result<(), Fault>{
     match instruction {
-        Instruction::Nop => (),
-        Instruction::Jump(addr) => registers.pc = addr as usize,
+        // waiting for the frame is up to `Chip8`, which knows whether the extension is on
+        Instruction::Nop | Instruction::VSync => (),
+        Instruction::Jump(addr) => registers.pc = addr.index(),
         Instruction::Call(addr) => {  
             memory.stack.push(registers.pc);
-            registers.pc = addr as usize;          
+            registers.pc = addr.index();          
         }, 
         Instruction::Ret => {
-            registers.pc = memory.stack.pop().expect("Returning from empty call stack")
+            registers.pc = memory.stack.pop().ok_or(Fault::StackUnderflow)?
         }
         Instruction::SkipEqImm(reg,imm ) => {
-            if registers.vn[reg as usize] == imm {
+            if registers.vn[reg.index()] == imm {
                 registers.pc += INSTRUCTION_SIZE;
             }
         }
         Instruction::SkipNeImm(reg,imm ) => {
-            if registers.vn[reg as usize] != imm {
+            if registers.vn[reg.index()] != imm {
                 registers.pc += INSTRUCTION_SIZE
             }
         }
         Instruction::SkipEqReg(r1,r2 ) => {
-            if registers.vn[r1 as usize] == registers.vn[r2 as usize] {
+            if registers.vn[r1.index()] == registers.vn[r2.index()] {
                 registers.pc += INSTRUCTION_SIZE
             }
         }
         Instruction::SkipNeReg(r1, r2 ) => {
-            if registers.vn[r1 as usize] != registers.vn[r2 as usize] {
+            if registers.vn[r1.index()] != registers.vn[r2.index()] {
                 registers.pc += INSTRUCTION_SIZE
             }
         }
-        Instruction::SetImm(reg,imm) => registers.vn[reg as usize] = imm,
+        Instruction::SetImm(reg,imm) => registers.vn[reg.index()] = imm,
         Instruction::AddImm(reg,imm ) => {
-            let (result, _ ) = add_with_overflow(registers.vn[reg as usize], imm);
-            registers.vn[reg as usize] = result
+            let (result, _ ) = add_with_overflow(registers.vn[reg.index()], imm);
+            registers.vn[reg.index()] = result
         }
         Instruction::AddReg(vx, vy) => {
-            let x: u8 = registers.vn[vx as usize];
-            let y: u8 = registers.vn[vy as usize];
+            let x: u8 = registers.vn[vx.index()];
+            let y: u8 = registers.vn[vy.index()];
             let (result, flag) = add_with_overflow(x,y);
+            registers.vn[vx.index()] = result;
             registers.vn[15] = flag as u8;
-            registers.vn[vx as usize] = result;
         }
         Instruction::SubReg(vx, vy) => {
             let (result, flag) = subtract_with_underflow(
-                registers.vn[vx as usize], registers.vn[vy as usize]);
-            registers.vn[vx as usize] = result;
+                registers.vn[vx.index()], registers.vn[vy.index()]);
+            registers.vn[vx.index()] = result;
             registers.vn[15] = flag as u8;
         }
         Instruction::SubFrom(vx, vy) => {
-            let (result, flag) = subtract_with_underflow(vy, vx);
-            registers.vn[vx as usize] = result;
+            let (result, flag) = subtract_with_underflow(
+                registers.vn[vy.index()], registers.vn[vx.index()]);
+            registers.vn[vx.index()] = result;
             registers.vn[15] = flag as u8;
         }
         Instruction::ClearScreen => memory.display.fill(false),
         // Draws n bytes from memory on screen
         // Successive bytes are drawn one below the next
         Instruction::Draw(vx,vy ,n ) => {
-            let x = registers.vn[vx as usize] as usize;
-            let y = registers.vn[vy as usize] as usize;
+            let x = registers.vn[vx.index()] as usize;
+            let y = registers.vn[vy.index()] as usize;
             // assert!(x < DISPLAY_COLUMNS);
             // assert!(y < DISPLAY_ROWS);
             let mut collided = false;
-            for count in 0..n as usize{
-                let addr = registers.i + count;
+            for (count, addr) in ram_range(registers.i, n as usize)?.enumerate(){
                 let sprite_row = memory.ram[addr];
                 collided |= memory.set_row(x, y+count, sprite_row);
             }
             registers.vn[15] = collided as u8;
         },
         Instruction::SetChar(reg) => {
-            let char_index = registers.vn[reg as usize];
-            assert!(char_index < 16);
+            let char_index = registers.vn[reg.index()];
+            if char_index >= 16 {
+                return Err(Fault::InvalidCharacter(char_index))
+            }
             registers.i = char_index as usize * 5;
         }
         Instruction::SetMemPtr(imm) => {
-            registers.i = imm as usize;
+            registers.i = imm.index();
         }
-        Instruction::WaitForKey(reg) => registers.key_flag = Some(reg as usize),
-        Instruction::SetReg(r1, r2) => registers.vn[r1 as usize] = registers.vn[r2 as usize],
-        Instruction::OrReg(r1, r2) => registers.vn[r1 as usize] |= registers.vn[r2 as usize],
-        Instruction::AndReg(r1,r2) => registers.vn[r1 as usize] &= registers.vn[r2 as usize],
-        Instruction::XorReg(r1, r2) => registers.vn[r1 as usize] ^= registers.vn[r2 as usize],
+        Instruction::WaitForKey(reg) => registers.state = CpuState::WaitingForKey(reg),
+        Instruction::SetReg(r1, r2) => registers.vn[r1.index()] = registers.vn[r2.index()],
+        Instruction::OrReg(r1, r2) => registers.vn[r1.index()] |= registers.vn[r2.index()],
+        Instruction::AndReg(r1,r2) => registers.vn[r1.index()] &= registers.vn[r2.index()],
+        Instruction::XorReg(r1, r2) => registers.vn[r1.index()] ^= registers.vn[r2.index()],
+        // VF is written last, so that with VF as the operand it ends up holding the flag
         Instruction::Rsh(r1) => {
-            registers.vn[15] = registers.vn[r1 as usize] & 0x0001;
-            registers.vn[r1 as usize] >>= 1;
+            let flag = registers.vn[r1.index()] & 1;
+            registers.vn[r1.index()] >>= 1;
+            registers.vn[15] = flag;
         },
         Instruction::Lsh(r1) =>{
-            registers.vn[15] =registers.vn[r1 as usize] & (1<<7);
-             registers.vn[r1 as usize] <<= 1;
+            let flag = registers.vn[r1.index()] >> 7;
+            registers.vn[r1.index()] <<= 1;
+            registers.vn[15] = flag;
         },
-        Instruction::JumpOffset(imm) => registers.pc = (registers.vn[0] as u16 + imm) as usize,
-        Instruction::Rand(reg, imm) => registers.vn[reg as usize] = rand::random::<u8>() & imm,
-        Instruction::SkipKeyPressed(reg) => if memory.keys[registers.vn[reg as usize] as usize ] {
+        Instruction::JumpOffset(imm) => registers.pc = imm.index() + registers.vn[0] as usize,
+        Instruction::Rand(reg, imm) => registers.vn[reg.index()] = rng.next_byte() & imm,
+        Instruction::SkipKeyPressed(reg) => if key_pressed(memory, registers.vn[reg.index()])? {
             registers.pc += INSTRUCTION_SIZE
         },
-        Instruction::SkipKeyNotPressed(reg) => if !memory.keys[registers.vn[reg as usize] as usize ] {
+        Instruction::SkipKeyNotPressed(reg) => if !key_pressed(memory, registers.vn[reg.index()])? {
             registers.pc += INSTRUCTION_SIZE
         },
-        Instruction::GetDelay(reg) => registers.vn[reg as usize] = registers.delay,
-        Instruction::SetDelay(reg) => registers.delay = registers.vn[reg as usize],
-        Instruction::SetSound(reg) => registers.sound = registers.vn[reg as usize],
-        Instruction::AddMemPtr(reg) => registers.i += registers.vn[reg as usize] as usize, 
+        Instruction::GetDelay(reg) => registers.vn[reg.index()] = registers.delay,
+        Instruction::SetDelay(reg) => registers.delay = registers.vn[reg.index()],
+        Instruction::SetSound(reg) => registers.sound = registers.vn[reg.index()],
+        Instruction::AddMemPtr(reg) => registers.i += registers.vn[reg.index()] as usize, 
         Instruction::BCD(reg) => {
-            let val = registers.vn[reg as usize];
+            let val = registers.vn[reg.index()];
             let ones = val % 10;
             let tens = (val % 100) / 10;
             let hundreds = (val - tens - ones) / 100;
-            memory.ram[registers.i] = hundreds;
-            memory.ram[registers.i+1] = tens;
-            memory.ram[registers.i+2] = ones; 
+            memory.ram[ram_range(registers.i, 3)?].copy_from_slice(&[hundreds, tens, ones]);
         }
         Instruction::RegDump(reg) => {
-                memory.ram[registers.i..(registers.i + reg as usize) + 1].copy_from_slice(&registers.vn[0..(reg+1) as usize])
+                memory.ram[ram_range(registers.i, reg.index() + 1)?].copy_from_slice(&registers.vn[0..reg.index() + 1]);
         }
         Instruction::RegLoad(vx) => {
-            registers.vn[0..vx as usize + 1].copy_from_slice(&memory.ram[registers.i..registers.i + vx as usize + 1])
+            registers.vn[0..vx.index() + 1].copy_from_slice(&memory.ram[ram_range(registers.i, vx.index() + 1)?]);
         }
     }
-    if !matches!(instruction, Instruction::Jump(_) | Instruction::JumpOffset(_) | Instruction::Call(_) ){
-        registers.pc += INSTRUCTION_SIZE;
-    }
+    Ok(())
 }
 
+/// Whether the key numbered `key` is held, or a fault if there is no such key
+fn key_pressed(memory: &Memory, key: u8) -> Result<bool, Fault>{
+    (key < 16).then(|| memory.keypad.is_down(key)).ok_or(Fault::InvalidKey(key))
+}
 
 #[test]
 fn test_instructions(){
     assert_eq!(<u16 as Into<Instruction>>::into(0x00E0_u16), Instruction::ClearScreen);
-    assert_eq!(<u16 as Into<Instruction>>::into(0x1e35_u16), Instruction::Jump(0xe35));
-    assert_eq!(<u16 as Into<Instruction>>::into(0x5e30_u16), Instruction::SkipEqReg(0xe, 0x3));
+    assert_eq!(<u16 as Into<Instruction>>::into(0x1e35_u16), Instruction::Jump(Addr(0xe35)));
+    assert_eq!(<u16 as Into<Instruction>>::into(0x5e30_u16), Instruction::SkipEqReg(Reg(0xe), Reg(0x3)));
 }
 
 
@@ -331,9 +745,57 @@ fn test_instruction_in_memory() {
     registers.pc=1024;
     assert_eq!(get_instruction(&memory, &registers), Instruction::ClearScreen);
     registers.pc += INSTRUCTION_SIZE ;
-    assert_eq!(get_instruction(&memory, &registers), Instruction::Jump(0xe35));
+    assert_eq!(get_instruction(&memory, &registers), Instruction::Jump(Addr(0xe35)));
     registers.pc += INSTRUCTION_SIZE;
-    assert_eq!(get_instruction(&memory, &registers), Instruction::SkipEqReg(0xe, 0x3))
+    assert_eq!(get_instruction(&memory, &registers), Instruction::SkipEqReg(Reg(0xe), Reg(0x3)))
+}
+
+
+#[test]
+fn test_cycles_per_frame(){
+    assert_eq!(Chip8::init(Some(600)).cycles_per_frame(), 10);
+    assert_eq!(Chip8::init(Some(30)).cycles_per_frame(), 1);
+}
+
+
+#[test]
+fn test_embedding_api(){
+    let mut chip8 = Chip8::init(Some(60));
+    // ld v0 5; ld f v0; drw v1 v1 5; jp 0x206
+    chip8.write_memory(0x200, &[0x60, 0x05, 0xf0, 0x29, 0xd1, 0x15, 0x12, 0x06]).unwrap();
+    assert_eq!(chip8.read_memory(0x200, 2).unwrap(), &[0x60, 0x05]);
+    assert_eq!(chip8.read_memory(0xfff, 1).unwrap(), &[0]);
+    assert!(chip8.read_memory(0xfff, 2).is_none());
+    assert!(chip8.read_memory(usize::MAX, 2).is_none());
+    assert_eq!(chip8.write_memory(0xffe, &[1, 2, 3]).unwrap_err().message, "3 bytes at 0xffe run past the end of memory");
+    assert_eq!(chip8.read_memory(0xffe, 2).unwrap(), &[0, 0]);
+    for _ in 0..3{
+        chip8.run_frame();
+    }
+    assert_eq!(chip8.pc(), 0x206);
+    // top row of the '5' sprite is 0xf0
+    assert!(chip8.frame_buffer()[[3, 0]]);
+    assert!(!chip8.frame_buffer()[[4, 0]]);
+    chip8.press_key(0xa);
+    assert!(chip8.memory.keypad.is_down(0xa));
+    chip8.release_key(0xa);
+    assert!(!chip8.memory.keypad.is_down(0xa));
+    // there is no key 16, rather than it being key 0 or a panic
+    chip8.press_key(16);
+    chip8.press_key(0xff);
+    assert_eq!(chip8.keys_down(), 0);
+    assert_eq!(chip8.key_state(16), KeyState::Up);
+    chip8.release_key(16);
+}
+
+#[test]
+fn test_load_rom_too_big(){
+    let mut chip8 = Chip8::init(None);
+    chip8.load_rom(&[0x12; crate::rom_check::MAX_ROM_SIZE]).unwrap();
+    let err = chip8.load_rom(&[0; crate::rom_check::MAX_ROM_SIZE + 1]).unwrap_err();
+    assert_eq!(err.message, "The ROM is 3585 bytes, but at most 3584 bytes fit in memory");
+    // nothing was written
+    assert_eq!(chip8.read_memory(0x200, 1).unwrap(), &[0x12]);
 }
 
 
@@ -341,11 +803,11 @@ fn test_instruction_in_memory() {
 fn test_jump() {
     let rom = [0x12, 0x04, 0x00, 0x00, 0x12, 0x00];
     let mut memory = Memory::default();
-    memory.load_rom(&rom);
+    memory.load_rom(&rom).unwrap();
     let mut registers = Registers::default();
-    do_instruction(&mut memory, &mut registers);
+    do_instruction(&mut memory, &mut registers, &mut StdRng::seed_from_u64(0));
     assert_eq!(registers.pc, 0x204);
-    do_instruction(&mut memory, &mut registers);
+    do_instruction(&mut memory, &mut registers, &mut StdRng::seed_from_u64(0));
     assert_eq!(registers.pc, 0x200)
 }
 
@@ -354,18 +816,29 @@ fn test_jump() {
 fn test_call_ret() {
     let rom = [0x22, 0x04, 0x00, 0x00, 0x00, 0xEE];
     let mut memory = Memory::default();
-    memory.load_rom(&rom);
+    memory.load_rom(&rom).unwrap();
     let mut registers = Registers::default();
     assert_eq!(registers.pc, 0x200);
-    do_instruction(&mut memory, &mut registers);
+    do_instruction(&mut memory, &mut registers, &mut StdRng::seed_from_u64(0));
     assert_eq!(registers.pc, 0x204);
     assert_eq!(memory.stack.len(), 1);
     assert_eq!(memory.stack[memory.stack.len()-1],0x200);
-    do_instruction(&mut memory, &mut registers);
+    do_instruction(&mut memory, &mut registers, &mut StdRng::seed_from_u64(0));
     assert_eq!(registers.pc, 0x202);
     assert_eq!(memory.stack.len(),0)
 }
 
+#[test]
+fn test_call_stack(){
+    let mut chip8 = Chip8::init(None);
+    chip8.load_rom(&[0x22, 0x04, 0x00, 0x00, 0x22, 0x08, 0x00, 0x00, 0x00, 0xEE]).unwrap();
+    chip8.do_instruction();
+    chip8.do_instruction();
+    assert_eq!(chip8.call_stack(), [0x200, 0x204]);
+    chip8.do_instruction();
+    assert_eq!(chip8.call_stack(), [0x200]);
+}
+
 #[test]
 fn test_load_char(){
     let instrs: Vec<u16> = ["ld v0 1", "ld v1 0", "ld v2 0", "ld f 0", "drw 1 2 5"].into_iter().map(
@@ -373,10 +846,10 @@ fn test_load_char(){
     ).collect();
     let rom: Vec<u8> = instrs.iter().flat_map(|s|s.to_be_bytes()).collect();
     let mut memory = Memory::default();
-    memory.load_rom(&rom);
+    memory.load_rom(&rom).unwrap();
     let mut registers = Registers::default();
     for _ in 0..instrs.len() {
-        do_instruction(&mut memory, &mut registers);
+        do_instruction(&mut memory, &mut registers, &mut StdRng::seed_from_u64(0));
     }
     assert!(memory.display[[2,0]]);                                 // xx*x
     assert!(memory.display[[1,1]]); assert!(memory.display[[2,1]]); // x**x
@@ -385,3 +858,210 @@ fn test_load_char(){
     let slice = s![1..4,4];    // x***
     assert!(memory.display.slice(slice).iter().all(|f|*f))
 }
+
+#[test]
+fn test_wait_for_key(){
+    // ld v0 k; jp 0x200
+    let run = |wait_for_release: bool| {
+        let mut chip8 = Chip8::init(None);
+        chip8.load_rom(&[0xf0, 0x0a, 0x12, 0x00]).unwrap();
+        chip8.set_quirk(Quirk::WaitForRelease, wait_for_release);
+        chip8.do_instruction();
+        chip8.press_key(0x7);
+        chip8.do_instruction();
+        let after_press = (chip8.state(), chip8.registers.vn[0]);
+        // held across frames the way the driver holds keys: released and pressed again
+        chip8.clear_keys();
+        chip8.press_key(0x7);
+        chip8.do_instruction();
+        chip8.do_instruction();
+        let while_held = chip8.state();
+        chip8.clear_keys();
+        chip8.do_instruction();
+        (after_press, while_held, chip8.state(), chip8.registers.vn[0])
+    };
+    let waiting = CpuState::WaitingForKey(Reg(0));
+    // a held key doesn't count as another press
+    assert_eq!(run(false), ((CpuState::Running, 7), waiting, waiting, 7));
+    assert_eq!(run(true), ((waiting, 0), waiting, CpuState::Running, 7));
+}
+
+#[test]
+fn test_set_quirk(){
+    let mut chip8 = Chip8::init(None);
+    chip8.set_quirk(Quirk::WaitForRelease, true);
+    chip8.set_quirk(Quirk::WaitForRelease, true);
+    chip8.set_quirk(Quirk::DisplayWait, false);
+    assert_eq!(chip8.quirks().enabled().collect::<Vec<_>>(), vec![Quirk::WaitForRelease]);
+    // only a change is reported, and a clear doesn't drop it
+    chip8.reset();
+    assert_eq!(chip8.display_events().iter().copied().collect::<Vec<_>>(),
+        [DisplayEvent::QuirkChanged{ quirk: Quirk::WaitForRelease, enabled: true }, DisplayEvent::Clear]);
+    assert_eq!(chip8.take_quirk_changes(), [(Quirk::WaitForRelease, true)]);
+    assert!(chip8.take_quirk_changes().is_empty());
+    assert!(chip8.display_events().cleared());
+}
+
+#[test]
+fn test_protect_low_memory(){
+    // ld i 0x1fe; ld v3 0xaa; ld [i] v3
+    let rom = [0xa1, 0xfe, 0x63, 0xaa, 0xf3, 0x55];
+    let mut chip8 = Chip8::init(None);
+    chip8.set_quirk(Quirk::ProtectLowMemory, true);
+    chip8.load_rom(&rom).unwrap();
+    let font = chip8.memory.ram[0x1fe..0x200].to_vec();
+    (0..3).for_each(|_| chip8.do_instruction());
+    // only the bytes below 0x200 are kept, and the rest of the instruction still happens
+    assert_eq!(chip8.memory.ram[0x1fe..0x200], font[..]);
+    assert_eq!((chip8.memory.ram[0x200], chip8.registers.i, chip8.pc()), (0x00, 0x1fe, 0x206));
+    assert_eq!(chip8.take_dropped_write(), Some((0x1fe, 0x204)));
+    assert_eq!(chip8.take_dropped_write(), None);
+
+    let mut chip8 = Chip8::init(None);
+    chip8.set_quirk(Quirk::ProtectLowMemory, true);
+    chip8.set_halt_on(HaltCondition::ProtectedWrite, true);
+    chip8.load_rom(&rom).unwrap();
+    (0..3).for_each(|_| chip8.do_instruction());
+    assert_eq!((chip8.fault(), chip8.pc()), (Some(Fault::ProtectedWrite(0x1fe)), 0x204));
+}
+
+#[test]
+fn test_catch_panic(){
+    let mut chip8 = Chip8::init(None);
+    chip8.load_rom(&[0x60, 0x07]).unwrap();
+    assert_eq!(chip8.catch_panic(Chip8::do_instruction), None);
+    assert_eq!(chip8.state(), CpuState::Running);
+    let message = chip8.catch_panic(|chip8| panic!("broke at {:#x}", chip8.pc()));
+    assert_eq!(message.as_deref(), Some("broke at 0x202"));
+    assert_eq!(chip8.fault(), Some(Fault::Panic));
+    chip8.resume();
+    assert_eq!(chip8.state(), CpuState::Running);
+}
+
+#[test]
+fn test_cpu_state(){
+    // ld v0 k; drw v0 v0 1; ret
+    let mut chip8 = Chip8::init(None);
+    chip8.load_rom(&[0xf0, 0x0a, 0xd0, 0x01, 0x00, 0xee]).unwrap();
+    chip8.set_quirk(Quirk::DisplayWait, true);
+    chip8.do_instruction();
+    assert_eq!(chip8.state(), CpuState::WaitingForKey(Reg(0)));
+    chip8.do_instruction();
+    assert_eq!(chip8.pc(), 0x202);
+    chip8.press_key(0x3);
+    assert_eq!(chip8.key_state(0x3), KeyState::Pressed);
+    // the press ends the wait and the next instruction runs
+    chip8.do_instruction();
+    assert_eq!(chip8.registers.vn[0], 0x3);
+    assert_eq!(chip8.state(), CpuState::WaitingForDisplay);
+    assert_eq!(chip8.key_state(0x3), KeyState::Held);
+    chip8.tick_timers();
+    assert_eq!(chip8.state(), CpuState::Running);
+    chip8.do_instruction();
+    assert_eq!(chip8.state(), CpuState::Halted(Fault::StackUnderflow));
+    assert_eq!(chip8.fault(), Some(Fault::StackUnderflow));
+    assert_eq!(chip8.pc(), 0x204);
+
+    // jp 0xfff, leaving half an instruction before the end of RAM
+    let mut chip8 = Chip8::init(None);
+    chip8.load_rom(&[0x1f, 0xff]).unwrap();
+    chip8.do_instruction();
+    assert_eq!(chip8.fault(), None);
+    chip8.do_instruction();
+    assert_eq!((chip8.fault(), chip8.pc()), (Some(Fault::MemoryOutOfRange), 0xfff));
+}
+
+#[test]
+fn test_stack_overflow(){
+    // call 0x200
+    let mut chip8 = Chip8::init(None);
+    chip8.load_rom(&[0x22, 0x00]).unwrap();
+    chip8.set_stack_depth(3);
+    for _ in 0..4{
+        chip8.do_instruction();
+    }
+    assert_eq!(chip8.fault(), Some(Fault::StackOverflow(3)));
+    assert_eq!((chip8.call_stack().len(), chip8.pc()), (3, 0x200));
+    assert_eq!(chip8.fault().unwrap().to_string(), "called a subroutine with 3 calls already on the stack");
+}
+
+#[test]
+fn test_halt_conditions(){
+    // 0x0123; jp 0x202
+    let mut chip8 = Chip8::init(None);
+    chip8.load_rom(&[0x01, 0x23, 0x12, 0x02]).unwrap();
+    chip8.do_instruction();
+    chip8.do_instruction();
+    assert_eq!((chip8.fault(), chip8.pc()), (None, 0x202));
+
+    chip8.reset();
+    chip8.load_rom(&[0x01, 0x23, 0x12, 0x02]).unwrap();
+    chip8.set_halt_on(HaltCondition::InvalidOpcode, true);
+    chip8.set_halt_on(HaltCondition::SelfJump, true);
+    chip8.do_instruction();
+    assert_eq!((chip8.fault(), chip8.pc()), (Some(Fault::InvalidOpcode(0x0123)), 0x200));
+    chip8.resume();
+    chip8.do_instruction();
+    assert_eq!((chip8.fault(), chip8.pc()), (Some(Fault::SelfJump), 0x202));
+}
+
+#[test]
+fn test_vsync(){
+    // vsync; vsync
+    let mut chip8 = Chip8::init(None);
+    chip8.load_rom(&[0x00, 0xf1, 0x00, 0xf1]).unwrap();
+    chip8.do_instruction();
+    assert_eq!((chip8.state(), chip8.pc()), (CpuState::Running, 0x202));
+    chip8.set_extension(Extension::VSync, true);
+    chip8.do_instruction();
+    assert_eq!((chip8.state(), chip8.pc()), (CpuState::WaitingForDisplay, 0x204));
+    chip8.do_instruction();
+    assert_eq!(chip8.pc(), 0x204);
+    chip8.tick_timers();
+    assert_eq!(chip8.state(), CpuState::Running);
+}
+
+#[test]
+fn test_seeded_rng(){
+    // rnd v0 0xff; rnd v1 0xff; rnd v2 0x0f
+    let rom = [0xc0, 0xff, 0xc1, 0xff, 0xc2, 0x0f];
+    let run = |seed: u64| {
+        let mut chip8 = Chip8::init(None);
+        chip8.seed_rng(seed);
+        chip8.load_rom(&rom).unwrap();
+        (0..3).for_each(|_| chip8.do_instruction());
+        chip8.registers.vn[..3].to_vec()
+    };
+    assert_eq!(run(7), run(7));
+    assert_ne!(run(7), run(8));
+    assert!(run(7)[2] <= 0x0f);
+
+    let mut chip8 = Chip8::init(None);
+    chip8.seed_rng(7);
+    assert_eq!(chip8.rng_seed(), 7);
+    chip8.press_key(0x3);
+    chip8.press_key(0xa);
+    assert_eq!(chip8.keys_down(), 1 << 0x3 | 1 << 0xa);
+}
+
+#[test]
+fn test_coverage_tracking(){
+    use crate::coverage::{EXECUTED, INSTRUCTION_START, READ, WRITTEN};
+    // ld i 0x20a; drw v0 v1 5; ld b v0; jp 0x206; then five bytes of sprite
+    let rom = [0xa2, 0x0a, 0xd0, 0x15, 0xf0, 0x33, 0x12, 0x06, 0x00, 0x00, 0xf0, 0x90, 0x90, 0x90, 0xf0];
+    let mut chip8 = Chip8::init(None);
+    chip8.load_rom(&rom).unwrap();
+    chip8.do_instruction();
+    assert!(chip8.coverage().is_none());
+    chip8.set_coverage(true);
+    (0..4).for_each(|_| chip8.do_instruction());
+    let coverage = chip8.coverage().unwrap();
+    // the first instruction ran before tracking started
+    assert_eq!(coverage.get(0x200), 0);
+    assert_eq!(coverage.get(0x202), EXECUTED | INSTRUCTION_START);
+    assert_eq!(coverage.get(0x207), EXECUTED);
+    assert_eq!(coverage.get(0x208), 0);
+    assert_eq!(coverage.get(0x20a), READ | WRITTEN);
+    assert_eq!(coverage.get(0x20e), READ);
+    assert_eq!(coverage.counts(), (6, 5, 3));
+}
@@ -1,18 +1,213 @@
+use std::fmt::Display;
 
-/// Represents an error encountered while parsing chip8 ASM
-#[derive(Debug)]
-pub struct ParseError{
-    /// The putative mnemonic encountered
-    pub mnemonic: String,
-    /// Additional error message
-    pub message: String
+use crate::Addr;
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity{
+    Error,
+    Warning,
+    Note
+}
+
+impl Display for Severity{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A range of source text, from a 1-based line and column
+/// up to (not including) `end_line` and `end_column`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span{
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize
+}
+
+impl Span{
+    pub fn new(line: usize, column: usize, end_line: usize, end_column: usize) -> Self{
+        Self { line, column, end_line, end_column }
+    }
+
+    /// A single position
+    pub fn point(line: usize, column: usize) -> Self{
+        Self::new(line, column, line, column)
+    }
+
+    /// The whole of line `line`, which is `text`
+    pub fn whole_line(line: usize, text: &str) -> Self{
+        Self::new(line, 1, line, text.chars().count() + 1)
+    }
 }
 
-impl ParseError{
-    pub fn new(mnemonic: &str, message: &str)-> Self{
+/// A problem found by the assembler, the debugger or the emulator itself:
+/// a message, with where in the source and/or where in memory it happened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic{
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<Span>,
+    /// The text of the line `span` starts on
+    pub source_line: Option<String>,
+    pub address: Option<Addr>
+}
+
+impl Diagnostic{
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self{
         Self{
-            mnemonic: mnemonic.to_string(),
-            message: message.to_string()
+            severity,
+            message: message.into(),
+            span: None,
+            source_line: None,
+            address: None
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self{
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self{
+        Self::new(Severity::Warning, message)
+    }
+
+    /// Point the diagnostic at `span`, which starts on the line `source_line`
+    pub fn with_span(mut self, span: Span, source_line: &str) -> Self{
+        self.span = Some(span);
+        self.source_line = Some(source_line.to_string());
+        self
+    }
+
+    pub fn at_address(mut self, address: Addr) -> Self{
+        self.address = Some(address);
+        self
+    }
+
+    /// Several lines for a terminal, underlining the offending text
+    /// and naming `path` as the source file if there is one
+    pub fn render(&self, path: Option<&str>) -> String{
+        let mut text = format!("{}: {}", self.severity, self.message);
+        let location = match (path, self.span) {
+            (Some(path), Some(span)) => Some(format!("{}:{}:{}", path, span.line, span.column)),
+            (None, Some(span)) => Some(format!("{}:{}", span.line, span.column)),
+            (Some(path), None) => Some(path.to_string()),
+            (None, None) => None
+        };
+        let location = match (location, self.address) {
+            (Some(location), Some(address)) => Some(format!("{} ({})", location, address)),
+            (None, Some(address)) => Some(address.to_string()),
+            (location, None) => location
+        };
+        if let Some(location) = location{
+            text += &format!("\n --> {}", location);
         }
+        if let (Some(span), Some(source_line)) = (self.span, &self.source_line){
+            let gutter = " ".repeat(span.line.to_string().len());
+            // spans over several lines are underlined to the end of the first
+            let end = if span.end_line == span.line {span.end_column} else {source_line.chars().count() + 1};
+            let underline = "^".repeat(end.saturating_sub(span.column).max(1));
+            text += &format!("\n{} |\n{} | {}\n{} | {}{}", gutter, span.line, source_line,
+                gutter, " ".repeat(span.column.saturating_sub(1)), underline);
+        }
+        text
+    }
+
+    /// The diagnostic as a JSON object, for editors and other tools
+    pub fn to_json(&self) -> String{
+        let mut fields = vec![
+            format!("\"severity\":\"{}\"", self.severity),
+            format!("\"message\":{}", json_string(&self.message))
+        ];
+        if let Some(span) = self.span{
+            fields.push(format!("\"span\":{{\"line\":{},\"column\":{},\"end_line\":{},\"end_column\":{}}}",
+                span.line, span.column, span.end_line, span.end_column));
+        }
+        if let Some(source_line) = &self.source_line{
+            fields.push(format!("\"source_line\":{}", json_string(source_line)));
+        }
+        if let Some(address) = self.address{
+            fields.push(format!("\"address\":{}", u16::from(address)));
+        }
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+/// `text` as a quoted JSON string
+pub fn json_string(text: &str) -> String{
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars(){
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c)
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// One line: where, how serious, and what
+impl Display for Diagnostic{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(span) = self.span{
+            write!(f, "{}:{}: ", span.line, span.column)?;
+        }
+        if let Some(address) = self.address{
+            write!(f, "{}: ", address)?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)
     }
 }
+
+impl std::error::Error for Diagnostic {}
+
+/// The one of `values` whose `Display` is `name`, ignoring case, for the `FromStr` of enums named like `two-player`.
+/// Fails listing the names a `kind` can have
+pub(crate) fn parse_name<T: Copy + Display>(values: &[T], kind: &str, name: &str) -> Result<T, Diagnostic>{
+    values.iter().copied().find(|value| value.to_string().eq_ignore_ascii_case(name.trim())).ok_or_else(|| {
+        let names: Vec<_> = values.iter().map(|value| value.to_string()).collect();
+        Diagnostic::error(format!("No {kind} named {name}, expected one of {}", names.join(", ")))
+    })
+}
+
+/// Thrown in JavaScript as the message, for methods exported to the page
+#[cfg(feature = "wasm")]
+impl From<Diagnostic> for wasm_bindgen::JsValue{
+    fn from(diagnostic: Diagnostic) -> Self {
+        Self::from_str(&diagnostic.message)
+    }
+}
+
+#[test]
+fn test_diagnostic_rendering(){
+    let diagnostic = Diagnostic::error("undefined label nowhere")
+        .with_span(Span::new(2, 4, 2, 11), "jp nowhere;");
+    assert_eq!(diagnostic.to_string(), "2:4: error: undefined label nowhere");
+    assert_eq!(diagnostic.render(Some("main.ch8sm")),
+        "error: undefined label nowhere\n --> main.ch8sm:2:4\n  |\n2 | jp nowhere;\n  |    ^^^^^^^");
+
+    let fault = Diagnostic::error("stack underflow").at_address(Addr(0x2a4));
+    assert_eq!(fault.to_string(), "0x2a4: error: stack underflow");
+    assert_eq!(fault.render(None), "error: stack underflow\n --> 0x2a4");
+    assert_eq!(fault.to_json(), r#"{"severity":"error","message":"stack underflow","address":676}"#);
+    assert_eq!(Diagnostic::warning("say \"hi\"\n").to_json(), r#"{"severity":"warning","message":"say \"hi\"\n"}"#);
+}
+
+#[test]
+fn test_parse_name(){
+    let severities = [Severity::Error, Severity::Warning, Severity::Note];
+    assert_eq!(parse_name(&severities, "severity", " Warning"), Ok(Severity::Warning));
+    assert_eq!(parse_name(&severities, "severity", "fatal").unwrap_err().message,
+        "No severity named fatal, expected one of error, warning, note");
+}
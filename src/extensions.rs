@@ -0,0 +1,38 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::errors::{parse_name, Diagnostic};
+
+/// Opcodes beyond the original CHIP-8 instruction set, which only run when switched on.
+/// They use `0NNN` opcodes, which other interpreters skip as machine code calls,
+/// so ROMs that use an extension still run elsewhere, just without it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Extension{
+    /// `VSYNC` (0x00F1) waits for the next 60 hz frame, to pace a game loop without
+    /// busy-waiting on the delay timer.
+    /// With the display wait of `--timing original`, a DRW already ends the frame,
+    /// so a VSYNC after it waits for the end of the frame after that
+    #[cfg_attr(feature = "cli", value(name = "vsync"))]
+    VSync
+}
+
+impl Extension{
+    pub const ALL: [Extension; 1] = [Extension::VSync];
+}
+
+impl Display for Extension{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Extension::VSync => write!(f, "vsync")
+        }
+    }
+}
+
+/// Parses the names `--extension` takes, e.g. `vsync`
+impl FromStr for Extension{
+    type Err = Diagnostic;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_name(&Self::ALL, "extension", s)
+    }
+}
@@ -0,0 +1,131 @@
+use std::{collections::VecDeque, path::Path, time::{Duration, Instant}};
+
+/// How long one pass of the main loop took, for tracking down stutter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FrameTiming{
+    /// When the pass started, since timing began
+    pub start: Duration,
+    /// Time since the previous pass, 1/60 of a second when everything keeps up
+    pub interval: Duration,
+    /// Time spent running instructions
+    pub emulation: Duration,
+    /// Time spent drawing the frame and handing it to the frontend
+    pub presentation: Duration,
+    /// How many 60 hz frames were emulated, more than one while catching up or fast-forwarding
+    pub frames_run: u32,
+    /// How many instructions were executed
+    pub instructions: u64
+}
+
+/// Frame timings as they are recorded, either the latest few for the debug graph
+/// or every one since the start for `--timing-log`
+#[derive(Debug, Clone)]
+pub struct TimingLog{
+    started: Instant,
+    samples: VecDeque<FrameTiming>,
+    /// Most samples kept, or `None` to keep all of them
+    capacity: Option<usize>
+}
+
+impl TimingLog{
+    /// Enough for the debug graph, four seconds
+    pub const RECENT: usize = 240;
+    pub const CSV_HEADER: &'static str = "frame,start_ms,interval_ms,emulation_ms,presentation_ms,frames_run,instructions";
+
+    pub fn new(capacity: Option<usize>) -> Self{
+        Self {
+            started: Instant::now(),
+            samples: VecDeque::new(),
+            capacity
+        }
+    }
+
+    /// Time from when timing began until `instant`
+    pub fn since_start(&self, instant: Instant) -> Duration{
+        instant.saturating_duration_since(self.started)
+    }
+
+    pub fn push(&mut self, timing: FrameTiming){
+        if self.capacity.is_some_and(|capacity| self.samples.len() >= capacity){
+            self.samples.pop_front();
+        }
+        self.samples.push_back(timing);
+    }
+
+    pub fn len(&self) -> usize{
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.samples.is_empty()
+    }
+
+    /// The last `count` timings, oldest first
+    pub fn recent(&self, count: usize) -> Vec<FrameTiming>{
+        self.samples.iter().skip(self.samples.len().saturating_sub(count)).copied().collect()
+    }
+
+    /// Every timing as a row of CSV, in milliseconds
+    pub fn to_csv(&self) -> String{
+        let mut csv = format!("{}\n", Self::CSV_HEADER);
+        for (frame, timing) in self.samples.iter().enumerate(){
+            csv.push_str(&format!("{},{:.3},{:.3},{:.3},{:.3},{},{}\n", frame, millis(timing.start), millis(timing.interval),
+                millis(timing.emulation), millis(timing.presentation), timing.frames_run, timing.instructions));
+        }
+        csv
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()>{
+        std::fs::write(path, self.to_csv())
+    }
+
+    /// The median, 90th and 99th percentiles and the longest of one measurement, in that order
+    pub fn percentiles(&self, measure: impl Fn(&FrameTiming) -> Duration) -> [Duration; 4]{
+        let mut values: Vec<_> = self.samples.iter().map(measure).collect();
+        values.sort();
+        // nearest rank
+        let rank = |percent: usize| values.get((values.len() * percent).div_ceil(100).saturating_sub(1)).copied().unwrap_or_default();
+        [rank(50), rank(90), rank(99), values.last().copied().unwrap_or_default()]
+    }
+
+    /// A table of the percentiles of each measurement, in milliseconds
+    pub fn summary(&self) -> String{
+        let mut summary = format!("{} frames, times in ms   p50     p90     p99     max\n", self.len());
+        let rows = [
+            ("interval", self.percentiles(|timing| timing.interval)),
+            ("emulation", self.percentiles(|timing| timing.emulation)),
+            ("presentation", self.percentiles(|timing| timing.presentation))
+        ];
+        for (name, [p50, p90, p99, max]) in rows{
+            summary.push_str(&format!("  {:<20}{:>8.2}{:>8.2}{:>8.2}{:>8.2}\n", name, millis(p50), millis(p90), millis(p99), millis(max)));
+        }
+        summary
+    }
+}
+
+fn millis(duration: Duration) -> f64{
+    duration.as_secs_f64() * 1000.0
+}
+
+
+#[test]
+fn test_timing_log(){
+    let mut log = TimingLog::new(Some(3));
+    for ms in 1..=5{
+        log.push(FrameTiming{ interval: Duration::from_millis(ms), frames_run: 1, ..FrameTiming::default() });
+    }
+    assert_eq!(log.len(), 3);
+    let recent: Vec<_> = log.recent(2).iter().map(|timing| timing.interval.as_millis()).collect();
+    assert_eq!(recent, vec![4, 5]);
+    let csv = log.to_csv();
+    assert_eq!(csv.lines().next(), Some(TimingLog::CSV_HEADER));
+    assert_eq!(csv.lines().nth(1), Some("0,0.000,3.000,0.000,0.000,1,0"));
+
+    let mut log = TimingLog::new(None);
+    for ms in 1..=100{
+        log.push(FrameTiming{ interval: Duration::from_millis(ms), ..FrameTiming::default() });
+    }
+    assert_eq!(log.percentiles(|timing| timing.interval).map(|duration| duration.as_millis()), [50, 90, 99, 100]);
+    assert!(log.summary().lines().nth(1).unwrap().contains("interval"));
+    assert_eq!(TimingLog::new(None).percentiles(|timing| timing.interval), [Duration::ZERO; 4]);
+}
@@ -0,0 +1,118 @@
+use std::{collections::{HashMap, VecDeque}, hash::Hash, time::{Duration, Instant}};
+
+use super::KeyInput;
+
+/// A key or button going down or coming up, as a frontend saw it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawEvent<B>{
+    Press(B),
+    Release(B)
+}
+
+/// Turns a frontend's key and button events into `KeyInput`s, so that every frontend repeats and holds keys the same way.
+/// Frontends queue raw events as they come in, or report which bindings are down if they poll, then take a frame's inputs.
+/// A binding sends its input on the frame it goes down, even if it came up again before the frame ended.
+/// While it stays down, CHIP-8 keys and fast-forward are sent every frame,
+/// and the emulator's other keys repeat every frame once held for `REPEAT_DELAY`
+pub struct InputManager<B>{
+    bindings: HashMap<B, KeyInput>,
+    events: VecDeque<RawEvent<B>>,
+    /// Bindings down, in the order they went down, and when
+    held: Vec<(B, Instant)>
+}
+
+impl<B: Copy + Eq + Hash> InputManager<B>{
+    pub const REPEAT_DELAY: Duration = Duration::from_millis(250);
+
+    pub fn new(bindings: HashMap<B, KeyInput>) -> Self{
+        Self{ bindings, events: VecDeque::new(), held: vec![] }
+    }
+
+    pub fn bindings(&self) -> &HashMap<B, KeyInput>{
+        &self.bindings
+    }
+
+    /// Replace the bindings, forgetting any held down
+    pub fn set_bindings(&mut self, bindings: HashMap<B, KeyInput>){
+        self.bindings = bindings;
+        self.events.clear();
+        self.held.clear();
+    }
+
+    pub fn push(&mut self, event: RawEvent<B>){
+        self.events.push_back(event);
+    }
+
+    /// For frontends that poll: queue a press or release if `binding` has gone down or come up since the last frame
+    #[cfg_attr(not(feature = "raylib"), allow(dead_code))]
+    pub fn set_down(&mut self, binding: B, down: bool){
+        let held = self.held.iter().any(|(other, _)| *other == binding);
+        match (down, held) {
+            (true, false) => self.push(RawEvent::Press(binding)),
+            (false, true) => self.push(RawEvent::Release(binding)),
+            _ => {}
+        }
+    }
+
+    /// The inputs for the frame starting at `now`, emptying the queue
+    pub fn frame(&mut self, now: Instant) -> Vec<KeyInput>{
+        let mut inputs = vec![];
+        let mut pressed = vec![];
+        while let Some(event) = self.events.pop_front(){
+            match event {
+                RawEvent::Press(binding) => {
+                    let Some(input) = self.bindings.get(&binding) else {continue};
+                    if !self.held.iter().any(|(other, _)| *other == binding){
+                        self.held.push((binding, now));
+                        inputs.push(*input);
+                        pressed.push(binding);
+                    }
+                },
+                RawEvent::Release(binding) => self.held.retain(|(other, _)| *other != binding)
+            }
+        }
+        for (binding, since) in self.held.iter(){
+            let input = self.bindings[binding];
+            let repeats = match input {
+                KeyInput::Chip8Key(_) | KeyInput::Turbo => true,
+                _ => now.duration_since(*since) > Self::REPEAT_DELAY
+            };
+            if repeats && !pressed.contains(binding){
+                inputs.push(input);
+            }
+        }
+        inputs
+    }
+}
+
+
+#[test]
+fn test_input_manager(){
+    let bindings = HashMap::from([('x', KeyInput::Chip8Key(0)), ('p', KeyInput::TogglePause), ('\t', KeyInput::Turbo)]);
+    let mut manager = InputManager::new(bindings);
+    let start = Instant::now();
+    let frame = |manager: &mut InputManager<char>, ms: u64| -> Vec<String> {
+        manager.frame(start + Duration::from_millis(ms)).iter().map(|input| format!("{input:?}")).collect()
+    };
+    manager.set_down('x', true);
+    manager.set_down('p', true);
+    manager.set_down('?', true);
+    assert_eq!(frame(&mut manager, 0), ["Chip8Key(0)", "TogglePause"]);
+    // the keypad key is held every frame, the pause key waits to repeat
+    manager.set_down('x', true);
+    manager.set_down('p', true);
+    assert_eq!(frame(&mut manager, 16), ["Chip8Key(0)"]);
+    assert_eq!(frame(&mut manager, 300), ["Chip8Key(0)", "TogglePause"]);
+    manager.set_down('x', false);
+    manager.set_down('p', false);
+    assert!(frame(&mut manager, 316).is_empty());
+
+    // a tap within one frame is still sent once
+    manager.push(RawEvent::Press('p'));
+    manager.push(RawEvent::Release('p'));
+    manager.push(RawEvent::Press('\t'));
+    assert_eq!(frame(&mut manager, 332), ["TogglePause", "Turbo"]);
+    assert_eq!(frame(&mut manager, 348), ["Turbo"]);
+    manager.set_bindings(HashMap::new());
+    assert!(frame(&mut manager, 364).is_empty());
+}
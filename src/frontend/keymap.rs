@@ -0,0 +1,312 @@
+use std::collections::{BTreeMap, HashMap};
+
+use raylib::consts::{GamepadButton, KeyboardKey};
+
+use crate::{errors::Diagnostic, keypad::KeyProfile, quirks::Quirk, settings::PadButton};
+
+use super::KeyInput;
+
+/// A keyboard key or gamepad button that produces a `KeyInput`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) enum Binding {
+    Key(KeyboardKey),
+    /// A key pressed while holding a modifier
+    Chord(Modifier, KeyboardKey),
+    Pad(GamepadButton),
+    /// A button of the on-screen keypad, held by the mouse or a touch
+    Touch(u8)
+}
+
+/// A key held down to turn another key into a chord
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) enum Modifier {
+    Shift,
+    Control
+}
+
+impl Modifier{
+    /// The left and right keys for this modifier
+    pub(super) fn keys(self) -> [KeyboardKey; 2]{
+        match self {
+            Modifier::Shift => [KeyboardKey::KEY_LEFT_SHIFT, KeyboardKey::KEY_RIGHT_SHIFT],
+            Modifier::Control => [KeyboardKey::KEY_LEFT_CONTROL, KeyboardKey::KEY_RIGHT_CONTROL]
+        }
+    }
+}
+
+/// The keyboard layout: the CHIP-8 keypad on 1234/QWER/ASDF/ZXCV, then the emulator's own keys.
+/// `web/keymap.js` has the same keypad layout
+pub(super) const KEYMAP: [(KeyboardKey,KeyInput); 41] = [
+    (KeyboardKey::KEY_ONE, KeyInput::Chip8Key(0x1)),
+    (KeyboardKey::KEY_TWO,  KeyInput::Chip8Key(0x2)),
+    (KeyboardKey::KEY_THREE,KeyInput::Chip8Key( 0x3)),
+    (KeyboardKey::KEY_FOUR, KeyInput::Chip8Key(0xc)),
+    (KeyboardKey::KEY_Q, KeyInput::Chip8Key(0x4)),
+    (KeyboardKey::KEY_W, KeyInput::Chip8Key(0x5)),
+    (KeyboardKey::KEY_E, KeyInput::Chip8Key(0x6)),
+    (KeyboardKey::KEY_R, KeyInput::Chip8Key(0xd)),
+    (KeyboardKey::KEY_A, KeyInput::Chip8Key(0x7)),
+    (KeyboardKey::KEY_S, KeyInput::Chip8Key(0x8)),
+    (KeyboardKey::KEY_D, KeyInput::Chip8Key(0x9)),
+    (KeyboardKey::KEY_F, KeyInput::Chip8Key(0xe)),
+    (KeyboardKey::KEY_Z, KeyInput::Chip8Key(0xa)),
+    (KeyboardKey::KEY_X, KeyInput::Chip8Key(0x0)),
+    (KeyboardKey::KEY_C, KeyInput::Chip8Key(0xb)),
+    (KeyboardKey::KEY_V, KeyInput::Chip8Key(0xf)),
+    (KeyboardKey::KEY_SPACE, KeyInput::TogglePause),
+    (KeyboardKey::KEY_P, KeyInput::TogglePause),
+    (KeyboardKey::KEY_PERIOD, KeyInput::ToggleDebug),
+    (KeyboardKey::KEY_ENTER, KeyInput::Step),
+    (KeyboardKey::KEY_T, KeyInput::StepTimers),
+    (KeyboardKey::KEY_N, KeyInput::StepFrame),
+    (KeyboardKey::KEY_BACKSPACE, KeyInput::StepBack),
+    (KeyboardKey::KEY_F2, KeyInput::ExportDebugScript),
+    (KeyboardKey::KEY_F3, KeyInput::ToggleTrace),
+    (KeyboardKey::KEY_F4, KeyInput::DumpTrace),
+    (KeyboardKey::KEY_F5, KeyInput::ToggleCollisions),
+    (KeyboardKey::KEY_F6, KeyInput::ExportRom),
+    (KeyboardKey::KEY_F7, KeyInput::Screenshot),
+    (KeyboardKey::KEY_F8, KeyInput::ToggleRecording),
+    (KeyboardKey::KEY_G, KeyInput::ToggleGrid),
+    (KeyboardKey::KEY_K, KeyInput::ToggleVirtualKeypad),
+    (KeyboardKey::KEY_RIGHT_BRACKET, KeyInput::PitchUp),
+    (KeyboardKey::KEY_LEFT_BRACKET, KeyInput::PitchDown),
+    (KeyboardKey::KEY_EQUAL, KeyInput::SpeedUp),
+    (KeyboardKey::KEY_MINUS, KeyInput::SpeedDown),
+    (KeyboardKey::KEY_TAB, KeyInput::Turbo),
+    (KeyboardKey::KEY_F9, KeyInput::ToggleQuirk(Quirk::DisplayWait)),
+    (KeyboardKey::KEY_F10, KeyInput::ToggleQuirk(Quirk::WaitForRelease)),
+    (KeyboardKey::KEY_F11, KeyInput::ToggleQuirk(Quirk::ProtectLowMemory)),
+    (KeyboardKey::KEY_F12, KeyInput::SaveCrashBundle)
+];
+
+/// Emulator keys that need a modifier held, so more of them fit around the keypad
+pub(super) const CHORDS: [(Modifier, KeyboardKey, KeyInput); 17] = [
+    (Modifier::Shift, KeyboardKey::KEY_ENTER, KeyInput::StepOver),
+    (Modifier::Control, KeyboardKey::KEY_ENTER, KeyInput::StepOut),
+    (Modifier::Control, KeyboardKey::KEY_SPACE, KeyInput::Reset),
+    (Modifier::Shift, KeyboardKey::KEY_SPACE, KeyInput::SoftReset),
+    (Modifier::Shift, KeyboardKey::KEY_TAB, KeyInput::NextKeyProfile),
+    (Modifier::Shift, KeyboardKey::KEY_K, KeyInput::RemapKeys),
+    (Modifier::Control, KeyboardKey::KEY_INSERT, KeyInput::Copy),
+    (Modifier::Shift, KeyboardKey::KEY_INSERT, KeyInput::Paste),
+    (Modifier::Shift, KeyboardKey::KEY_F1, KeyInput::OpenRecent(1)),
+    (Modifier::Shift, KeyboardKey::KEY_F2, KeyInput::OpenRecent(2)),
+    (Modifier::Shift, KeyboardKey::KEY_F3, KeyInput::OpenRecent(3)),
+    (Modifier::Shift, KeyboardKey::KEY_F4, KeyInput::OpenRecent(4)),
+    (Modifier::Shift, KeyboardKey::KEY_F5, KeyInput::OpenRecent(5)),
+    (Modifier::Shift, KeyboardKey::KEY_F6, KeyInput::OpenRecent(6)),
+    (Modifier::Shift, KeyboardKey::KEY_F7, KeyInput::OpenRecent(7)),
+    (Modifier::Shift, KeyboardKey::KEY_F8, KeyInput::OpenRecent(8)),
+    (Modifier::Shift, KeyboardKey::KEY_F9, KeyInput::OpenRecent(9))
+];
+
+/// Where `KeyProfile::TwoPlayer` moves the keypad's right column: C and D, the second paddle in Pong, on up and down
+pub(super) const PLAYER_TWO_KEYS: [(KeyboardKey, u8); 4] = [
+    (KeyboardKey::KEY_UP, 0xc),
+    (KeyboardKey::KEY_DOWN, 0xd),
+    (KeyboardKey::KEY_LEFT, 0xe),
+    (KeyboardKey::KEY_RIGHT, 0xf)
+];
+
+/// The keys a ROM's settings can give a CHIP-8 key as well as its usual one, by their name there.
+/// None of them are the emulator's own keys, so a remap never takes one away
+pub(super) const REMAP_KEYS: [(&str, KeyboardKey); 25] = [
+    ("UP", KeyboardKey::KEY_UP),
+    ("DOWN", KeyboardKey::KEY_DOWN),
+    ("LEFT", KeyboardKey::KEY_LEFT),
+    ("RIGHT", KeyboardKey::KEY_RIGHT),
+    ("FIVE", KeyboardKey::KEY_FIVE),
+    ("SIX", KeyboardKey::KEY_SIX),
+    ("SEVEN", KeyboardKey::KEY_SEVEN),
+    ("EIGHT", KeyboardKey::KEY_EIGHT),
+    ("NINE", KeyboardKey::KEY_NINE),
+    ("ZERO", KeyboardKey::KEY_ZERO),
+    ("Y", KeyboardKey::KEY_Y),
+    ("U", KeyboardKey::KEY_U),
+    ("O", KeyboardKey::KEY_O),
+    ("J", KeyboardKey::KEY_J),
+    ("B", KeyboardKey::KEY_B),
+    ("KP_0", KeyboardKey::KEY_KP_0),
+    ("KP_1", KeyboardKey::KEY_KP_1),
+    ("KP_2", KeyboardKey::KEY_KP_2),
+    ("KP_3", KeyboardKey::KEY_KP_3),
+    ("KP_4", KeyboardKey::KEY_KP_4),
+    ("KP_5", KeyboardKey::KEY_KP_5),
+    ("KP_6", KeyboardKey::KEY_KP_6),
+    ("KP_7", KeyboardKey::KEY_KP_7),
+    ("KP_8", KeyboardKey::KEY_KP_8),
+    ("KP_9", KeyboardKey::KEY_KP_9)
+];
+
+/// How far through remapping a key the user is, while the prompt for it is up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum RemapCapture{
+    /// Waiting for a keypad key, to choose which CHIP-8 key to remap
+    ChooseKey,
+    /// Waiting for the key to give this CHIP-8 key
+    ChooseHostKey(u8)
+}
+
+impl RemapCapture{
+    pub(super) fn prompt(self) -> String{
+        match self {
+            RemapCapture::ChooseKey => "Press the CHIP-8 key to remap\n[shift]+[K] cancels".to_string(),
+            RemapCapture::ChooseHostKey(key) => format!("Press the key you want for CHIP-8 key {key:X}: an arrow, 5-0, Y U O J B or the number pad\n\
+                [backspace] clears it, [shift]+[K] cancels")
+        }
+    }
+}
+
+/// The start button pauses and resumes, like the space bar
+pub(super) const PAD_PAUSE: GamepadButton = GamepadButton::GAMEPAD_BUTTON_MIDDLE_RIGHT;
+
+pub(super) fn pad_button(button: PadButton) -> GamepadButton{
+    match button {
+        PadButton::Up => GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_UP,
+        PadButton::Down => GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_DOWN,
+        PadButton::Left => GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_LEFT,
+        PadButton::Right => GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_RIGHT,
+        PadButton::A => GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_DOWN,
+        PadButton::B => GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_RIGHT,
+        PadButton::X => GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_LEFT,
+        PadButton::Y => GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_UP
+    }
+}
+
+/// Every keyboard binding and chord, plus the start button to pause and the on-screen keypad's buttons
+pub(super) fn default_bindings() -> HashMap<Binding, KeyInput>{
+    let (bindings, conflicts) = load_bindings(&KEYMAP, &CHORDS);
+    for conflict in conflicts{
+        eprintln!("{}", conflict.render(None));
+    }
+    bindings
+}
+
+/// Bind `keys` and `chords`, leaving out and reporting any chord on a CHIP-8 keypad key,
+/// since holding a modifier would take that key away from the game, or on a chord bound twice
+pub(super) fn load_bindings(keys: &[(KeyboardKey, KeyInput)], chords: &[(Modifier, KeyboardKey, KeyInput)]) -> (HashMap<Binding, KeyInput>, Vec<Diagnostic>){
+    let mut bindings: HashMap<_, _> = keys.iter().map(|(key, input)| (Binding::Key(*key), *input))
+        .chain([(Binding::Pad(PAD_PAUSE), KeyInput::TogglePause)])
+        .chain((0..16).map(|key| (Binding::Touch(key), KeyInput::Chip8Key(key))))
+        .collect();
+    let mut conflicts = vec![];
+    for (modifier, key, input) in chords{
+        let binding = Binding::Chord(*modifier, *key);
+        match bindings.get(&Binding::Key(*key)) {
+            Some(KeyInput::Chip8Key(chip8_key)) => conflicts.push(Diagnostic::warning(
+                format!("{:?}+{} is left unbound, {} is CHIP-8 key {:X}", modifier, key_name(*key), key_name(*key), chip8_key))),
+            _ if bindings.contains_key(&binding) => conflicts.push(Diagnostic::warning(
+                format!("{:?}+{} is bound more than once, only the first is kept", modifier, key_name(*key)))),
+            _ => { bindings.insert(binding, *input); }
+        }
+    }
+    (bindings, conflicts)
+}
+
+/// e.g. `ENTER` for `KEY_ENTER`
+fn key_name(key: KeyboardKey) -> String{
+    format!("{:?}", key).trim_start_matches("KEY_").to_string()
+}
+
+/// Replace the gamepad buttons bound to CHIP-8 keys with `map`
+pub(super) fn set_gamepad_map(keymap: &mut HashMap<Binding, KeyInput>, map: &BTreeMap<PadButton, u8>){
+    keymap.retain(|binding, input| !matches!((binding, input), (Binding::Pad(_), KeyInput::Chip8Key(_))));
+    for (button, key) in map{
+        keymap.insert(Binding::Pad(pad_button(*button)), KeyInput::Chip8Key(*key));
+    }
+}
+
+/// Replace the keyboard keys bound to CHIP-8 keys with the ones `profile` uses, plus those `remap` adds,
+/// which win over the profile's. Names not in `REMAP_KEYS` are left out
+pub(super) fn set_key_profile(keymap: &mut HashMap<Binding, KeyInput>, profile: KeyProfile, remap: &BTreeMap<u8, String>){
+    keymap.retain(|binding, input| !matches!((binding, input), (Binding::Key(_), KeyInput::Chip8Key(_))));
+    let player_two = profile.player_two();
+    let keypad = KEYMAP.iter().filter_map(|(key, input)| match input {
+        KeyInput::Chip8Key(chip8_key) if player_two & 1 << chip8_key == 0 => Some((*key, *chip8_key)),
+        _ => None
+    });
+    let moved = PLAYER_TWO_KEYS.into_iter().filter(|(_, chip8_key)| player_two & 1 << chip8_key != 0);
+    let remapped = remap.iter().filter_map(|(chip8_key, name)|
+        REMAP_KEYS.iter().find(|(other, _)| other == name).map(|(_, key)| (*key, *chip8_key)));
+    for (key, chip8_key) in keypad.chain(moved).chain(remapped){
+        keymap.insert(Binding::Key(key), KeyInput::Chip8Key(chip8_key));
+    }
+}
+
+
+#[test]
+fn test_keymap(){
+    let (bindings, conflicts) = load_bindings(&KEYMAP, &CHORDS);
+    assert!(conflicts.is_empty(), "{:?}", conflicts.iter().map(|conflict| &conflict.message).collect::<Vec<_>>());
+    assert_eq!(bindings.len(), KEYMAP.len() + CHORDS.len() + 1 + 16, "a key is bound twice");
+    let mut keypad: Vec<_> = KEYMAP.iter().filter_map(|(_, input)| match input {
+        KeyInput::Chip8Key(key) => Some(*key),
+        _ => None
+    }).collect();
+    keypad.sort();
+    assert_eq!(keypad, (0..16).collect::<Vec<_>>());
+
+    let mut bindings = default_bindings();
+    set_gamepad_map(&mut bindings, &BTreeMap::from([(PadButton::A, 0x5)]));
+    set_gamepad_map(&mut bindings, &BTreeMap::from([(PadButton::B, 0x6)]));
+    assert!(matches!(bindings.get(&Binding::Pad(pad_button(PadButton::B))), Some(KeyInput::Chip8Key(0x6))));
+    assert!(!bindings.contains_key(&Binding::Pad(pad_button(PadButton::A))));
+    assert!(matches!(bindings.get(&Binding::Pad(PAD_PAUSE)), Some(KeyInput::TogglePause)));
+}
+
+#[test]
+fn test_key_profiles(){
+    let chip8_keys = |bindings: &HashMap<Binding, KeyInput>| bindings.iter()
+        .filter_map(|(binding, input)| match (binding, input) {
+            (Binding::Key(_), KeyInput::Chip8Key(key)) => Some(*key),
+            _ => None
+        }).fold(0u16, |keys, key| keys | 1 << key);
+    let mut bindings = default_bindings();
+    set_gamepad_map(&mut bindings, &BTreeMap::from([(PadButton::A, 0xc)]));
+    set_key_profile(&mut bindings, KeyProfile::TwoPlayer, &BTreeMap::new());
+    assert_eq!(chip8_keys(&bindings), 0xffff);
+    assert!(matches!(bindings.get(&Binding::Key(KeyboardKey::KEY_UP)), Some(KeyInput::Chip8Key(0xc))));
+    assert!(!bindings.contains_key(&Binding::Key(KeyboardKey::KEY_FOUR)));
+    // the gamepad and the emulator's own keys stay where they were
+    assert!(matches!(bindings.get(&Binding::Pad(pad_button(PadButton::A))), Some(KeyInput::Chip8Key(0xc))));
+    assert!(matches!(bindings.get(&Binding::Key(KeyboardKey::KEY_TAB)), Some(KeyInput::Turbo)));
+    set_key_profile(&mut bindings, KeyProfile::Standard, &BTreeMap::new());
+    assert_eq!(chip8_keys(&bindings), 0xffff);
+    assert!(!bindings.contains_key(&Binding::Key(KeyboardKey::KEY_UP)));
+    assert!(matches!(bindings.get(&Binding::Key(KeyboardKey::KEY_FOUR)), Some(KeyInput::Chip8Key(0xc))));
+}
+
+#[test]
+fn test_key_remap(){
+    let defaults = default_bindings();
+    for (name, key) in REMAP_KEYS{
+        assert_eq!(name, key_name(key));
+        assert!(!defaults.contains_key(&Binding::Key(key)), "{name} is already bound");
+    }
+    let mut bindings = defaults.clone();
+    let remap = BTreeMap::from([(0x5, "UP".to_string()), (0x6, "SPACE".to_string())]);
+    set_key_profile(&mut bindings, KeyProfile::TwoPlayer, &remap);
+    // the remap wins over player two's C, and the usual key still plays 5
+    assert!(matches!(bindings.get(&Binding::Key(KeyboardKey::KEY_UP)), Some(KeyInput::Chip8Key(0x5))));
+    assert!(matches!(bindings.get(&Binding::Key(KeyboardKey::KEY_W)), Some(KeyInput::Chip8Key(0x5))));
+    assert!(matches!(bindings.get(&Binding::Key(KeyboardKey::KEY_SPACE)), Some(KeyInput::TogglePause)));
+    set_key_profile(&mut bindings, KeyProfile::Standard, &BTreeMap::new());
+    assert_eq!(bindings.len(), defaults.len());
+    assert!(RemapCapture::ChooseHostKey(0xa).prompt().starts_with("Press the key you want for CHIP-8 key A"));
+}
+
+#[test]
+fn test_chord_conflicts(){
+    let chords = [
+        (Modifier::Shift, KeyboardKey::KEY_W, KeyInput::Reset),
+        (Modifier::Control, KeyboardKey::KEY_ENTER, KeyInput::Reset),
+        (Modifier::Control, KeyboardKey::KEY_ENTER, KeyInput::StepOver)
+    ];
+    let (bindings, conflicts) = load_bindings(&KEYMAP, &chords);
+    assert_eq!(conflicts.len(), 2);
+    assert!(conflicts[0].message.contains("CHIP-8 key 5"), "{}", conflicts[0].message);
+    assert!(!bindings.contains_key(&Binding::Chord(Modifier::Shift, KeyboardKey::KEY_W)));
+    assert!(matches!(bindings.get(&Binding::Chord(Modifier::Control, KeyboardKey::KEY_ENTER)), Some(KeyInput::Reset)));
+    assert!(matches!(bindings.get(&Binding::Key(KeyboardKey::KEY_W)), Some(KeyInput::Chip8Key(0x5))));
+}
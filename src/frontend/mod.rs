@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+
+use crate::{breakpoints::Breakpoints, collision::Collision, errors::Diagnostic, frame_timing::FrameTiming, panel_style::PanelStyles, quirks::Quirk, keypad::KeyProfile, settings::PadButton, symbols::Symbols, theme::DisplayStyle, Addr, Chip8};
+
+mod input;
+#[cfg(feature = "raylib")]
+mod keymap;
+#[cfg(feature = "raylib")]
+mod raylib;
+#[cfg(feature = "sdl2")]
+mod sdl2;
+
+pub use self::input::InputManager;
+#[cfg(feature = "raylib")]
+pub use self::raylib::RaylibDisplay;
+#[cfg(feature = "sdl2")]
+pub use self::sdl2::Sdl2Display;
+
+/// A position in the window, in pixels from its top left
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Point{
+    pub x: f32,
+    pub y: f32
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum KeyInput{
+    Chip8Key(u8),
+    Step,
+    StepTimers,
+    StepFrame,
+    StepBack,
+    ExportDebugScript,
+    ToggleTrace,
+    DumpTrace,
+    ToggleGrid,
+    /// Show or hide the on-screen keypad
+    ToggleVirtualKeypad,
+    PitchUp,
+    PitchDown,
+    TogglePause,
+    ToggleDebug,
+    Click(Point),
+    Scroll(Point, isize),
+    /// Overwrite a byte of RAM, edited in the memory view
+    WriteMemory(Addr, u8),
+    /// Start or stop logging and flashing sprite collisions
+    ToggleCollisions,
+    /// Write the ROM region of RAM, with any edits, out to a new file
+    ExportRom,
+    /// Save the display as an image
+    Screenshot,
+    /// Start recording the display, or stop and save the recording
+    ToggleRecording,
+    /// Step, running a whole subroutine if the instruction calls one
+    StepOver,
+    /// Run until the current subroutine returns
+    StepOut,
+    /// Start the ROM again from the beginning
+    Reset,
+    /// Start again from 0x200 without reloading the ROM, keeping RAM as it is
+    SoftReset,
+    /// Run more or fewer instructions a second
+    SpeedUp,
+    SpeedDown,
+    /// Fast-forward, sent every frame the key is held
+    Turbo,
+    /// Switch a quirk the other way
+    ToggleQuirk(Quirk),
+    /// Save what is needed to report a crash in the emulator
+    SaveCrashBundle,
+    /// Switch to the next keyboard layout for the keypad
+    NextKeyProfile,
+    /// Start or cancel choosing another key for a CHIP-8 key
+    RemapKeys,
+    /// Give a CHIP-8 key another key, by the frontend's name for it, or with `None` take it away
+    RemapKey(u8, Option<&'static str>),
+    /// Copy the panel under the mouse as text
+    Copy,
+    /// Paste hex bytes into memory at the byte selected in the memory view
+    Paste,
+    /// Open a recently used ROM, 1 for the one before the ROM running
+    OpenRecent(usize)
+} 
+
+
+/// Something the driver draws to each frame, such as a window or a mirror of it
+pub trait Renderer{
+    /// Rendering. Returns true once the output has been closed
+    fn update(&mut self, chip8: &crate::Chip8, breakpoints: &Breakpoints, show_current_instruction: bool) -> bool;
+    /// Colours and effects used to draw the display
+    fn set_display_style(&mut self, _style: &DisplayStyle) {}
+    /// Colours and text sizes of the debug panels
+    fn set_panel_styles(&mut self, _styles: &PanelStyles) {}
+    /// RAM from a few steps ago for the memory view to diff against, while paused
+    fn set_memory_snapshot(&mut self, _snapshot: Option<&[u8]>) {}
+    /// Highlight the pixels involved in a sprite collision for a few frames
+    fn show_collision(&mut self, _collision: &Collision) {}
+    /// Label names to show in place of addresses
+    fn set_symbols(&mut self, _symbols: &Symbols) {}
+    /// The ROM as loaded, so that bytes patched since can be marked
+    fn set_rom(&mut self, _rom: &[u8]) {}
+    /// How long the latest frames took, oldest first, for the frame time graph
+    fn set_frame_times(&mut self, _times: &[FrameTiming]) {}
+    /// Tell the user something for a few seconds, e.g. that the ROM doesn't look like a CHIP-8 program
+    fn show_message(&mut self, _message: &str) {}
+}
+
+/// Somewhere the driver takes key presses and commands from
+pub trait InputSource{
+    /// Keyboard input
+    fn get_inputs(&mut self)->Vec<KeyInput>;
+}
+
+/// The driver's main frontend, which as well as drawing and taking input
+/// owns the debug view and the sound
+pub trait Chip8Frontend: Renderer + InputSource{
+    /// Toggle debug mode
+    fn toggle_debug(&mut self);
+    /// Whether the debug panels are shown
+    fn is_debug_view(&self) -> bool;
+    /// Toggle the sprite grid and coordinate overlay
+    fn toggle_grid(&mut self);
+    /// Show or hide a keypad drawn over the display, played by mouse or touch
+    fn toggle_virtual_keypad(&mut self) {}
+
+    fn on_mouse_scroll(&mut self, position: Point, direction: isize);
+
+    /// Handle a click, e.g. toggling a breakpoint in the instruction view
+    fn on_mouse_click(&mut self, position: Point, breakpoints: &mut Breakpoints);
+    /// Sound output
+    fn audio(&mut self) -> &mut dyn Audio;
+    /// Which CHIP-8 key each gamepad button presses
+    fn set_gamepad_map(&mut self, _map: &BTreeMap<PadButton, u8>) {}
+    /// Play the keypad with the keyboard keys of `profile`
+    fn set_key_profile(&mut self, _profile: KeyProfile) {}
+    /// Extra keys for CHIP-8 keys, by the frontend's name for them, on top of the profile's
+    fn set_key_remap(&mut self, _remap: &BTreeMap<u8, String>) {}
+    /// Prompt for a CHIP-8 key and then the key to give it, sending `KeyInput::RemapKey` once chosen,
+    /// or stop prompting if already
+    fn toggle_key_remap(&mut self) {}
+    /// Copy the panel under the mouse to the clipboard as text
+    fn copy(&mut self, _chip8: &Chip8, _breakpoints: &Breakpoints) -> Result<(), Diagnostic> {
+        Err(Diagnostic::error("There's no clipboard to copy to"))
+    }
+    /// The bytes on the clipboard to write into memory, from the byte selected in the memory view
+    fn paste(&mut self) -> Result<Vec<(Addr, u8)>, Diagnostic> {
+        Err(Diagnostic::error("There's no clipboard to paste from"))
+    }
+}
+
+/// Plays the buzzer
+pub trait Audio{
+    /// Sound the buzzer while `playing` is true
+    fn set_buzzer(&mut self, playing: bool);
+    /// Pitch of the buzzer, as a multiple of its base frequency
+    fn set_pitch(&mut self, pitch: f32);
+}
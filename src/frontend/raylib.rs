@@ -0,0 +1,774 @@
+use std::{collections::BTreeMap, time};
+
+use itertools::Itertools;
+use ndarray::Array2;
+use raylib::{self, audio::{RaylibAudio, Sound}, color::Color, consts::KeyboardKey, drawing::RaylibDraw, ffi::Vector2, math::Rectangle, text::Font, RaylibBuilder, RaylibHandle, RaylibThread};
+
+use crate::{breakpoints::Breakpoints, clipboard::Clipboard, collision::Collision, coverage, errors::Diagnostic, frame_timing::{FrameTiming, TimingLog}, keypad::KeyProfile, layout::{Layout, Panel, Rect}, memory_view::{MemoryView, ViewKey}, panel_style::PanelStyles, panels::{BreakpointMark, DebugPanels}, ram_search::RamSearch, resources::{Resource, Resources}, screenshot::Screenshot, settings::PadButton, symbols::Symbols, theme::{DisplayStyle, FrameHistory, Phosphor, PixelEffect, Rgb}, virtual_keypad, Addr, Chip8, Fault, MEMORY_SIZE};
+
+use super::{keymap::{self, Binding, Modifier, RemapCapture}, Audio, Chip8Frontend, InputManager, InputSource, KeyInput, Point, Renderer};
+
+/// The buzzer's sound played through raylib, restarted whenever it runs out
+pub struct RaylibBuzzer{
+    sound: Sound<'static>
+}
+
+impl RaylibBuzzer{
+    fn new(resources: &Resources) -> Self{
+        // The audio device stays open for as long as the program runs
+        let audio: &'static RaylibAudio = Box::leak(Box::new(RaylibAudio::init_audio_device().unwrap()));
+        let wave = audio.new_wave_from_memory(".wav", &resources.load(Resource::Buzzer))
+            .or_else(|err| {
+                eprintln!("Could not load the buzzer sound, using the built-in one: {}", err);
+                audio.new_wave_from_memory(".wav", &Resource::Buzzer.embedded())
+            }).unwrap();
+        Self{
+            sound: audio.new_sound_from_wave(&wave).unwrap()
+        }
+    }
+}
+
+impl Audio for RaylibBuzzer{
+    fn set_buzzer(&mut self, playing: bool) {
+        if playing && !self.sound.is_playing(){
+            self.sound.play();
+        }
+        if !playing && self.sound.is_playing(){
+            self.sound.stop();
+        }
+    }
+
+    fn set_pitch(&mut self, pitch: f32) {
+        self.sound.set_pitch(pitch);
+    }
+}
+
+pub struct RaylibDisplay{
+    raylib_handle: RaylibHandle,
+    raylib_thread: RaylibThread,
+    buzzer: RaylibBuzzer,
+    debug_mode: bool,
+    show_grid: bool,
+    show_virtual_keypad: bool,
+    font: Option<Font>,
+    input: InputManager<Binding>,
+    /// Index of the connected gamepad, if any
+    gamepad: Option<i32>,
+    style: DisplayStyle,
+    frames: FrameHistory,
+    phosphor: Phosphor,
+    /// Frames left to flash each pixel involved in a recent collision
+    collision_flash: Array2<u8>,
+    panels: DebugPanels,
+    game_layout: Layout,
+    /// A message shown over the display and the frames left to show it
+    message: Option<(String, u16)>,
+    /// Extra keys for CHIP-8 keys from the ROM's settings
+    key_remap: BTreeMap<u8, String>,
+    /// The prompt for choosing a key to remap, while it is up
+    remap: Option<RemapCapture>
+}
+
+macro_rules! vec2 {
+    ($obj: expr) => {
+        {Vector2{
+            x: $obj.x as f32,
+            y: $obj.y as f32
+        }}
+    };
+    ($a:expr, $b: expr) => {
+        {Vector2{
+            x: $a as f32,
+            y: $b as f32
+        }}
+    }
+}
+
+impl RaylibDisplay{
+    const WINDOW_WIDTH: i32 = 960;
+    const WINDOW_HEIGHT: i32 = 480;
+    /// How many frames the pixels of a collision flash for, switching every `FLASH_PERIOD` frames
+    const FLASH_FRAMES: u8 = 48;
+    const FLASH_PERIOD: u8 = 8;
+    /// How many frames a message from `show_message` stays up for
+    const MESSAGE_FRAMES: u16 = 300;
+    const LINE_SPACING: i32 = 20;
+
+
+    
+    fn draw_memory(font: &Font, chip8: &Chip8, panels: &mut DebugPanels, position: Rect, mouse: Vector2, handle: &mut raylib::prelude::RaylibDrawHandle, ) {
+        let header = panels.memory_header();
+        let view = &mut panels.memory_view;
+        view.set_height(position.height);
+        let start = view.first_row(chip8.registers.i);
+        let style = *panels.styles.get(Panel::Memory);
+        handle.draw_rectangle_rec(Rectangle::from(position), Color::from(style.background));
+        handle.draw_text_ex(font, &header,
+            vec2!(position.x + MemoryView::MARGIN, position.y + MemoryView::MARGIN), 18.0, 1.0, Color::from(style.text));
+        let selected = view.selected();
+        for row in 0..view.rows(){
+            let row_addr = start + row * MemoryView::BYTES_PER_ROW;
+            if row_addr >= MEMORY_SIZE{
+                break;
+            }
+            let (_, y) = view.cell_position(row, 0);
+            handle.draw_text_ex(font, &format!("{:03x}", row_addr),
+                vec2!(position.x + MemoryView::MARGIN, position.y + y), 18.0, 1.0, Color::from(style.text));
+            for column in 0..MemoryView::BYTES_PER_ROW{
+                let addr = row_addr + column;
+                let (x, y) = view.cell_position(row, column);
+                let cell = Rectangle::new(position.x + x - 2.0, position.y + y, MemoryView::CELL_WIDTH - 2.0, MemoryView::LINE_HEIGHT);
+                let mut text = format!("{:02x}", chip8.memory.ram[addr]);
+                match selected {
+                    Some((selected, high)) if usize::from(selected) == addr => {
+                        handle.draw_rectangle_rec(cell, Color::SKYBLUE);
+                        if let Some(high) = high{
+                            text = format!("{:x}_", high);
+                        }
+                    },
+                    _ if addr == chip8.registers.i => handle.draw_rectangle_rec(cell, Color::GOLD),
+                    _ if view.changed(addr, chip8.memory.ram[addr]).is_some() => handle.draw_rectangle_rec(cell, Color::PINK),
+                    _ if view.patched(addr, chip8.memory.ram[addr]) => handle.draw_rectangle_rec(cell, Color::LIME),
+                    _ => {}
+                }
+                // a bar under each byte the run has used: green for code, orange for written, blue for read
+                let used = match chip8.coverage().map_or(0, |coverage| coverage.get(addr)) {
+                    access if access & coverage::EXECUTED != 0 => Some(Color::GREEN),
+                    access if access & coverage::WRITTEN != 0 => Some(Color::ORANGE),
+                    access if access & coverage::READ != 0 => Some(Color::BLUE),
+                    _ => None
+                };
+                if let Some(color) = used{
+                    handle.draw_rectangle_rec(Rectangle::new(cell.x, cell.y + cell.height - 3.0, cell.width, 3.0), color);
+                }
+                handle.draw_text_ex(font, &text, vec2!(position.x + x, position.y + y), 18.0, 1.0, Color::from(style.text));
+            }
+        }
+        // show the old value of a changed byte under the mouse
+        let hovered = view.addr_at(mouse.x - position.x, mouse.y - position.y).map(usize::from);
+        if let Some((addr, old)) = hovered.and_then(|addr| Some((addr, view.changed(addr, chip8.memory.ram[addr])?))){
+            let tooltip = Rectangle::new(mouse.x + 12.0, mouse.y + 12.0, 120.0, MemoryView::LINE_HEIGHT);
+            handle.draw_rectangle_rec(tooltip, Color::BLACK);
+            handle.draw_text_ex(font, &format!("{:03x} was {:02x}", addr, old),
+                vec2!(tooltip.x + 4.0, tooltip.y), 18.0, 1.0, Color::WHITE);
+        }
+    }
+    fn draw_registers(chip8: &Chip8, panels: &DebugPanels, position: Rect, handle: &mut raylib::prelude::RaylibDrawHandle) {
+        let style = panels.styles.get(Panel::Registers);
+        handle.draw_rectangle_rec(Rectangle::from(position), Color::from(style.background));
+        let text = DebugPanels::register_lines(chip8, panels.key_profile).join("\n");
+        handle.draw_text(&text,
+        (position.x + style.padding) as i32,
+        (position.y + style.padding) as i32,
+            style.font_size as i32, Color::from(style.text));
+    }
+
+    /// One line for each CALL that hasn't returned, innermost first
+    fn draw_call_stack(font: &Font, chip8: &Chip8, panels: &DebugPanels, position: Rect, handle: &mut raylib::prelude::RaylibDrawHandle) {
+        let style = panels.styles.get(Panel::CallStack);
+        handle.draw_rectangle_rec(Rectangle::from(position), Color::from(style.background));
+        for (i, line) in panels.call_stack_lines(chip8, position.height).iter().enumerate(){
+            handle.draw_text_ex(font, line,
+                vec2!(position.x + style.padding, position.y + style.padding + i as f32 * style.font_size), style.font_size, 1.0, Color::from(style.text));
+        }
+    }
+
+    fn draw_variables(font: &Font, chip8: &Chip8, panels: &DebugPanels, position: Rect, handle: &mut raylib::prelude::RaylibDrawHandle) {
+        let style = panels.styles.get(Panel::Variables);
+        handle.draw_rectangle_rec(Rectangle::from(position), Color::from(style.background));
+        for (i, line) in panels.variable_lines(chip8, position.height).iter().enumerate(){
+            handle.draw_text_ex(font, line,
+                vec2!(position.x + style.padding, position.y + style.padding + i as f32 * style.font_size), style.font_size, 1.0, Color::from(style.text));
+        }
+    }
+
+    /// One bar per frame, oldest on the left: emulation in blue, drawing in green, and the rest of the interval,
+    /// mostly waiting for the next frame, in grey. The red line is 1/60 of a second
+    fn draw_frame_times(font: &Font, panels: &DebugPanels, position: Rect, handle: &mut raylib::prelude::RaylibDrawHandle) {
+        let style = panels.styles.get(Panel::FrameTimes);
+        handle.draw_rectangle_rec(Rectangle::from(position), Color::from(style.background));
+        handle.draw_text_ex(font, &panels.frame_times_header(),
+            vec2!(position.x + style.padding, position.y + style.padding), style.font_size, 1.0, Color::from(style.text));
+        let top = position.y + style.padding + style.font_size;
+        let bottom = position.y + position.height;
+        let frame_ms = crate::timing::FRAME_LENGTH.as_secs_f32() * 1000.0;
+        let longest = panels.frame_times.iter().map(|timing| timing.interval.as_secs_f32() * 1000.0).fold(frame_ms * 2.0, f32::max);
+        let height = |ms: f32| ms / longest * (bottom - top);
+        let bar_width = position.width / TimingLog::RECENT as f32;
+        for (index, timing) in panels.frame_times.iter().enumerate(){
+            let x = position.x + index as f32 * bar_width;
+            let emulation = height(timing.emulation.as_secs_f32() * 1000.0);
+            let presentation = height(timing.presentation.as_secs_f32() * 1000.0);
+            let interval = height(timing.interval.as_secs_f32() * 1000.0).max(emulation + presentation);
+            handle.draw_rectangle_rec(Rectangle::new(x, bottom - interval, bar_width, interval), Color::GRAY);
+            handle.draw_rectangle_rec(Rectangle::new(x, bottom - emulation - presentation, bar_width, presentation), Color::GREEN);
+            handle.draw_rectangle_rec(Rectangle::new(x, bottom - emulation, bar_width, emulation), Color::BLUE);
+        }
+        let target = bottom - height(frame_ms);
+        handle.draw_line_ex(vec2!(position.x, target), vec2!(position.x + position.width, target), 1.0, Color::RED);
+    }
+
+    /// The value being searched for, the scan buttons, then the candidates with the pinned addresses beside them
+    fn draw_ram_search(font: &Font, chip8: &Chip8, panels: &DebugPanels, position: Rect, handle: &mut raylib::prelude::RaylibDrawHandle) {
+        let ram = &chip8.memory.ram;
+        let search = &panels.ram_search;
+        let origin = vec2!(position.x + RamSearch::MARGIN, position.y + RamSearch::MARGIN);
+        let style = panels.styles.get(Panel::Search);
+        handle.draw_rectangle_rec(Rectangle::from(position), Color::from(style.background));
+        handle.draw_text_ex(font, &panels.search_header(), origin, 18.0, 1.0, Color::from(style.text));
+        for (index, (label, _)) in RamSearch::BUTTONS.iter().enumerate(){
+            let x = origin.x + index as f32 * RamSearch::BUTTON_WIDTH;
+            let button = Rectangle::new(x, origin.y + RamSearch::LINE_HEIGHT, RamSearch::BUTTON_WIDTH - 4.0, RamSearch::LINE_HEIGHT - 2.0);
+            handle.draw_rectangle_rec(button, Color::GRAY);
+            handle.draw_text_ex(font, label, vec2!(x + 4.0, button.y), 18.0, 1.0, Color::WHITE);
+        }
+        for (index, &(addr, old)) in search.visible(RamSearch::rows(position.height)).iter().enumerate(){
+            let (x, y) = search.row_position(index, false);
+            let pinned = Addr::try_from(addr).is_ok_and(|addr| search.pins().contains(&addr));
+            handle.draw_text_ex(font, &format!("{:03x}: {:02x} (was {:02x})", addr, ram[addr], old),
+                vec2!(position.x + x, position.y + y), 18.0, 1.0, if pinned {Color::DARKBLUE} else {Color::from(style.text)});
+        }
+        for (index, addr) in search.pins().iter().enumerate(){
+            let (x, y) = search.row_position(index, true);
+            let value = ram[addr.index()];
+            handle.draw_text_ex(font, &format!("pin {:03x}: {:02x} = {}", u16::from(*addr), value, value),
+                vec2!(position.x + x, position.y + y), 18.0, 1.0, Color::DARKBLUE);
+        }
+    }
+
+    fn draw_instructions<T: RaylibDraw>(font: &Font, breakpoints: &Breakpoints, chip8: &Chip8, panels: &DebugPanels, position: Rect, handle: &mut T) {
+        let line_height = DebugPanels::instruction_line_height(position);
+        let style = panels.styles.get(Panel::Instructions);
+        handle.draw_rectangle_rec(Rectangle::from(position), Color::from(style.background));
+        for (i, line) in panels.instruction_lines(chip8, breakpoints).iter().enumerate() {
+            let y = DebugPanels::instruction_line_y(position, i);
+            // red for a plain breakpoint, orange for a conditional one, blue for one that logs or counts, gray if disabled
+            if let Some(mark) = line.breakpoint{
+                let color = match mark {
+                    BreakpointMark::Plain => Color::RED,
+                    BreakpointMark::Conditional => Color::ORANGE,
+                    BreakpointMark::Disabled => Color::GRAY,
+                    BreakpointMark::Tracepoint => Color::SKYBLUE
+                };
+                handle.draw_circle((line_height / 2.0) as i32, (y + line_height / 2.0) as i32, line_height / 4.0, color);
+            }
+            handle.draw_text_ex(font, &line.text, vec2!(DebugPanels::INSTRUCTION_MARGIN_LEFT, y), style.font_size, 1.0, Color::from(style.text));
+        }
+    }
+
+    const GRID_SPACING: usize = 8;
+    const GRID_COLOR: Color = Color::new(80, 120, 255, 140);
+
+    /// Draw lines every 8 pixels over the chip8 display, labelled with their coordinates
+    fn draw_grid(origin: Vector2, pixel_width: i32, pixel_height: i32, handle: &mut raylib::prelude::RaylibDrawHandle) {
+        let (left, top) = (origin.x as i32, origin.y as i32);
+        let width = pixel_width * crate::DISPLAY_COLUMNS as i32;
+        let height = pixel_height * crate::DISPLAY_ROWS as i32;
+        for x in (0..crate::DISPLAY_COLUMNS).step_by(Self::GRID_SPACING){
+            let screen_x = left + x as i32 * pixel_width;
+            handle.draw_line(screen_x, top, screen_x, top + height, Self::GRID_COLOR);
+            handle.draw_text(&x.to_string(), screen_x + 2, top + 2, 10, Self::GRID_COLOR);
+        }
+        for y in (0..crate::DISPLAY_ROWS).step_by(Self::GRID_SPACING){
+            let screen_y = top + y as i32 * pixel_height;
+            handle.draw_line(left, screen_y, left + width, screen_y, Self::GRID_COLOR);
+            if y > 0 {
+                handle.draw_text(&y.to_string(), left + 2, screen_y + 2, 10, Self::GRID_COLOR);
+            }
+        }
+    }
+
+    /// Draw the on-screen keypad over the display, lighting up the keys held down
+    fn draw_virtual_keypad(font: &Font, keys_down: u16, display: Rect, handle: &mut raylib::prelude::RaylibDrawHandle) {
+        for (key, button) in virtual_keypad::buttons(display){
+            let held = keys_down & 1 << key != 0;
+            handle.draw_rectangle_rec(Rectangle::from(button), if held {Color::YELLOW.fade(0.6)} else {Color::DARKGRAY.fade(0.5)});
+            handle.draw_rectangle_lines_ex(Rectangle::from(button), 1.0, Color::LIGHTGRAY.fade(0.8));
+            let size = button.height / 2.0;
+            let label = format!("{:X}", key);
+            let width = raylib::text::measure_text_ex(font, &label, size, 1.0).x;
+            handle.draw_text_ex(font, &label, vec2!(button.x + (button.width - width) / 2.0, button.y + size / 2.0), size, 1.0,
+                if held {Color::BLACK} else {Color::WHITE});
+        }
+    }
+
+    /// The layout for the current mode
+    fn layout(&self) -> &Layout{
+        if self.debug_mode {self.panels.layout()} else {&self.game_layout}
+    }
+
+    fn screen_size(&self) -> (f32, f32){
+        (self.raylib_handle.get_screen_width() as f32, self.raylib_handle.get_screen_height() as f32)
+    }
+
+    /// Where the CHIP-8 display is drawn, in pixels
+    fn display_rect(&self) -> Rect{
+        let (screen_width, screen_height) = self.screen_size();
+        self.layout().screen_rect(Panel::Display, screen_width, screen_height)
+            .unwrap_or(Rect::new(0.0, 0.0, screen_width, screen_height))
+    }
+
+    /// While the remap prompt is up, the keypad key chosen and then the key to give it, instead of the frame's `inputs`
+    fn remap_inputs(&mut self, remap: RemapCapture, inputs: &[KeyInput]) -> Vec<KeyInput>{
+        if inputs.iter().any(|input| matches!(input, KeyInput::RemapKeys)){
+            self.remap = None;
+            return vec![];
+        }
+        match remap {
+            RemapCapture::ChooseKey => {
+                if let Some(key) = inputs.iter().find_map(|input| match input {
+                    KeyInput::Chip8Key(key) => Some(*key),
+                    _ => None
+                }){
+                    self.remap = Some(RemapCapture::ChooseHostKey(key));
+                }
+                vec![]
+            },
+            RemapCapture::ChooseHostKey(key) => {
+                let host_key = keymap::REMAP_KEYS.iter()
+                    .find(|(_, host_key)| self.raylib_handle.is_key_pressed(*host_key))
+                    .map(|(name, _)| *name);
+                if host_key.is_none() && !self.raylib_handle.is_key_pressed(KeyboardKey::KEY_BACKSPACE){
+                    return vec![];
+                }
+                self.remap = None;
+                vec![KeyInput::RemapKey(key, host_key)]
+            }
+        }
+    }
+
+    /// Typed characters while the memory view is taking text, as memory writes once a byte is complete.
+    /// Clicks still come through, so clicking elsewhere stops editing, and so do copying and pasting
+    fn memory_view_inputs(&mut self, bound: &[KeyInput]) -> Vec<KeyInput>{
+        let mut inputs = self.typed_keys().into_iter()
+            .filter_map(|key| self.panels.memory_view.key(key))
+            .map(|(addr, value)| KeyInput::WriteMemory(addr, value))
+            .collect_vec();
+        inputs.extend(bound.iter().filter(|input| matches!(input, KeyInput::Copy | KeyInput::Paste)).copied());
+        inputs.extend(self.click());
+        inputs
+    }
+
+    /// Typed characters while the search panel is taking a value, which is scanned for once entered
+    fn ram_search_inputs(&mut self) -> Vec<KeyInput>{
+        for key in self.typed_keys(){
+            if let Some(scan) = self.panels.ram_search.key(key){
+                self.panels.pending_scan = Some(scan);
+            }
+        }
+        self.click().into_iter().collect()
+    }
+
+    fn typed_keys(&mut self) -> Vec<ViewKey>{
+        let mut keys = Vec::new();
+        while let Some(c) = self.raylib_handle.get_char_pressed(){
+            keys.push(ViewKey::Char(c));
+        }
+        if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_BACKSPACE){
+            keys.push(ViewKey::Backspace);
+        }
+        if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_ENTER){
+            keys.push(ViewKey::Enter);
+        }
+        keys
+    }
+
+    fn mouse(&self) -> Point{
+        Point{ x: self.raylib_handle.get_mouse_x() as f32, y: self.raylib_handle.get_mouse_y() as f32 }
+    }
+
+    fn click(&self) -> Option<KeyInput>{
+        self.raylib_handle.is_mouse_button_pressed(raylib::ffi::MouseButton::MOUSE_BUTTON_LEFT)
+            .then(|| KeyInput::Click(self.mouse()))
+    }
+
+    /// Draw the style's scanlines or LCD grid over the display
+    fn draw_effect(style: &DisplayStyle, display: Rect, pixel_width: i32, pixel_height: i32, handle: &mut raylib::prelude::RaylibDrawHandle){
+        let (left, top) = (display.x as i32, display.y as i32);
+        let (width, height) = (pixel_width * crate::DISPLAY_COLUMNS as i32, pixel_height * crate::DISPLAY_ROWS as i32);
+        let row_edge = style.effect.edge(pixel_height as usize) as i32;
+        let column_edge = style.effect.edge(pixel_width as usize) as i32;
+        match style.effect {
+            PixelEffect::None => {},
+            PixelEffect::Scanlines => {
+                for y in 0..crate::DISPLAY_ROWS as i32{
+                    handle.draw_rectangle(left, top + (y + 1) * pixel_height - row_edge, width, row_edge, Color::BLACK.fade(0.5));
+                }
+            },
+            PixelEffect::LcdGrid => {
+                let background = Color::from(style.background);
+                for y in 0..crate::DISPLAY_ROWS as i32{
+                    handle.draw_rectangle(left, top + (y + 1) * pixel_height - row_edge, width, row_edge, background);
+                }
+                for x in 0..crate::DISPLAY_COLUMNS as i32{
+                    handle.draw_rectangle(left + (x + 1) * pixel_width - column_edge, top, column_edge, height, background);
+                }
+            }
+        }
+    }
+
+    /// Gamepads raylib can report at once
+    const MAX_GAMEPADS: i32 = 4;
+    /// Pick up gamepads as they are plugged in and unplugged
+    fn poll_gamepad(&mut self){
+        if let Some(gamepad) = self.gamepad{
+            if self.raylib_handle.is_gamepad_available(gamepad){
+                return;
+            }
+            eprintln!("Gamepad {} disconnected", gamepad);
+            self.gamepad = None;
+        }
+        self.gamepad = (0..Self::MAX_GAMEPADS).find(|gamepad| self.raylib_handle.is_gamepad_available(*gamepad));
+        if let Some(gamepad) = self.gamepad{
+            let name = self.raylib_handle.get_gamepad_name(gamepad).unwrap_or_default();
+            eprintln!("Gamepad {} connected: {}", gamepad, name);
+        }
+    }
+
+    fn is_down(&self, binding: Binding) -> bool{
+        match binding {
+            // a key that starts a chord doesn't count on its own while the chord's modifier is held
+            Binding::Key(key) => self.raylib_handle.is_key_down(key) && !self.input.bindings().keys().any(|other|
+                matches!(other, Binding::Chord(modifier, chord_key) if *chord_key == key && self.is_modifier_down(*modifier))),
+            Binding::Chord(modifier, key) => self.is_modifier_down(modifier) && self.raylib_handle.is_key_down(key),
+            Binding::Pad(button) => self.gamepad
+                .is_some_and(|gamepad| self.raylib_handle.is_gamepad_button_down(gamepad, button)),
+            // raylib reports the first touch as the mouse, so one button is held at a time
+            Binding::Touch(key) => self.show_virtual_keypad && self.layout().rect(Panel::Display).is_some()
+                && self.raylib_handle.is_mouse_button_down(raylib::ffi::MouseButton::MOUSE_BUTTON_LEFT)
+                && virtual_keypad::key_at(self.display_rect(), self.raylib_handle.get_mouse_x() as f32, self.raylib_handle.get_mouse_y() as f32) == Some(key)
+        }
+    }
+
+    fn is_modifier_down(&self, modifier: Modifier) -> bool{
+        modifier.keys().iter().any(|key| self.raylib_handle.is_key_down(*key))
+    }
+
+    /// Open the window, with the font and buzzer sound from `resources`
+    pub fn new(resources: &Resources) -> Self {
+        let (mut rhandle, rthread) = RaylibBuilder::default()
+            .width(Self::WINDOW_WIDTH)
+            .height(Self::WINDOW_HEIGHT)
+            .resizable()
+            .title("Chip-8")
+            .build();
+        rhandle.set_text_line_spacing(Self::LINE_SPACING);
+        let font = rhandle.load_font_from_memory(&rthread, "ttf", &resources.load(Resource::Font), 18, None)
+            .or_else(|err| {
+                eprintln!("Could not load the font, using the built-in one: {}", err);
+                rhandle.load_font_from_memory(&rthread, "ttf", &Resource::Font.embedded(), 18, None)
+            }).unwrap();
+        Self{
+            raylib_handle:rhandle,
+            raylib_thread:rthread,
+            buzzer: RaylibBuzzer::new(resources),
+            input: InputManager::new(keymap::default_bindings()),
+            font: Some(font),
+            debug_mode: false,
+            show_grid: false,
+            show_virtual_keypad: false,
+            gamepad: None,
+            style: DisplayStyle::default(),
+            frames: FrameHistory::default(),
+            phosphor: Phosphor::default(),
+            collision_flash: Array2::zeros([crate::DISPLAY_COLUMNS, crate::DISPLAY_ROWS]),
+            panels: DebugPanels::default(),
+            game_layout: Layout::fullscreen(),
+            message: None,
+            key_remap: BTreeMap::new(),
+            remap: None
+        }
+    }
+}
+    
+impl From<Rect> for Rectangle{
+    fn from(rect: Rect) -> Self{
+        Rectangle{ x: rect.x, y: rect.y, width: rect.width, height: rect.height }
+    }
+}
+
+impl From<Rgb> for Color{
+    fn from(rgb: Rgb) -> Self{
+        Color::new(rgb.r, rgb.g, rgb.b, 255)
+    }
+}
+
+impl Renderer for RaylibDisplay{
+    fn update(&mut self, chip8: &Chip8, breakpoints: &Breakpoints, show_current_instruction: bool) -> bool {
+        let (screen_width, screen_height) = self.screen_size();
+        let layout = self.layout();
+        let display = self.display_rect();
+        // hidden while another panel is zoomed in on
+        let display_shown = layout.rect(Panel::Display).is_some();
+        let pixel_width = (display.width / crate::DISPLAY_COLUMNS as f32) as i32;
+        let pixel_height = (display.height / crate::DISPLAY_ROWS as f32) as i32;
+        let memory_rect = layout.screen_rect(Panel::Memory, screen_width, screen_height);
+        let register_rect = layout.screen_rect(Panel::Registers, screen_width, screen_height);
+        let instruction_rect = layout.screen_rect(Panel::Instructions, screen_width, screen_height);
+        let call_stack_rect = layout.screen_rect(Panel::CallStack, screen_width, screen_height);
+        let search_rect = layout.screen_rect(Panel::Search, screen_width, screen_height);
+        let frame_times_rect = layout.screen_rect(Panel::FrameTimes, screen_width, screen_height);
+        let variables_rect = layout.screen_rect(Panel::Variables, screen_width, screen_height);
+        self.panels.run_pending_scan(&chip8.memory.ram);
+        self.poll_gamepad();
+        self.frames.push(&chip8.memory.display, self.style.blend_frames);
+        self.phosphor.update(&self.frames, self.style.frame_ghosting(chip8.display_events()));
+        let mouse = vec2!(self.raylib_handle.get_mouse_x(), self.raylib_handle.get_mouse_y());
+        {        
+            let mut handle = self.raylib_handle.begin_drawing(&self.raylib_thread);
+            handle.clear_background(Color::from(self.style.background));
+            if display_shown{
+                for x in 0..crate::DISPLAY_COLUMNS{
+                    for y in 0..crate::DISPLAY_ROWS{
+                        let level = self.phosphor.level(x, y);
+                        if level > 0.0 {
+                            handle.draw_rectangle(display.x as i32 + x as i32 * pixel_width, display.y as i32 + y as i32 * pixel_height,
+                                pixel_width, pixel_height, Color::from(self.style.pixel_color(level)))
+                        }
+                    }
+                }
+                Self::draw_effect(&self.style, display, pixel_width, pixel_height, &mut handle);
+                for ((x, y), frames) in self.collision_flash.indexed_iter_mut(){
+                    if *frames > 0{
+                        *frames -= 1;
+                        if *frames / Self::FLASH_PERIOD % 2 == 0{
+                            handle.draw_rectangle(display.x as i32 + x as i32 * pixel_width, display.y as i32 + y as i32 * pixel_height,
+                                pixel_width, pixel_height, Color::RED);
+                        }
+                    }
+                }
+                if self.show_grid {
+                    Self::draw_grid(vec2!(display.x, display.y), pixel_width, pixel_height, &mut handle);
+                }
+                if self.show_virtual_keypad {
+                    Self::draw_virtual_keypad(self.font.as_ref().unwrap(), chip8.keys_down(), display, &mut handle);
+                }
+            }
+            if let Some((message, frames)) = &mut self.message{
+                *frames -= 1;
+                handle.draw_rectangle(display.x as i32, display.y as i32, display.width as i32, message.lines().count() as i32 * Self::LINE_SPACING + 16, Color::new(0, 0, 0, 192));
+                handle.draw_text_ex(self.font.as_ref().unwrap(), message, vec2!(display.x + 8.0, display.y + 8.0), 18.0, 1.0, Color::YELLOW);
+                if *frames == 0{
+                    self.message = None;
+                }
+            }
+            if let Some(remap) = self.remap{
+                // stays up until a key is chosen, over any message
+                let prompt = remap.prompt();
+                handle.draw_rectangle(display.x as i32, display.y as i32, display.width as i32, prompt.lines().count() as i32 * Self::LINE_SPACING + 16, Color::new(0, 0, 64, 224));
+                handle.draw_text_ex(self.font.as_ref().unwrap(), &prompt, vec2!(display.x + 8.0, display.y + 8.0), 18.0, 1.0, Color::WHITE);
+            }
+            if let Some(fault) = chip8.fault(){
+                // stays up for as long as the CPU is halted, unlike a message
+                let prompt = match fault {
+                    Fault::Panic => "[F12] saves a crash bundle, [space] skips the instruction, [ctrl]+[space] resets",
+                    _ => "[space] skips it and carries on, [ctrl]+[space] resets"
+                };
+                let banner = format!("Halted at 0x{:03x}: {}\n{}", chip8.pc(), fault, prompt);
+                let height = 2 * Self::LINE_SPACING + 16;
+                let top = display.y as i32 + display.height as i32 - height;
+                handle.draw_rectangle(display.x as i32, top, display.width as i32, height, Color::new(128, 0, 0, 224));
+                handle.draw_text_ex(self.font.as_ref().unwrap(), &banner, vec2!(display.x + 8.0, top as f32 + 8.0), 18.0, 1.0, Color::WHITE);
+            }
+            if self.debug_mode {
+                // Draw instructions
+                if let Some(position) = instruction_rect{
+                    if show_current_instruction{
+                        self.panels.follow_pc(chip8.pc());
+                    }
+                    Self::draw_instructions(self.font.as_ref().unwrap(), breakpoints, chip8, &self.panels, position, &mut handle);
+                }
+                // Draw memory view
+                if let Some(position) = memory_rect{
+                    Self::draw_memory(self.font.as_ref().unwrap(), chip8, &mut self.panels, position, mouse, &mut handle);
+                }
+
+                // Draw register view
+                if let Some(position) = register_rect{
+                    Self::draw_registers(chip8, &self.panels, position, &mut handle);
+                }
+
+                if let Some(position) = call_stack_rect{
+                    Self::draw_call_stack(self.font.as_ref().unwrap(), chip8, &self.panels, position, &mut handle);
+                }
+
+                if let Some(position) = search_rect{
+                    Self::draw_ram_search(self.font.as_ref().unwrap(), chip8, &self.panels, position, &mut handle);
+                }
+
+                if let Some(position) = variables_rect{
+                    Self::draw_variables(self.font.as_ref().unwrap(), chip8, &self.panels, position, &mut handle);
+                }
+
+                if let Some(position) = frame_times_rect{
+                    Self::draw_frame_times(self.font.as_ref().unwrap(), &self.panels, position, &mut handle);
+                }
+                }
+        }
+        self.raylib_handle.window_should_close()
+    }
+
+    fn show_message(&mut self, message: &str) {
+        self.message = Some((message.to_string(), Self::MESSAGE_FRAMES));
+    }
+
+    fn set_display_style(&mut self, style: &DisplayStyle) {
+        self.style = *style;
+    }
+
+    fn set_panel_styles(&mut self, styles: &PanelStyles) {
+        self.panels.styles = styles.clone();
+    }
+
+    fn set_memory_snapshot(&mut self, snapshot: Option<&[u8]>) {
+        self.panels.memory_view.set_snapshot(snapshot);
+    }
+
+    fn set_symbols(&mut self, symbols: &Symbols) {
+        self.panels.set_symbols(symbols);
+    }
+
+    fn set_frame_times(&mut self, times: &[FrameTiming]) {
+        self.panels.frame_times = times.to_vec();
+    }
+
+    fn set_rom(&mut self, rom: &[u8]) {
+        self.panels.memory_view.set_rom(rom);
+    }
+
+    fn show_collision(&mut self, collision: &Collision) {
+        for &position in collision.pixels.iter(){
+            self.collision_flash[position] = Self::FLASH_FRAMES;
+        }
+    }
+}
+
+impl InputSource for RaylibDisplay{
+    fn get_inputs(&mut self) -> Vec<KeyInput> {
+        // raylib is polled, so each binding is checked against what the manager last saw.
+        // Keys are tracked even while typing, so the key that ends an edit isn't taken as a press afterwards
+        let bindings = self.input.bindings().keys().copied().collect_vec();
+        for binding in bindings{
+            let down = self.is_down(binding);
+            self.input.set_down(binding, down);
+        }
+        let mut inputs = self.input.frame(time::Instant::now());
+        if let Some(remap) = self.remap{
+            return self.remap_inputs(remap, &inputs);
+        }
+        if self.debug_mode{
+            if self.panels.memory_view.is_editing(){
+                // typing goes to the memory view rather than the keypad
+                return self.memory_view_inputs(&inputs);
+            }
+            if self.panels.ram_search.is_editing(){
+                return self.ram_search_inputs();
+            }
+            if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_SLASH){
+                self.panels.memory_view.toggle_goto();
+            }
+            if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_I){
+                self.panels.memory_view.toggle_follow_i();
+            }
+            if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_H){
+                self.panels.memory_view.toggle_diff();
+            }
+            if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_M){
+                self.panels.toggle_search();
+            }
+            if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_L){
+                self.panels.toggle_frame_times();
+            }
+            if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_O){
+                let (screen_width, screen_height) = self.screen_size();
+                let (x, y) = (self.raylib_handle.get_mouse_x() as f32, self.raylib_handle.get_mouse_y() as f32);
+                self.panels.toggle_zoom(x, y, screen_width, screen_height);
+            }
+        }
+        inputs.extend(self.click());
+        let mouse_wheel = self.raylib_handle.get_mouse_wheel_move().round() as isize;
+        if  mouse_wheel != 0 {
+            // negative is down, but everywhere else negative is up, so we invert the scroll amount to match
+            inputs.push(KeyInput::Scroll(self.mouse(), -mouse_wheel))
+        }
+        inputs
+    }
+}
+
+impl Chip8Frontend for RaylibDisplay{
+    fn toggle_debug(&mut self) {
+        self.debug_mode = !self.debug_mode;
+    }
+
+    fn is_debug_view(&self) -> bool {
+        self.debug_mode
+    }
+
+    fn toggle_grid(&mut self) {
+        self.show_grid = !self.show_grid;
+    }
+
+    fn toggle_virtual_keypad(&mut self) {
+        self.show_virtual_keypad = !self.show_virtual_keypad;
+    }
+
+    fn audio(&mut self) -> &mut dyn Audio {
+        &mut self.buzzer
+    }
+
+    fn set_gamepad_map(&mut self, map: &BTreeMap<PadButton, u8>) {
+        let mut bindings = self.input.bindings().clone();
+        keymap::set_gamepad_map(&mut bindings, map);
+        self.input.set_bindings(bindings);
+    }
+
+    fn set_key_profile(&mut self, profile: KeyProfile) {
+        let mut bindings = self.input.bindings().clone();
+        keymap::set_key_profile(&mut bindings, profile, &self.key_remap);
+        self.input.set_bindings(bindings);
+        self.panels.key_profile = profile;
+    }
+
+    fn set_key_remap(&mut self, remap: &BTreeMap<u8, String>) {
+        self.key_remap = remap.clone();
+        self.set_key_profile(self.panels.key_profile);
+    }
+
+    fn toggle_key_remap(&mut self) {
+        self.remap = match self.remap {
+            None => Some(RemapCapture::ChooseKey),
+            Some(_) => None
+        };
+    }
+
+    fn on_mouse_click(&mut self, position: Point, breakpoints: &mut Breakpoints) {
+        if self.debug_mode{
+            let (screen_width, screen_height) = self.screen_size();
+            self.panels.click(position.x, position.y, screen_width, screen_height, breakpoints);
+        }
+    }
+
+    fn on_mouse_scroll(&mut self, position: Point, direction: isize) {
+        if self.debug_mode{
+            let (screen_width, screen_height) = self.screen_size();
+            self.panels.scroll(position.x, position.y, direction, screen_width, screen_height);
+        }
+    }
+
+    fn copy(&mut self, chip8: &Chip8, breakpoints: &Breakpoints) -> Result<(), Diagnostic> {
+        let text = if self.debug_mode{
+            let (screen_width, screen_height) = self.screen_size();
+            let (x, y) = (self.raylib_handle.get_mouse_x() as f32, self.raylib_handle.get_mouse_y() as f32);
+            self.panels.copy_text(chip8, breakpoints, x, y, screen_width, screen_height)
+                .ok_or_else(|| Diagnostic::error("There's nothing to copy under the mouse"))?
+        } else {
+            Screenshot::of(chip8.frame_buffer()).to_string()
+        };
+        self.raylib_handle.copy(&text)
+    }
+
+    fn paste(&mut self) -> Result<Vec<(Addr, u8)>, Diagnostic> {
+        let text = self.raylib_handle.paste()?;
+        self.panels.paste(&text)
+    }
+}
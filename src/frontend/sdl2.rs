@@ -0,0 +1,385 @@
+use std::{collections::HashMap, time};
+
+use sdl2::{audio::{AudioCVT, AudioFormat, AudioQueue, AudioSpecDesired, AudioSpecWAV}, clipboard::ClipboardUtil, event::Event, keyboard::{Mod, Scancode}, pixels::{Color, PixelFormatEnum}, render::WindowCanvas, rwops::RWops, AudioSubsystem, EventPump};
+
+use crate::{breakpoints::Breakpoints, clipboard::Clipboard, errors::Diagnostic, keypad::KeyProfile, quirks::Quirk, resources::{Resource, Resources}, screenshot::Screenshot, theme::{DisplayStyle, FrameHistory, Phosphor, Rgb}, Chip8, DISPLAY_COLUMNS, DISPLAY_ROWS};
+
+use super::{input::RawEvent, Audio, Chip8Frontend, InputManager, InputSource, KeyInput, Point, Renderer};
+
+/// A key, by where it is on the keyboard, or a key pressed while holding shift or control
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Binding{
+    Key(Scancode),
+    Shift(Scancode),
+    Control(Scancode)
+}
+
+/// The same layout as the raylib frontend's, less the keys for what only it has:
+/// the on-screen keypad, remapping keys and pasting into the memory view
+const KEYMAP: [(Scancode, KeyInput); 40] = [
+    (Scancode::Num1, KeyInput::Chip8Key(0x1)),
+    (Scancode::Num2, KeyInput::Chip8Key(0x2)),
+    (Scancode::Num3, KeyInput::Chip8Key(0x3)),
+    (Scancode::Num4, KeyInput::Chip8Key(0xc)),
+    (Scancode::Q, KeyInput::Chip8Key(0x4)),
+    (Scancode::W, KeyInput::Chip8Key(0x5)),
+    (Scancode::E, KeyInput::Chip8Key(0x6)),
+    (Scancode::R, KeyInput::Chip8Key(0xd)),
+    (Scancode::A, KeyInput::Chip8Key(0x7)),
+    (Scancode::S, KeyInput::Chip8Key(0x8)),
+    (Scancode::D, KeyInput::Chip8Key(0x9)),
+    (Scancode::F, KeyInput::Chip8Key(0xe)),
+    (Scancode::Z, KeyInput::Chip8Key(0xa)),
+    (Scancode::X, KeyInput::Chip8Key(0x0)),
+    (Scancode::C, KeyInput::Chip8Key(0xb)),
+    (Scancode::V, KeyInput::Chip8Key(0xf)),
+    (Scancode::Space, KeyInput::TogglePause),
+    (Scancode::P, KeyInput::TogglePause),
+    (Scancode::Period, KeyInput::ToggleDebug),
+    (Scancode::Return, KeyInput::Step),
+    (Scancode::T, KeyInput::StepTimers),
+    (Scancode::N, KeyInput::StepFrame),
+    (Scancode::Backspace, KeyInput::StepBack),
+    (Scancode::F2, KeyInput::ExportDebugScript),
+    (Scancode::F3, KeyInput::ToggleTrace),
+    (Scancode::F4, KeyInput::DumpTrace),
+    (Scancode::F5, KeyInput::ToggleCollisions),
+    (Scancode::F6, KeyInput::ExportRom),
+    (Scancode::F7, KeyInput::Screenshot),
+    (Scancode::F8, KeyInput::ToggleRecording),
+    (Scancode::G, KeyInput::ToggleGrid),
+    (Scancode::RightBracket, KeyInput::PitchUp),
+    (Scancode::LeftBracket, KeyInput::PitchDown),
+    (Scancode::Equals, KeyInput::SpeedUp),
+    (Scancode::Minus, KeyInput::SpeedDown),
+    (Scancode::Tab, KeyInput::Turbo),
+    (Scancode::F9, KeyInput::ToggleQuirk(Quirk::DisplayWait)),
+    (Scancode::F10, KeyInput::ToggleQuirk(Quirk::WaitForRelease)),
+    (Scancode::F11, KeyInput::ToggleQuirk(Quirk::ProtectLowMemory)),
+    (Scancode::F12, KeyInput::SaveCrashBundle)
+];
+
+const CHORDS: [(Binding, KeyInput); 15] = [
+    (Binding::Shift(Scancode::Return), KeyInput::StepOver),
+    (Binding::Control(Scancode::Return), KeyInput::StepOut),
+    (Binding::Control(Scancode::Space), KeyInput::Reset),
+    (Binding::Shift(Scancode::Space), KeyInput::SoftReset),
+    (Binding::Shift(Scancode::Tab), KeyInput::NextKeyProfile),
+    (Binding::Control(Scancode::Insert), KeyInput::Copy),
+    (Binding::Shift(Scancode::F1), KeyInput::OpenRecent(1)),
+    (Binding::Shift(Scancode::F2), KeyInput::OpenRecent(2)),
+    (Binding::Shift(Scancode::F3), KeyInput::OpenRecent(3)),
+    (Binding::Shift(Scancode::F4), KeyInput::OpenRecent(4)),
+    (Binding::Shift(Scancode::F5), KeyInput::OpenRecent(5)),
+    (Binding::Shift(Scancode::F6), KeyInput::OpenRecent(6)),
+    (Binding::Shift(Scancode::F7), KeyInput::OpenRecent(7)),
+    (Binding::Shift(Scancode::F8), KeyInput::OpenRecent(8)),
+    (Binding::Shift(Scancode::F9), KeyInput::OpenRecent(9))
+];
+
+/// Where `KeyProfile::TwoPlayer` moves the keypad's right column
+const PLAYER_TWO_KEYS: [(Scancode, u8); 4] = [
+    (Scancode::Up, 0xc),
+    (Scancode::Down, 0xd),
+    (Scancode::Left, 0xe),
+    (Scancode::Right, 0xf)
+];
+
+fn default_bindings() -> HashMap<Binding, KeyInput>{
+    KEYMAP.iter().map(|(key, input)| (Binding::Key(*key), *input))
+        .chain(CHORDS)
+        .collect()
+}
+
+/// Replace the keys bound to CHIP-8 keys with the ones `profile` uses
+fn set_key_profile(bindings: &mut HashMap<Binding, KeyInput>, profile: KeyProfile){
+    bindings.retain(|binding, input| !matches!((binding, input), (Binding::Key(_), KeyInput::Chip8Key(_))));
+    let player_two = profile.player_two();
+    let keypad = KEYMAP.iter().filter_map(|(key, input)| match input {
+        KeyInput::Chip8Key(chip8_key) if player_two & 1 << chip8_key == 0 => Some((*key, *chip8_key)),
+        _ => None
+    });
+    let moved = PLAYER_TWO_KEYS.into_iter().filter(|(_, chip8_key)| player_two & 1 << chip8_key != 0);
+    for (key, chip8_key) in keypad.chain(moved){
+        bindings.insert(Binding::Key(key), KeyInput::Chip8Key(chip8_key));
+    }
+}
+
+/// The binding a key going down presses: its chord if the modifier for one is held, otherwise the key itself
+fn pressed(bindings: &HashMap<Binding, KeyInput>, key: Scancode, keymod: Mod) -> Binding{
+    let chord = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+        Binding::Shift(key)
+    } else if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+        Binding::Control(key)
+    } else {
+        Binding::Key(key)
+    };
+    if bindings.contains_key(&chord) {chord} else {Binding::Key(key)}
+}
+
+/// The buzzer's sound played through an SDL2 audio queue, queued again before it runs out
+pub struct Sdl2Buzzer{
+    queue: AudioQueue<i16>,
+    wav: AudioSpecWAV,
+    /// The sound converted for the device, at `pitch`
+    samples: Vec<i16>,
+    pitch: f32
+}
+
+impl Sdl2Buzzer{
+    fn new(audio: &AudioSubsystem, resources: &Resources) -> Self{
+        let load = |bytes: &[u8]| RWops::from_bytes(bytes).and_then(|mut source| AudioSpecWAV::load_wav_rw(&mut source));
+        let wav = load(&resources.load(Resource::Buzzer))
+            .or_else(|err| {
+                eprintln!("Could not load the buzzer sound, using the built-in one: {}", err);
+                load(&Resource::Buzzer.embedded())
+            }).unwrap();
+        let desired = AudioSpecDesired{ freq: Some(wav.freq), channels: Some(wav.channels), samples: None };
+        let queue = audio.open_queue(None, &desired).expect("Could not open the audio device");
+        queue.resume();
+        let mut buzzer = Self{ queue, wav, samples: vec![], pitch: 1.0 };
+        buzzer.convert();
+        buzzer
+    }
+
+    /// Resample the sound for the device, played back faster or slower for the pitch
+    fn convert(&mut self){
+        let spec = *self.queue.spec();
+        let rate = (self.wav.freq as f32 * self.pitch) as i32;
+        self.samples = match AudioCVT::new(self.wav.format, self.wav.channels, rate, AudioFormat::s16_sys(), spec.channels, spec.freq) {
+            Ok(cvt) => cvt.convert(self.wav.buffer().to_vec()).chunks_exact(2)
+                .map(|sample| i16::from_ne_bytes([sample[0], sample[1]]))
+                .collect(),
+            Err(err) => {
+                eprintln!("Could not convert the buzzer sound for the audio device: {}", err);
+                vec![]
+            }
+        };
+    }
+}
+
+impl Audio for Sdl2Buzzer{
+    fn set_buzzer(&mut self, playing: bool) {
+        if !playing{
+            self.queue.clear();
+        }
+        // queued again once less than half of it is left, so it doesn't run out between frames
+        else if (self.queue.size() as usize) < self.samples.len(){
+            self.queue.queue_audio(&self.samples).unwrap_or_else(|err| eprintln!("Could not play the buzzer: {}", err));
+        }
+    }
+
+    fn set_pitch(&mut self, pitch: f32) {
+        if pitch != self.pitch{
+            self.pitch = pitch;
+            self.convert();
+        }
+    }
+}
+
+/// A plainer window than raylib's, for where raylib doesn't build: the display scaled to fit the window,
+/// the keyboard and the buzzer. It has no debug panels, gamepads or on-screen keypad,
+/// and shows messages in its title bar
+pub struct Sdl2Display{
+    canvas: WindowCanvas,
+    events: EventPump,
+    clipboard: ClipboardUtil,
+    buzzer: Sdl2Buzzer,
+    input: InputManager<Binding>,
+    style: DisplayStyle,
+    frames: FrameHistory,
+    phosphor: Phosphor,
+    show_grid: bool,
+    /// A message shown in the title bar and the frames left to show it
+    message: Option<(String, u16)>,
+    closed: bool
+}
+
+impl Sdl2Display{
+    /// Window pixels to each CHIP-8 pixel in a new window, the same size as raylib's
+    pub const DEFAULT_SCALE: u32 = 15;
+    const TITLE: &'static str = "Chip-8";
+    /// How many frames a message from `show_message` stays up for
+    const MESSAGE_FRAMES: u16 = 300;
+    const GRID_SPACING: usize = 8;
+    const GRID_COLOR: Color = Color::RGBA(80, 120, 255, 140);
+
+    /// Open a window `scale` times the size of the display, with the buzzer sound from `resources`
+    pub fn new(resources: &Resources, scale: u32) -> Self{
+        let context = sdl2::init().expect("Could not start SDL2");
+        let video = context.video().expect("Could not start SDL2's video");
+        let window = video.window(Self::TITLE, DISPLAY_COLUMNS as u32 * scale, DISPLAY_ROWS as u32 * scale)
+            .resizable()
+            .position_centered()
+            .build()
+            .expect("Could not open the window");
+        let mut canvas = window.into_canvas().build().expect("Could not draw to the window");
+        canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
+        let audio = context.audio().expect("Could not start SDL2's audio");
+        Self{
+            canvas,
+            events: context.event_pump().expect("Could not take SDL2's events"),
+            clipboard: video.clipboard(),
+            buzzer: Sdl2Buzzer::new(&audio, resources),
+            input: InputManager::new(default_bindings()),
+            style: DisplayStyle::default(),
+            frames: FrameHistory::default(),
+            phosphor: Phosphor::default(),
+            show_grid: false,
+            message: None,
+            closed: false
+        }
+    }
+
+    /// Draw the display as large as a whole number of window pixels to each CHIP-8 pixel allows, in the middle of the window
+    fn draw(&mut self, chip8: &Chip8) -> Result<(), String>{
+        self.frames.push(chip8.frame_buffer(), self.style.blend_frames);
+        self.phosphor.update(&self.frames, self.style.frame_ghosting(chip8.display_events()));
+        let (window_width, window_height) = self.canvas.output_size()?;
+        let scale = (window_width / DISPLAY_COLUMNS as u32).min(window_height / DISPLAY_ROWS as u32).max(1);
+        let (width, height) = (DISPLAY_COLUMNS as u32 * scale, DISPLAY_ROWS as u32 * scale);
+        let creator = self.canvas.texture_creator();
+        let mut texture = creator.create_texture_static(PixelFormatEnum::RGBA32, width, height).map_err(|err| err.to_string())?;
+        texture.update(None, &self.style.render_rgba(&self.phosphor, scale as usize), width as usize * 4).map_err(|err| err.to_string())?;
+        let (left, top) = (window_width.saturating_sub(width) as i32 / 2, window_height.saturating_sub(height) as i32 / 2);
+        self.canvas.set_draw_color(Color::from(self.style.background));
+        self.canvas.clear();
+        self.canvas.copy(&texture, None, sdl2::rect::Rect::new(left, top, width, height))?;
+        if self.show_grid{
+            // unlabelled, there being no font
+            self.canvas.set_draw_color(Self::GRID_COLOR);
+            for x in (0..DISPLAY_COLUMNS).step_by(Self::GRID_SPACING){
+                let screen_x = left + (x as u32 * scale) as i32;
+                self.canvas.draw_line((screen_x, top), (screen_x, top + height as i32))?;
+            }
+            for y in (0..DISPLAY_ROWS).step_by(Self::GRID_SPACING){
+                let screen_y = top + (y as u32 * scale) as i32;
+                self.canvas.draw_line((left, screen_y), (left + width as i32, screen_y))?;
+            }
+        }
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn set_title(&mut self, title: &str){
+        if let Err(err) = self.canvas.window_mut().set_title(title){
+            eprintln!("Could not set the window title: {}", err);
+        }
+    }
+}
+
+impl From<Rgb> for Color{
+    fn from(rgb: Rgb) -> Self{
+        Color::RGB(rgb.r, rgb.g, rgb.b)
+    }
+}
+
+impl Renderer for Sdl2Display{
+    fn update(&mut self, chip8: &Chip8, _breakpoints: &Breakpoints, _show_current_instruction: bool) -> bool {
+        if let Err(err) = self.draw(chip8){
+            eprintln!("Could not draw the display: {}", err);
+        }
+        if let Some((_, frames)) = &mut self.message{
+            *frames -= 1;
+            if *frames == 0{
+                self.message = None;
+                self.set_title(Self::TITLE);
+            }
+        }
+        self.closed
+    }
+
+    fn set_display_style(&mut self, style: &DisplayStyle) {
+        self.style = *style;
+    }
+
+    fn show_message(&mut self, message: &str) {
+        // a title bar has room for one line
+        let title = format!("{} - {}", Self::TITLE, message.lines().next().unwrap_or_default());
+        self.set_title(&title);
+        self.message = Some((message.to_string(), Self::MESSAGE_FRAMES));
+    }
+}
+
+impl InputSource for Sdl2Display{
+    fn get_inputs(&mut self) -> Vec<KeyInput> {
+        for event in self.events.poll_iter(){
+            match event {
+                Event::Quit{..} => self.closed = true,
+                Event::KeyDown{ scancode: Some(key), keymod, repeat: false, .. } =>
+                    self.input.push(RawEvent::Press(pressed(self.input.bindings(), key, keymod))),
+                // whichever way it went down, it's up now
+                Event::KeyUp{ scancode: Some(key), .. } => {
+                    for binding in [Binding::Key(key), Binding::Shift(key), Binding::Control(key)]{
+                        self.input.push(RawEvent::Release(binding));
+                    }
+                },
+                _ => {}
+            }
+        }
+        self.input.frame(time::Instant::now())
+    }
+}
+
+impl Chip8Frontend for Sdl2Display{
+    fn toggle_debug(&mut self) {
+        self.show_message("The debug panels need the raylib frontend");
+    }
+
+    fn is_debug_view(&self) -> bool {
+        false
+    }
+
+    fn toggle_grid(&mut self) {
+        self.show_grid = !self.show_grid;
+    }
+
+    fn on_mouse_scroll(&mut self, _position: Point, _direction: isize) {}
+
+    fn on_mouse_click(&mut self, _position: Point, _breakpoints: &mut Breakpoints) {}
+
+    fn audio(&mut self) -> &mut dyn Audio {
+        &mut self.buzzer
+    }
+
+    fn set_key_profile(&mut self, profile: KeyProfile) {
+        let mut bindings = self.input.bindings().clone();
+        set_key_profile(&mut bindings, profile);
+        self.input.set_bindings(bindings);
+    }
+
+    fn copy(&mut self, chip8: &Chip8, _breakpoints: &Breakpoints) -> Result<(), Diagnostic> {
+        self.clipboard.copy(&Screenshot::of(chip8.frame_buffer()).to_string())
+    }
+}
+
+
+#[test]
+fn test_sdl2_keymap(){
+    let mut bindings = default_bindings();
+    assert_eq!(bindings.len(), KEYMAP.len() + CHORDS.len(), "a key is bound twice");
+    let chip8_keys = |bindings: &HashMap<Binding, KeyInput>| bindings.values()
+        .filter_map(|input| match input {
+            KeyInput::Chip8Key(key) => Some(*key),
+            _ => None
+        }).fold(0u16, |keys, key| keys | 1 << key);
+    assert_eq!(chip8_keys(&bindings), 0xffff);
+    // holding a modifier takes no key away from the game
+    for (chord, _) in CHORDS{
+        let (Binding::Shift(key) | Binding::Control(key)) = chord else {panic!("{chord:?} isn't a chord")};
+        assert!(!matches!(bindings.get(&Binding::Key(key)), Some(KeyInput::Chip8Key(_))), "{chord:?} is on the keypad");
+    }
+    assert_eq!(pressed(&bindings, Scancode::Return, Mod::LSHIFTMOD), Binding::Shift(Scancode::Return));
+    assert_eq!(pressed(&bindings, Scancode::Return, Mod::RCTRLMOD), Binding::Control(Scancode::Return));
+    // the keypad still plays with shift held, e.g. with caps lock's neighbour down by accident
+    assert_eq!(pressed(&bindings, Scancode::W, Mod::LSHIFTMOD), Binding::Key(Scancode::W));
+
+    set_key_profile(&mut bindings, KeyProfile::TwoPlayer);
+    assert_eq!(chip8_keys(&bindings), 0xffff);
+    assert!(matches!(bindings.get(&Binding::Key(Scancode::Up)), Some(KeyInput::Chip8Key(0xc))));
+    assert!(!bindings.contains_key(&Binding::Key(Scancode::Num4)));
+    assert!(matches!(bindings.get(&Binding::Key(Scancode::Tab)), Some(KeyInput::Turbo)));
+    set_key_profile(&mut bindings, KeyProfile::Standard);
+    assert_eq!(bindings.len(), KEYMAP.len() + CHORDS.len());
+    assert!(!bindings.contains_key(&Binding::Key(Scancode::Up)));
+}
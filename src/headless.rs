@@ -0,0 +1,415 @@
+use std::{str::FromStr, time::{Duration, Instant}};
+
+use crate::{breakpoints::parse_number, display_events::DisplayEvent, emulator, errors::Diagnostic, extensions::Extension, quirks::Quirk, screenshot::Screenshot, Chip8, CpuState, Display, Fault, HaltCondition, HeadlessDriver, Instruction};
+
+/// Limits on a headless run, so that a ROM stuck in a loop it never leaves can't hang a test suite
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Watchdog{
+    /// Most instructions to run after the ROM is loaded
+    pub max_instructions: Option<u64>,
+    /// Longest to run for by the wall clock, from the first instruction after the ROM is loaded
+    pub max_time: Option<Duration>
+}
+
+/// The limit a run went past
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutReason{
+    Instructions(u64),
+    WallClock(Duration)
+}
+
+/// A run the watchdog stopped, and where the ROM had got to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeout{
+    pub reason: TimeoutReason,
+    pub pc: usize,
+    /// Instructions run since the ROM was loaded
+    pub instructions: u64,
+    pub elapsed: Duration
+}
+
+impl std::fmt::Display for Timeout{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.reason {
+            TimeoutReason::Instructions(limit) => write!(f, "ran {limit} instructions without finishing")?,
+            TimeoutReason::WallClock(limit) => write!(f, "ran for {:.1}s without finishing", limit.as_secs_f64())?
+        }
+        write!(f, ", stopped at {:#05x} after {} instructions in {:.1}s", self.pc, self.instructions, self.elapsed.as_secs_f64())
+    }
+}
+
+/// How a headless run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunResult{
+    /// Ran everything it was asked to
+    Finished,
+    /// The CPU halted, with the program counter on the faulting instruction
+    Halted(Fault),
+    /// The watchdog stopped it
+    TimedOut(Timeout)
+}
+
+/// A key held down for part of a headless run, written `KEY@FRAME` or `KEY@FRAME+FRAMES`,
+/// e.g. `5@60+10` holds key 5 for 10 frames from frame 60. Without `+FRAMES` it is held for one frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptedPress{
+    pub key: u8,
+    /// The frame it goes down on, counting from 0
+    pub frame: u64,
+    /// How many frames it is held for
+    pub frames: u64
+}
+
+impl ScriptedPress{
+    /// Whether the key is down during `frame`
+    pub fn is_down(&self, frame: u64) -> bool{
+        (self.frame..self.frame + self.frames).contains(&frame)
+    }
+}
+
+impl FromStr for ScriptedPress{
+    type Err = Diagnostic;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Diagnostic::error(format!("`{s}` should be KEY@FRAME or KEY@FRAME+FRAMES, e.g. 5@60+10"));
+        let (key, when) = s.split_once('@').ok_or_else(invalid)?;
+        let (frame, frames) = when.split_once('+').unwrap_or((when, "1"));
+        let key = u8::from_str_radix(key.trim(), 16).ok().filter(|key| *key < 16).ok_or_else(invalid)?;
+        let frame = parse_number(frame.trim()).ok_or_else(invalid)? as u64;
+        let frames = parse_number(frames.trim()).filter(|frames| *frames > 0).ok_or_else(invalid)? as u64;
+        Ok(Self{ key, frame, frames })
+    }
+}
+
+impl HeadlessDriver{
+    /// Most instructions `step_over` and `step_out` run waiting for a subroutine to return
+    pub const STEP_LIMIT: u64 = 1_000_000;
+    /// How many instructions run between looks at the clock, for a watchdog with a time limit
+    const CLOCK_CHECK_INTERVAL: u64 = 1024;
+
+    pub fn new(speed: Option<u64>) -> Self{
+        Self{
+            chip8: Chip8::init(speed),
+            cycles: 0,
+            panic: None,
+            watchdog: Watchdog::default(),
+            instructions: 0,
+            started: None
+        }
+    }
+
+    /// Load `rom`, starting the watchdog's count and clock again
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Diagnostic>{
+        self.chip8.load_rom(rom)?;
+        self.instructions = 0;
+        self.started = None;
+        Ok(())
+    }
+
+    /// Stop runs that go past `watchdog`'s limits with `RunResult::TimedOut`
+    pub fn set_watchdog(&mut self, watchdog: Watchdog){
+        self.watchdog = watchdog;
+    }
+
+    /// Halt the CPU on `condition` instead of carrying on through it, e.g. to end a run on a jump to itself
+    pub fn set_halt_on(&mut self, condition: HaltCondition, enabled: bool){
+        self.chip8.set_halt_on(condition, enabled)
+    }
+
+    /// Switch an extension's opcodes on or off
+    pub fn set_extension(&mut self, extension: Extension, enabled: bool){
+        self.chip8.set_extension(extension, enabled)
+    }
+
+    /// Switch a quirk on or off
+    pub fn set_quirk(&mut self, quirk: Quirk, enabled: bool){
+        self.chip8.set_quirk(quirk, enabled);
+    }
+
+    /// Start the random numbers of `RND` from `seed`, so that runs come out the same
+    pub fn seed_rng(&mut self, seed: u64){
+        self.chip8.seed_rng(seed)
+    }
+
+    /// Execute `n` instructions, ticking the timers once every
+    /// frame's worth of instructions as the windowed driver does.
+    /// Stops early if the CPU halts or the watchdog runs out.
+    /// A panic in the emulator halts the CPU with `Fault::Panic`, see `panic_message`
+    pub fn run_cycles(&mut self, n: u64) -> RunResult{
+        for _ in 0..n{
+            if let Some(fault) = self.chip8.fault(){
+                return RunResult::Halted(fault);
+            }
+            if let Some(timeout) = self.check_watchdog(){
+                return RunResult::TimedOut(timeout);
+            }
+            self.instructions += 1;
+            if self.cycles == 0{
+                self.chip8.tick_timers();
+            }
+            if let Some(message) = self.chip8.catch_panic(Chip8::do_instruction){
+                self.panic = Some(message);
+            }
+            self.cycles = (self.cycles + 1) % self.chip8.cycles_per_frame();
+        }
+        self.chip8.fault().map_or(RunResult::Finished, RunResult::Halted)
+    }
+
+    /// Execute `n` frames (60ths of a second) of emulation
+    pub fn run_frames(&mut self, n: u64) -> RunResult{
+        self.run_cycles(n * self.chip8.cycles_per_frame())
+    }
+
+    /// Execute `n` frames, holding down keys as `presses` say, with frame 0 the first of these frames
+    pub fn run_frames_with_presses(&mut self, n: u64, presses: &[ScriptedPress]) -> RunResult{
+        for frame in 0..n{
+            for press in presses{
+                if press.frame == frame{
+                    self.press_key(press.key);
+                } else if press.frame + press.frames == frame && !presses.iter().any(|other| other.key == press.key && other.is_down(frame)){
+                    self.release_key(press.key);
+                }
+            }
+            match self.run_frames(1) {
+                RunResult::Finished => {},
+                result => return result
+            }
+        }
+        RunResult::Finished
+    }
+
+    /// Run until the CPU halts, e.g. on a jump to itself with `set_halt_on`.
+    /// Only a watchdog stops a ROM that never halts, so set one first
+    pub fn run_until_halted(&mut self) -> RunResult{
+        loop {
+            match self.run_frames(1) {
+                RunResult::Finished => {},
+                result => return result
+            }
+        }
+    }
+
+    /// Execute the rest of the current frame, up to the next timer tick
+    pub fn step_frame(&mut self){
+        self.run_cycles(self.chip8.cycles_per_frame() - self.cycles);
+    }
+
+    /// The watchdog's timeout, if the next instruction would go past one of its limits
+    fn check_watchdog(&mut self) -> Option<Timeout>{
+        let Watchdog{ max_instructions, max_time } = self.watchdog;
+        let started = match (max_time, self.started) {
+            (None, _) => None,
+            (Some(_), Some(started)) => Some(started),
+            (Some(_), None) => Some(*self.started.insert(Instant::now()))
+        };
+        let elapsed = || started.map_or(Duration::ZERO, |started| started.elapsed());
+        let reason = match (max_instructions, max_time) {
+            (Some(limit), _) if self.instructions >= limit => TimeoutReason::Instructions(limit),
+            (_, Some(limit)) if self.instructions % Self::CLOCK_CHECK_INTERVAL == 0 && elapsed() >= limit => TimeoutReason::WallClock(limit),
+            _ => return None
+        };
+        Some(Timeout{ reason, pc: self.chip8.pc(), instructions: self.instructions, elapsed: elapsed() })
+    }
+
+    /// Execute one instruction, or the whole subroutine if it is a call.
+    /// Returns false if the subroutine hadn't returned after `STEP_LIMIT` instructions
+    pub fn step_over(&mut self) -> bool{
+        let instruction = emulator::get_instruction(&self.chip8.memory, &self.chip8.registers);
+        self.run_cycles(1);
+        !matches!(instruction, Instruction::Call(_)) || self.run_until_return(self.chip8.call_stack().len())
+    }
+
+    /// Execute the rest of the current subroutine, up to its return; outside a subroutine it does nothing.
+    /// Returns false if it hadn't returned after `STEP_LIMIT` instructions
+    pub fn step_out(&mut self) -> bool{
+        let depth = self.chip8.call_stack().len();
+        depth == 0 || self.run_until_return(depth)
+    }
+
+    /// Run until the call stack is shallower than `depth`, or the CPU stops
+    fn run_until_return(&mut self, depth: usize) -> bool{
+        for _ in 0..Self::STEP_LIMIT{
+            if self.chip8.call_stack().len() < depth || matches!(self.chip8.state(), CpuState::Halted(_)){
+                return true;
+            }
+            self.run_cycles(1);
+        }
+        false
+    }
+
+    /// Hold down a key until it is released with `release_key`. Keys past F are ignored
+    pub fn press_key(&mut self, key: u8){
+        self.chip8.press_key(key)
+    }
+
+    pub fn release_key(&mut self, key: u8){
+        self.chip8.release_key(key)
+    }
+
+    /// The display buffer, indexed like (column, row)
+    pub fn display(&self) -> &Display{
+        self.chip8.frame_buffer()
+    }
+
+    /// The clears, sprites and quirk changes since this was last called, oldest first, e.g. to send on only what changed
+    pub fn take_display_events(&mut self) -> Vec<DisplayEvent>{
+        let events = self.chip8.display_events().iter().copied().collect();
+        self.chip8.clear_display_events();
+        events
+    }
+
+    /// A copy of the display, for comparing with a golden screenshot
+    pub fn screenshot(&self) -> Screenshot{
+        Screenshot::of(self.chip8.frame_buffer())
+    }
+
+    /// Copy `bytes` into RAM starting at `addr`, see `Chip8::write_memory`
+    pub fn write_memory(&mut self, addr: usize, bytes: &[u8]) -> Result<(), Diagnostic>{
+        self.chip8.write_memory(addr, bytes)
+    }
+
+    pub fn pc(&self) -> usize{
+        self.chip8.pc()
+    }
+
+    /// Why the CPU halted, if it has
+    pub fn fault(&self) -> Option<Fault>{
+        self.chip8.fault()
+    }
+
+    /// What the emulator last panicked with, if it ever has
+    pub fn panic_message(&self) -> Option<&str>{
+        self.panic.as_deref()
+    }
+
+    pub fn sound(&self) -> bool{
+        self.chip8.sound()
+    }
+}
+
+
+#[test]
+fn test_headless_draw(){
+    // ld v0 0xa; ld f v0; drw v1 v1 5
+    let rom = [0x60, 0x0a, 0xf0, 0x29, 0xd1, 0x15];
+    let mut driver = HeadlessDriver::new(None);
+    driver.load_rom(&rom).unwrap();
+    driver.run_cycles(3);
+    assert_eq!(driver.pc(), 0x206);
+    // top row of the 'A' sprite is 0xf0
+    for x in 0..4{
+        assert!(driver.display()[[x, 0]]);
+    }
+    assert!(!driver.display()[[4, 0]]);
+}
+
+#[test]
+fn test_headless_rom_too_big(){
+    let mut driver = HeadlessDriver::new(None);
+    assert!(driver.load_rom(&[0; 4096]).is_err());
+}
+
+#[test]
+fn test_headless_stepping(){
+    // call 0x206; add v2 1; jp 0x204; ld v2 7; add v2 1; ret
+    let rom = [0x22, 0x06, 0x72, 0x01, 0x12, 0x04, 0x62, 0x07, 0x72, 0x01, 0x00, 0xee];
+    let mut driver = HeadlessDriver::new(Some(600));
+    driver.load_rom(&rom).unwrap();
+    assert!(driver.step_over());
+    assert_eq!((driver.pc(), driver.chip8.registers.vn[2]), (0x202, 8));
+    let mut driver = HeadlessDriver::new(Some(600));
+    driver.load_rom(&rom).unwrap();
+    driver.run_cycles(2);
+    assert!(driver.step_out());
+    assert_eq!((driver.pc(), driver.chip8.registers.vn[2]), (0x202, 8));
+    // from the top level there is no return to wait for
+    assert!(driver.step_out());
+    assert_eq!(driver.pc(), 0x202);
+    driver.step_frame();
+    assert_eq!((driver.cycles, driver.pc()), (0, 0x204));
+    driver.step_frame();
+    assert_eq!(driver.cycles, 0);
+}
+
+#[test]
+fn test_headless_watchdog(){
+    // add v0 1; jp 0x200
+    let rom = [0x70, 0x01, 0x12, 0x00];
+    let mut driver = HeadlessDriver::new(Some(600));
+    driver.load_rom(&rom).unwrap();
+    driver.set_watchdog(Watchdog{ max_instructions: Some(25), max_time: None });
+    assert_eq!(driver.run_frames(2), RunResult::Finished);
+    let RunResult::TimedOut(timeout) = driver.run_until_halted() else {panic!("the loop never halts")};
+    assert_eq!((timeout.reason, timeout.instructions, timeout.pc), (TimeoutReason::Instructions(25), 25, 0x202));
+    assert!(timeout.to_string().starts_with("ran 25 instructions without finishing, stopped at 0x202"));
+    // loading a ROM starts the count again
+    driver.load_rom(&rom).unwrap();
+    assert_eq!(driver.run_cycles(25), RunResult::Finished);
+
+    driver.set_watchdog(Watchdog{ max_instructions: None, max_time: Some(Duration::ZERO) });
+    driver.load_rom(&rom).unwrap();
+    assert!(matches!(driver.run_until_halted(), RunResult::TimedOut(Timeout{ reason: TimeoutReason::WallClock(_), .. })));
+
+    // a jump to itself ends the run
+    let mut driver = HeadlessDriver::new(Some(600));
+    driver.set_halt_on(HaltCondition::SelfJump, true);
+    driver.load_rom(&[0x12, 0x00]).unwrap();
+    assert_eq!(driver.run_until_halted(), RunResult::Halted(Fault::SelfJump));
+}
+
+#[test]
+fn test_headless_display_events(){
+    // ld v0 0x3f; drw v0 v0 2; drw v0 v0 1; cls; drw vf vf 1
+    let rom = [0x60, 0x3f, 0xd0, 0x02, 0xd0, 0x01, 0x00, 0xe0, 0xdf, 0xf1];
+    let mut driver = HeadlessDriver::new(Some(600));
+    driver.load_rom(&rom).unwrap();
+    driver.run_cycles(3);
+    assert_eq!(driver.take_display_events(), [
+        DisplayEvent::Sprite{ x: 63, y: 31, height: 2, collided: false },
+        DisplayEvent::Sprite{ x: 63, y: 31, height: 1, collided: true }
+    ]);
+    assert!(driver.take_display_events().is_empty());
+    // the sprite is placed by vf as it was before the draw set it
+    driver.run_cycles(2);
+    assert_eq!(driver.take_display_events(), [DisplayEvent::Clear, DisplayEvent::sprite(1, 1, 1, false)]);
+    driver.set_quirk(Quirk::WaitForRelease, true);
+    assert_eq!(driver.take_display_events(), [DisplayEvent::QuirkChanged{ quirk: Quirk::WaitForRelease, enabled: true }]);
+}
+
+#[test]
+fn test_headless_keys(){
+    // ld v0 k; ld v1 v0
+    let rom = [0xf0, 0x0a, 0x81, 0x00];
+    let mut driver = HeadlessDriver::new(None);
+    driver.load_rom(&rom).unwrap();
+    driver.run_cycles(5);
+    assert_eq!(driver.pc(), 0x202);
+    driver.press_key(0x7);
+    driver.run_cycles(1);
+    assert_eq!(driver.pc(), 0x204);
+    assert_eq!(driver.chip8.registers.vn[1], 0x7);
+    driver.release_key(0x7);
+    assert!(!driver.chip8.memory.keypad.is_down(0x7));
+    driver.press_key(16);
+    driver.release_key(16);
+    assert_eq!(driver.chip8.keys_down(), 0);
+}
+
+#[test]
+fn test_scripted_presses(){
+    assert_eq!("5@60+10".parse(), Ok(ScriptedPress{ key: 0x5, frame: 60, frames: 10 }));
+    assert_eq!("c@0x10".parse(), Ok(ScriptedPress{ key: 0xc, frame: 16, frames: 1 }));
+    for invalid in ["5", "g@1", "10@1", "5@x", "5@1+0"]{
+        assert!(invalid.parse::<ScriptedPress>().is_err(), "{invalid}");
+    }
+
+    // loop: ld v0 k; add v1 1; jp loop
+    let rom = [0xf0, 0x0a, 0x71, 0x01, 0x12, 0x00];
+    let mut driver = HeadlessDriver::new(Some(60));
+    driver.load_rom(&rom).unwrap();
+    let presses = ["3@2".parse().unwrap(), "4@5+3".parse().unwrap()];
+    assert_eq!(driver.run_frames_with_presses(10, &presses), RunResult::Finished);
+    assert_eq!(driver.chip8.registers.vn[0], 0x4);
+    assert_eq!(driver.chip8.registers.vn[1], 2);
+    assert!(!driver.chip8.memory.keypad.is_down(0x4));
+}
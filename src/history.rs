@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+
+use crate::Chip8;
+
+/// How far the emulator went after a snapshot was taken, and so how far stepping back to it goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Granularity{
+    /// A paused step, timer tick, reset or memory edit
+    Step,
+    /// A whole 60 hz frame, run or stepped
+    Frame
+}
+
+/// A ring buffer of recent emulator states, used to step backwards
+/// through execution while paused
+pub(crate) struct StateHistory{
+    states: VecDeque<(Chip8, Granularity)>,
+    depth: usize
+}
+
+impl StateHistory{
+    /// Ten seconds' worth of frames at 60 hz
+    pub const DEFAULT_DEPTH: usize = 60 * 10;
+
+    pub fn new(depth: usize) -> Self{
+        Self{
+            states: VecDeque::with_capacity(depth),
+            depth
+        }
+    }
+
+    /// Record a snapshot of `chip8` from before a `granularity` of running, dropping the oldest snapshot if the buffer is full
+    pub fn push(&mut self, chip8: &Chip8, granularity: Granularity){
+        if self.depth == 0 {
+            return
+        }
+        while self.states.len() >= self.depth{
+            self.states.pop_front();
+        }
+        self.states.push_back((chip8.clone(), granularity));
+    }
+
+    /// Remove and return the most recent snapshot
+    pub fn pop(&mut self) -> Option<(Chip8, Granularity)>{
+        self.states.pop_back()
+    }
+
+    pub fn set_depth(&mut self, depth: usize){
+        self.depth = depth;
+        while self.states.len() > self.depth{
+            self.states.pop_front();
+        }
+    }
+
+    /// The snapshot from `steps` pushes ago, where 1 is the most recent,
+    /// or none if a snapshot since then was taken before a whole frame
+    pub fn steps_ago(&self, steps: usize) -> Option<&Chip8>{
+        let index = self.states.len().checked_sub(steps)?;
+        let (chip8, _) = self.states.get(index)?;
+        self.states.range(index + 1..).all(|(_, granularity)| *granularity == Granularity::Step).then_some(chip8)
+    }
+
+    /// Forget every snapshot, e.g. after jumping to a saved session
+    pub fn clear(&mut self){
+        self.states.clear();
+    }
+}
+
+
+#[test]
+fn test_history_depth(){
+    let mut history = StateHistory::new(3);
+    let mut chip8 = Chip8::init(None);
+    for pc in 0..5{
+        chip8.registers.pc = 0x200 + pc * 2;
+        history.push(&chip8, Granularity::Step);
+    }
+    assert_eq!(history.steps_ago(3).unwrap().pc(), 0x204);
+    assert_eq!(history.steps_ago(2).unwrap().pc(), 0x206);
+    assert!(history.steps_ago(4).is_none());
+    assert!(history.steps_ago(0).is_none());
+    assert_eq!(history.pop().map(|(chip8, _)| chip8.pc()), Some(0x208));
+    assert_eq!(history.pop().map(|(chip8, _)| chip8.pc()), Some(0x206));
+    assert_eq!(history.pop().map(|(chip8, _)| chip8.pc()), Some(0x204));
+    assert!(history.pop().is_none());
+}
+
+#[test]
+fn test_history_shrink(){
+    let mut history = StateHistory::new(4);
+    let chip8 = Chip8::init(None);
+    for _ in 0..4{
+        history.push(&chip8, Granularity::Step);
+    }
+    history.set_depth(2);
+    assert!(history.steps_ago(2).is_some());
+    assert!(history.steps_ago(3).is_none());
+    history.set_depth(0);
+    history.push(&chip8, Granularity::Step);
+    assert!(history.pop().is_none());
+}
+
+#[test]
+fn test_history_granularity(){
+    let mut history = StateHistory::new(4);
+    let mut chip8 = Chip8::init(None);
+    for (pc, granularity) in [(0x200, Granularity::Frame), (0x210, Granularity::Frame), (0x220, Granularity::Step)]{
+        chip8.registers.pc = pc;
+        history.push(&chip8, granularity);
+    }
+    // the diff can go back to before the last frame, but not across it
+    assert_eq!(history.steps_ago(2).unwrap().pc(), 0x210);
+    assert!(history.steps_ago(3).is_none());
+    assert_eq!(history.pop().map(|(chip8, granularity)| (chip8.pc(), granularity)), Some((0x220, Granularity::Step)));
+    assert_eq!(history.pop().map(|(chip8, granularity)| (chip8.pc(), granularity)), Some((0x210, Granularity::Frame)));
+}
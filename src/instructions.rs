@@ -1,303 +1,409 @@
 
-use crate::Instruction;
-use crate::errors::ParseError;
-
-macro_rules! get_arg {
-    ($parts: expr, $index: expr) => {
-        {
-        let __arg_part = $parts.get($index);
-        match __arg_part {
-            Some(__arg_str) => {
-                let __arg_str = if let Some(__suffix) = __arg_str.strip_prefix("v"){
-                    __suffix
-                } else {__arg_str};
-                if let Ok(__val) = __arg_str.parse() {
-                    Ok(__val)
-                } else {Err(crate::errors::ParseError::new(&$parts.join(" "), &format!("Couldn't parse value {}", __arg_str)))}
-            },
-            None => Err(crate::errors::ParseError::new(&$parts.join(" "), &format!("Missing argument {}", $index)))
-        }}
+use std::str::FromStr;
+
+use crate::{Addr, Instruction, Reg, INSTRUCTION_SIZE};
+use crate::errors::{Diagnostic, Span};
+
+impl Reg{
+    pub const V0: Reg = Reg(0);
+    /// The flag register, set by arithmetic and drawing
+    pub const VF: Reg = Reg(0xF);
+
+    /// The register numbered `index`, or `None` if there is no such register
+    pub const fn new(index: u8) -> Option<Reg>{
+        if index <= 0xF {Some(Reg(index))} else {None}
+    }
+
+    /// The register named by the low 4 bits of `bits`
+    pub(crate) const fn from_nibble(bits: u16) -> Reg{
+        Reg((bits & 0xF) as u8)
+    }
+
+    /// Position of the register in the register file
+    pub const fn index(self) -> usize{
+        self.0 as usize
     }
 }
 
-impl Instruction{
-    pub fn from_mnemonic(mnemonic: &str) -> Result<Instruction,ParseError> {
-        let lower = mnemonic.to_ascii_lowercase();
-        let mnemonic_parts:Vec<_> = lower.split(|s:char|{s.is_whitespace()}).collect();
-        Ok(
-            match mnemonic_parts[0] {
-            "cls" => Instruction::ClearScreen,
-            "ret" => Instruction::Ret,
-            "nop" => Instruction::Nop,
-            "jp" => { 
-                if mnemonic_parts.len() == 1 {Instruction::Jump(get_arg!(mnemonic_parts, 1)?)}
-                else {Instruction::JumpOffset(get_arg!(mnemonic_parts,2)?)}
-            },
-            "call" => Instruction::Call(get_arg!(mnemonic_parts, 1)?),
-            "se" => { 
-                if mnemonic_parts[2].starts_with('V'){
-                    Instruction::SkipEqReg(get_arg!(mnemonic_parts, 1)?, get_arg!(mnemonic_parts, 2)?)
-                } else {Instruction::SkipEqImm(get_arg!(mnemonic_parts, 1)?, get_arg!(mnemonic_parts, 2)?)}
-            },
-            "sne" => { 
-                if mnemonic_parts[2].starts_with('V'){
-                    Instruction::SkipNeReg(get_arg!(mnemonic_parts, 1)?, get_arg!(mnemonic_parts, 2)?)
-                } else {Instruction::SkipNeImm(get_arg!(mnemonic_parts, 1)?, get_arg!(mnemonic_parts, 2)?)}
-            },
-            "ld" => {
-                match mnemonic_parts[1] {
-                    "i" => {Instruction::SetMemPtr(get_arg!(mnemonic_parts, 2)?)},
-                    "[i]" => {Instruction::RegDump(get_arg!(mnemonic_parts, 2)?)},
-                    "dt" => {Instruction::SetDelay(get_arg!(mnemonic_parts, 2)?)},
-                    "st" => {Instruction::SetSound(get_arg!(mnemonic_parts, 2)?)},
-                    "f" => {Instruction::SetChar(get_arg!(mnemonic_parts, 2)?)},
-                    "b" => {Instruction::BCD(get_arg!(mnemonic_parts, 2)?)},
-                    _ => {
-                        match mnemonic_parts[2] {
-                            "dt" => {Instruction::GetDelay(get_arg!(mnemonic_parts, 1)?)},
-                            "k" => {Instruction::WaitForKey(get_arg!(mnemonic_parts, 1)?)},
-                            "[i]" => {Instruction::RegLoad(get_arg!(mnemonic_parts, 1)?)},
-                            _ => {
-                                if mnemonic_parts[2].starts_with('v') {
-                                    Instruction::SetReg(get_arg!(mnemonic_parts, 1)?, get_arg!(mnemonic_parts, 2)?)
-                                } else {
-                                    Instruction::SetImm(get_arg!(mnemonic_parts, 1)?, get_arg!(mnemonic_parts, 2)?)
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            "add" => {
-                if mnemonic_parts[1] == "I" {
-                    Instruction::AddMemPtr(get_arg!(mnemonic_parts, 1)?)}
-                else if mnemonic_parts[2].starts_with('V'){ Instruction::AddReg(get_arg!(mnemonic_parts, 1)?, get_arg!(mnemonic_parts, 2)?)}
-                else {Instruction::AddImm(get_arg!(mnemonic_parts, 1)?, get_arg!(mnemonic_parts, 2)?)}
-            },
-            "or" => Instruction::OrReg(get_arg!(mnemonic_parts, 1)?, get_arg!(mnemonic_parts, 2)?),
-            "and" => Instruction::AndReg(get_arg!(mnemonic_parts, 1)?, get_arg!(mnemonic_parts, 2)?),
-            "xor" => Instruction::XorReg(get_arg!(mnemonic_parts, 1)?, get_arg!(mnemonic_parts, 2)?),
-            "sub" => Instruction::SubReg(get_arg!(mnemonic_parts, 1)?, get_arg!(mnemonic_parts, 2)?),
-            "rsh" => Instruction::Rsh(get_arg!(mnemonic_parts, 1)?),
-            "subn" => Instruction::SubFrom(get_arg!(mnemonic_parts, 1)?, get_arg!(mnemonic_parts, 2)?),
-            "lsh" => Instruction::Lsh(get_arg!(mnemonic_parts, 1)?),
-            "rnd" => Instruction::Rand(get_arg!(mnemonic_parts, 1)?, get_arg!(mnemonic_parts, 2)?),
-            "drw" => Instruction::Draw(get_arg!(mnemonic_parts, 1)?, get_arg!(mnemonic_parts, 2)?, get_arg!(mnemonic_parts, 3)?),
-            "skp" => Instruction::SkipKeyPressed(get_arg!(mnemonic_parts, 1)?),
-            "sknp" => Instruction::SkipKeyNotPressed(get_arg!(mnemonic_parts, 1)?),
-            _=> { return Err(ParseError::new(mnemonic, "Unknown instruction"))}
-        })
+impl TryFrom<u8> for Reg{
+    type Error = Diagnostic;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Reg::new(value).ok_or_else(|| Diagnostic::error(format!("No such register: v{value}")))
     }
 }
 
-// First register argument
-macro_rules! X {
-    ($opcode: expr) => {{
-        let reg = ($opcode & 0x0F00) >> 8;
-        assert!(reg < u8::MAX.into());
-        reg as u8
+impl From<Reg> for u16{
+    fn from(value: Reg) -> Self {
+        value.0 as u16
     }
-    };
 }
 
-// Second register argument
-macro_rules! Y {
-    ($opcode: expr) => {
-{        let reg = ($opcode & 0x00F0) >> 4;
-        assert!(reg < u8::MAX.into());
-        reg as u8
-}    };
+/// Parses a hex register number, with or without a leading `v`
+impl FromStr for Reg{
+    type Err = Diagnostic;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_ascii_lowercase();
+        let number = lower.strip_prefix('v').unwrap_or(&lower);
+        u8::from_str_radix(number, 16).ok().and_then(Reg::new)
+            .ok_or_else(|| Diagnostic::error(format!("No such register: {s}")))
+    }
 }
 
-// 4-bit immediate
-macro_rules! N {
-    ($opcode: expr) => {
-        ($opcode & 0x000F) as u8
+impl std::fmt::Display for Reg{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:X}", self.0)
     }
 }
 
-// 8-bit immediate
-macro_rules! NN {
-    ($opcode: expr) => {
-        ($opcode & 0x00FF) as u8
-    };
+impl std::fmt::LowerHex for Reg{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::LowerHex::fmt(&self.0, f)
+    }
 }
 
-// 12-bit immediate
-macro_rules! NNN {
-    ($opcode: expr) => {
-        $opcode & 0x0FFF
-    };
+impl Addr{
+    /// Where ROMs are loaded, and where execution starts
+    pub const ROM_START: Addr = Addr(0x200);
+    pub const MAX: Addr = Addr(0xFFF);
+
+    /// The address `addr`, or `None` if it doesn't fit in 12 bits
+    pub const fn new(addr: u16) -> Option<Addr>{
+        if addr <= Self::MAX.0 {Some(Addr(addr))} else {None}
+    }
+
+    /// The address in the low 12 bits of `bits`
+    pub(crate) const fn from_bits(bits: u16) -> Addr{
+        Addr(bits & Self::MAX.0)
+    }
+
+    /// Position of the address in RAM
+    pub const fn index(self) -> usize{
+        self.0 as usize
+    }
+
+    /// The address `delta` bytes away, or `None` if that is outside of memory
+    pub fn offset(self, delta: isize) -> Option<Addr>{
+        self.index().checked_add_signed(delta)
+            .and_then(|addr| Addr::try_from(addr).ok())
+    }
+
+    /// Whether the address is past the interpreter's reserved memory, where ROMs are loaded
+    pub const fn is_in_rom(self) -> bool{
+        self.0 >= Self::ROM_START.0
+    }
+
+    /// The start of the instruction containing this address
+    pub const fn align_down(self) -> Addr{
+        Addr(self.0 - self.0 % INSTRUCTION_SIZE as u16)
+    }
 }
 
-macro_rules! XNN {
-    ($reg: expr, $imm: expr) => {
-        ($reg as u16) << 8 | $imm as u16
-    };
+impl TryFrom<usize> for Addr{
+    type Error = Diagnostic;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        u16::try_from(value).ok().and_then(Addr::new)
+            .ok_or_else(|| Diagnostic::error(format!("Address out of range: {value:#x}")))
+    }
 }
 
-macro_rules! XY {
-    ($r1: expr, $r2: expr) => {
-        ($r1 as u16) << 8 | (($r2 as u16) << 4)
-    };
+impl From<Addr> for u16{
+    fn from(value: Addr) -> Self {
+        value.0
+    }
 }
 
-impl From<Instruction> for u16 {
-    fn from(value: Instruction) -> Self {
-        match value {
-            Instruction::Nop => 0x0000,
-            Instruction::ClearScreen => 0x00E0,
-            Instruction::Ret => 0x00EE,
-            Instruction::Jump(v) => 0x1000 | v,
-            Instruction::Call(v) => 0x2000 | v,
-            Instruction::SkipEqImm(reg, imm) => 0x3000 | XNN!(reg, imm),
-            Instruction::SkipNeImm(reg, imm ) =>  0x4000 | XNN!(reg, imm),
-            Instruction::SkipEqReg(r1, r2 ) => 0x5000 | XY!(r1, r2),
-            Instruction::SetImm(reg, imm) => 0x6000 | XNN!(reg, imm),
-            Instruction::AddImm(reg, imm) => 0x7000 | XNN!(reg, imm),
-            Instruction::SetReg(r1,r2) => 0x8000 | XY!(r1, r2) | 0,
-            Instruction::OrReg(r1,r2 ) => 0x8000 | XY!(r1, r2) | 1,
-            Instruction::AndReg(r1,r2 ) => 0x8000 | XY!(r1, r2) | 2,
-            Instruction::XorReg(r1,r2 ) => 0x8000 | XY!(r1, r2) | 3,
-            Instruction::AddReg(r1,r2 ) => 0x8000 | XY!(r1, r2) | 4,
-            Instruction::SubReg(r1,r2 ) => 0x8000 | XY!(r1, r2) | 5,
-            Instruction::Rsh(r1 ) => 0x8000 | XY!(r1, 0) | 6,
-            Instruction::SubFrom(r1,r2 ) => 0x8000 | XY!(r1, r2) | 7,
-            Instruction::Lsh(r1 ) => 0x8000 | XY!(r1, 0) | 0xe,
-            Instruction::SkipNeReg(r1,r2 )=> 0x9000 | XY!(r1, r2),
-            Instruction::SetMemPtr(imm) => 0xA000 | imm,
-            Instruction::JumpOffset(imm) => 0xB000 | imm,
-            Instruction::Rand(reg,imm ) => 0xC000 | XNN!(reg, imm),
-            Instruction::Draw(x,y ,n) => 0xD000 | XY!(x,y) | n as u16,
-            Instruction::SkipKeyPressed(reg) => 0xE09E | XY!(reg,0),
-            Instruction::SkipKeyNotPressed(reg) => 0xE0A1 | XY!(reg,0),
-            Instruction::GetDelay(reg) => 0xF007 | XY!(reg,0),
-            Instruction::WaitForKey(reg) => 0xF00A | XY!(reg,0),
-            Instruction::SetDelay(reg) => 0xF015 | XY!(reg,0),
-            Instruction::SetSound(reg) => 0xF018 | XY!(reg,0),
-            Instruction::AddMemPtr(reg) => 0xF01E | XY!(reg,0),
-            Instruction::SetChar(reg) => 0xF029 | XY!(reg,0),
-            Instruction::BCD(reg) => 0xF033 | XY!(reg,0),
-            Instruction::RegDump(reg) => 0xF055 | XY!(reg,0),
-            Instruction::RegLoad(reg) => 0xF065 | XY!(reg,0)
-        }
+impl From<Addr> for usize{
+    fn from(value: Addr) -> Self {
+        value.index()
     }
 }
 
-impl std::fmt::Display for Instruction {
+/// Parses a decimal or 0x-prefixed hex address
+impl FromStr for Addr{
+    type Err = Diagnostic;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        crate::breakpoints::parse_number(s)
+            .ok_or_else(|| Diagnostic::error(format!("Invalid address: {s}")))
+            .and_then(Addr::try_from)
+    }
+}
+
+impl std::fmt::Display for Addr{
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Instruction::Nop => write!(f, "NOP"),
-            Instruction::ClearScreen => write!(f, "CLS"),
-            Instruction::Ret => write!(f, "RET"),
-            Instruction::Jump(v) => write!(f, "JMP V{}", v),
-            Instruction::Call(v) => write!(f, "CALL V{}", v),
-            Instruction::SkipEqImm(reg, imm) => write!(f, "SE V{} {}", reg, imm),
-            Instruction::SkipEqReg(r1, r2 ) => write!(f, "SE V{} V{}", r1, r2),
-            Instruction::SkipNeImm(reg, imm ) =>  write!(f, "SNE V{} {}", reg, imm),
-            Instruction::SkipNeReg(r1,r2 )=> write!(f, "SNE V{r1} V{r2}"),
-            Instruction::SetImm(reg, imm) => write!(f, "LD V{reg} {imm}"),
-            Instruction::AddImm(reg, imm) => write!(f, "ADD V{reg} {imm}"),
-            Instruction::AddMemPtr(reg) => write!(f, "ADD I V{reg}"),
-            Instruction::AddReg(r1,r2 ) => write!(f, "ADD V{r1} V{r2}"),
-            Instruction::SetReg(r1,r2) => write!(f, "LD V{r1} V{r2}"),
-            Instruction::OrReg(r1,r2 ) => write!(f, "OR V{r1} V{r2}"),
-            Instruction::AndReg(r1,r2 ) => write!(f, "AND V{r1} V{r2}"),
-            Instruction::XorReg(r1,r2 ) => write!(f, "XOR V{r1} V{r2}"),
-            Instruction::SubReg(r1,r2 ) => write!(f, "SUB V{r1} V{r2}"),
-            Instruction::Rsh(r1 ) => write!(f, "RSH V{r1}"),
-            Instruction::SubFrom(r1,r2 ) => write!(f, "SUBN V{r1} V{r2}"),
-            Instruction::Lsh(r1 ) => write!(f, "LSH V{r1}"),
-            Instruction::SetMemPtr(imm) => write!(f, "LD I {imm}"),
-            Instruction::JumpOffset(imm) => write!(f, "JP V0 {imm}"),
-            Instruction::Rand(reg,imm ) => write!(f, "RND V{reg} {imm}"),
-            Instruction::Draw(x,y ,n) => write!(f, "DRW V{x} V{y} {n}"),
-            Instruction::SkipKeyPressed(reg) => write!(f, "SKP V{reg}"),
-            Instruction::SkipKeyNotPressed(reg) => write!(f, "SKNP V{reg}"),
-            Instruction::GetDelay(reg) => write!(f, "LD V{reg} DT"),
-            Instruction::WaitForKey(reg) => write!(f, "LD V{reg} K"),
-            Instruction::SetDelay(reg) => write!(f, "LD DT V{reg}"),
-            Instruction::SetSound(reg) => write!(f, "LD ST V{reg}"),
-            Instruction::SetChar(reg) => write!(f, "LD F V{reg}"),
-            Instruction::BCD(reg) => write!(f, "LD B V{reg}"),
-            Instruction::RegDump(reg) => write!(f, "LD [I] V{reg}"),
-            Instruction::RegLoad(reg) => write!(f, "LD V{reg} [I]")
-        }
+        write!(f, "{:#x}", self.0)
     }
 }
 
+impl std::fmt::LowerHex for Addr{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::LowerHex::fmt(&self.0, f)
+    }
+}
 
+/// One operand of a mnemonic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Operand{
+    Reg(Reg),
+    /// A number or a character constant
+    Number(u16),
+    I,
+    /// `[I]`, memory at I
+    IndirectI,
+    DT,
+    ST,
+    K,
+    F,
+    B
+}
 
-impl From<u16> for Instruction {
-    fn from(opcode: u16) -> Self {
-        match opcode {
-            0x00E0 => Self::ClearScreen,
-            0x00EE => Self::Ret,
-            0x1000..=0x1fff => {
-                Self::Jump(NNN!(opcode))
-            },
-            0x2000..=0x2fff => {
-                Self::Call(NNN!(opcode))
-            },
-            0x3000..=0x3fff => {
-                Self::SkipEqImm(X!(opcode), NN!(opcode))
-            },
-            0x4000..=0x4fff => {
-                Self::SkipNeImm(X!(opcode), NN!(opcode))
-            },
-            0x5000..=0x5ff0 => {
-                Self::SkipEqReg(X!(opcode), Y!(opcode))
-            },
-            0x6000..=0x6fff => {
-                Self::SetImm(X!(opcode), NN!(opcode))
-            },
-            0x7000..=0x7fff => {
-                Self::AddImm(X!(opcode), NN!(opcode))
-            },
-            0x8000..=0x8fff => {
-                match opcode & 0x000f{
-                    0x0000 => Self::SetReg(X!(opcode), Y!(opcode)),
-                    0x0001 => Self::OrReg(X!(opcode), Y!(opcode)),
-                    0x0002 => Self::AndReg(X!(opcode), Y!(opcode)),
-                    0x0003 => Self::XorReg(X!(opcode), Y!(opcode)),
-                    0x0004 => Self::AddReg(X!(opcode), Y!(opcode)),
-                    0x0005 => Self::SubReg(X!(opcode), Y!(opcode)),
-                    0x0006 => Self::Rsh(X!(opcode)),
-                    0x0007 => Self::SubFrom(X!(opcode), Y!(opcode)),
-                    0x000E => Self::Lsh(X!(opcode)),
-                    _ => Self::Nop
-                }
-            },
-            0x9000..=0x9ff0 => Self::SkipNeReg(X!(opcode), Y!(opcode)),
-            0xA000..=0xAfff => Self::SetMemPtr(NNN!(opcode)),
-            0xB000..=0xBfff => Self::JumpOffset(NNN!(opcode)),
-            0xC000..=0xCfff => Self::Rand(X!(opcode), NN!(opcode)),
-            0xD000..=0xDfff => Self::Draw(X!(opcode), Y!(opcode), N!(opcode)),
-            0xE000..=0xEfff => {
-                let lower = NN!(opcode);
-                match lower {
-                    0x9E => Self::SkipKeyPressed(X!(opcode)),
-                    0xA1 => Self::SkipKeyNotPressed(X!(opcode)),
-                    _ => Self::Nop
-                }
-            },
-            0xF000..=0xFfff => {
-                let lower = NN!(opcode);
-                match lower {
-                    0x07 => Self::GetDelay(X!(opcode)),
-                    0x0A => Self::WaitForKey(X!(opcode)),
-                    0x15 => Self::SetDelay(X!(opcode)),
-                    0x18 => Self::SetSound(X!(opcode)),
-                    0x1E => Self::AddMemPtr(X!(opcode)),
-                    0x29 => Self::SetChar(X!(opcode)),
-                    0x33 => Self::BCD(X!(opcode)),
-                    0x55 => Self::RegDump(X!(opcode)),
-                    0x65 => Self::RegLoad(X!(opcode)),
-                    _ => Self::Nop
+/// A word of a mnemonic and the 1-based column it starts at
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Token<'a>{
+    pub(crate) text: &'a str,
+    column: usize
+}
+
+/// Split a mnemonic into words, separated by whitespace or commas.
+/// Character constants like `' '` are kept whole
+fn tokenize(mnemonic: &str) -> Result<Vec<Token<'_>>, Diagnostic>{
+    let mut tokens = Vec::new();
+    let mut chars = mnemonic.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek(){
+        if c.is_whitespace() || c == ','{
+            chars.next();
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        chars.next();
+        if c == '\''{
+            // the character, then the closing quote
+            let quoted: Vec<_> = chars.by_ref().take(2).collect();
+            match quoted[..] {
+                [(_, _), (close, '\'')] => end = close + 1,
+                _ => return Err(Diagnostic::error("Unterminated character constant")
+                    .with_span(Span::new(1, start + 1, 1, mnemonic.len() + 1), mnemonic))
+            }
+        } else {
+            while let Some(&(index, c)) = chars.peek(){
+                if c.is_whitespace() || c == ','{
+                    break;
                 }
+                end = index + c.len_utf8();
+                chars.next();
             }
-            _ => Self::Nop
         }
+        tokens.push(Token{ text: &mnemonic[start..end], column: start + 1 });
     }
+    Ok(tokens)
 }
 
+/// A decimal, 0x-prefixed hex or 0b-prefixed binary number, or a character constant like `'A'`
+fn parse_literal(text: &str) -> Option<u16>{
+    let lower = text.to_ascii_lowercase();
+    if let Some(quoted) = text.strip_prefix('\'').and_then(|rest| rest.strip_suffix('\'')){
+        let mut chars = quoted.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii() => Some(c as u16),
+            _ => None
+        }
+    }
+    if let Some(hex) = lower.strip_prefix("0x"){
+        u16::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b"){
+        u16::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+impl Operand{
+    fn parse(text: &str) -> Option<Operand>{
+        let lower = text.to_ascii_lowercase();
+        Some(match lower.as_str() {
+            "i" => Operand::I,
+            "[i]" => Operand::IndirectI,
+            "dt" => Operand::DT,
+            "st" => Operand::ST,
+            "k" => Operand::K,
+            "f" => Operand::F,
+            "b" => Operand::B,
+            _ => match lower.strip_prefix('v') {
+                Some(number) if number.len() == 1 => Operand::Reg(Reg::new(u8::from_str_radix(number, 16).ok()?)?),
+                _ => Operand::Number(parse_literal(text)?)
+            }
+        })
+    }
+}
+
+/// The operands of a mnemonic, for turning into the arguments of an instruction
+pub(crate) struct Operands<'a>{
+    mnemonic: &'a str,
+    tokens: Vec<Token<'a>>,
+    pub(crate) operands: Vec<Operand>
+}
+
+impl<'a> Operands<'a>{
+    /// Split a mnemonic into the instruction name and its operands
+    pub(crate) fn split(mnemonic: &'a str) -> Result<(Token<'a>, Self), Diagnostic>{
+        let mut tokens = tokenize(mnemonic)?;
+        if tokens.is_empty(){
+            return Err(Diagnostic::error("Empty instruction"));
+        }
+        let name = tokens.remove(0);
+        let operands = tokens.iter().map(|token| Operand::parse(token.text)
+            .ok_or_else(|| Self::error_at(mnemonic, *token, format!("Invalid operand: {}", token.text))))
+            .collect::<Result<_, _>>()?;
+        Ok((name, Self { mnemonic, tokens, operands }))
+    }
+
+    /// A known instruction `name` with operands that don't fit it
+    pub(crate) fn invalid(&self, name: Token) -> Diagnostic{
+        Diagnostic::error(format!("Invalid operands for {}: {}", name.text.to_ascii_lowercase(), self.mnemonic.trim()))
+            .with_span(Span::new(1, name.column, 1, self.mnemonic.trim_end().len() + 1), self.mnemonic)
+    }
+
+    pub(crate) fn unknown(&self, name: Token) -> Diagnostic{
+        Self::error_at(self.mnemonic, name, format!("Unknown instruction: {}", name.text))
+    }
+
+    fn error_at(mnemonic: &str, token: Token, message: String) -> Diagnostic{
+        Diagnostic::error(message)
+            .with_span(Span::new(1, token.column, 1, token.column + token.text.len()), mnemonic)
+    }
+
+    fn error(&self, index: usize, message: String) -> Diagnostic{
+        Self::error_at(self.mnemonic, self.tokens[index], message)
+    }
+
+    fn number(&self, index: usize) -> Result<u16, Diagnostic>{
+        match self.operands[index] {
+            Operand::Number(value) => Ok(value),
+            _ => Err(self.error(index, format!("Expected a number, found {}", self.tokens[index].text)))
+        }
+    }
+
+    /// Operand `index` as a register. Bare register numbers like the `1` of `drw 1 2 5`
+    /// are accepted where only a register makes sense
+    pub(crate) fn reg(&self, index: usize) -> Result<Reg, Diagnostic>{
+        match self.operands[index] {
+            Operand::Reg(reg) => Ok(reg),
+            Operand::Number(value) if value <= 0xF => Ok(Reg(value as u8)),
+            _ => Err(self.error(index, format!("No such register: {}", self.tokens[index].text)))
+        }
+    }
+
+    pub(crate) fn byte(&self, index: usize) -> Result<u8, Diagnostic>{
+        let value = self.number(index)?;
+        u8::try_from(value).map_err(|_| self.error(index, format!("Value {value} doesn't fit in a byte")))
+    }
+
+    pub(crate) fn nibble(&self, index: usize) -> Result<u8, Diagnostic>{
+        let value = self.number(index)?;
+        u8::try_from(value).ok().filter(|value| *value <= 0xF)
+            .ok_or_else(|| self.error(index, format!("Value {value} doesn't fit in 4 bits")))
+    }
+
+    pub(crate) fn addr(&self, index: usize) -> Result<Addr, Diagnostic>{
+        let value = self.number(index)?;
+        Addr::new(value).ok_or_else(|| self.error(index, format!("Address out of range: {value:#x}")))
+    }
+}
+
+/// Parses one instruction, see `Instruction::from_mnemonic`
+impl FromStr for Instruction{
+    type Err = Diagnostic;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Instruction::from_mnemonic(s)
+    }
+}
+
+#[test]
+fn test_register_operands(){
+    assert_eq!(Reg::new(0xF), Some(Reg::VF));
+    assert_eq!(Reg::new(0x10), None);
+    assert_eq!("va".parse::<Reg>().unwrap(), Reg(0xA));
+    assert!("v10".parse::<Reg>().is_err());
+    let instruction = Instruction::from_mnemonic("ld va 7").unwrap();
+    assert_eq!(instruction, Instruction::SetImm(Reg(0xA), 7));
+    assert_eq!(instruction.to_string(), "LD VA 7");
+    assert_eq!(u16::from(instruction), 0x6A07);
+}
+
+#[test]
+fn test_address_operands(){
+    assert_eq!(Addr::new(0x1000), None);
+    assert_eq!(Addr(0x203).align_down(), Addr(0x202));
+    assert_eq!(Addr(0x200).offset(-2), Some(Addr(0x1fe)));
+    assert_eq!(Addr::MAX.offset(1), None);
+    assert!(Addr::ROM_START.is_in_rom() && !Addr(0x1fe).is_in_rom());
+    let instruction = Instruction::from_mnemonic("jp 0x2a4").unwrap();
+    assert_eq!(instruction, Instruction::Jump(Addr(0x2a4)));
+    assert_eq!(instruction.to_string(), "JP 0x2a4");
+    assert_eq!(u16::from(instruction), 0x12a4);
+}
+
+#[test]
+fn test_every_mnemonic(){
+    let cases = [
+        ("cls", 0x00E0), ("ret", 0x00EE), ("nop", 0x0000), ("vsync", 0x00F1),
+        ("jp 0x2a4", 0x12A4), ("call 0x300", 0x2300),
+        ("se v3 0x1f", 0x331F), ("sne v3 'A'", 0x4341), ("se v3 v4", 0x5340),
+        ("ld v3 0b1010", 0x630A), ("add v3 255", 0x73FF),
+        ("ld v3 v4", 0x8340), ("or v3 v4", 0x8341), ("and v3 v4", 0x8342), ("xor v3 v4", 0x8343),
+        ("add v3 v4", 0x8344), ("sub v3 v4", 0x8345), ("shr v3", 0x8306), ("subn v3 v4", 0x8347),
+        ("shl v3 v4", 0x830E), ("sne v3 v4", 0x9340),
+        ("ld i 0x3ff", 0xA3FF), ("jp v0 0x300", 0xB300), ("rnd v3 0x0f", 0xC30F), ("drw v3 v4 5", 0xD345),
+        ("skp v3", 0xE39E), ("sknp v3", 0xE3A1),
+        ("ld v3 dt", 0xF307), ("ld v3 k", 0xF30A), ("ld dt v3", 0xF315), ("ld st v3", 0xF318),
+        ("add i v3", 0xF31E), ("ld f v3", 0xF329), ("ld b v3", 0xF333),
+        ("ld [i] v3", 0xF355), ("ld v3 [i]", 0xF365)
+    ];
+    assert_eq!(cases.len(), 36);
+    for (mnemonic, opcode) in cases{
+        let instruction = Instruction::from_mnemonic(mnemonic).unwrap_or_else(|err| panic!("{mnemonic}: {err}"));
+        assert_eq!(u16::from(instruction), opcode, "{mnemonic}");
+    }
+}
+
+#[test]
+fn test_mnemonic_round_trip(){
+    // everything the disassembler prints assembles back to the same instruction
+    for opcode in 0..=u16::MAX{
+        let instruction = Instruction::from(opcode);
+        assert_eq!(instruction.to_string().parse(), Ok(instruction), "{opcode:#06x}");
+    }
+}
+
+#[test]
+fn test_mnemonic_syntax(){
+    assert_eq!(Instruction::from_mnemonic("DRW V0, VA, 0xf"), Ok(Instruction::Draw(Reg(0), Reg(0xA), 0xF)));
+    assert_eq!(Instruction::from_mnemonic("ld v1 ' '"), Ok(Instruction::SetImm(Reg(1), b' ')));
+    assert_eq!(Instruction::from_mnemonic("drw 1 2 5"), Ok(Instruction::Draw(Reg(1), Reg(2), 5)));
+    assert_eq!(Instruction::from_mnemonic("rand v2 0b11"), Ok(Instruction::Rand(Reg(2), 3)));
+
+    let error = Instruction::from_mnemonic("ld v1 256").unwrap_err();
+    assert_eq!(error.message, "Value 256 doesn't fit in a byte");
+    assert_eq!(error.span, Some(Span::new(1, 7, 1, 10)));
+    assert_eq!(Instruction::from_mnemonic("jmp 0x200").unwrap_err().message, "Unknown instruction: jmp");
+    assert_eq!(Instruction::from_mnemonic("jp v1 0x200").unwrap_err().message, "Invalid operands for jp: jp v1 0x200");
+    assert!(Instruction::from_mnemonic("jp 0x1000").is_err());
+    assert!(Instruction::from_mnemonic("drw v0 v1 16").is_err());
+    assert!(Instruction::from_mnemonic("ld v1 'ab'").is_err());
+    assert!(Instruction::from_mnemonic("ld v1 'a").is_err());
+    assert!(Instruction::from_mnemonic("  ").is_err());
+}
+
+#[test]
+fn test_decode_ignored_bits(){
+    // the Vy of the shifts is ignored, but the last nibble of 5XY0 and 9XY0 isn't
+    assert_eq!(Instruction::from(0x83A6), Instruction::Rsh(Reg(3)));
+    assert_eq!(u16::from(Instruction::Rsh(Reg(3))), 0x8306);
+    assert_eq!(Instruction::from(0x5341), Instruction::Nop);
+    assert_eq!(Instruction::from(0x9341), Instruction::Nop);
+    assert_eq!(Instruction::from(0xE39F), Instruction::Nop);
+}
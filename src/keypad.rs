@@ -0,0 +1,218 @@
+use std::{fmt::Display, str::FromStr};
+
+use itertools::Itertools;
+
+use crate::errors::{parse_name, Diagnostic};
+
+/// Keys 0 to F
+pub const KEY_COUNT: u8 = 16;
+
+/// What a key is doing, as the CPU sees it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyState{
+    Up,
+    /// Went down since the CPU last looked
+    Pressed,
+    /// Was already down when the CPU last looked, and still is
+    Held,
+    /// Came up since the CPU last looked
+    Released
+}
+
+/// The 16 keys, one bit each. Keys go up and down whenever the frontend says, but presses and releases
+/// are only counted when the CPU next looks, before each instruction. So releasing a held key and pressing it
+/// again in between, as the driver does every frame, isn't a new press, while a tap too quick to be seen held still is
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Keypad{
+    down: u16,
+    /// The keys down when the CPU last looked
+    seen: u16,
+    /// Keys pressed and released since the CPU last looked
+    pressed: u16,
+    released: u16,
+    /// The key pressed while waiting for a key, until it is released, when waiting for release
+    awaiting: Option<u8>
+}
+
+/// The bit for `key`. Callers check keys come from the keypad; past F, a release build wraps round rather than panicking
+fn bit(key: u8) -> u16{
+    debug_assert!(key < KEY_COUNT, "there is no key {key:#x}");
+    1 << (key & 0xf)
+}
+
+impl Keypad{
+    pub fn press(&mut self, key: u8){
+        self.down |= bit(key);
+        self.pressed |= bit(key);
+    }
+
+    pub fn release(&mut self, key: u8){
+        self.released |= self.down & bit(key);
+        self.down &= !bit(key);
+    }
+
+    pub fn clear(&mut self){
+        self.released |= self.down;
+        self.down = 0;
+    }
+
+    pub fn is_down(&self, key: u8) -> bool{
+        self.down & bit(key) != 0
+    }
+
+    /// The keys held down, as a bit for each key
+    pub fn down(&self) -> u16{
+        self.down
+    }
+
+    pub fn state(&self, key: u8) -> KeyState{
+        match (self.seen & bit(key) != 0, self.is_down(key)) {
+            (false, false) => KeyState::Up,
+            (false, true) => KeyState::Pressed,
+            (true, true) => KeyState::Held,
+            (true, false) => KeyState::Released
+        }
+    }
+
+    /// Look at the keys: the ones newly pressed and the ones released since the last look
+    pub fn poll(&mut self) -> (u16, u16){
+        let pressed = self.pressed & !self.seen;
+        let released = self.released & (self.seen | pressed) & !self.down;
+        self.seen = self.down;
+        self.pressed = 0;
+        self.released = 0;
+        (pressed, released)
+    }
+
+    /// The key that ends a wait for a key, given the keys `pressed` and `released` at the last `poll`:
+    /// the lowest one pressed, or with `on_release`, the lowest one pressed once it has been released
+    pub fn wait(&mut self, pressed: u16, released: u16, on_release: bool) -> Option<u8>{
+        let lowest = |keys: u16| (keys != 0).then(|| keys.trailing_zeros() as u8);
+        if !on_release{
+            return lowest(pressed);
+        }
+        if self.awaiting.is_none(){
+            self.awaiting = lowest(pressed);
+        }
+        let key = self.awaiting.filter(|key| released & bit(*key) != 0)?;
+        self.awaiting = None;
+        Some(key)
+    }
+}
+
+/// Which keyboard keys play the CHIP-8 keypad
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum KeyProfile{
+    /// The keypad on 1234/QWER/ASDF/ZXCV
+    #[default]
+    Standard,
+    /// Two players on one keyboard: the keypad's right column, C D E F, moves to the arrow keys,
+    /// so the second paddle in games like Pong is played from the other side of the keyboard
+    TwoPlayer
+}
+
+impl KeyProfile{
+    pub const ALL: [KeyProfile; 2] = [KeyProfile::Standard, KeyProfile::TwoPlayer];
+
+    /// The second player's keys, as a bit for each key, or none if the keyboard isn't split
+    pub fn player_two(self) -> u16{
+        match self {
+            KeyProfile::Standard => 0,
+            KeyProfile::TwoPlayer => 0xf000
+        }
+    }
+
+    /// The profile after this one, to cycle through them
+    pub fn next(self) -> Self{
+        let index = Self::ALL.iter().position(|profile| *profile == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// The keys held down, split between the players if the keyboard is, e.g. `P1: 5 8  P2: c`
+    pub fn describe(self, down: u16) -> String{
+        let keys = |mask: u16| match down & mask {
+            0 => "-".to_string(),
+            keys => (0..16u8).filter(|key| keys & 1 << key != 0).map(|key| format!("{key:x}")).join(" ")
+        };
+        match self.player_two() {
+            0 => keys(0xffff),
+            player_two => format!("P1: {}  P2: {}", keys(!player_two), keys(player_two))
+        }
+    }
+}
+
+impl Display for KeyProfile{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            KeyProfile::Standard => "standard",
+            KeyProfile::TwoPlayer => "two-player"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Parses the names `--keymap` takes, e.g. `two-player`
+impl FromStr for KeyProfile{
+    type Err = Diagnostic;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_name(&Self::ALL, "key profile", s)
+    }
+}
+
+
+#[test]
+fn test_keypad(){
+    let mut keypad = Keypad::default();
+    keypad.press(5);
+    assert_eq!(keypad.state(5), KeyState::Pressed);
+    assert_eq!(keypad.poll(), (1 << 5, 0));
+    assert_eq!(keypad.state(5), KeyState::Held);
+    // the driver releases every key and presses the held ones again each frame
+    keypad.clear();
+    keypad.press(5);
+    assert_eq!(keypad.poll(), (0, 0));
+    keypad.clear();
+    assert_eq!(keypad.state(5), KeyState::Released);
+    assert_eq!(keypad.poll(), (0, 1 << 5));
+    assert_eq!(keypad.state(5), KeyState::Up);
+    // a tap between two looks is still seen
+    keypad.press(2);
+    keypad.release(2);
+    assert_eq!(keypad.poll(), (1 << 2, 1 << 2));
+    assert_eq!(keypad.down(), 0);
+
+    // waiting for release holds on to the key pressed until it comes up
+    keypad.press(0xa);
+    let (pressed, released) = keypad.poll();
+    assert_eq!(keypad.wait(pressed, released, false), Some(0xa));
+    assert_eq!(keypad.wait(pressed, released, true), None);
+    keypad.press(3);
+    let (pressed, released) = keypad.poll();
+    assert_eq!(keypad.wait(pressed, released, true), None);
+    keypad.release(0xa);
+    let (pressed, released) = keypad.poll();
+    assert_eq!(keypad.wait(pressed, released, true), Some(0xa));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "there is no key 0x10")]
+fn test_keypad_range(){
+    Keypad::default().press(16);
+}
+
+#[test]
+fn test_key_profile(){
+    assert_eq!(KeyProfile::Standard.next(), KeyProfile::TwoPlayer);
+    assert_eq!(KeyProfile::TwoPlayer.next(), KeyProfile::Standard);
+    let down = 1 << 0x5 | 1 << 0x8 | 1 << 0xc;
+    assert_eq!(KeyProfile::Standard.describe(down), "5 8 c");
+    assert_eq!(KeyProfile::TwoPlayer.describe(down), "P1: 5 8  P2: c");
+    assert_eq!(KeyProfile::TwoPlayer.describe(0), "P1: -  P2: -");
+    for profile in KeyProfile::ALL{
+        assert_eq!(profile.to_string().parse(), Ok(profile));
+    }
+    assert!("qwerty".parse::<KeyProfile>().is_err());
+}
@@ -0,0 +1,215 @@
+/// The panels shown by a frontend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Panel{
+    Display,
+    Instructions,
+    Memory,
+    Registers,
+    CallStack,
+    /// Searching RAM for a value, shown over the memory panel
+    Search,
+    /// A graph of recent frame times, shown over the call stack
+    FrameTimes,
+    /// Values of the variables named in the symbol file, shown beside the call stack when there are any
+    Variables
+}
+
+/// A rectangle, either in fractions of the screen or in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect{
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32
+}
+
+impl Rect{
+    pub const fn new(x: f32, y: f32, width: f32, height: f32) -> Self{
+        Self { x, y, width, height }
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool{
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    /// Whether `other` lies entirely inside this rectangle
+    pub fn encloses(&self, other: &Rect) -> bool{
+        other.x >= self.x && other.y >= self.y
+            && other.x + other.width <= self.x + self.width && other.y + other.height <= self.y + self.height
+    }
+
+    /// Convert a rectangle in screen fractions to one in pixels
+    pub fn scale(&self, screen_width: f32, screen_height: f32) -> Self{
+        Self{
+            x: self.x * screen_width,
+            y: self.y * screen_height,
+            width: self.width * screen_width,
+            height: self.height * screen_height
+        }
+    }
+}
+
+struct PanelPlacement{
+    panel: Panel,
+    /// Position as fractions of the screen
+    rect: Rect,
+    visible: bool
+}
+
+/// Where each panel sits on screen, in fractions of the screen size
+pub struct Layout{
+    panels: Vec<PanelPlacement>,
+    /// A panel blown up to fill the screen, along with any panels shown inside it
+    zoomed: Option<Panel>
+}
+
+/*
+|----------------------|----------------------|
+|                      |    Memory            |
+|  picture             |                      |
+|                      |                      |
+|----------------------|                      |
+|                      |                      |
+|  instructions        | -------------------- |
+|                      |                      |
+|                      |  registers           |
+-----------------------|----------------------|
+*/
+impl Layout{
+
+    /// The display fills the whole screen
+    pub fn fullscreen() -> Self{
+        Self{
+            panels: vec![
+                PanelPlacement{ panel: Panel::Display, rect: Rect::new(0.0, 0.0, 1.0, 1.0), visible: true }
+            ],
+            zoomed: None
+        }
+    }
+
+    /// The screen split into four quadrants for debugging
+    pub fn debug() -> Self{
+        Self{
+            panels: vec![
+                PanelPlacement{ panel: Panel::Display, rect: Rect::new(0.0, 0.0, 0.5, 0.5), visible: true },
+                PanelPlacement{ panel: Panel::Instructions, rect: Rect::new(0.0, 0.5, 0.5, 0.5), visible: true },
+                PanelPlacement{ panel: Panel::Memory, rect: Rect::new(0.5, 0.0, 0.5, 0.5), visible: true },
+                PanelPlacement{ panel: Panel::Registers, rect: Rect::new(0.5, 0.5, 0.5, 0.25), visible: true },
+                PanelPlacement{ panel: Panel::CallStack, rect: Rect::new(0.5, 0.75, 0.5, 0.25), visible: true },
+                PanelPlacement{ panel: Panel::Search, rect: Rect::new(0.5, 0.0, 0.5, 0.5), visible: false },
+                PanelPlacement{ panel: Panel::FrameTimes, rect: Rect::new(0.5, 0.75, 0.5, 0.25), visible: false },
+                PanelPlacement{ panel: Panel::Variables, rect: Rect::new(0.75, 0.75, 0.25, 0.25), visible: false },
+            ],
+            zoomed: None
+        }
+    }
+
+    fn placement(&self, panel: Panel) -> Option<&PanelPlacement>{
+        self.panels.iter().find(|placement| placement.panel == panel)
+    }
+
+    /// Where `placement` is shown, blown up with the zoomed panel if it's inside it
+    fn placed_rect(&self, placement: &PanelPlacement) -> Option<Rect>{
+        if !placement.visible{
+            return None;
+        }
+        let Some(zoomed) = self.zoomed.and_then(|zoomed| self.placement(zoomed)) else {return Some(placement.rect)};
+        let (outer, rect) = (zoomed.rect, placement.rect);
+        outer.encloses(&rect).then(|| Rect::new((rect.x - outer.x) / outer.width, (rect.y - outer.y) / outer.height,
+            rect.width / outer.width, rect.height / outer.height))
+    }
+
+    /// Position of `panel` in screen fractions, or `None` if it isn't shown
+    pub fn rect(&self, panel: Panel) -> Option<Rect>{
+        self.placement(panel).and_then(|placement| self.placed_rect(placement))
+    }
+
+    /// Position of `panel` in pixels, or `None` if it isn't shown
+    pub fn screen_rect(&self, panel: Panel, screen_width: f32, screen_height: f32) -> Option<Rect>{
+        self.rect(panel).map(|rect| rect.scale(screen_width, screen_height))
+    }
+
+    /// The visible panel under the pixel position (x, y).
+    /// Panels added later are on top
+    pub fn hit_test(&self, x: f32, y: f32, screen_width: f32, screen_height: f32) -> Option<Panel>{
+        self.panels.iter().rev()
+            .find(|placement| self.placed_rect(placement)
+                .is_some_and(|rect| rect.scale(screen_width, screen_height).contains(x, y)))
+            .map(|placement| placement.panel)
+    }
+
+    /// Blow `panel` up to fill the screen, hiding the panels outside it, or put everything back with `None`
+    pub fn zoom(&mut self, panel: Option<Panel>){
+        self.zoomed = panel.filter(|panel| self.placement(*panel).is_some());
+    }
+
+    /// The panel filling the screen, if any
+    pub fn zoomed(&self) -> Option<Panel>{
+        self.zoomed
+    }
+
+    pub fn set_visible(&mut self, panel: Panel, visible: bool){
+        self.panels.iter_mut()
+            .filter(|placement| placement.panel == panel)
+            .for_each(|placement| placement.visible = visible);
+    }
+
+    /// Move `panel` to `rect` (in screen fractions), adding it if it isn't part of the layout
+    pub fn resize(&mut self, panel: Panel, rect: Rect){
+        match self.panels.iter_mut().find(|placement| placement.panel == panel){
+            Some(placement) => placement.rect = rect,
+            None => self.panels.push(PanelPlacement{ panel, rect, visible: true })
+        }
+    }
+}
+
+
+#[test]
+fn test_hit_test(){
+    let mut layout = Layout::debug();
+    assert_eq!(layout.hit_test(10.0, 10.0, 960.0, 480.0), Some(Panel::Display));
+    assert_eq!(layout.hit_test(10.0, 300.0, 960.0, 480.0), Some(Panel::Instructions));
+    assert_eq!(layout.hit_test(700.0, 10.0, 960.0, 480.0), Some(Panel::Memory));
+    assert_eq!(layout.hit_test(700.0, 300.0, 960.0, 480.0), Some(Panel::Registers));
+    assert_eq!(layout.hit_test(700.0, 400.0, 960.0, 480.0), Some(Panel::CallStack));
+    layout.set_visible(Panel::Search, true);
+    assert_eq!(layout.hit_test(700.0, 10.0, 960.0, 480.0), Some(Panel::Search));
+    layout.set_visible(Panel::Search, false);
+    layout.set_visible(Panel::Registers, false);
+    assert_eq!(layout.hit_test(700.0, 300.0, 960.0, 480.0), None);
+    assert_eq!(layout.rect(Panel::Registers), None);
+    layout.resize(Panel::Memory, Rect::new(0.5, 0.0, 0.5, 1.0));
+    assert_eq!(layout.hit_test(700.0, 300.0, 960.0, 480.0), Some(Panel::Memory));
+}
+
+#[test]
+fn test_zoom(){
+    let mut layout = Layout::debug();
+    layout.set_visible(Panel::Variables, true);
+    layout.zoom(Some(Panel::CallStack));
+    assert_eq!(layout.zoomed(), Some(Panel::CallStack));
+    assert_eq!(layout.rect(Panel::CallStack), Some(Rect::new(0.0, 0.0, 1.0, 1.0)));
+    // the variables panel inside the call stack's corner takes up the same part of the screen
+    assert_eq!(layout.rect(Panel::Variables), Some(Rect::new(0.5, 0.0, 0.5, 1.0)));
+    assert_eq!(layout.rect(Panel::Display), None);
+    assert_eq!(layout.hit_test(10.0, 10.0, 960.0, 480.0), Some(Panel::CallStack));
+    assert_eq!(layout.hit_test(700.0, 10.0, 960.0, 480.0), Some(Panel::Variables));
+
+    // the search panel sits over the memory panel, so it's blown up along with it
+    layout.zoom(Some(Panel::Memory));
+    layout.set_visible(Panel::Search, true);
+    assert_eq!(layout.screen_rect(Panel::Search, 960.0, 480.0), Some(Rect::new(0.0, 0.0, 960.0, 480.0)));
+    assert_eq!(layout.hit_test(10.0, 470.0, 960.0, 480.0), Some(Panel::Search));
+    layout.zoom(None);
+    assert_eq!(layout.rect(Panel::Display), Some(Rect::new(0.0, 0.0, 0.5, 0.5)));
+    let mut game = Layout::fullscreen();
+    game.zoom(Some(Panel::Memory));
+    assert_eq!(game.zoomed(), None);
+}
+
+#[test]
+fn test_screen_rect(){
+    let layout = Layout::fullscreen();
+    assert_eq!(layout.screen_rect(Panel::Display, 640.0, 320.0), Some(Rect::new(0.0, 0.0, 640.0, 320.0)));
+    assert_eq!(layout.screen_rect(Panel::Memory, 640.0, 320.0), None);
+}
@@ -2,16 +2,71 @@ use ndarray::prelude::*;
 #[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-type Addr = u16;
-type Reg = u8;
+/// A 12-bit memory address, 0x000 to 0xFFF.
+/// Only addresses that fit in an opcode can be constructed, see `Addr::new`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Addr(u16);
+/// One of the sixteen general purpose registers, V0 to VF.
+/// Only valid register numbers can be constructed, see `Reg::new`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Reg(u8);
 type Display = Array2<bool>;
 
 
 pub(crate) mod emulator;
+// raylib and SDL2 can't target the browser, so the windowed frontends are native-only, and left out without the `window` feature
+#[cfg(all(feature = "window", not(target_arch = "wasm32")))]
 pub(crate) mod frontend;
+/// For drawing the display somewhere else too, or taking input from somewhere else, see `Chip8Driver::add_mirror`
+#[cfg(all(feature = "window", not(target_arch = "wasm32")))]
+pub use frontend::{InputSource, KeyInput, Renderer};
+#[cfg(all(feature = "window", not(target_arch = "wasm32")))]
+pub(crate) mod history;
+#[cfg(all(test, feature = "window", not(target_arch = "wasm32")))]
+mod test_frontend;
 pub mod errors;
+pub mod extensions;
+pub mod frame_timing;
 pub mod instructions;
+pub mod keypad;
+#[cfg(all(feature = "window", not(target_arch = "wasm32")))]
 pub mod driver;
+pub mod headless;
+pub mod dap;
+pub mod debug_script;
+pub mod differential;
+pub mod display_events;
+pub mod breakpoints;
+pub mod cfg;
+pub mod clipboard;
+pub mod collision;
+pub mod coverage;
+pub mod layout;
+pub mod memory_view;
+pub mod panels;
+pub mod panel_style;
+pub mod quirks;
+pub mod random;
+pub mod recent;
+pub mod ram_search;
+pub mod recording;
+pub mod replay;
+pub mod resources;
+pub mod rom_check;
+pub mod rom_inspect;
+pub mod theme;
+pub mod screenshot;
+pub mod session;
+pub mod settings;
+pub mod symbols;
+pub mod test_suite;
+pub mod trace;
+pub mod trace_filter;
+pub mod virtual_keypad;
+pub mod timing;
+pub mod audio;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 pub const INSTRUCTION_SIZE: usize = emulator::INSTRUCTION_SIZE;
 
 #[derive(Clone, Copy)]
@@ -21,160 +76,299 @@ pub enum EmulatorMode {
     Paused, 
 }
 
+#[cfg(all(feature = "window", not(target_arch = "wasm32")))]
 #[cfg_attr(feature="wasm", wasm_bindgen)]
 pub struct Chip8Driver{
     chip8: Chip8,   
-    frontend: Box<dyn frontend::Chip8Frontend>,
-    mode: EmulatorMode
+    frontend: driver::Multiplexer,
+    mode: EmulatorMode,
+    history: history::StateHistory,
+    breakpoints: breakpoints::Breakpoints,
+    /// Where the debugger script is exported to
+    debug_script_path: Option<std::path::PathBuf>,
+    settings: settings::RomSettings,
+    /// Where changes to `settings` are saved
+    settings_path: Option<std::path::PathBuf>,
+    stats: driver::RunStats,
+    /// Display style given for this run, overriding the one in `settings`
+    display_style: Option<theme::DisplayStyle>,
+    /// How many steps back the memory view's diff compares against
+    diff_steps: usize,
+    /// How many instructions a single step runs
+    step_count: usize,
+    /// Label names shown by the debugger
+    symbols: symbols::Symbols,
+    /// Recently executed instructions, while tracing is enabled
+    trace: Option<trace::Trace>,
+    /// Where the trace is dumped to
+    trace_path: Option<std::path::PathBuf>,
+    /// Which instructions the trace records
+    trace_filter: Option<trace_filter::TraceFilter>,
+    /// Log sprite collisions and have the frontend flash them
+    show_collisions: bool,
+    /// Pause instead of carrying on when the window stalls, e.g. while it is dragged or resized
+    pause_on_stall: bool,
+    /// The ROM as loaded, before any edits in the memory view
+    rom: Vec<u8>,
+    /// Where screenshots of the display are written to
+    screenshot_path: Option<std::path::PathBuf>,
+    recorder: driver::DisplayRecorder,
+    /// Where the RAM coverage is written to on exit, while tracking it
+    coverage_path: Option<std::path::PathBuf>,
+    replays: driver::ReplayState,
+    session_state: driver::SessionState,
+    timing: timing::TimingMode,
+    /// Cycles an instruction ran past the end of the last frame, taken out of the next one
+    overrun: u64,
+    scheduler: timing::FrameScheduler,
+    /// Time given to `advance` that doesn't make up a whole frame yet
+    virtual_time: std::time::Duration,
+    /// What the emulator panicked with, while the CPU is halted by it
+    panic: Option<String>,
+    /// Instructions whose writes to protected memory have been reported, so each is reported once
+    dropped_writes: std::collections::BTreeSet<usize>
+}
+
+/// Runs the emulator without a window or audio device,
+/// for scripted runs and tests
+pub struct HeadlessDriver{
+    chip8: Chip8,
+    /// Instructions executed since the last timer tick
+    cycles: u64,
+    /// What the emulator last panicked with, see `panic_message`
+    panic: Option<String>,
+    watchdog: headless::Watchdog,
+    /// Instructions executed since the ROM was loaded
+    instructions: u64,
+    /// When the first instruction since the ROM was loaded ran, if the watchdog has a time limit
+    started: Option<std::time::Instant>
 }
 
 
-pub(crate) struct Chip8{
+/// The emulator core: memory, registers and display,
+/// with no dependency on any particular frontend
+#[derive(Clone)]
+pub struct Chip8{
     clock_speed: u64, // Cycles per second,
     memory: Memory,
     registers: Registers,
+    /// Most nested `CALL`s before the stack overflows
+    stack_depth: usize,
+    /// Behaviours that differ between interpreters, switched on
+    quirks: quirks::Quirks,
+    /// Opcodes beyond the original instruction set that are switched on
+    extensions: std::collections::BTreeSet<extensions::Extension>,
+    /// Things a ROM can do that halt the CPU only when asked for
+    halt_on: std::collections::BTreeSet<HaltCondition>,
+    /// Source of `RND`, seeded so that a run can be replayed
+    rng: random::RandomSource,
+    /// The first address and the instruction of the last write `protect-low-memory` dropped, until taken
+    dropped_write: Option<(usize, usize)>,
+    /// Clears and sprites drawn since a frontend last took them
+    display_events: display_events::DisplayEvents,
+    /// How each byte of RAM has been used, while tracking it
+    coverage: Option<coverage::Coverage>
+}
+
+/// What the CPU is doing; only a `Running` CPU executes instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CpuState{
+    #[default]
+    Running,
+    /// Blocked on `LD Vx K` until a key is pressed, which is stored in the register
+    WaitingForKey(Reg),
+    /// Blocked after a draw until the next 60 hz frame
+    WaitingForDisplay,
+    /// Stopped by an instruction that can't be executed.
+    /// The program counter is left on the faulting instruction
+    Halted(Fault)
+}
+
+/// Why the CPU halted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault{
+    /// `RET` with an empty call stack
+    StackUnderflow,
+    /// `CALL` with this many calls already on the stack
+    StackOverflow(usize),
+    /// An instruction reached past the end of memory
+    MemoryOutOfRange,
+    /// A key instruction was given a value that isn't a key
+    InvalidKey(u8),
+    /// `LD F Vx` was given a value that isn't a hex digit
+    InvalidCharacter(u8),
+    /// An opcode that isn't an instruction, with `HaltCondition::InvalidOpcode`
+    InvalidOpcode(u16),
+    /// A jump to the jump itself, with `HaltCondition::SelfJump`
+    SelfJump,
+    /// A write to this address below 0x200, with the `protect-low-memory` quirk and `HaltCondition::ProtectedWrite`
+    ProtectedWrite(usize),
+    /// The emulator itself panicked running the instruction; a bug in the emulator, not the ROM
+    Panic
+}
+
+/// Things a ROM can do that usually carry on, but can be made to halt the CPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum HaltCondition{
+    /// An opcode that isn't an instruction, including `0NNN` machine code calls, which otherwise do nothing
+    InvalidOpcode,
+    /// `JP` to itself, which many ROMs end with to loop forever
+    SelfJump,
+    /// A write below 0x200 with the `protect-low-memory` quirk, which is otherwise dropped
+    ProtectedWrite
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-// Todo: turn these docs into attributes for a proc macro
-// ideally could derive: instruction->mnemonic, mnemonic-> instruction,
-// instruction -> u16, u16 -> instruction
-// all from attributes 
+/// One CHIP-8 instruction. The attributes give its opcode and assembly syntax,
+/// see the `chip8-macros` crate for how they are read
+#[derive(Debug, PartialEq, Eq, Clone, Copy, chip8_macros::Chip8Instr)]
 pub enum Instruction{
-    /// CLS
-    /// 0x00e0
     /// Clear screen
+    #[opcode("0x00E0")]
+    #[mnemonic("CLS")]
     ClearScreen,
-    /// RET
-    /// 0x00ee
     /// Return
+    #[opcode("0x00EE")]
+    #[mnemonic("RET")]
     Ret, // Return 
-    /// NOP
-    /// 0x0000 or anything that isn't a valid instruction
+    /// 0x0000, and anything that isn't a valid instruction
+    #[opcode("0x0000")]
+    #[mnemonic("NOP")]
+    #[fallback]
     Nop,
-    /// JP imm12
-    /// 0x1NNN
+    /// Wait for the next 60 hz frame. Only with `Extension::VSync`, a NOP otherwise
+    #[opcode("0x00F1")]
+    #[mnemonic("VSYNC")]
+    VSync,
     /// Jump to addr
+    #[opcode("0x1NNN")]
+    #[mnemonic("JP NNN")]
     Jump(Addr),
-    /// CALL imm12
-    /// 0x2NNN
     ///  call function at addr
+    #[opcode("0x2NNN")]
+    #[mnemonic("CALL NNN")]
     Call(Addr),
-    /// SE Vx imm8
-    /// 0x3XNN
     /// Skip next instruction if  *reg == imm8
+    #[opcode("0x3XNN")]
+    #[mnemonic("SE X NN")]
     SkipEqImm(Reg,u8),
-    /// SNE Vx imm8
-    /// 0x4xnn
     /// Skip next instruction if *reg != imm8
+    #[opcode("0x4XNN")]
+    #[mnemonic("SNE X NN")]
     SkipNeImm(Reg, u8),
-    /// SE Vx Vy
-    /// 0x5XY0
     /// Skip next instruction if *Vx == *Vy
+    #[opcode("0x5XY0")]
+    #[mnemonic("SE X Y")]
     SkipEqReg(Reg, Reg),
-    /// LD Vx imm8
-    /// 0x6XNN
     /// Set *Vx to imm8
+    #[opcode("0x6XNN")]
+    #[mnemonic("LD X NN")]
     SetImm(Reg, u8),
-    /// ADD Vx imm8
-    /// 0x7XNN
     /// *Vx += imm
+    #[opcode("0x7XNN")]
+    #[mnemonic("ADD X NN")]
     AddImm(Reg, u8),
-    /// LD Vx Vy 
-    /// 0x8XY0
+    #[opcode("0x8XY0")]
+    #[mnemonic("LD X Y")]
     SetReg(Reg, Reg), // Set *Vx to *Vy
-    /// OR Vx Vy
-    /// 0x8XY1
+    #[opcode("0x8XY1")]
+    #[mnemonic("OR X Y")]
     OrReg(Reg, Reg), // *Vx |= *Vy
-    /// AND Vx Vy
-    /// 0x8XY2
+    #[opcode("0x8XY2")]
+    #[mnemonic("AND X Y")]
     AndReg(Reg, Reg), // *Vx &= *Vy
-    /// XOR Vx Vy
-    /// 0x8XY3
+    #[opcode("0x8XY3")]
+    #[mnemonic("XOR X Y")]
     XorReg(Reg, Reg), // *Vx ^= Vy
-    /// ADD Vx Vy
-    /// 0x8XY4
+    #[opcode("0x8XY4")]
+    #[mnemonic("ADD X Y")]
     AddReg(Reg, Reg), // *Vx += *Vy
-    /// SUB Vx Vy
-    /// 0x8XY5
+    #[opcode("0x8XY5")]
+    #[mnemonic("SUB X Y")]
     SubReg(Reg, Reg), // *Vx -= *Vy; set VF to 1 if the subtraction succeds
-    /// RSH Vx Vy
-    /// 0x8XY6
+    #[opcode("0x8X_6")]
+    #[mnemonic("RSH X")]
+    #[mnemonic("RSH X Y")]
+    #[mnemonic("SHR X")]
+    #[mnemonic("SHR X Y")]
     Rsh(Reg), // *Vx >>= 1; store least significant bit in VF
-    /// SUBN Vx Vy
-    /// 0x8XY7
+    #[opcode("0x8XY7")]
+    #[mnemonic("SUBN X Y")]
     SubFrom(Reg, Reg), // Vx = Vy - Vx; set VF to 1 if the subtraction succeeds
-    /// LSH Vx Vy
-    /// 0x8XYE
+    #[opcode("0x8X_E")]
+    #[mnemonic("LSH X")]
+    #[mnemonic("LSH X Y")]
+    #[mnemonic("SHL X")]
+    #[mnemonic("SHL X Y")]
     Lsh(Reg), // *Vx <<= 1; store most significant bit in VF
-    /// SKNV Vx Vy
-    /// 0x9XY0
+    #[opcode("0x9XY0")]
+    #[mnemonic("SNE X Y")]
     SkipNeReg(Reg, Reg), // Skip next instruction if Vx != Vy
-    /// LD I imm12
-    /// 0xANNN
-    SetMemPtr(u16), // Sets the I register to imm
-    /// JP V0 imm12
-    /// 0xBNNN
-    JumpOffset(u16), // Jump to V0 + imm
-    /// RND Vx imm8
-    /// 0xCXNN
+    #[opcode("0xANNN")]
+    #[mnemonic("LD I NNN")]
+    SetMemPtr(Addr), // Sets the I register to imm
+    #[opcode("0xBNNN")]
+    #[mnemonic("JP V0 NNN")]
+    JumpOffset(Addr), // Jump to V0 + imm
+    #[opcode("0xCXNN")]
+    #[mnemonic("RND X NN")]
+    #[mnemonic("RAND X NN")]
     Rand(Reg, u8), //Set Vx to rand() & imm
-    /// DRW Vx Vy imm4
-    /// 0xDXYN
     /// Draw N-byte sprite at (Vx,Vy) 
     /// Successive bytes are drawn one below the next
     /// Note that the chip8 display is indexed like (column, row)
+    #[opcode("0xDXYN")]
+    #[mnemonic("DRW X Y N")]
     Draw(Reg,Reg, u8), 
-    /// SKP Vx
-    /// 0xEX9E
+    #[opcode("0xEX9E")]
+    #[mnemonic("SKP X")]
     SkipKeyPressed(Reg), // Skip next instruction if the key in Vx is pressed
-    /// SKNP Vx
-    /// 0xEXA1
+    #[opcode("0xEXA1")]
+    #[mnemonic("SKNP X")]
     SkipKeyNotPressed(Reg), // Skip next instruction if the key in Vx is *not* pressed
-    /// LD DT Vx
-    /// 0xFX07
+    #[opcode("0xFX07")]
+    #[mnemonic("LD X DT")]
     GetDelay(Reg), // Set Vx to the value of the delay timer
-    /// LD Vx, K
-    /// 0xFX0A
+    #[opcode("0xFX0A")]
+    #[mnemonic("LD X K")]
     WaitForKey(Reg), // Block until key pressed, then store the key pressed in Vx
-    /// LD DT Vx 
-    /// 0xFX15
+    #[opcode("0xFX15")]
+    #[mnemonic("LD DT X")]
     SetDelay(Reg), // Set delay timer to *Vx
-    /// LD ST Vx
-    /// 0xFX18
+    #[opcode("0xFX18")]
+    #[mnemonic("LD ST X")]
     SetSound(Reg), // Set sound timer to *Vx
-    /// ADD I Vx
-    /// 0xFX1E
+    #[opcode("0xFX1E")]
+    #[mnemonic("ADD I X")]
     AddMemPtr(Reg), // *I += *Vx
-    /// LD F Vx
-    /// 0xFX29
+    #[opcode("0xFX29")]
+    #[mnemonic("LD F X")]
     SetChar(Reg), // *I = sprites[SPRITE_LEN * *Vx]
-    /// LD B Vx
-    /// 0xFX33
+    #[opcode("0xFX33")]
+    #[mnemonic("LD B X")]
     BCD(Reg), // Store the binary-coded decimal representation of Vx at I..=I+2
-    /// LD [I] VX
-    /// 0xFX55
+    #[opcode("0xFX55")]
+    #[mnemonic("LD [I] X")]
     RegDump(Reg), // Store registers V0..Vx in memory, starting at I
-    /// LD VX [I]
-    /// 0xFX55
+    #[opcode("0xFX65")]
+    #[mnemonic("LD X [I]")]
     RegLoad(Reg), // Fill registers V0..Vx from memory, starting at I
 }
 
 pub(crate) const MEMORY_SIZE: usize = 4096;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct Memory{
     /// Random access memory
     ram: [u8;MEMORY_SIZE],
     /// Graphic display
     display: Display,
-    /// Key array
-    keys: [bool; 16],
+    keypad: keypad::Keypad,
     // call stack
     stack: Vec<usize>
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct Registers{
     /// General purpose registers
     vn: [u8;16], 
@@ -189,8 +383,7 @@ pub(crate) struct Registers{
     sp: usize,
     /// RAM pointer
     i: usize,
-    /// When set, stores the register to store the next keypress in
-    key_flag: Option<usize>
+    state: CpuState
 }
 
 
@@ -0,0 +1,284 @@
+use crate::{Addr, MEMORY_SIZE};
+
+/// A key typed into the memory view while it is taking text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewKey{
+    Char(char),
+    Enter,
+    Backspace
+}
+
+/// What the memory view is taking text for
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Edit{
+    /// Overwriting the byte at `addr`, with the high nibble once it has been typed
+    Byte{ addr: Addr, high: Option<u8> },
+    /// An address to jump to, in hex
+    Goto(String)
+}
+
+/// A scrollable hex view over all of RAM, eight bytes to a row.
+/// Positions are in pixels relative to the top left of the panel,
+/// with a header line above the rows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryView{
+    /// Address of the first row shown
+    start: usize,
+    /// How many rows fit in the panel
+    rows: usize,
+    /// Keep the row containing I in view
+    follow_i: bool,
+    edit: Option<Edit>,
+    /// Highlight bytes that differ from `snapshot`
+    diff: bool,
+    /// RAM from a few steps ago, while paused
+    snapshot: Option<Vec<u8>>,
+    /// The ROM as it was loaded, to mark the bytes patched since
+    rom: Vec<u8>
+}
+
+impl Default for MemoryView{
+    fn default() -> Self {
+        Self { start: 0, rows: 1, follow_i: true, edit: None, diff: false, snapshot: None, rom: Vec::new() }
+    }
+}
+
+impl MemoryView{
+    pub const BYTES_PER_ROW: usize = 8;
+    pub const MARGIN: f32 = 5.0;
+    pub const LINE_HEIGHT: f32 = 20.0;
+    pub const ADDRESS_WIDTH: f32 = 60.0;
+    pub const CELL_WIDTH: f32 = 26.0;
+
+    /// Fit the view to a panel `height` pixels tall
+    pub fn set_height(&mut self, height: f32){
+        self.rows = (((height - Self::MARGIN) / Self::LINE_HEIGHT) as usize).saturating_sub(1).max(1);
+        self.start = self.clamp(self.start);
+    }
+
+    pub fn rows(&self) -> usize{
+        self.rows
+    }
+
+    /// Address of the first row to draw, following `i` if that is turned on
+    pub fn first_row(&mut self, i: usize) -> usize{
+        if self.follow_i{
+            let row = i / Self::BYTES_PER_ROW * Self::BYTES_PER_ROW;
+            if row < self.start || row >= self.start + self.rows * Self::BYTES_PER_ROW{
+                self.start = self.clamp(row);
+            }
+        }
+        self.start
+    }
+
+    /// Move the view by `rows`, which stops it following I
+    pub fn scroll(&mut self, rows: isize){
+        self.follow_i = false;
+        let offset = rows * Self::BYTES_PER_ROW as isize;
+        self.start = self.clamp(self.start.saturating_add_signed(offset));
+    }
+
+    /// Show the row containing `addr` at the top, which stops the view following I
+    pub fn goto(&mut self, addr: Addr){
+        self.follow_i = false;
+        self.start = self.clamp(usize::from(addr) / Self::BYTES_PER_ROW * Self::BYTES_PER_ROW);
+    }
+
+    pub fn follow_i(&self) -> bool{
+        self.follow_i
+    }
+
+    pub fn toggle_follow_i(&mut self){
+        self.follow_i = !self.follow_i;
+    }
+
+    pub fn diff(&self) -> bool{
+        self.diff
+    }
+
+    pub fn toggle_diff(&mut self){
+        self.diff = !self.diff;
+    }
+
+    /// RAM to compare against when diffing, or `None` to stop comparing
+    pub fn set_snapshot(&mut self, snapshot: Option<&[u8]>){
+        match (&mut self.snapshot, snapshot) {
+            (Some(kept), Some(snapshot)) if kept.len() == snapshot.len() => kept.copy_from_slice(snapshot),
+            (kept, snapshot) => *kept = snapshot.map(<[u8]>::to_vec)
+        }
+    }
+
+    /// The old value of the byte at `addr` if diffing is on and it has changed to `value`
+    pub fn changed(&self, addr: usize, value: u8) -> Option<u8>{
+        let old = *self.snapshot.as_ref()?.get(addr)?;
+        (self.diff && old != value).then_some(old)
+    }
+
+    /// The ROM as loaded at 0x200
+    pub fn set_rom(&mut self, rom: &[u8]){
+        self.rom = rom.to_vec();
+    }
+
+    /// Whether the byte at `addr` is part of the ROM and has been changed to `value` since it was loaded
+    pub fn patched(&self, addr: usize, value: u8) -> bool{
+        addr.checked_sub(Addr::ROM_START.index())
+            .and_then(|offset| self.rom.get(offset))
+            .is_some_and(|loaded| *loaded != value)
+    }
+
+    /// Start typing an address to go to, or stop if already typing one
+    pub fn toggle_goto(&mut self){
+        self.edit = match self.edit {
+            Some(Edit::Goto(_)) => None,
+            _ => Some(Edit::Goto(String::new()))
+        };
+    }
+
+    /// The address typed so far, while going to one
+    pub fn goto_text(&self) -> Option<&str>{
+        match &self.edit {
+            Some(Edit::Goto(text)) => Some(text),
+            _ => None
+        }
+    }
+
+    /// Start editing the byte at `addr`, or stop editing if `None`
+    pub fn select(&mut self, addr: Option<Addr>){
+        self.edit = addr.map(|addr| Edit::Byte { addr, high: None });
+    }
+
+    /// The byte being edited and the high nibble if it has been typed
+    pub fn selected(&self) -> Option<(Addr, Option<u8>)>{
+        match self.edit {
+            Some(Edit::Byte { addr, high }) => Some((addr, high)),
+            _ => None
+        }
+    }
+
+    /// Whether typing should go to the view instead of the CHIP-8 keypad
+    pub fn is_editing(&self) -> bool{
+        self.edit.is_some()
+    }
+
+    /// Handle a typed key, returning a byte to write to memory once one is complete
+    pub fn key(&mut self, key: ViewKey) -> Option<(Addr, u8)>{
+        match (&mut self.edit, key) {
+            (Some(Edit::Goto(text)), ViewKey::Char(c)) if c.is_ascii_hexdigit() && text.len() < 3 => text.push(c),
+            (Some(Edit::Goto(text)), ViewKey::Backspace) => {text.pop();},
+            (Some(Edit::Goto(text)), ViewKey::Enter) => {
+                let addr = u16::from_str_radix(text, 16).ok().and_then(Addr::new);
+                self.edit = None;
+                if let Some(addr) = addr{
+                    self.goto(addr);
+                }
+            },
+            (Some(Edit::Byte { addr, high }), ViewKey::Char(c)) => {
+                let digit = c.to_digit(16)? as u8;
+                let Some(high_nibble) = *high else {
+                    *high = Some(digit);
+                    return None
+                };
+                let written = (*addr, high_nibble << 4 | digit);
+                // carry on with the next byte, like a hex editor
+                self.select(written.0.offset(1));
+                if let Some((next, _)) = self.selected(){
+                    if usize::from(next) >= self.start + self.rows * Self::BYTES_PER_ROW{
+                        self.scroll(1);
+                    }
+                }
+                return Some(written)
+            },
+            (Some(Edit::Byte { high, .. }), ViewKey::Backspace) => *high = None,
+            (Some(Edit::Byte { .. }), ViewKey::Enter) => self.edit = None,
+            _ => {}
+        }
+        None
+    }
+
+    /// Top left of the byte in `column` of `row`
+    pub fn cell_position(&self, row: usize, column: usize) -> (f32, f32){
+        (Self::MARGIN + Self::ADDRESS_WIDTH + column as f32 * Self::CELL_WIDTH,
+            Self::MARGIN + (row + 1) as f32 * Self::LINE_HEIGHT)
+    }
+
+    /// The byte drawn at `x`, `y`, if any
+    pub fn addr_at(&self, x: f32, y: f32) -> Option<Addr>{
+        let column = (x - Self::MARGIN - Self::ADDRESS_WIDTH) / Self::CELL_WIDTH;
+        let row = (y - Self::MARGIN) / Self::LINE_HEIGHT - 1.0;
+        if column < 0.0 || column >= Self::BYTES_PER_ROW as f32 || row < 0.0 || row >= self.rows as f32{
+            return None;
+        }
+        Addr::try_from(self.start + row as usize * Self::BYTES_PER_ROW + column as usize).ok()
+    }
+
+    /// Keep a full page of rows within RAM
+    fn clamp(&self, start: usize) -> usize{
+        start.min(MEMORY_SIZE.saturating_sub(self.rows * Self::BYTES_PER_ROW))
+    }
+}
+
+#[test]
+fn test_memory_view_scrolling(){
+    let mut view = MemoryView::default();
+    view.set_height(MemoryView::MARGIN + 5.0 * MemoryView::LINE_HEIGHT);
+    assert_eq!(view.rows(), 4);
+    assert_eq!(view.first_row(0x20a), 0x208);
+    // I moving within the page doesn't scroll
+    assert_eq!(view.first_row(0x21f), 0x208);
+    view.scroll(-2);
+    assert!(!view.follow_i());
+    assert_eq!(view.first_row(0x500), 0x1f8);
+    view.scroll(1000);
+    assert_eq!(view.first_row(0), MEMORY_SIZE - 32);
+    view.toggle_goto();
+    for key in [ViewKey::Char('3'), ViewKey::Char('x'), ViewKey::Char('0'), ViewKey::Char('4'), ViewKey::Enter]{
+        assert_eq!(view.key(key), None);
+    }
+    assert_eq!(view.first_row(0), 0x300);
+    assert!(!view.is_editing());
+    assert_eq!(view.addr_at(MemoryView::MARGIN + MemoryView::ADDRESS_WIDTH + 1.0, MemoryView::MARGIN + 2.5 * MemoryView::LINE_HEIGHT),
+        Addr::new(0x308));
+    assert_eq!(view.addr_at(1.0, 1.0), None);
+}
+
+#[test]
+fn test_memory_view_editing(){
+    let mut view = MemoryView::default();
+    view.set_height(MemoryView::MARGIN + 5.0 * MemoryView::LINE_HEIGHT);
+    view.first_row(0x300);
+    view.select(Addr::new(0x31f));
+    assert_eq!(view.key(ViewKey::Char('a')), None);
+    assert_eq!(view.selected(), Some((Addr::new(0x31f).unwrap(), Some(0xa))));
+    assert_eq!(view.key(ViewKey::Char('B')), Some((Addr::new(0x31f).unwrap(), 0xab)));
+    // the next byte is on the row below the page, which scrolls into view
+    assert_eq!(view.selected(), Some((Addr::new(0x320).unwrap(), None)));
+    assert_eq!(view.first_row(0x300), 0x308);
+    assert_eq!(view.key(ViewKey::Char('g')), None);
+    view.key(ViewKey::Enter);
+    assert!(!view.is_editing());
+}
+
+#[test]
+fn test_memory_view_diff(){
+    let mut view = MemoryView::default();
+    let mut ram = vec![0u8; MEMORY_SIZE];
+    view.set_snapshot(Some(&ram));
+    ram[0x300] = 7;
+    assert_eq!(view.changed(0x300, ram[0x300]), None);
+    view.toggle_diff();
+    assert_eq!(view.changed(0x300, ram[0x300]), Some(0));
+    assert_eq!(view.changed(0x301, ram[0x301]), None);
+    view.set_snapshot(None);
+    assert_eq!(view.changed(0x300, ram[0x300]), None);
+}
+
+#[test]
+fn test_memory_view_patched(){
+    let mut view = MemoryView::default();
+    view.set_rom(&[0x60, 0x01]);
+    assert!(!view.patched(0x201, 0x01));
+    assert!(view.patched(0x201, 0x02));
+    // outside the ROM nothing counts as patched
+    assert!(!view.patched(0x202, 0x02));
+    assert!(!view.patched(0x1ff, 0x02));
+}
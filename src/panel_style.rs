@@ -0,0 +1,147 @@
+use std::fmt::Display;
+
+use crate::{layout::Panel, theme::Rgb};
+
+/// How one debug panel is drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PanelStyle{
+    pub background: Rgb,
+    pub text: Rgb,
+    pub font_size: f32,
+    /// Space between the panel's edges and its text, in pixels
+    pub padding: f32
+}
+
+impl PanelStyle{
+    pub const MIN_FONT_SIZE: f32 = 8.0;
+    pub const MAX_FONT_SIZE: f32 = 64.0;
+    pub const MAX_PADDING: f32 = 40.0;
+
+    const fn new(background: Rgb, text: Rgb, font_size: f32) -> Self{
+        Self { background, text, font_size, padding: 5.0 }
+    }
+}
+
+/// The style of each debug panel, set in a ROM's settings as `<panel>_<property> = value`,
+/// e.g. `instructions_font_size = 24` or `registers_background = #202020`.
+/// The memory and RAM search grids have fixed cells, so only their colours can change
+#[derive(Debug, Clone, PartialEq)]
+pub struct PanelStyles{
+    styles: [PanelStyle; Self::PANELS.len()]
+}
+
+impl PanelStyles{
+    /// The styled panels and the name of each in the settings file
+    pub const PANELS: [(Panel, &'static str); 7] = [
+        (Panel::Instructions, "instructions"),
+        (Panel::Memory, "memory"),
+        (Panel::Registers, "registers"),
+        (Panel::CallStack, "call_stack"),
+        (Panel::Search, "search"),
+        (Panel::FrameTimes, "frame_times"),
+        (Panel::Variables, "variables")
+    ];
+    const PROPERTIES: [&'static str; 4] = ["background", "text", "font_size", "padding"];
+
+    /// The style of `panel`; the display isn't a debug panel and gets the instructions' style
+    pub fn get(&self, panel: Panel) -> &PanelStyle{
+        &self.styles[Self::index(panel).unwrap_or_default()]
+    }
+
+    pub fn get_mut(&mut self, panel: Panel) -> Option<&mut PanelStyle>{
+        Self::index(panel).map(|index| &mut self.styles[index])
+    }
+
+    fn index(panel: Panel) -> Option<usize>{
+        Self::PANELS.iter().position(|(styled, _)| *styled == panel)
+    }
+
+    /// Apply one setting such as `memory_text = #000000`.
+    /// Returns false if `key` isn't a panel style, and ignores malformed values like other settings
+    pub fn set(&mut self, key: &str, value: &str) -> bool{
+        let setting = Self::PANELS.iter().find_map(|(panel, name)| {
+            let property = key.strip_prefix(name)?.strip_prefix('_')?;
+            Self::PROPERTIES.contains(&property).then_some((*panel, property))
+        });
+        let Some((panel, property)) = setting else {return false};
+        let style = self.get_mut(panel).expect("only styled panels are named");
+        let value = value.trim();
+        match property {
+            "background" => if let Ok(color) = value.parse(){
+                style.background = color;
+            },
+            "text" => if let Ok(color) = value.parse(){
+                style.text = color;
+            },
+            "font_size" => if let Ok(size) = value.parse::<f32>(){
+                style.font_size = size.clamp(PanelStyle::MIN_FONT_SIZE, PanelStyle::MAX_FONT_SIZE);
+            },
+            _ => if let Ok(padding) = value.parse::<f32>(){
+                style.padding = padding.clamp(0.0, PanelStyle::MAX_PADDING);
+            }
+        }
+        true
+    }
+}
+
+impl Default for PanelStyles{
+    /// The look the panels had before they could be styled
+    fn default() -> Self {
+        const LIGHTGRAY: Rgb = Rgb::new(200, 200, 200);
+        const GRAY: Rgb = Rgb::new(130, 130, 130);
+        const DARKGRAY: Rgb = Rgb::new(80, 80, 80);
+        const RAYWHITE: Rgb = Rgb::new(245, 245, 245);
+        Self {
+            styles: [
+                PanelStyle::new(Rgb::WHITE, Rgb::BLACK, 32.0),
+                PanelStyle::new(LIGHTGRAY, DARKGRAY, 18.0),
+                PanelStyle::new(DARKGRAY, Rgb::WHITE, 18.0),
+                PanelStyle::new(GRAY, Rgb::WHITE, 18.0),
+                PanelStyle::new(LIGHTGRAY, DARKGRAY, 18.0),
+                PanelStyle::new(RAYWHITE, Rgb::BLACK, 18.0),
+                PanelStyle::new(DARKGRAY, Rgb::WHITE, 18.0)
+            ]
+        }
+    }
+}
+
+impl Display for PanelStyles{
+    /// Only the properties that differ from the defaults, one setting per line
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let defaults = Self::default();
+        for ((_, name), (style, default)) in Self::PANELS.iter().zip(self.styles.iter().zip(defaults.styles.iter())){
+            if style.background != default.background{
+                writeln!(f, "{name}_background = {}", style.background)?;
+            }
+            if style.text != default.text{
+                writeln!(f, "{name}_text = {}", style.text)?;
+            }
+            if style.font_size != default.font_size{
+                writeln!(f, "{name}_font_size = {}", style.font_size)?;
+            }
+            if style.padding != default.padding{
+                writeln!(f, "{name}_padding = {}", style.padding)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[test]
+fn test_panel_styles(){
+    let mut styles = PanelStyles::default();
+    assert_eq!(styles.to_string(), "");
+    assert!(styles.set("instructions_font_size", " 24"));
+    assert!(styles.set("call_stack_background", "#102030"));
+    assert!(styles.set("registers_padding", "100"));
+    assert!(styles.set("memory_text", "green"));
+    assert!(!styles.set("display_text", "#ffffff"));
+    assert!(!styles.set("memory_border", "1"));
+    assert!(!styles.set("foreground", "#ffffff"));
+    assert_eq!(styles.get(Panel::Instructions).font_size, 24.0);
+    assert_eq!(styles.get(Panel::CallStack).background, Rgb::new(0x10, 0x20, 0x30));
+    assert_eq!(styles.get(Panel::Registers).padding, PanelStyle::MAX_PADDING);
+    assert_eq!(styles.get(Panel::Memory).text, PanelStyles::default().get(Panel::Memory).text);
+    assert_eq!(styles.to_string().lines().count(), 3);
+}
@@ -0,0 +1,481 @@
+use std::cmp::max;
+
+use itertools::Itertools;
+
+use crate::{breakpoints::{Action, Breakpoints}, clipboard, errors::Diagnostic, frame_timing::FrameTiming, keypad::KeyProfile, layout::{Layout, Panel, Rect}, memory_view::MemoryView, panel_style::PanelStyles, ram_search::{RamSearch, Scan}, screenshot::Screenshot, symbols::Symbols, Addr, Chip8, INSTRUCTION_SIZE, MEMORY_SIZE};
+
+/// How a breakpoint is marked beside its instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakpointMark{
+    Plain,
+    Conditional,
+    Disabled,
+    /// Logs or counts rather than pausing
+    Tracepoint
+}
+
+/// One line of the instruction panel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstructionLine{
+    pub addr: Addr,
+    pub text: String,
+    pub breakpoint: Option<BreakpointMark>
+}
+
+/// The debug view's panels apart from drawing them: where they are, what they show,
+/// and what clicking and scrolling does to them. Nothing here needs a window
+pub struct DebugPanels{
+    layout: Layout,
+    /// Address of the first line of the instruction panel
+    instruction_start: Addr,
+    pub(crate) memory_view: MemoryView,
+    pub(crate) ram_search: RamSearch,
+    /// A scan clicked in the search panel, run against RAM on the next update
+    pub(crate) pending_scan: Option<Scan>,
+    pub(crate) symbols: Symbols,
+    /// Timing of the latest frames, for the frame time graph
+    pub(crate) frame_times: Vec<FrameTiming>,
+    pub(crate) styles: PanelStyles,
+    /// How the keyboard plays the keypad, to group the keys held by player
+    pub(crate) key_profile: KeyProfile
+}
+
+impl Default for DebugPanels{
+    fn default() -> Self {
+        Self {
+            layout: Layout::debug(),
+            instruction_start: Addr::ROM_START,
+            memory_view: MemoryView::default(),
+            ram_search: RamSearch::default(),
+            pending_scan: None,
+            symbols: Symbols::default(),
+            frame_times: Vec::new(),
+            styles: PanelStyles::default(),
+            key_profile: KeyProfile::default()
+        }
+    }
+}
+
+impl DebugPanels{
+    /// Instructions shown at once
+    pub const INSTRUCTION_ROWS: usize = 8;
+    pub const INSTRUCTION_MARGIN_TOP: f32 = 15.0;
+    pub const INSTRUCTION_MARGIN_BOTTOM: f32 = 25.0;
+    pub const INSTRUCTION_MARGIN_LEFT: f32 = 50.0;
+
+    pub fn layout(&self) -> &Layout{
+        &self.layout
+    }
+
+    /// Show or hide the RAM search panel
+    pub fn toggle_search(&mut self){
+        let searching = self.layout.rect(Panel::Search).is_some();
+        self.layout.set_visible(Panel::Search, !searching);
+    }
+
+    /// Show or hide the frame time graph
+    pub fn toggle_frame_times(&mut self){
+        let shown = self.layout.rect(Panel::FrameTimes).is_some();
+        self.layout.set_visible(Panel::FrameTimes, !shown);
+    }
+
+    /// Blow the panel under pixel (x, y) up to fill the screen, or put the panels back if one already does
+    pub fn toggle_zoom(&mut self, x: f32, y: f32, screen_width: f32, screen_height: f32){
+        let panel = match self.layout.zoomed() {
+            Some(_) => None,
+            None => self.layout.hit_test(x, y, screen_width, screen_height)
+        };
+        self.layout.zoom(panel);
+    }
+
+    /// The panel under pixel (x, y) as text to copy: the display as `#` and `.`, the memory panel's rows as hex,
+    /// or the lines of the instruction or register panel
+    pub fn copy_text(&mut self, chip8: &Chip8, breakpoints: &Breakpoints, x: f32, y: f32, screen_width: f32, screen_height: f32) -> Option<String>{
+        let lines = match self.layout.hit_test(x, y, screen_width, screen_height)? {
+            Panel::Display => return Some(Screenshot::of(chip8.frame_buffer()).to_string()),
+            Panel::Memory => {
+                let start = self.memory_view.first_row(chip8.registers.i);
+                let end = (start + self.memory_view.rows() * MemoryView::BYTES_PER_ROW).min(MEMORY_SIZE);
+                return Some(clipboard::hex_text(&chip8.memory.ram[start..end]))
+            },
+            Panel::Instructions => self.instruction_lines(chip8, breakpoints).into_iter().map(|line| line.text).collect(),
+            Panel::Registers => Self::register_lines(chip8, self.key_profile),
+            Panel::CallStack | Panel::Search | Panel::FrameTimes | Panel::Variables => return None
+        };
+        Some(lines.into_iter().map(|line| line + "\n").collect())
+    }
+
+    /// The bytes to write for hex `text` pasted into the memory view, from the selected byte on.
+    /// The selection moves on past them, as if they were typed
+    pub fn paste(&mut self, text: &str) -> Result<Vec<(Addr, u8)>, Diagnostic>{
+        let (start, _) = self.memory_view.selected()
+            .ok_or_else(|| Diagnostic::error("Click a byte in the memory panel to paste there"))?;
+        let bytes = clipboard::parse_hex(text)?;
+        let writes: Vec<_> = bytes.iter().enumerate()
+            .map(|(offset, byte)| start.offset(offset as isize).map(|addr| (addr, *byte)))
+            .collect::<Option<_>>()
+            .ok_or_else(|| Diagnostic::error(format!("{} bytes don't fit in memory from {}", bytes.len(), start)))?;
+        if let Some((last, _)) = writes.last(){
+            self.memory_view.select(last.offset(1));
+        }
+        Ok(writes)
+    }
+
+    /// Label names to show in place of addresses, and the variables panel if they name any variables
+    pub fn set_symbols(&mut self, symbols: &Symbols){
+        self.symbols = symbols.clone();
+        self.layout.set_visible(Panel::Variables, !symbols.variables().is_empty());
+    }
+
+    /// Scroll the instruction panel so that `pc` is the fourth line
+    pub fn follow_pc(&mut self, pc: usize){
+        let pc = Addr::try_from(pc).unwrap_or(Addr::MAX).align_down();
+        self.instruction_start = max(pc.offset(-3 * INSTRUCTION_SIZE as isize).unwrap_or(Addr::ROM_START), Addr::ROM_START);
+    }
+
+    /// Run a scan clicked or typed into the search panel
+    pub fn run_pending_scan(&mut self, ram: &[u8]){
+        if let Some(scan) = self.pending_scan.take(){
+            self.ram_search.scan(scan, ram);
+        }
+    }
+
+    /// Height of one line of the instruction panel, which is `panel` in pixels
+    pub fn instruction_line_height(panel: Rect) -> f32{
+        (panel.height - (Self::INSTRUCTION_MARGIN_TOP + Self::INSTRUCTION_MARGIN_BOTTOM)) / Self::INSTRUCTION_ROWS as f32
+    }
+
+    /// Top of line `lineno` of the instruction panel, in pixels
+    pub fn instruction_line_y(panel: Rect, lineno: usize) -> f32{
+        panel.y + Self::INSTRUCTION_MARGIN_TOP + lineno as f32 * Self::instruction_line_height(panel)
+    }
+
+    /// The instruction at pixel height `y` in the instruction panel
+    pub fn instruction_at(&self, panel: Rect, y: f32) -> Option<Addr>{
+        let offset = y - panel.y - Self::INSTRUCTION_MARGIN_TOP;
+        if offset.is_sign_negative() {
+            None
+        } else {
+            let line_no = (offset / Self::instruction_line_height(panel)).trunc() as usize;
+            self.instruction_start.offset((line_no * INSTRUCTION_SIZE) as isize)
+        }
+    }
+
+    pub fn instruction_lines(&self, chip8: &Chip8, breakpoints: &Breakpoints) -> Vec<InstructionLine>{
+        let start = self.instruction_start.index();
+        let end = (start + Self::INSTRUCTION_ROWS * INSTRUCTION_SIZE).min(MEMORY_SIZE);
+        chip8.memory.ram[start..end].iter().enumerate().tuples().filter_map(|((offset, high), (_, low))| {
+            let addr = self.instruction_start.offset(offset as isize)?;
+            let instruction = self.symbols.instruction(u16::from_be_bytes([*high, *low]).into());
+            // labels go in front of the instruction, as in the source
+            let instruction = match self.symbols.name(addr) {
+                Some(label) => format!("{}: {}", label, instruction),
+                None => instruction
+            };
+            let mut text = if addr.index() == chip8.pc() {format!("\t>>0x{:x}\t\t{}", addr, instruction)} else {format!("0x{:x}\t\t{}", addr, instruction)};
+            let first = breakpoints.at(addr).next();
+            let breakpoint = first.map(|breakpoint| {
+                if !breakpoint.enabled {BreakpointMark::Disabled}
+                else if breakpoint.action != Action::Pause {BreakpointMark::Tracepoint}
+                else if breakpoint.condition.is_some() {BreakpointMark::Conditional}
+                else {BreakpointMark::Plain}
+            });
+            // a tracepoint doesn't stop, so its hits are shown instead
+            if let Some(breakpoint) = first.filter(|breakpoint| breakpoint.action != Action::Pause){
+                text.push_str(&format!("\t({} hits)", breakpoint.hits));
+            }
+            Some(InstructionLine{ addr, text, breakpoint })
+        }).collect()
+    }
+
+    /// The registers, four to a line
+    pub fn register_lines(chip8: &Chip8, key_profile: KeyProfile) -> Vec<String>{
+        let registers = &chip8.registers;
+        let mut register_desc: Vec<_> = registers.vn.iter().enumerate().map(
+            |(index, value)| format!("V{:x}: {:x}", index, value)
+        ).collect();
+        register_desc.push(format!("delay: {}", registers.delay));
+        register_desc.push(format!("sound: {}", registers.sound));
+        register_desc.push(format!("pc: {:x}", registers.pc));
+        register_desc.push(format!("sp: {:x}", registers.sp));
+        register_desc.push(format!("memory: {:x}", registers.i));
+        register_desc.push(format!("cpu: {}", registers.state));
+        register_desc.push(format!("quirks: {}", chip8.quirks()));
+        register_desc.push(format!("keys: {}", key_profile.describe(chip8.keys_down())));
+        // itertools::tuples() drops any elements that don't fit in a tuple,
+        // so we need to make sure that everything lines up
+        while register_desc.len() % 4 != 0{
+            register_desc.push(String::new());
+        }
+        register_desc.iter().tuples().map(
+            |(v1, v2, v3, v4)| format!("{v1}\t{v2}\t{v3}\t{v4}\t")
+        ).collect()
+    }
+
+    /// Each variable kept somewhere at the current instruction and its value, as many as fit in `height` pixels
+    pub fn variable_lines(&self, chip8: &Chip8, height: f32) -> Vec<String>{
+        let style = self.styles.get(Panel::Variables);
+        let rows = (((height - 2.0 * style.padding) / style.font_size) as usize).saturating_sub(1).max(1);
+        let mut lines = vec!["variables".to_string()];
+        lines.extend(self.symbols.live_variables(chip8.pc()).take(rows).map(|variable| {
+            let value = variable.location.value(chip8);
+            format!("{} ({}): {} {:#04x}", variable.name, variable.location, value, value)
+        }));
+        lines
+    }
+
+    /// One line for each CALL that hasn't returned, innermost first, as many as fit in `height` pixels
+    pub fn call_stack_lines(&self, chip8: &Chip8, height: f32) -> Vec<String>{
+        let stack = chip8.call_stack();
+        let style = self.styles.get(Panel::CallStack);
+        let rows = (((height - 2.0 * style.padding) / style.font_size) as usize).saturating_sub(1).max(1);
+        let mut lines = vec![format!("call stack ({}/{})", stack.len(), chip8.stack_depth())];
+        lines.extend(stack.iter().rev().take(rows).map(|&site| {
+            match chip8.memory.ram.get(site..site + INSTRUCTION_SIZE) {
+                Some(&[high, low]) => format!("0x{:03x}  {}", site, self.symbols.instruction(u16::from_be_bytes([high, low]).into())),
+                _ => format!("0x{:03x}", site)
+            }
+        }));
+        if stack.len() > rows{
+            lines.pop();
+            lines.push(format!("... {} more", stack.len() - rows + 1));
+        }
+        lines
+    }
+
+    pub fn memory_header(&self) -> String{
+        match self.memory_view.goto_text() {
+            Some(text) => format!("goto: {}_", text),
+            None => format!("follow I: {}  diff: {}   / goto  i follow  h diff",
+                if self.memory_view.follow_i() {"on"} else {"off"}, if self.memory_view.diff() {"on"} else {"off"})
+        }
+    }
+
+    pub fn search_header(&self) -> String{
+        match self.ram_search.value_text() {
+            Some(text) => format!("value: {}_", text),
+            None if self.ram_search.started() => format!("{} candidates   click here to search for a value", self.ram_search.candidates().len()),
+            None => "click here to search for a value, or 'new' to start from all of RAM".to_string()
+        }
+    }
+
+    pub fn frame_times_header(&self) -> String{
+        let Some(longest) = self.frame_times.iter().map(|timing| timing.interval).max() else {
+            return "frame times: none yet, they are measured while running".to_string()
+        };
+        let count = self.frame_times.len() as u32;
+        let average = |measure: fn(&FrameTiming) -> std::time::Duration|
+            self.frame_times.iter().map(measure).sum::<std::time::Duration>().as_secs_f64() * 1000.0 / count as f64;
+        let seconds = self.frame_times.iter().map(|timing| timing.interval).sum::<std::time::Duration>().as_secs_f64();
+        let instructions: u64 = self.frame_times.iter().map(|timing| timing.instructions).sum();
+        let speed = if seconds > 0.0 {(instructions as f64 / seconds).round().to_string()} else {"-".to_string()};
+        format!("frame {:.1} ms avg, {:.1} max  emulation {:.1}  drawing {:.1}  {} ips",
+            average(|timing| timing.interval), longest.as_secs_f64() * 1000.0,
+            average(|timing| timing.emulation), average(|timing| timing.presentation), speed)
+    }
+
+    /// Handle a click at pixel (x, y) on a screen of the given size
+    pub fn click(&mut self, x: f32, y: f32, screen_width: f32, screen_height: f32, breakpoints: &mut Breakpoints){
+        let panel = self.layout.hit_test(x, y, screen_width, screen_height);
+        let Some(rect) = panel.and_then(|panel| self.layout.screen_rect(panel, screen_width, screen_height)) else {return};
+        match panel {
+            Some(Panel::Instructions) => {
+                if let Some(addr) = self.instruction_at(rect, y){
+                    breakpoints.toggle(addr);
+                }
+            },
+            Some(Panel::Memory) => {
+                let addr = self.memory_view.addr_at(x - rect.x, y - rect.y);
+                self.memory_view.select(addr);
+            },
+            Some(Panel::Search) => {
+                if let Some(scan) = self.ram_search.click(x - rect.x, y - rect.y, RamSearch::rows(rect.height)){
+                    self.pending_scan = Some(scan);
+                }
+            },
+            Some(Panel::Display) | Some(Panel::Registers) | Some(Panel::CallStack) | Some(Panel::FrameTimes) | Some(Panel::Variables) | None => {}
+        }
+    }
+
+    /// Scroll the panel under pixel (x, y) by `direction` rows
+    pub fn scroll(&mut self, x: f32, y: f32, direction: isize, screen_width: f32, screen_height: f32){
+        match self.layout.hit_test(x, y, screen_width, screen_height) {
+            Some(Panel::Instructions) => {
+                let delta = direction * INSTRUCTION_SIZE as isize;
+                self.instruction_start = match self.instruction_start.offset(delta){
+                    Some(addr) => addr,
+                    None if delta < 0 => Addr::default(),
+                    None => Addr::MAX.align_down()
+                }
+            },
+            Some(Panel::Memory) => self.memory_view.scroll(direction),
+            Some(Panel::Search) => self.ram_search.scroll(direction),
+            Some(Panel::Display) | Some(Panel::Registers) | Some(Panel::CallStack) | Some(Panel::FrameTimes) | Some(Panel::Variables) | None => {}
+        }
+    }
+
+    /// The text of every visible panel and where it is, for checking the debug view in tests
+    #[cfg(debug_assertions)]
+    pub fn snapshot(&mut self, chip8: &Chip8, breakpoints: &Breakpoints, screen_width: f32, screen_height: f32) -> Snapshot{
+        let panels = [Panel::Display, Panel::Instructions, Panel::Memory, Panel::Registers, Panel::CallStack, Panel::Search, Panel::FrameTimes, Panel::Variables];
+        let panels = panels.into_iter().filter_map(|panel| {
+            let rect = self.layout.screen_rect(panel, screen_width, screen_height)?;
+            let lines = match panel {
+                Panel::Display => (0..crate::DISPLAY_ROWS).map(|y| (0..crate::DISPLAY_COLUMNS)
+                    .map(|x| if chip8.frame_buffer()[(x, y)] {'#'} else {'.'}).collect()).collect(),
+                Panel::Instructions => self.instruction_lines(chip8, breakpoints).into_iter().map(|line| {
+                    let mark = match line.breakpoint {
+                        Some(BreakpointMark::Plain) => '*',
+                        Some(BreakpointMark::Conditional) => '?',
+                        Some(BreakpointMark::Disabled) => '-',
+                        Some(BreakpointMark::Tracepoint) => '+',
+                        None => ' '
+                    };
+                    format!("{mark} {}", line.text)
+                }).collect(),
+                Panel::Memory => {
+                    self.memory_view.set_height(rect.height);
+                    let start = self.memory_view.first_row(chip8.registers.i);
+                    let mut lines = vec![self.memory_header()];
+                    lines.extend((0..self.memory_view.rows())
+                        .map(|row| start + row * MemoryView::BYTES_PER_ROW)
+                        .take_while(|&row_addr| row_addr < MEMORY_SIZE)
+                        .map(|row_addr| format!("{:03x}  {:02x}", row_addr,
+                            chip8.memory.ram[row_addr..row_addr + MemoryView::BYTES_PER_ROW].iter().format(" "))));
+                    lines
+                },
+                Panel::Registers => Self::register_lines(chip8, self.key_profile),
+                Panel::CallStack => self.call_stack_lines(chip8, rect.height),
+                Panel::Search => {
+                    let mut lines = vec![self.search_header(), RamSearch::BUTTONS.iter().map(|(label, _)| label).join(" ")];
+                    lines.extend(self.ram_search.visible(RamSearch::rows(rect.height)).iter()
+                        .map(|&(addr, old)| format!("{:03x}: {:02x} (was {:02x})", addr, chip8.memory.ram[addr], old)));
+                    lines.extend(self.ram_search.pins().iter()
+                        .map(|addr| format!("pin {:03x}: {:02x}", u16::from(*addr), chip8.memory.ram[addr.index()])));
+                    lines
+                },
+                Panel::FrameTimes => vec![self.frame_times_header()],
+                Panel::Variables => self.variable_lines(chip8, rect.height)
+            };
+            Some(PanelSnapshot{ panel, rect, lines })
+        }).collect();
+        Snapshot{ panels }
+    }
+}
+
+/// What one panel shows, in pixels on screen
+#[cfg(debug_assertions)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PanelSnapshot{
+    pub panel: Panel,
+    pub rect: Rect,
+    pub lines: Vec<String>
+}
+
+/// The text of every visible panel of the debug view, from `DebugPanels::snapshot`
+#[cfg(debug_assertions)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot{
+    pub panels: Vec<PanelSnapshot>
+}
+
+#[cfg(debug_assertions)]
+impl Snapshot{
+    pub fn panel(&self, panel: Panel) -> Option<&PanelSnapshot>{
+        self.panels.iter().find(|snapshot| snapshot.panel == panel)
+    }
+}
+
+#[cfg(debug_assertions)]
+impl std::fmt::Display for Snapshot{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for snapshot in self.panels.iter(){
+            let rect = snapshot.rect;
+            writeln!(f, "[{:?} {}x{} at {},{}]", snapshot.panel, rect.width, rect.height, rect.x, rect.y)?;
+            for line in snapshot.lines.iter(){
+                writeln!(f, "{line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn test_snapshot(){
+    use crate::Instruction;
+    let program: Vec<u8> = ["call 0x206", "jp 0x202", "nop", "ld v0 2", "drw 0 0 5", "ret"].into_iter()
+        .flat_map(|mnemonic| u16::from(Instruction::from_mnemonic(mnemonic).unwrap()).to_be_bytes())
+        .collect();
+    let mut chip8 = Chip8::init(None);
+    chip8.load_rom(&program).unwrap();
+    for _ in 0..3{
+        chip8.do_instruction();
+    }
+    let mut breakpoints = Breakpoints::default();
+    let mut panels = DebugPanels::default();
+    panels.follow_pc(chip8.pc());
+
+    let snapshot = panels.snapshot(&chip8, &breakpoints, 960.0, 480.0);
+    assert_eq!(snapshot.panels.iter().map(|snapshot| snapshot.panel).collect_vec(),
+        [Panel::Display, Panel::Instructions, Panel::Memory, Panel::Registers, Panel::CallStack]);
+    let display = snapshot.panel(Panel::Display).unwrap();
+    assert_eq!(display.rect, Rect::new(0.0, 0.0, 480.0, 240.0));
+    assert_eq!(display.lines.len(), crate::DISPLAY_ROWS);
+    assert!(display.lines[2].starts_with("..####.") && display.lines[3].starts_with("..#..#."), "{snapshot}");
+    let instructions = &snapshot.panel(Panel::Instructions).unwrap().lines;
+    assert_eq!(instructions[2], "  0x208\t\tDRW V0 V0 5");
+    assert_eq!(instructions[3], "  \t>>0x20a\t\tRET");
+    assert_eq!(snapshot.panel(Panel::Registers).unwrap().lines[0], "V0: 2\tV1: 0\tV2: 0\tV3: 0\t");
+    assert_eq!(snapshot.panel(Panel::CallStack).unwrap().lines, ["call stack (1/16)", "0x200  CALL 0x206"]);
+
+    // clicking the third instruction line toggles a breakpoint on it
+    let rect = panels.layout().screen_rect(Panel::Instructions, 960.0, 480.0).unwrap();
+    let y = DebugPanels::instruction_line_y(rect, 2) + 1.0;
+    panels.click(10.0, y, 960.0, 480.0, &mut breakpoints);
+    let instructions = panels.snapshot(&chip8, &breakpoints, 960.0, 480.0).panel(Panel::Instructions).unwrap().lines.clone();
+    assert!(instructions[2].starts_with("* "), "{instructions:?}");
+    breakpoints.set_enabled(0, false);
+    let instructions = panels.snapshot(&chip8, &breakpoints, 960.0, 480.0).panel(Panel::Instructions).unwrap().lines.clone();
+    assert!(instructions[2].starts_with("- "), "{instructions:?}");
+    panels.click(10.0, y, 960.0, 480.0, &mut breakpoints);
+    assert_eq!(breakpoints.iter().count(), 0);
+    breakpoints.add(crate::breakpoints::Breakpoint::parse(&["0x208", "then", "count"]).unwrap());
+    let instructions = panels.snapshot(&chip8, &breakpoints, 960.0, 480.0).panel(Panel::Instructions).unwrap().lines.clone();
+    assert_eq!(instructions[2], "+ 0x208\t\tDRW V0 V0 5\t(0 hits)");
+    breakpoints.remove(0);
+
+    panels.toggle_search();
+    let snapshot = panels.snapshot(&chip8, &breakpoints, 960.0, 480.0);
+    let search = snapshot.panel(Panel::Search).unwrap();
+    assert_eq!(search.rect, Rect::new(480.0, 0.0, 480.0, 240.0));
+    assert!(search.lines[0].starts_with("click here"));
+
+    panels.toggle_frame_times();
+    panels.frame_times = [16, 17, 30].map(|ms| FrameTiming{ interval: std::time::Duration::from_millis(ms), ..FrameTiming::default() }).to_vec();
+    let snapshot = panels.snapshot(&chip8, &breakpoints, 960.0, 480.0);
+    let frame_times = snapshot.panel(Panel::FrameTimes).unwrap();
+    assert_eq!(frame_times.rect, Rect::new(480.0, 360.0, 480.0, 120.0));
+    assert!(frame_times.lines[0].starts_with("frame 21.0 ms avg, 30.0 max"), "{:?}", frame_times.lines);
+
+    // the variables panel only shows up once the symbols name some, and only lists the ones live at pc
+    panels.set_symbols(&Symbols::parse(".var x v0 0x206-0x20c\n.var y v1 0x300-0x310\n").unwrap());
+    let snapshot = panels.snapshot(&chip8, &breakpoints, 960.0, 480.0);
+    let variables = snapshot.panel(Panel::Variables).unwrap();
+    assert_eq!(variables.rect, Rect::new(720.0, 360.0, 240.0, 120.0));
+    assert_eq!(variables.lines, ["variables", "x (v0): 2 0x02"]);
+    panels.set_symbols(&Symbols::default());
+    assert!(panels.snapshot(&chip8, &breakpoints, 960.0, 480.0).panel(Panel::Variables).is_none());
+
+    // zooming in on the memory panel fits more rows of RAM, and zooming again puts the panels back
+    panels.toggle_search();
+    let rows = panels.snapshot(&chip8, &breakpoints, 960.0, 480.0).panel(Panel::Memory).unwrap().lines.len();
+    panels.toggle_zoom(700.0, 10.0, 960.0, 480.0);
+    let snapshot = panels.snapshot(&chip8, &breakpoints, 960.0, 480.0);
+    assert_eq!(snapshot.panels.iter().map(|snapshot| snapshot.panel).collect_vec(), [Panel::Memory]);
+    assert_eq!(snapshot.panels[0].rect, Rect::new(0.0, 0.0, 960.0, 480.0));
+    assert!(snapshot.panels[0].lines.len() > rows, "{snapshot}");
+    panels.toggle_zoom(10.0, 10.0, 960.0, 480.0);
+    assert_eq!(panels.layout().zoomed(), None);
+    assert!(panels.snapshot(&chip8, &breakpoints, 960.0, 480.0).panel(Panel::Display).is_some());
+}
@@ -0,0 +1,96 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::errors::{parse_name, Diagnostic};
+
+/// Behaviours that differ between CHIP-8 interpreters, which a ROM may depend on.
+/// A game that misbehaves often just expects a quirk the other way round
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Quirk{
+    /// `DRW` waits for the next 60 hz frame, like the original interpreter. On with `--timing original`
+    #[cfg_attr(feature = "cli", value(name = "display-wait"))]
+    DisplayWait,
+    /// `LD Vx K` finishes when the key is released rather than when it is pressed, like the original interpreter
+    #[cfg_attr(feature = "cli", value(name = "wait-for-release"))]
+    WaitForRelease,
+    /// `LD [I] Vx` and `LD B Vx` can't write below 0x200, where the font is kept, so a stray write can't garble `LD F Vx`.
+    /// The write is dropped, or halts the CPU with `HaltCondition::ProtectedWrite`
+    #[cfg_attr(feature = "cli", value(name = "protect-low-memory"))]
+    ProtectLowMemory
+}
+
+impl Quirk{
+    pub const ALL: [Quirk; 3] = [Quirk::DisplayWait, Quirk::WaitForRelease, Quirk::ProtectLowMemory];
+}
+
+impl Display for Quirk{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Quirk::DisplayWait => write!(f, "display-wait"),
+            Quirk::WaitForRelease => write!(f, "wait-for-release"),
+            Quirk::ProtectLowMemory => write!(f, "protect-low-memory")
+        }
+    }
+}
+
+/// Parses the names `--quirk` takes, e.g. `display-wait`, for switching quirks at runtime as well
+impl FromStr for Quirk{
+    type Err = Diagnostic;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_name(&Self::ALL, "quirk", s)
+    }
+}
+
+/// Which quirks are switched on, one bit each
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Quirks(u8);
+
+impl Quirks{
+    pub fn get(&self, quirk: Quirk) -> bool{
+        self.0 & 1 << quirk as u8 != 0
+    }
+
+    pub fn set(&mut self, quirk: Quirk, enabled: bool){
+        if enabled {
+            self.0 |= 1 << quirk as u8;
+        } else {
+            self.0 &= !(1 << quirk as u8);
+        }
+    }
+
+    /// The quirks switched on
+    pub fn enabled(&self) -> impl Iterator<Item = Quirk> + '_{
+        Quirk::ALL.into_iter().filter(|quirk| self.get(*quirk))
+    }
+}
+
+impl Display for Quirks{
+    /// The quirks switched on, e.g. `display-wait, protect-low-memory`, or `none`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let enabled: Vec<_> = self.enabled().map(|quirk| quirk.to_string()).collect();
+        if enabled.is_empty(){
+            write!(f, "none")
+        } else {
+            write!(f, "{}", enabled.join(", "))
+        }
+    }
+}
+
+
+#[test]
+fn test_quirks(){
+    let mut quirks = Quirks::default();
+    assert_eq!(quirks.to_string(), "none");
+    quirks.set(Quirk::ProtectLowMemory, true);
+    quirks.set(Quirk::DisplayWait, true);
+    quirks.set(Quirk::DisplayWait, false);
+    quirks.set(Quirk::WaitForRelease, true);
+    assert!(quirks.get(Quirk::ProtectLowMemory) && !quirks.get(Quirk::DisplayWait));
+    assert_eq!(quirks.to_string(), "wait-for-release, protect-low-memory");
+    for quirk in Quirk::ALL{
+        assert_eq!(quirk.to_string().to_uppercase().parse(), Ok(quirk));
+    }
+    assert_eq!("vsync".parse::<Quirk>().unwrap_err().message,
+        "No quirk named vsync, expected one of display-wait, wait-for-release, protect-low-memory");
+}
@@ -0,0 +1,238 @@
+use crate::{memory_view::ViewKey, Addr};
+
+/// How a scan narrows down the candidates, comparing each byte with its value at the last scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter{
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased
+}
+
+impl Filter{
+    fn keeps(&self, old: u8, new: u8) -> bool{
+        match self {
+            Filter::Equal(value) => new == *value,
+            Filter::Changed => new != old,
+            Filter::Unchanged => new == old,
+            Filter::Increased => new > old,
+            Filter::Decreased => new < old
+        }
+    }
+}
+
+/// A scan for the search to run against RAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scan{
+    /// Start again with every byte of RAM as a candidate
+    New,
+    Filter(Filter)
+}
+
+/// Searching RAM for the bytes that behave like a value in the game, e.g. the score:
+/// scan for its current value, then keep re-scanning as it changes until only a few addresses are left.
+/// Positions are in pixels relative to the top left of the panel
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RamSearch{
+    /// Addresses still in the running and their values at the last scan,
+    /// or `None` before the first scan
+    candidates: Option<Vec<(usize, u8)>>,
+    /// Addresses kept on the watch list
+    pins: Vec<Addr>,
+    /// The value being typed, while typing one
+    value: Option<String>,
+    /// How many candidates are scrolled past
+    scroll: usize
+}
+
+impl RamSearch{
+    pub const MARGIN: f32 = 5.0;
+    pub const LINE_HEIGHT: f32 = 20.0;
+    pub const BUTTON_WIDTH: f32 = 80.0;
+    /// Candidates are listed on the left, pins on the right
+    pub const RESULTS_WIDTH: f32 = 220.0;
+    pub const MAX_PINS: usize = 10;
+    pub const BUTTONS: [(&'static str, Scan); 5] = [
+        ("new", Scan::New),
+        ("changed", Scan::Filter(Filter::Changed)),
+        ("same", Scan::Filter(Filter::Unchanged)),
+        ("up", Scan::Filter(Filter::Increased)),
+        ("down", Scan::Filter(Filter::Decreased))
+    ];
+
+    /// How many candidates fit in a panel `height` pixels tall, below the value and the buttons
+    pub fn rows(height: f32) -> usize{
+        (((height - Self::MARGIN) / Self::LINE_HEIGHT) as usize).saturating_sub(2).max(1)
+    }
+
+    /// Narrow the candidates down to the bytes of `ram` that pass `scan`.
+    /// The first scan starts from all of RAM
+    pub fn scan(&mut self, scan: Scan, ram: &[u8]){
+        self.scroll = 0;
+        let candidates = match (scan, self.candidates.take()) {
+            (Scan::New, _) => ram.iter().copied().enumerate().collect(),
+            (Scan::Filter(Filter::Equal(value)), None) => ram.iter().copied().enumerate()
+                .filter(|&(_, old)| old == value)
+                .collect(),
+            // there is nothing to compare against yet, so start from here
+            (Scan::Filter(_), None) => ram.iter().copied().enumerate().collect(),
+            (Scan::Filter(filter), Some(candidates)) => candidates.into_iter()
+                .filter_map(|(addr, old)| {
+                    let new = *ram.get(addr)?;
+                    filter.keeps(old, new).then_some((addr, new))
+                })
+                .collect()
+        };
+        self.candidates = Some(candidates);
+    }
+
+    /// Addresses still in the running and their values at the last scan
+    pub fn candidates(&self) -> &[(usize, u8)]{
+        self.candidates.as_deref().unwrap_or_default()
+    }
+
+    /// Whether anything has been scanned for yet
+    pub fn started(&self) -> bool{
+        self.candidates.is_some()
+    }
+
+    /// Add `addr` to the watch list, or take it off if it is already there
+    pub fn toggle_pin(&mut self, addr: Addr){
+        match self.pins.iter().position(|pin| *pin == addr) {
+            Some(index) => {self.pins.remove(index);},
+            None if self.pins.len() < Self::MAX_PINS => self.pins.push(addr),
+            None => {}
+        }
+    }
+
+    pub fn pins(&self) -> &[Addr]{
+        &self.pins
+    }
+
+    pub fn scroll(&mut self, rows: isize){
+        self.scroll = self.scroll.saturating_add_signed(rows).min(self.candidates().len().saturating_sub(1));
+    }
+
+    /// The candidates scrolled into view, at most `rows` of them
+    pub fn visible(&self, rows: usize) -> &[(usize, u8)]{
+        let candidates = self.candidates();
+        &candidates[self.scroll.min(candidates.len())..(self.scroll + rows).min(candidates.len())]
+    }
+
+    /// The value typed so far, while typing one
+    pub fn value_text(&self) -> Option<&str>{
+        self.value.as_deref()
+    }
+
+    /// Whether typing should go to the search instead of the CHIP-8 keypad
+    pub fn is_editing(&self) -> bool{
+        self.value.is_some()
+    }
+
+    /// Handle a typed key, returning a scan for the value once it is entered.
+    /// Values are decimal, or hex after `0x`
+    pub fn key(&mut self, key: ViewKey) -> Option<Scan>{
+        let text = self.value.as_mut()?;
+        match key {
+            ViewKey::Char(c) if c.is_ascii_hexdigit() || c == 'x' => {
+                if text.len() < 4{
+                    text.push(c);
+                }
+            },
+            ViewKey::Char(_) => {},
+            ViewKey::Backspace => {text.pop();},
+            ViewKey::Enter => {
+                let value = match text.strip_prefix("0x") {
+                    Some(hex) => u8::from_str_radix(hex, 16).ok(),
+                    None => text.parse().ok()
+                };
+                self.value = None;
+                return value.map(|value| Scan::Filter(Filter::Equal(value)))
+            }
+        }
+        None
+    }
+
+    /// Top left of candidate or pin number `index`
+    pub fn row_position(&self, index: usize, pinned: bool) -> (f32, f32){
+        let x = if pinned {Self::MARGIN + Self::RESULTS_WIDTH} else {Self::MARGIN};
+        (x, Self::MARGIN + (index + 2) as f32 * Self::LINE_HEIGHT)
+    }
+
+    /// Handle a click at `x`, `y`: on the value to start typing one, on a candidate to pin it
+    /// or on a pin to drop it. Clicking a button returns its scan
+    pub fn click(&mut self, x: f32, y: f32, rows: usize) -> Option<Scan>{
+        let line = (y - Self::MARGIN) / Self::LINE_HEIGHT;
+        if line < 0.0 || x < Self::MARGIN{
+            return None;
+        }
+        match line as usize {
+            0 => self.value = Some(String::new()),
+            1 => return Self::BUTTONS.get(((x - Self::MARGIN) / Self::BUTTON_WIDTH) as usize).map(|(_, scan)| *scan),
+            line if x < Self::MARGIN + Self::RESULTS_WIDTH => {
+                if let Some(addr) = self.visible(rows).get(line - 2).and_then(|(addr, _)| Addr::try_from(*addr).ok()){
+                    self.toggle_pin(addr);
+                }
+            },
+            line => {
+                if let Some(addr) = self.pins.get(line - 2).copied(){
+                    self.toggle_pin(addr);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[test]
+fn test_ram_search(){
+    let mut ram = vec![0u8; 16];
+    ram[3] = 5;
+    ram[9] = 5;
+    let mut search = RamSearch::default();
+    assert!(!search.started());
+    search.scan(Scan::Filter(Filter::Equal(5)), &ram);
+    assert_eq!(search.candidates(), [(3, 5), (9, 5)]);
+    ram[3] = 6;
+    search.scan(Scan::Filter(Filter::Unchanged), &ram);
+    assert_eq!(search.candidates(), [(9, 5)]);
+
+    search.scan(Scan::New, &ram);
+    assert_eq!(search.candidates().len(), 16);
+    ram[3] = 7;
+    ram[4] = 1;
+    search.scan(Scan::Filter(Filter::Changed), &ram);
+    assert_eq!(search.candidates(), [(3, 7), (4, 1)]);
+    ram[4] = 0;
+    search.scan(Scan::Filter(Filter::Decreased), &ram);
+    assert_eq!(search.candidates(), [(4, 0)]);
+}
+
+#[test]
+fn test_ram_search_panel(){
+    let mut search = RamSearch::default();
+    // the first relative scan only records where to start from
+    search.scan(Scan::Filter(Filter::Increased), &[1, 2, 3]);
+    assert_eq!(search.candidates().len(), 3);
+    assert_eq!(RamSearch::rows(RamSearch::MARGIN + 6.0 * RamSearch::LINE_HEIGHT), 4);
+
+    assert_eq!(search.click(RamSearch::MARGIN + 1.0, RamSearch::MARGIN + 1.0, 4), None);
+    assert!(search.is_editing());
+    for key in [ViewKey::Char('0'), ViewKey::Char('x'), ViewKey::Char('1'), ViewKey::Char('f')]{
+        assert_eq!(search.key(key), None);
+    }
+    assert_eq!(search.value_text(), Some("0x1f"));
+    assert_eq!(search.key(ViewKey::Enter), Some(Scan::Filter(Filter::Equal(0x1f))));
+    assert!(!search.is_editing());
+
+    let (_, y) = search.row_position(0, false);
+    search.click(RamSearch::MARGIN + 1.0, y + 1.0, 4);
+    assert_eq!(search.pins(), [Addr(0)]);
+    let (x, y) = search.row_position(0, true);
+    search.click(x + 1.0, y + 1.0, 4);
+    assert!(search.pins().is_empty());
+    let button = RamSearch::MARGIN + 2.5 * RamSearch::BUTTON_WIDTH;
+    assert_eq!(search.click(button, RamSearch::MARGIN + 1.5 * RamSearch::LINE_HEIGHT, 4),
+        Some(Scan::Filter(Filter::Unchanged)));
+}
@@ -0,0 +1,86 @@
+use std::rc::Rc;
+
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+
+/// Where `RND` gets its random bytes from. Any `rand` generator is one
+pub trait RandomDevice{
+    fn next_byte(&mut self) -> u8;
+}
+
+impl<R: RngCore> RandomDevice for R{
+    fn next_byte(&mut self) -> u8{
+        self.gen()
+    }
+}
+
+/// The CPU's random device: a seeded generator, or bytes recorded earlier in a replay,
+/// handed out in order until they run out, then the generator again.
+/// Replayed bytes come out the same whatever the generator does, even if a later version changes it.
+/// The recorded bytes are shared, so snapshots of the CPU stay cheap to take
+#[derive(Clone)]
+pub struct RandomSource{
+    rng: StdRng,
+    seed: u64,
+    /// Bytes to hand out before the generator's, and how many have been
+    playback: Option<(Rc<[u8]>, usize)>,
+    /// The byte handed out since `take_last` was last called
+    last: Option<u8>
+}
+
+impl RandomSource{
+    pub fn seeded(seed: u64) -> Self{
+        Self { rng: StdRng::seed_from_u64(seed), seed, playback: None, last: None }
+    }
+
+    /// What the generator was started from
+    pub fn seed(&self) -> u64{
+        self.seed
+    }
+
+    /// Hand out `bytes` before any from the generator
+    pub fn play_back(&mut self, bytes: Rc<[u8]>){
+        self.playback = Some((bytes, 0));
+    }
+
+    /// Whether recorded bytes are still being handed out
+    pub fn is_playing_back(&self) -> bool{
+        self.playback.is_some()
+    }
+
+    /// The byte handed out since this was last called, for recording it
+    pub fn take_last(&mut self) -> Option<u8>{
+        self.last.take()
+    }
+}
+
+impl RandomDevice for RandomSource{
+    fn next_byte(&mut self) -> u8{
+        let recorded = self.playback.as_mut().and_then(|(bytes, used)| {
+            let byte = bytes.get(*used).copied();
+            *used += 1;
+            byte
+        });
+        if recorded.is_none(){
+            self.playback = None;
+        }
+        let byte = recorded.unwrap_or_else(|| self.rng.gen());
+        self.last = Some(byte);
+        byte
+    }
+}
+
+
+#[test]
+fn test_random_source(){
+    let mut source = RandomSource::seeded(7);
+    let generated: Vec<_> = (0..4).map(|_| source.next_byte()).collect();
+    assert_eq!(source.take_last(), Some(generated[3]));
+    assert_eq!(source.take_last(), None);
+
+    let mut source = RandomSource::seeded(7);
+    source.play_back(Rc::from([1, 2].as_slice()));
+    assert!(source.is_playing_back());
+    let mixed: Vec<_> = (0..4).map(|_| source.next_byte()).collect();
+    assert_eq!(mixed, [1, 2, generated[0], generated[1]]);
+    assert!(!source.is_playing_back());
+}
@@ -0,0 +1,74 @@
+use std::{fmt::Display, path::{Path, PathBuf}};
+
+/// ROMs opened lately, most recent first, kept in a small state file so that they can be opened again quickly
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecentRoms{
+    paths: Vec<PathBuf>
+}
+
+impl RecentRoms{
+    /// The ROM running and the nine before it, one for each of `[shift]+F1` to `[shift]+F9`
+    pub const MAX_ROMS: usize = 10;
+    pub const FILE_NAME: &'static str = ".chip8_recent";
+
+    /// The state file in the home directory, if there is a home directory
+    pub fn default_path() -> Option<PathBuf>{
+        std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))
+            .map(|home| PathBuf::from(home).join(Self::FILE_NAME))
+    }
+
+    /// One path per line, most recent first
+    pub fn parse(text: &str) -> Self{
+        let mut recent = Self::default();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().into_iter().rev(){
+            recent.push(PathBuf::from(line));
+        }
+        recent
+    }
+
+    /// Load the ROMs in the state file at `path`, or none if there isn't one
+    pub fn load(path: &Path) -> Self{
+        std::fs::read_to_string(path)
+            .map(|text| Self::parse(&text))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()>{
+        std::fs::write(path, self.to_string())
+    }
+
+    /// Put `rom` first, moving it up if it was already there
+    pub fn push(&mut self, rom: PathBuf){
+        self.paths.retain(|path| *path != rom);
+        self.paths.insert(0, rom);
+        self.paths.truncate(Self::MAX_ROMS);
+    }
+
+    pub fn paths(&self) -> &[PathBuf]{
+        &self.paths
+    }
+}
+
+impl Display for RecentRoms{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for path in self.paths.iter(){
+            writeln!(f, "{}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+
+#[test]
+fn test_recent_roms(){
+    let mut recent = RecentRoms::parse("roms/pong.ch8\n\nroms/tetris.ch8\nroms/pong.ch8\n");
+    assert_eq!(recent.paths(), [PathBuf::from("roms/pong.ch8"), PathBuf::from("roms/tetris.ch8")]);
+    recent.push(PathBuf::from("roms/tetris.ch8"));
+    assert_eq!(recent.paths()[0], PathBuf::from("roms/tetris.ch8"));
+    assert_eq!(RecentRoms::parse(&recent.to_string()), recent);
+    for rom in 0..20{
+        recent.push(PathBuf::from(format!("{rom}.ch8")));
+    }
+    assert_eq!(recent.paths().len(), RecentRoms::MAX_ROMS);
+    assert_eq!(recent.paths()[0], PathBuf::from("19.ch8"));
+}
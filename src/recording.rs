@@ -0,0 +1,142 @@
+use std::{borrow::Cow, path::Path};
+
+use crate::{screenshot::Screenshot, theme::DisplayStyle, DISPLAY_COLUMNS, DISPLAY_ROWS};
+
+/// The display, frame by frame, for saving as an animated GIF.
+/// Runs of identical frames are kept once, so a mostly still game takes up little memory
+#[derive(Debug, Clone, Default)]
+pub struct Recording{
+    /// Each distinct screen and how many 60 hz frames it was shown for
+    screens: Vec<(Screenshot, usize)>,
+    frames: usize
+}
+
+impl Recording{
+    /// Ten minutes at 60 frames a second
+    pub const MAX_FRAMES: usize = 60 * 60 * 10;
+    /// GIF frame delays are in hundredths of a second, and many viewers slow down anything shorter than 2,
+    /// so the recording is played back at 50 frames a second
+    const GIF_DELAY: u16 = 2;
+    /// How many image pixels wide each CHIP-8 pixel is in a saved GIF
+    pub const GIF_SCALE: usize = 4;
+
+    /// Add the screen shown for the next frame, unless the recording is full
+    pub fn push(&mut self, screen: Screenshot){
+        if self.is_full(){
+            return;
+        }
+        self.frames += 1;
+        match self.screens.last_mut() {
+            Some((last, frames)) if *last == screen => *frames += 1,
+            _ => self.screens.push((screen, 1))
+        }
+    }
+
+    /// How many frames have been recorded
+    pub fn len(&self) -> usize{
+        self.frames
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.frames == 0
+    }
+
+    pub fn is_full(&self) -> bool{
+        self.frames >= Self::MAX_FRAMES
+    }
+
+    /// The screen shown at each GIF frame, as runs of identical screens
+    fn gif_frames(&self) -> Vec<(&Screenshot, u16)>{
+        let mut gif_frames: Vec<(&Screenshot, u16)> = vec![];
+        let mut screens = self.screens.iter();
+        let (mut screen, mut end) = match screens.next() {
+            Some((screen, frames)) => (screen, *frames),
+            None => return gif_frames
+        };
+        // the 60 hz frame shown at each 50 hz GIF frame
+        for frame in (0..).map(|gif_frame| gif_frame * 6 / 5).take_while(|frame| *frame < self.frames){
+            while frame >= end{
+                let (next, frames) = screens.next().expect("screens cover every recorded frame");
+                screen = next;
+                end += frames;
+            }
+            match gif_frames.last_mut() {
+                Some((last, delay)) if std::ptr::eq(*last, screen) => *delay += Self::GIF_DELAY,
+                _ => gif_frames.push((screen, Self::GIF_DELAY))
+            }
+        }
+        gif_frames
+    }
+
+    /// As a looping GIF in the colours of `style`, with each pixel `scale` image pixels wide.
+    /// The pixel effect, ghosting and frame blending aren't applied
+    pub fn to_gif(&self, style: &DisplayStyle, scale: usize) -> Vec<u8>{
+        let (width, height) = (DISPLAY_COLUMNS * scale, DISPLAY_ROWS * scale);
+        let palette = [style.background, style.foreground].iter().flat_map(|color| [color.r, color.g, color.b]).collect::<Vec<_>>();
+        let mut gif = Vec::new();
+        {
+            // writing to memory only fails on a size mismatch, which the frames are built to avoid
+            let mut encoder = gif::Encoder::new(&mut gif, width as u16, height as u16, &palette).expect("valid GIF header");
+            encoder.set_repeat(gif::Repeat::Infinite).expect("GIF written to memory");
+            for (screen, delay) in self.gif_frames(){
+                let pixels: Vec<u8> = (0..height)
+                    .flat_map(|y| (0..width).map(move |x| screen.pixel(x / scale, y / scale) as u8))
+                    .collect();
+                let frame = gif::Frame{
+                    width: width as u16,
+                    height: height as u16,
+                    buffer: Cow::Owned(pixels),
+                    delay,
+                    ..gif::Frame::default()
+                };
+                encoder.write_frame(&frame).expect("GIF written to memory");
+            }
+        }
+        gif
+    }
+
+    pub fn save(&self, path: &Path, style: &DisplayStyle) -> std::io::Result<()>{
+        std::fs::write(path, self.to_gif(style, Self::GIF_SCALE))
+    }
+}
+
+
+#[test]
+fn test_recording(){
+    let blank = ndarray::Array2::from_elem([DISPLAY_COLUMNS, DISPLAY_ROWS], false);
+    let mut lit = blank.clone();
+    lit[(0, 0)] = true;
+    let mut recording = Recording::default();
+    assert!(recording.is_empty());
+    for _ in 0..30{
+        recording.push(Screenshot::of(&blank));
+    }
+    for _ in 0..30{
+        recording.push(Screenshot::of(&lit));
+    }
+    assert_eq!(recording.len(), 60);
+    assert_eq!(recording.screens.len(), 2);
+    // a second at 50 frames a second, half blank and half lit
+    let delays: Vec<_> = recording.gif_frames().iter().map(|(_, delay)| *delay).collect();
+    assert_eq!(delays, vec![50, 50]);
+
+    let gif = recording.to_gif(&DisplayStyle::default(), 1);
+    assert!(gif.starts_with(b"GIF89a"));
+    let mut decoder = gif::DecodeOptions::new().read_info(gif.as_slice()).unwrap();
+    let first = decoder.read_next_frame().unwrap().unwrap();
+    assert_eq!((first.width, first.height, first.delay), (64, 32, 50));
+    let second = decoder.read_next_frame().unwrap().unwrap();
+    assert_eq!(second.buffer[0], 1);
+    assert!(decoder.read_next_frame().unwrap().is_none());
+}
+
+#[test]
+fn test_recording_full(){
+    let blank = Screenshot::of(&ndarray::Array2::from_elem([DISPLAY_COLUMNS, DISPLAY_ROWS], false));
+    let mut recording = Recording::default();
+    for _ in 0..Recording::MAX_FRAMES + 10{
+        recording.push(blank.clone());
+    }
+    assert!(recording.is_full());
+    assert_eq!(recording.len(), Recording::MAX_FRAMES);
+}
@@ -0,0 +1,292 @@
+use std::{fmt::Display, path::Path, rc::Rc};
+
+use itertools::Itertools;
+
+use crate::{breakpoints::parse_number, errors::{Diagnostic, Span}, extensions::Extension, quirks::{Quirk, Quirks}, timing::TimingMode, Chip8, CpuState};
+
+/// A change to the keypad, made between two frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEvent{
+    /// Every key released
+    Clear,
+    Press(u8)
+}
+
+/// Everything needed to play a run back exactly: the ROM it was recorded on, the settings and quirks that change
+/// how it runs, the seed of the random numbers, every key pressed, by frame, and every random byte `RND` drew,
+/// so that playback doesn't depend on the random number generator staying the same between versions.
+/// Stored as one `key = value` pair per line, with a `frame` line for each frame that had key events
+/// and `random` lines of the random bytes in order:
+///
+/// ```text
+/// # chip8 replay
+/// rom = 0x1c2f0a9e3b4d5f60
+/// state = 0x8f3e27a1c04b9d52
+/// seed = 0x5eed
+/// speed = 500
+/// timing = fixed-ipf
+/// quirk = wait-for-release
+/// frames = 300
+/// frame = 42 clear 5
+/// random = 3f a0 07
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Replay{
+    /// Hash of the ROM, see `rom_hash`
+    pub rom: u64,
+    /// Hash of the machine state the recording starts from, see `state_hash`. It's the state just after
+    /// loading the ROM unless the recording started from a saved session; older replays don't have it
+    pub state: Option<u64>,
+    pub seed: u64,
+    pub speed: u64,
+    pub timing: TimingMode,
+    pub extensions: Vec<Extension>,
+    pub quirks: Quirks,
+    /// Key events and the frame they happen before
+    events: Vec<(usize, KeyEvent)>,
+    /// How many frames were recorded
+    frames: usize,
+    /// The bytes `RND` drew, in order
+    random: Vec<u8>
+}
+
+/// A 64-bit FNV-1a hash of a ROM, to tell whether a replay was recorded on it
+pub fn rom_hash(rom: &[u8]) -> u64{
+    fnv_hash(rom.iter().copied())
+}
+
+/// A hash of `chip8`'s RAM, display, call stack and registers, to tell whether a replay starts from it.
+/// A fault counts as running, as that's how a saved session resumes it
+pub fn state_hash(chip8: &Chip8) -> u64{
+    let (memory, registers) = (&chip8.memory, &chip8.registers);
+    let state = match registers.state {
+        CpuState::Running | CpuState::Halted(_) => 0,
+        CpuState::WaitingForDisplay => 1,
+        CpuState::WaitingForKey(reg) => 2 + reg.index() as u8
+    };
+    fnv_hash(memory.ram.iter().copied()
+        .chain(memory.display.iter().map(|pixel| *pixel as u8))
+        .chain(memory.stack.iter().flat_map(|addr| (*addr as u16).to_be_bytes()))
+        .chain((registers.pc as u16).to_be_bytes())
+        .chain((registers.i as u16).to_be_bytes())
+        .chain([registers.delay, registers.sound, state])
+        .chain(registers.vn))
+}
+
+fn fnv_hash(bytes: impl Iterator<Item = u8>) -> u64{
+    bytes.fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+impl Replay{
+    pub const EXTENSION: &'static str = "c8replay";
+    /// How many random bytes are written on each `random` line
+    const RANDOM_PER_LINE: usize = 32;
+
+    /// Start recording a run of `rom` on `chip8`, from the state it's in
+    pub fn new(rom: &[u8], chip8: &Chip8, timing: TimingMode) -> Self{
+        Self{
+            rom: rom_hash(rom),
+            state: Some(state_hash(chip8)),
+            seed: chip8.rng_seed(),
+            speed: chip8.speed(),
+            timing,
+            extensions: Extension::ALL.into_iter().filter(|extension| chip8.has_extension(*extension)).collect(),
+            quirks: chip8.quirks(),
+            events: vec![],
+            frames: 0,
+            random: vec![]
+        }
+    }
+
+    /// Set `chip8` up to run like the recording: the same seed, speed, extensions and quirks,
+    /// and the same random bytes
+    pub fn apply(&self, chip8: &mut Chip8){
+        chip8.seed_rng(self.seed);
+        if !self.random.is_empty(){
+            chip8.play_back_random(Rc::from(self.random.as_slice()));
+        }
+        chip8.clock_speed(self.speed);
+        for extension in Extension::ALL{
+            chip8.set_extension(extension, self.extensions.contains(&extension));
+        }
+        for quirk in Quirk::ALL{
+            chip8.set_quirk(quirk, self.quirks.get(quirk));
+        }
+    }
+
+    /// Add a key event before the next frame
+    pub fn record(&mut self, event: KeyEvent){
+        self.events.push((self.frames, event));
+    }
+
+    /// Add a byte `RND` drew
+    pub fn record_random(&mut self, byte: u8){
+        self.random.push(byte);
+    }
+
+    /// The bytes `RND` drew, in order
+    pub fn random(&self) -> &[u8]{
+        &self.random
+    }
+
+    /// Move on to the next frame
+    pub fn next_frame(&mut self){
+        self.frames += 1;
+    }
+
+    /// How many frames were recorded
+    pub fn frames(&self) -> usize{
+        self.frames
+    }
+
+    /// The key events that happen before `frame`, in order
+    pub fn events(&self, frame: usize) -> impl Iterator<Item = KeyEvent> + '_{
+        let start = self.events.partition_point(|(event_frame, _)| *event_frame < frame);
+        self.events[start..].iter().take_while(move |(event_frame, _)| *event_frame == frame).map(|(_, event)| *event)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Diagnostic>{
+        let mut replay = Self{
+            rom: 0,
+            state: None,
+            seed: 0,
+            speed: Chip8::DEFAULT_SPEED,
+            timing: TimingMode::default(),
+            extensions: vec![],
+            quirks: Quirks::default(),
+            events: vec![],
+            frames: 0,
+            random: vec![]
+        };
+        let mut frames = None;
+        for (lineno, source_line) in text.lines().enumerate(){
+            let error = |message: String| Diagnostic::error(message)
+                .with_span(Span::whole_line(lineno + 1, source_line), source_line);
+            let line = source_line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty(){
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(format!("expected 'key = value', found '{line}'")))
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let invalid = || error(format!("invalid {key} '{value}'"));
+            let number = || parse_number(value).ok_or_else(invalid);
+            match key {
+                "rom" => replay.rom = u64::from_str_radix(value.trim_start_matches("0x"), 16).map_err(|_| invalid())?,
+                "state" => replay.state = Some(u64::from_str_radix(value.trim_start_matches("0x"), 16).map_err(|_| invalid())?),
+                "seed" => replay.seed = u64::from_str_radix(value.trim_start_matches("0x"), 16).map_err(|_| invalid())?,
+                "speed" => replay.speed = number()? as u64,
+                "timing" => replay.timing = value.parse().map_err(|_| invalid())?,
+                "extension" => replay.extensions.push(value.parse().map_err(|_| invalid())?),
+                "quirk" => replay.quirks.set(value.parse().map_err(|_| invalid())?, true),
+                "frames" => frames = Some(number()?),
+                "random" => for byte in value.split_whitespace(){
+                    replay.random.push(u8::from_str_radix(byte, 16).map_err(|_| invalid())?);
+                },
+                "frame" => {
+                    let mut words = value.split_whitespace();
+                    let frame = words.next().and_then(parse_number).ok_or_else(invalid)?;
+                    if replay.events.last().is_some_and(|(last, _)| *last > frame){
+                        return Err(error(format!("frame {frame} comes after a later frame")));
+                    }
+                    for word in words{
+                        let event = match word {
+                            "clear" => KeyEvent::Clear,
+                            key => KeyEvent::Press(u8::from_str_radix(key, 16).ok().filter(|key| *key < 16).ok_or_else(invalid)?)
+                        };
+                        replay.events.push((frame, event));
+                    }
+                },
+                _ => return Err(error(format!("unknown key '{key}'")))
+            }
+        }
+        replay.frames = frames.ok_or_else(|| Diagnostic::error("replay doesn't say how many frames it has"))?;
+        Ok(replay)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Diagnostic>{
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| Diagnostic::error(format!("could not read {}: {}", path.display(), err)))?;
+        Self::parse(&text)
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()>{
+        std::fs::write(path, self.to_string())
+    }
+}
+
+impl Display for Replay{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "# chip8 replay")?;
+        writeln!(f, "rom = {:#018x}", self.rom)?;
+        if let Some(state) = self.state{
+            writeln!(f, "state = {:#018x}", state)?;
+        }
+        writeln!(f, "seed = {:#018x}", self.seed)?;
+        writeln!(f, "speed = {}", self.speed)?;
+        writeln!(f, "timing = {}", self.timing)?;
+        for extension in self.extensions.iter(){
+            writeln!(f, "extension = {}", extension)?;
+        }
+        for quirk in self.quirks.enabled(){
+            writeln!(f, "quirk = {}", quirk)?;
+        }
+        writeln!(f, "frames = {}", self.frames)?;
+        for (frame, events) in self.events.iter().chunk_by(|(frame, _)| *frame).into_iter(){
+            let events: Vec<_> = events.map(|(_, event)| match event {
+                KeyEvent::Clear => "clear".to_string(),
+                KeyEvent::Press(key) => format!("{key:x}")
+            }).collect();
+            writeln!(f, "frame = {} {}", frame, events.join(" "))?;
+        }
+        for bytes in self.random.chunks(Self::RANDOM_PER_LINE){
+            writeln!(f, "random = {}", bytes.iter().map(|byte| format!("{byte:02x}")).join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+
+#[test]
+fn test_replay_round_trip(){
+    let mut chip8 = Chip8::init(Some(600));
+    chip8.seed_rng(0x5eed);
+    chip8.set_extension(Extension::VSync, true);
+    chip8.set_quirk(Quirk::WaitForRelease, true);
+    let mut replay = Replay::new(&[0x12, 0x00], &chip8, TimingMode::Original);
+    replay.next_frame();
+    replay.record(KeyEvent::Press(0xa));
+    replay.next_frame();
+    replay.record(KeyEvent::Clear);
+    replay.record(KeyEvent::Press(5));
+    replay.next_frame();
+    replay.next_frame();
+    for byte in 0..40{
+        replay.record_random(byte);
+    }
+    let text = replay.to_string();
+    assert!(text.contains("\nframe = 2 clear 5\n"), "{text}");
+    assert!(text.contains("\nrandom = 00 01 02 ") && text.contains("\nrandom = 20 21 "), "{text}");
+    let parsed = Replay::parse(&text).unwrap();
+    assert_eq!(parsed, replay);
+    assert_eq!(parsed.frames(), 4);
+    assert_eq!(parsed.events(1).collect::<Vec<_>>(), vec![KeyEvent::Press(0xa)]);
+    assert_eq!(parsed.events(2).collect::<Vec<_>>(), vec![KeyEvent::Clear, KeyEvent::Press(5)]);
+    assert_eq!(parsed.events(3).count(), 0);
+
+    let mut other = Chip8::init(None);
+    parsed.apply(&mut other);
+    assert_eq!((other.rng_seed(), other.speed(), other.has_extension(Extension::VSync)), (0x5eed, 600, true));
+    assert_eq!(other.quirks(), chip8.quirks());
+    assert_eq!(parsed.rom, rom_hash(&[0x12, 0x00]));
+    assert_eq!(parsed.state, Some(state_hash(&chip8)));
+    chip8.load_rom(&[0x12, 0x00]).unwrap();
+    assert_ne!(parsed.state, Some(state_hash(&chip8)));
+    assert_eq!(Replay::parse("frames = 0").unwrap().state, None);
+    assert_eq!(parsed.random(), (0..40).collect::<Vec<u8>>());
+
+    let error = Replay::parse("frames = 2\nframe = 1 g").unwrap_err();
+    assert_eq!(error.span.map(|span| span.line), Some(2));
+    assert!(Replay::parse("seed = 1").is_err());
+}
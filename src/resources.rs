@@ -0,0 +1,87 @@
+use std::{borrow::Cow, path::{Path, PathBuf}};
+
+use crate::audio::SquareWave;
+
+/// A file the emulator needs, built into the binary so that it runs on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource{
+    /// The TrueType font of the debug panels
+    Font,
+    /// The buzzer's sound, a WAV file played on a loop while the sound timer runs
+    Buzzer
+}
+
+impl Resource{
+    pub const ALL: [Resource; 2] = [Resource::Font, Resource::Buzzer];
+    /// How long the generated buzzer tone lasts before it is looped
+    const TONE_SECONDS: f32 = 1.0;
+
+    /// Name of the file in the resources directory that replaces it
+    pub fn file_name(self) -> &'static str{
+        match self {
+            Resource::Font => "font.ttf",
+            Resource::Buzzer => "buzzer.wav"
+        }
+    }
+
+    /// The copy built into the binary
+    pub fn embedded(self) -> Cow<'static, [u8]>{
+        match self {
+            Resource::Font => Cow::Borrowed(include_bytes!("..\\resources\\fonts\\VT323\\VT323-Regular.ttf")),
+            Resource::Buzzer => Cow::Owned(SquareWave::default().to_wav(Self::TONE_SECONDS))
+        }
+    }
+}
+
+/// Where resources are loaded from: files in an optional override directory, such as `--resources themes/amber`,
+/// and the built-in copies of anything it doesn't have, so themes can be changed without rebuilding
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Resources{
+    dir: Option<PathBuf>
+}
+
+impl Resources{
+    /// Prefer the files in `dir` to the built-in ones
+    pub fn with_override_dir(dir: PathBuf) -> Self{
+        Self { dir: Some(dir) }
+    }
+
+    /// The file that overrides `resource`, if there is one
+    pub fn override_path(&self, resource: Resource) -> Option<PathBuf>{
+        self.dir.as_deref().map(|dir| dir.join(resource.file_name())).filter(|path| path.is_file())
+    }
+
+    /// The override of `resource` if there is one that can be read, or the built-in copy
+    pub fn load(&self, resource: Resource) -> Cow<'static, [u8]>{
+        let Some(path) = self.override_path(resource) else {
+            return resource.embedded()
+        };
+        match std::fs::read(&path) {
+            Ok(bytes) => Cow::Owned(bytes),
+            Err(err) => {
+                eprintln!("Could not read {}, using the built-in copy: {}", path.display(), err);
+                resource.embedded()
+            }
+        }
+    }
+
+    /// The override directory, if any
+    pub fn dir(&self) -> Option<&Path>{
+        self.dir.as_deref()
+    }
+}
+
+
+#[test]
+fn test_resources(){
+    let dir = std::env::temp_dir().join(format!("chip8-resources-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("buzzer.wav"), b"RIFF").unwrap();
+    let resources = Resources::with_override_dir(dir.clone());
+    assert_eq!(resources.load(Resource::Buzzer).as_ref(), b"RIFF");
+    assert_eq!(resources.override_path(Resource::Font), None);
+    assert_eq!(resources.load(Resource::Font), Resource::Font.embedded());
+    assert_eq!(Resources::default().load(Resource::Buzzer), Resource::Buzzer.embedded());
+    assert!(Resource::Buzzer.embedded().starts_with(b"RIFF"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
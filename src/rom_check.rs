@@ -0,0 +1,98 @@
+use crate::{errors::{Diagnostic, Severity}, rom_inspect, Addr, Chip8, CpuState, Instruction, MEMORY_SIZE};
+
+/// Largest ROM that fits in memory after `Addr::ROM_START`
+pub const MAX_ROM_SIZE: usize = MEMORY_SIZE - Addr::ROM_START.index();
+
+/// Other file formats, by the bytes they start with
+const SIGNATURES: [(&[u8], &str); 9] = [
+    (b"PK\x03\x04", "a zip archive"),
+    (b"\x1f\x8b", "a gzip archive"),
+    (b"Rar!", "a RAR archive"),
+    (b"7z\xbc\xaf", "a 7z archive"),
+    (b"\x89PNG", "a PNG image"),
+    (b"GIF8", "a GIF image"),
+    (b"\xff\xd8\xff", "a JPEG image"),
+    (b"%PDF", "a PDF document"),
+    (b"\x7fELF", "an ELF executable")
+];
+
+/// Files this short can be all printable by chance
+const MIN_TEXT_LEN: usize = 16;
+
+/// Reasons to think `rom` isn't a CHIP-8 program, found before running it.
+/// Errors mean it can't be loaded at all; warnings are guesses, and it can be loaded anyway
+pub fn check_rom(rom: &[u8]) -> Vec<Diagnostic>{
+    if rom.is_empty(){
+        return vec![Diagnostic::error("The file is empty")];
+    }
+    if rom.len() > MAX_ROM_SIZE{
+        return vec![Diagnostic::error(format!("The file is {} bytes, but at most {} bytes fit in memory", rom.len(), MAX_ROM_SIZE))];
+    }
+    // a file of some other format says so plainly, the checks below would only add noise
+    if let Some((_, format)) = SIGNATURES.iter().find(|(signature, _)| rom.starts_with(signature)){
+        return vec![Diagnostic::warning(format!("This looks like {format}, not a CHIP-8 ROM"))];
+    }
+    if rom.len() >= MIN_TEXT_LEN && rom.iter().all(|byte| byte.is_ascii_graphic() || byte.is_ascii_whitespace()){
+        return vec![Diagnostic::warning("This looks like a text file, not a CHIP-8 ROM; assembly source can be built with chip8cc")];
+    }
+    // nor does a ROM for a later interpreter, which is bound to do something odd early on
+    if let Some(diagnostic) = rom_inspect::inspect(rom).diagnostic(){
+        return vec![diagnostic];
+    }
+    let mut diagnostics = vec![];
+    let entry = u16::from_be_bytes([rom[0], rom.get(1).copied().unwrap_or_default()]);
+    if Instruction::from(entry) == Instruction::Nop{
+        diagnostics.push(Diagnostic::warning(format!("The first instruction, {entry:04x}, isn't a CHIP-8 instruction"))
+            .at_address(Addr::ROM_START));
+    }
+    // a program that faults straight away is most likely not a program
+    let mut chip8 = Chip8::init(None);
+    if let Err(err) = chip8.load_rom(rom){
+        return vec![err];
+    }
+    chip8.run_frame();
+    if let CpuState::Halted(fault) = chip8.state(){
+        let mut diagnostic = fault.diagnostic(chip8.pc());
+        diagnostic.severity = Severity::Warning;
+        diagnostic.message = format!("The ROM stops in its first frame: {}", diagnostic.message);
+        diagnostics.push(diagnostic);
+    }
+    diagnostics
+}
+
+/// Whether any of `diagnostics` stops a ROM from loading even when asked to load it anyway
+pub fn is_fatal(diagnostics: &[Diagnostic]) -> bool{
+    diagnostics.iter().any(|diagnostic| diagnostic.severity == Severity::Error)
+}
+
+
+#[test]
+fn test_check_rom(){
+    // ld v0 0xa; ld f v0; drw v1 v1 5; jp 0x206
+    assert_eq!(check_rom(&[0x60, 0x0a, 0xf0, 0x29, 0xd1, 0x15, 0x12, 0x06]), vec![]);
+
+    assert!(is_fatal(&check_rom(&[])));
+    assert!(is_fatal(&check_rom(&vec![0x12; MAX_ROM_SIZE + 1])));
+    assert!(!is_fatal(&check_rom(&[0x12; MAX_ROM_SIZE])));
+
+    let zip = check_rom(b"PK\x03\x04\x14\x00\x00\x00\x08\x00");
+    assert_eq!(zip.len(), 1);
+    assert_eq!(zip[0].message, "This looks like a zip archive, not a CHIP-8 ROM");
+    assert!(!is_fatal(&zip));
+    let text = check_rom(b"ld v0 0x0a\nld f v0\ndrw v1 v1 5\n");
+    assert!(text[0].message.starts_with("This looks like a text file"));
+
+    let entry = check_rom(&[0x00, 0x00, 0x12, 0x02]);
+    assert_eq!(entry[0].address, Some(Addr::ROM_START));
+    // ret with nothing to return to
+    let fault = check_rom(&[0x00, 0xee]);
+    assert_eq!(fault.len(), 1);
+    assert!(fault[0].message.starts_with("The ROM stops in its first frame"));
+    assert_eq!(fault[0].severity, Severity::Warning);
+
+    // hires; jp 0x202
+    let schip = check_rom(&[0x00, 0xff, 0x12, 0x02]);
+    assert_eq!(schip.len(), 1);
+    assert!(schip[0].message.starts_with("This ROM needs SUPER-CHIP"));
+    assert!(is_fatal(&schip));
+}
@@ -0,0 +1,112 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::{cfg::Cfg, errors::Diagnostic, extensions::Extension, Addr, Instruction};
+
+/// A later CHIP-8 interpreter whose instructions this emulator doesn't run,
+/// in the order they build on each other
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Variant{
+    SuperChip,
+    XoChip,
+    MegaChip
+}
+
+impl Variant{
+    /// The variant `opcode` belongs to, if it isn't a CHIP-8 instruction.
+    /// Mega-Chip is only recognised by switching its mode on or off, since its other opcodes
+    /// are `0NNN` machine code calls that old ROMs use too
+    pub(crate) fn of(opcode: u16) -> Option<Variant>{
+        let [high, low] = opcode.to_be_bytes();
+        match (high >> 4, high & 0xf, low) {
+            (0x0, 0x0, 0x10 | 0x11) => Some(Variant::MegaChip),
+            (0x0, 0x0, low) if low >> 4 == 0xd => Some(Variant::XoChip),
+            (0x5, _, low) if low & 0xf == 2 || low & 0xf == 3 => Some(Variant::XoChip),
+            (0xf, 0x0, 0x00 | 0x02) | (0xf, 0x0..=0x3, 0x01) | (0xf, _, 0x3a) => Some(Variant::XoChip),
+            (0x0, 0x0, low) if low >> 4 == 0xc => Some(Variant::SuperChip),
+            (0x0, 0x0, 0xfb..=0xff) => Some(Variant::SuperChip),
+            (0xd, _, low) if low & 0xf == 0 => Some(Variant::SuperChip),
+            (0xf, _, 0x30 | 0x75 | 0x85) => Some(Variant::SuperChip),
+            _ => None
+        }
+    }
+}
+
+impl Display for Variant{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Variant::SuperChip => "SUPER-CHIP",
+            Variant::XoChip => "XO-CHIP",
+            Variant::MegaChip => "Mega-Chip"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What a ROM's code uses beyond the original instruction set, found by following it from 0x200
+/// through jumps, calls and skips without running it. Data is seldom mistaken for code this way,
+/// though code only reached by a computed jump (`JP V0`) is missed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Inspection{
+    /// Extensions the code uses, with the first address using each
+    pub extensions: BTreeMap<Extension, Addr>,
+    /// Variants the code has instructions from, with the first address and opcode of each
+    pub variants: BTreeMap<Variant, (Addr, u16)>
+}
+
+impl Inspection{
+    /// The variant the ROM needs, the latest if it has instructions from more than one
+    pub fn needs(&self) -> Option<(Variant, Addr, u16)>{
+        self.variants.iter().next_back().map(|(variant, (addr, opcode))| (*variant, *addr, *opcode))
+    }
+
+    /// An error if the ROM is for a variant this emulator can't run, rather than let it run wrong
+    pub fn diagnostic(&self) -> Option<Diagnostic>{
+        let (variant, addr, opcode) = self.needs()?;
+        Some(Diagnostic::error(format!("This ROM needs {variant}, which isn't supported: {opcode:04x} at {addr} is a {variant} instruction"))
+            .at_address(addr))
+    }
+}
+
+/// Follow `rom`'s code from its entry point, noting what it needs
+pub fn inspect(rom: &[u8]) -> Inspection{
+    let mut inspection = Inspection::default();
+    for offset in Cfg::new(rom, Addr::ROM_START, [0], |_| true).instructions(){
+        let opcode = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+        let Some(addr) = Addr::ROM_START.offset(offset as isize) else {continue};
+        if let Some(variant) = Variant::of(opcode){
+            let first = inspection.variants.entry(variant).or_insert((addr, opcode));
+            if addr < first.0{
+                *first = (addr, opcode);
+            }
+        }
+        if Instruction::from(opcode) == Instruction::VSync{
+            let first = inspection.extensions.entry(Extension::VSync).or_insert(addr);
+            *first = addr.min(*first);
+        }
+    }
+    inspection
+}
+
+
+#[test]
+fn test_inspect(){
+    // ld v0 0xa; ld f v0; drw v1 v1 5; jp 0x206
+    assert_eq!(inspect(&[0x60, 0x0a, 0xf0, 0x29, 0xd1, 0x15, 0x12, 0x06]), Inspection::default());
+
+    // high; call 0x208; jp 0x204; sprite data that looks like scroll-down; ld hf v0; ret
+    let schip = inspect(&[0x00, 0xff, 0x22, 0x08, 0x12, 0x04, 0x00, 0xc1, 0xf0, 0x30, 0x00, 0xee]);
+    assert_eq!(schip.needs(), Some((Variant::SuperChip, Addr::ROM_START, 0x00ff)));
+    let diagnostic = schip.diagnostic().unwrap();
+    assert_eq!(diagnostic.message, "This ROM needs SUPER-CHIP, which isn't supported: 00ff at 0x200 is a SUPER-CHIP instruction");
+    assert_eq!(diagnostic.address, Some(Addr::ROM_START));
+
+    // se v0 0; save v1 - v2 in the skipped instruction; ld i 0x1234 over two words; vsync; jp 0x20a
+    let xo = inspect(&[0x30, 0x00, 0x51, 0x22, 0xf0, 0x00, 0x12, 0x34, 0x00, 0xf1, 0x12, 0x0a]);
+    assert_eq!(xo.needs(), Some((Variant::XoChip, Addr::new(0x202).unwrap(), 0x5122)));
+    assert_eq!(xo.extensions, BTreeMap::from([(Extension::VSync, Addr::new(0x208).unwrap())]));
+
+    // megaon; high; jp 0x204
+    assert_eq!(inspect(&[0x00, 0x10, 0x00, 0xff, 0x12, 0x04]).needs().unwrap().0, Variant::MegaChip);
+    // unreachable data isn't counted: jp 0x200; data
+    assert_eq!(inspect(&[0x12, 0x00, 0x00, 0xff]), Inspection::default());
+}
@@ -0,0 +1,148 @@
+use std::{fmt::Display, path::Path};
+
+use crate::{errors::{Diagnostic, Span}, theme::DisplayStyle, DISPLAY_COLUMNS, DISPLAY_ROWS};
+
+/// A copy of the display, for comparing against a stored golden image.
+/// Stored as text, one row per line, with `#` for lit pixels and `.` for unlit ones
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screenshot{
+    /// Indexed by `y * DISPLAY_COLUMNS + x`
+    pixels: Vec<bool>
+}
+
+impl Screenshot{
+    /// How many image pixels wide each CHIP-8 pixel is in a saved PNG
+    pub const PNG_SCALE: usize = 8;
+
+    /// The display buffer of `Chip8::frame_buffer`, indexed like (column, row)
+    pub fn of(display: &ndarray::Array2<bool>) -> Self{
+        Self{
+            pixels: (0..DISPLAY_ROWS).flat_map(|y| (0..DISPLAY_COLUMNS).map(move |x| display[(x, y)])).collect()
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Diagnostic>{
+        let rows: Vec<_> = text.lines().filter(|line| !line.trim().is_empty()).collect();
+        if rows.len() != DISPLAY_ROWS{
+            return Err(Diagnostic::error(format!("expected {DISPLAY_ROWS} rows, found {}", rows.len())));
+        }
+        let mut pixels = Vec::with_capacity(DISPLAY_ROWS * DISPLAY_COLUMNS);
+        for (lineno, row) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()){
+            let row = row.trim_end();
+            let error = |message: String| Diagnostic::error(message)
+                .with_span(Span::whole_line(lineno + 1, row), row);
+            if row.chars().count() != DISPLAY_COLUMNS{
+                return Err(error(format!("expected {DISPLAY_COLUMNS} pixels, found {}", row.chars().count())));
+            }
+            for c in row.chars(){
+                pixels.push(match c {
+                    '#' => true,
+                    '.' => false,
+                    _ => return Err(error(format!("unexpected '{c}', pixels are '#' or '.'")))
+                });
+            }
+        }
+        Ok(Self { pixels })
+    }
+
+    /// A 64-bit FNV-1a hash of the pixels, the same on every platform and run
+    pub fn hash(&self) -> u64{
+        self.pixels.chunks(8).fold(0xcbf29ce484222325, |hash, byte| {
+            let byte = byte.iter().fold(0u8, |byte, &pixel| byte << 1 | pixel as u8);
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    /// How many pixels differ from `other`
+    pub fn diff(&self, other: &Screenshot) -> usize{
+        self.pixels.iter().zip(other.pixels.iter()).filter(|(a, b)| a != b).count()
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool{
+        self.pixels[y * DISPLAY_COLUMNS + x]
+    }
+
+    /// As a plain PBM image, which most image viewers and tools can read
+    pub fn to_pbm(&self) -> String{
+        let mut pbm = format!("P1\n{DISPLAY_COLUMNS} {DISPLAY_ROWS}\n");
+        for row in self.pixels.chunks(DISPLAY_COLUMNS){
+            let line: Vec<_> = row.iter().map(|&pixel| if pixel {"1"} else {"0"}).collect();
+            pbm.push_str(&line.join(" "));
+            pbm.push('\n');
+        }
+        pbm
+    }
+
+    /// As a PNG image in the colours and effect of `style`, with each pixel `scale` image pixels wide
+    pub fn to_png(&self, style: &DisplayStyle, scale: usize) -> Vec<u8>{
+        let rgba = style.render_levels(|x, y| if self.pixel(x, y) {1.0} else {0.0}, scale);
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, (DISPLAY_COLUMNS * scale) as u32, (DISPLAY_ROWS * scale) as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        // writing to memory only fails on a size mismatch, which render_levels rules out
+        let mut writer = encoder.write_header().expect("valid PNG header");
+        writer.write_image_data(&rgba).expect("image data matches the header");
+        writer.finish().expect("PNG written to memory");
+        png
+    }
+
+    /// Write to `path` as a PNG, a PBM or golden screenshot text, depending on its extension
+    pub fn save(&self, path: &Path, style: &DisplayStyle) -> std::io::Result<()>{
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("png") => std::fs::write(path, self.to_png(style, Self::PNG_SCALE)),
+            Some("pbm") => std::fs::write(path, self.to_pbm()),
+            _ => std::fs::write(path, self.to_string())
+        }
+    }
+}
+
+impl Display for Screenshot{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for row in self.pixels.chunks(DISPLAY_COLUMNS){
+            let line: String = row.iter().map(|&pixel| if pixel {'#'} else {'.'}).collect();
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+
+#[test]
+fn test_screenshot(){
+    let mut display = ndarray::Array2::from_elem([DISPLAY_COLUMNS, DISPLAY_ROWS], false);
+    display[(1, 0)] = true;
+    display[(63, 31)] = true;
+    let screenshot = Screenshot::of(&display);
+    let text = screenshot.to_string();
+    assert!(text.starts_with(".#....") && text.ends_with("..#\n"));
+    assert_eq!(Screenshot::parse(&text), Ok(screenshot.clone()));
+
+    display[(2, 0)] = true;
+    let other = Screenshot::of(&display);
+    assert_eq!(screenshot.diff(&other), 1);
+    assert_ne!(screenshot.hash(), other.hash());
+    assert_eq!(screenshot.hash(), Screenshot::parse(&text).unwrap().hash());
+
+    let error = Screenshot::parse(&text.replacen('.', "x", 1)).unwrap_err();
+    assert_eq!(error.span.map(|span| span.line), Some(1));
+    assert!(Screenshot::parse("#.\n").is_err());
+}
+
+#[test]
+fn test_screenshot_export(){
+    let mut display = ndarray::Array2::from_elem([DISPLAY_COLUMNS, DISPLAY_ROWS], false);
+    display[(1, 0)] = true;
+    let screenshot = Screenshot::of(&display);
+    let pbm = screenshot.to_pbm();
+    assert!(pbm.starts_with("P1\n64 32\n0 1 0 0"));
+    assert_eq!(pbm.lines().count(), 2 + DISPLAY_ROWS);
+
+    let png = screenshot.to_png(&DisplayStyle::default(), 2);
+    let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+    let mut rgba = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut rgba).unwrap();
+    assert_eq!((info.width, info.height), (128, 64));
+    // the second pixel of the first row is lit, and 2 image pixels wide
+    assert_eq!(&rgba[..16], &[0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255, 255, 255, 255, 255, 255]);
+}
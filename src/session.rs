@@ -0,0 +1,199 @@
+use std::{fmt::Display, path::{Path, PathBuf}};
+
+use crate::{breakpoints::Breakpoint, breakpoints::parse_number, errors::{Diagnostic, Span}, Chip8, CpuState, Memory, Reg, Registers, DISPLAY_COLUMNS, DISPLAY_ROWS, MEMORY_SIZE};
+
+/// Everything needed to carry on where a run left off: the ROM, the machine's state,
+/// whether it was paused in the debug view, and the breakpoints.
+/// Stored as one `key = value` pair per line, with breakpoints as debugger script lines
+#[derive(Debug, Clone)]
+pub struct Session{
+    pub rom: PathBuf,
+    pub paused: bool,
+    /// Whether the debug panels were shown
+    pub debug_view: bool,
+    pub breakpoints: Vec<Breakpoint>,
+    memory: Memory,
+    registers: Registers
+}
+
+impl Session{
+    pub const DEFAULT_PATH: &'static str = "chip8.session";
+
+    /// A session running `rom`, at the state `chip8` is in
+    pub fn new(rom: PathBuf, chip8: &Chip8) -> Self{
+        let mut registers = chip8.registers.clone();
+        // a fault is raised again by the instruction it was left on
+        if matches!(registers.state, CpuState::Halted(_)){
+            registers.state = CpuState::Running;
+        }
+        Self{
+            rom,
+            paused: false,
+            debug_view: false,
+            breakpoints: vec![],
+            memory: chip8.memory.clone(),
+            registers
+        }
+    }
+
+    /// Put the saved machine state into `chip8`, keeping its clock speed, quirks and extensions
+    pub fn restore(&self, chip8: &mut Chip8){
+        chip8.memory = self.memory.clone();
+        chip8.registers = self.registers.clone();
+        chip8.clear_keys();
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Diagnostic>{
+        let mut rom = None;
+        let mut session = Self::new(PathBuf::new(), &Chip8::init(None));
+        for (lineno, source_line) in text.lines().enumerate(){
+            let error = |message: String| Diagnostic::error(message)
+                .with_span(Span::whole_line(lineno + 1, source_line), source_line);
+            let line = source_line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty(){
+                continue;
+            }
+            if let Some(breakpoint) = line.strip_prefix("break "){
+                let words: Vec<_> = breakpoint.split_whitespace().map(str::to_ascii_lowercase).collect();
+                let words: Vec<_> = words.iter().map(String::as_str).collect();
+                session.breakpoints.push(Breakpoint::parse(&words).map_err(error)?);
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(format!("expected 'key = value', found '{line}'")))
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let invalid = || error(format!("invalid {key} '{value}'"));
+            let number = || parse_number(value).ok_or_else(invalid);
+            let byte = || number().and_then(|n| u8::try_from(n).map_err(|_| invalid()));
+            let registers = &mut session.registers;
+            match key {
+                "rom" => rom = Some(PathBuf::from(value)),
+                "paused" => session.paused = value.parse().map_err(|_| invalid())?,
+                "debug_view" => session.debug_view = value.parse().map_err(|_| invalid())?,
+                "pc" => registers.pc = number().and_then(|pc| if pc < MEMORY_SIZE - 1 {Ok(pc)} else {Err(invalid())})?,
+                "i" => registers.i = number()?,
+                "delay" => registers.delay = byte()?,
+                "sound" => registers.sound = byte()?,
+                "v" => registers.vn = parse_hex(value).and_then(|vn| vn.try_into().ok()).ok_or_else(invalid)?,
+                "state" => registers.state = match value.split_whitespace().collect::<Vec<_>>()[..] {
+                    ["running"] => CpuState::Running,
+                    ["display"] => CpuState::WaitingForDisplay,
+                    ["key", reg] => CpuState::WaitingForKey(reg.parse::<Reg>().map_err(|_| invalid())?),
+                    _ => return Err(invalid())
+                },
+                "stack" => session.memory.stack = value.split_whitespace()
+                    .map(|addr| parse_number(addr).ok_or_else(invalid))
+                    .collect::<Result<_, _>>()?,
+                "ram" => session.memory.ram = parse_hex(value).and_then(|ram| ram.try_into().ok()).ok_or_else(invalid)?,
+                "display" => {
+                    let rows = parse_hex(value).filter(|rows| rows.len() == DISPLAY_COLUMNS * DISPLAY_ROWS / 8)
+                        .ok_or_else(invalid)?;
+                    for ((x, y), pixel) in session.memory.display.indexed_iter_mut(){
+                        let index = y * DISPLAY_COLUMNS + x;
+                        *pixel = rows[index / 8] & (0x80 >> (index % 8)) != 0;
+                    }
+                },
+                _ => return Err(error(format!("unknown key '{key}'")))
+            }
+        }
+        session.rom = rom.ok_or_else(|| Diagnostic::error("session doesn't name a ROM"))?;
+        Ok(session)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Diagnostic>{
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| Diagnostic::error(format!("could not read {}: {}", path.display(), err)))?;
+        Self::parse(&text)
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()>{
+        std::fs::write(path, self.to_string())
+    }
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>>{
+    text.as_bytes().chunks(2)
+        .map(|pair| std::str::from_utf8(pair).ok().filter(|pair| pair.len() == 2)
+            .and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String{
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+impl Display for Session{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let registers = &self.registers;
+        writeln!(f, "# chip8 session")?;
+        writeln!(f, "rom = {}", self.rom.display())?;
+        writeln!(f, "paused = {}", self.paused)?;
+        writeln!(f, "debug_view = {}", self.debug_view)?;
+        writeln!(f, "pc = {:#x}", registers.pc)?;
+        writeln!(f, "i = {:#x}", registers.i)?;
+        writeln!(f, "delay = {}", registers.delay)?;
+        writeln!(f, "sound = {}", registers.sound)?;
+        writeln!(f, "v = {}", hex(&registers.vn))?;
+        match registers.state {
+            CpuState::WaitingForKey(reg) => writeln!(f, "state = key {reg}")?,
+            CpuState::WaitingForDisplay => writeln!(f, "state = display")?,
+            CpuState::Running | CpuState::Halted(_) => writeln!(f, "state = running")?
+        }
+        let stack: Vec<_> = self.memory.stack.iter().map(|addr| format!("{addr:#x}")).collect();
+        writeln!(f, "stack = {}", stack.join(" "))?;
+        let mut rows = vec![0u8; DISPLAY_COLUMNS * DISPLAY_ROWS / 8];
+        for ((x, y), pixel) in self.memory.display.indexed_iter(){
+            let index = y * DISPLAY_COLUMNS + x;
+            if *pixel{
+                rows[index / 8] |= 0x80 >> (index % 8);
+            }
+        }
+        writeln!(f, "display = {}", hex(&rows))?;
+        writeln!(f, "ram = {}", hex(&self.memory.ram))?;
+        for breakpoint in self.breakpoints.iter(){
+            writeln!(f, "break {breakpoint}")?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_session_round_trip(){
+    use crate::{Addr, Instruction};
+    // call a subroutine that draws the 0 glyph, then wait for a key
+    let program: Vec<u8> = ["call 0x206", "ld v5 k", "jp 0x204", "ld v0 2", "ld v1 3", "drw 0 1 5", "ret"].into_iter()
+        .flat_map(|mnemonic| u16::from(Instruction::from_mnemonic(mnemonic).unwrap()).to_be_bytes())
+        .collect();
+    let mut chip8 = Chip8::init(None);
+    chip8.load_rom(&program).unwrap();
+    for _ in 0..4{
+        chip8.do_instruction();
+    }
+    let mut session = Session::new(PathBuf::from("games/test.ch8"), &chip8);
+    session.paused = true;
+    session.debug_view = true;
+    session.breakpoints.push(Breakpoint::at(Addr::new(0x20c).unwrap()));
+
+    let parsed = Session::parse(&session.to_string()).unwrap();
+    assert_eq!(parsed.rom, PathBuf::from("games/test.ch8"));
+    assert!(parsed.paused && parsed.debug_view);
+    assert_eq!(parsed.breakpoints, session.breakpoints);
+    let mut restored = Chip8::init(None);
+    parsed.restore(&mut restored);
+    assert_eq!(restored.pc(), 0x20c);
+    assert_eq!(restored.call_stack(), [0x200]);
+    assert_eq!(restored.frame_buffer(), chip8.frame_buffer());
+    assert_eq!(restored.registers.vn, chip8.registers.vn);
+    assert_eq!(restored.read_memory(0, MEMORY_SIZE), chip8.read_memory(0, MEMORY_SIZE));
+    for _ in 0..3{
+        restored.do_instruction();
+    }
+    assert_eq!(restored.state(), CpuState::WaitingForKey(Reg::new(5).unwrap()));
+    let parsed = Session::parse(&Session::new(PathBuf::from("test.ch8"), &restored).to_string()).unwrap();
+    assert_eq!(parsed.registers.state, CpuState::WaitingForKey(Reg::new(5).unwrap()));
+
+    assert!(Session::parse("paused = true\n").is_err());
+    let error = Session::parse("rom = test.ch8\nv = 0102\n").unwrap_err();
+    assert_eq!(error.span.map(|span| span.line), Some(2));
+}
@@ -0,0 +1,326 @@
+use std::{collections::BTreeMap, fmt::Display, path::{Path, PathBuf}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use crate::{breakpoints::parse_number, keypad::KeyProfile, panel_style::PanelStyles, quirks::Quirk, theme::{DisplayStyle, PixelEffect}};
+
+/// Settings that are remembered per ROM, stored next to the ROM
+/// as `<rom>.cfg` with one `key = value` pair per line
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomSettings{
+    /// Playback rate of the buzzer sound; 1.0 is the original pitch
+    pub buzzer_pitch: f32,
+    /// The CHIP-8 key each gamepad button presses
+    pub gamepad: BTreeMap<PadButton, u8>,
+    /// Which keyboard keys play the keypad
+    pub key_profile: KeyProfile,
+    /// Extra host keys for CHIP-8 keys, by the frontend's name for them, e.g. `UP` for key 5 in a jumping game
+    pub key_remap: BTreeMap<u8, String>,
+    pub style: DisplayStyle,
+    /// Colours and text of the debug panels
+    pub panels: PanelStyles,
+    /// Quirks switched on or off while playing, which this ROM keeps from then on
+    pub quirks: BTreeMap<Quirk, bool>,
+    /// Whether `stats` are kept up to date
+    pub track_stats: bool,
+    pub stats: UsageStats
+}
+
+/// How much a ROM has been played. Only ever kept in its settings file, never sent anywhere
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct UsageStats{
+    pub launches: u64,
+    /// Time spent running, not counting time paused
+    pub play_time: Duration,
+    /// When it was last launched, in seconds since the Unix epoch
+    pub last_played: Option<u64>
+}
+
+impl UsageStats{
+    /// Seconds since the Unix epoch, for `last_played`
+    pub fn now() -> u64{
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+    }
+
+    /// E.g. "Last played 2 days ago, 3h total", or `None` if it has never been played
+    pub fn summary(&self, now: u64) -> Option<String>{
+        let last_played = self.last_played?;
+        let ago = match now.saturating_sub(last_played) {
+            seconds if seconds < 60 * 60 => "less than an hour ago".to_string(),
+            seconds if seconds < 24 * 60 * 60 => plural(seconds / (60 * 60), "hour") + " ago",
+            seconds => plural(seconds / (24 * 60 * 60), "day") + " ago"
+        };
+        let minutes = self.play_time.as_secs() / 60;
+        let total = match minutes {
+            0 => "under a minute".to_string(),
+            minutes if minutes < 60 => format!("{minutes}m"),
+            minutes => format!("{}h {}m", minutes / 60, minutes % 60)
+        };
+        Some(format!("Last played {ago}, {total} total over {}", plural(self.launches, "launch")))
+    }
+}
+
+/// Name of a quirk's setting in the settings file, e.g. `quirk_wait_for_release`
+fn quirk_key(quirk: Quirk) -> String{
+    format!("quirk_{}", quirk.to_string().replace('-', "_"))
+}
+
+fn plural(count: u64, noun: &str) -> String{
+    match (count, noun.ends_with("ch")) {
+        (1, _) => format!("1 {noun}"),
+        (_, true) => format!("{count} {noun}es"),
+        (_, false) => format!("{count} {noun}s")
+    }
+}
+
+/// A gamepad button that can be mapped to a CHIP-8 key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PadButton{
+    Up,
+    Down,
+    Left,
+    Right,
+    /// The bottom face button
+    A,
+    /// The right face button
+    B,
+    /// The left face button
+    X,
+    /// The top face button
+    Y
+}
+
+impl PadButton{
+    pub const ALL: [PadButton; 8] = [PadButton::Up, PadButton::Down, PadButton::Left, PadButton::Right,
+        PadButton::A, PadButton::B, PadButton::X, PadButton::Y];
+
+    /// Name of the button's setting in the settings file
+    fn key(&self) -> &'static str{
+        match self {
+            PadButton::Up => "pad_up",
+            PadButton::Down => "pad_down",
+            PadButton::Left => "pad_left",
+            PadButton::Right => "pad_right",
+            PadButton::A => "pad_a",
+            PadButton::B => "pad_b",
+            PadButton::X => "pad_x",
+            PadButton::Y => "pad_y"
+        }
+    }
+}
+
+impl Default for RomSettings{
+    fn default() -> Self {
+        Self {
+            buzzer_pitch: 1.0,
+            // The d-pad plays the part of WASD on the keyboard, which most games use for movement
+            gamepad: BTreeMap::from([
+                (PadButton::Up, 0x5),
+                (PadButton::Down, 0x8),
+                (PadButton::Left, 0x7),
+                (PadButton::Right, 0x9),
+                (PadButton::A, 0x6),
+                (PadButton::B, 0x4),
+                (PadButton::X, 0x1),
+                (PadButton::Y, 0xc)
+            ]),
+            key_profile: KeyProfile::default(),
+            key_remap: BTreeMap::new(),
+            style: DisplayStyle::default(),
+            panels: PanelStyles::default(),
+            quirks: BTreeMap::new(),
+            track_stats: true,
+            stats: UsageStats::default()
+        }
+    }
+}
+
+impl RomSettings{
+    pub const MIN_PITCH: f32 = 0.25;
+    pub const MAX_PITCH: f32 = 4.0;
+
+    /// Path of the settings file for the ROM at `rom`
+    pub fn path_for(rom: &Path) -> PathBuf{
+        let mut path = rom.as_os_str().to_owned();
+        path.push(".cfg");
+        PathBuf::from(path)
+    }
+
+    /// Parse settings, falling back to the defaults for missing or malformed values
+    pub fn parse(text: &str) -> Self{
+        let mut settings = Self::default();
+        for line in text.lines(){
+            let Some((key, value)) = line.split_once('=') else {continue};
+            match key.trim(){
+                "buzzer_pitch" => {
+                    if let Ok(pitch) = value.trim().parse::<f32>(){
+                        settings.buzzer_pitch = pitch.clamp(Self::MIN_PITCH, Self::MAX_PITCH);
+                    }
+                },
+                "foreground" => {
+                    if let Ok(color) = value.parse(){
+                        settings.style.foreground = color;
+                    }
+                },
+                "background" => {
+                    if let Ok(color) = value.parse(){
+                        settings.style.background = color;
+                    }
+                },
+                "pixel_effect" => {
+                    if let Ok(effect) = value.trim().parse::<PixelEffect>(){
+                        settings.style.effect = effect;
+                    }
+                },
+                "blend_frames" => {
+                    if let Ok(frames) = value.trim().parse::<usize>(){
+                        settings.style.blend_frames = frames.clamp(1, DisplayStyle::MAX_BLEND_FRAMES);
+                    }
+                },
+                "ghosting" => {
+                    if let Ok(ghosting) = value.trim().parse::<f32>(){
+                        settings.style.ghosting = ghosting.clamp(0.0, DisplayStyle::MAX_GHOSTING);
+                    }
+                },
+                "clear_fade" => {
+                    if let Ok(fade) = value.trim().parse::<f32>(){
+                        settings.style.clear_fade = fade.clamp(0.0, DisplayStyle::MAX_GHOSTING);
+                    }
+                },
+                "key_profile" => {
+                    if let Ok(profile) = value.trim().parse::<KeyProfile>(){
+                        settings.key_profile = profile;
+                    }
+                },
+                "track_stats" => {
+                    if let Ok(track) = value.trim().parse(){
+                        settings.track_stats = track;
+                    }
+                },
+                "launches" => {
+                    if let Ok(launches) = value.trim().parse(){
+                        settings.stats.launches = launches;
+                    }
+                },
+                "play_time" => {
+                    if let Ok(seconds) = value.trim().parse(){
+                        settings.stats.play_time = Duration::from_secs(seconds);
+                    }
+                },
+                "last_played" => settings.stats.last_played = value.trim().parse().ok(),
+                key if settings.panels.set(key, value) => {},
+                key if key.starts_with("remap_") => {
+                    let chip8_key = u8::from_str_radix(&key["remap_".len()..], 16).ok().filter(|chip8_key| *chip8_key < 16);
+                    let host_key = value.trim().to_uppercase();
+                    if let (Some(chip8_key), false) = (chip8_key, host_key.is_empty()){
+                        settings.key_remap.insert(chip8_key, host_key);
+                    }
+                },
+                key if key.starts_with("quirk_") => {
+                    let quirk = Quirk::ALL.into_iter().find(|quirk| quirk_key(*quirk) == key);
+                    if let (Some(quirk), Ok(enabled)) = (quirk, value.trim().parse()){
+                        settings.quirks.insert(quirk, enabled);
+                    }
+                },
+                key => {
+                    let button = PadButton::ALL.into_iter().find(|button| button.key() == key);
+                    let chip8_key = parse_number(value.trim()).filter(|chip8_key| *chip8_key < 16);
+                    if let (Some(button), Some(chip8_key)) = (button, chip8_key){
+                        settings.gamepad.insert(button, chip8_key as u8);
+                    }
+                }
+            }
+        }
+        settings
+    }
+
+    /// Load the settings for the ROM at `rom`, or the defaults if there are none
+    pub fn load(rom: &Path) -> Self{
+        std::fs::read_to_string(Self::path_for(rom))
+            .map(|text| Self::parse(&text))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()>{
+        std::fs::write(path, self.to_string())
+    }
+
+    /// Move the buzzer pitch up or down by `semitones`
+    pub fn shift_pitch(&mut self, semitones: i32){
+        let factor = 2f32.powf(semitones as f32 / 12.0);
+        self.buzzer_pitch = (self.buzzer_pitch * factor).clamp(Self::MIN_PITCH, Self::MAX_PITCH);
+    }
+}
+
+impl Display for RomSettings{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "buzzer_pitch = {}", self.buzzer_pitch)?;
+        writeln!(f, "foreground = {}", self.style.foreground)?;
+        writeln!(f, "background = {}", self.style.background)?;
+        writeln!(f, "pixel_effect = {}", self.style.effect)?;
+        writeln!(f, "ghosting = {}", self.style.ghosting)?;
+        writeln!(f, "clear_fade = {}", self.style.clear_fade)?;
+        writeln!(f, "blend_frames = {}", self.style.blend_frames)?;
+        for (button, chip8_key) in self.gamepad.iter(){
+            writeln!(f, "{} = {:#x}", button.key(), chip8_key)?;
+        }
+        writeln!(f, "key_profile = {}", self.key_profile)?;
+        for (chip8_key, host_key) in self.key_remap.iter(){
+            writeln!(f, "remap_{:x} = {}", chip8_key, host_key)?;
+        }
+        write!(f, "{}", self.panels)?;
+        for (quirk, enabled) in self.quirks.iter(){
+            writeln!(f, "{} = {}", quirk_key(*quirk), enabled)?;
+        }
+        writeln!(f, "track_stats = {}", self.track_stats)?;
+        if self.stats != UsageStats::default(){
+            writeln!(f, "launches = {}", self.stats.launches)?;
+            writeln!(f, "play_time = {}", self.stats.play_time.as_secs())?;
+            if let Some(last_played) = self.stats.last_played{
+                writeln!(f, "last_played = {}", last_played)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[test]
+fn test_settings_round_trip(){
+    let mut settings = RomSettings::default();
+    settings.shift_pitch(12);
+    assert_eq!(settings.buzzer_pitch, 2.0);
+    assert_eq!(RomSettings::parse(&settings.to_string()), settings);
+    assert_eq!(RomSettings::parse("buzzer_pitch = loud\nvolume = 3"), RomSettings::default());
+    assert_eq!(RomSettings::parse("buzzer_pitch = 100").buzzer_pitch, RomSettings::MAX_PITCH);
+    let settings = RomSettings::parse("foreground = #33ff33\npixel_effect = LCD-grid\nghosting = 2");
+    assert_eq!(settings.style.foreground.to_string(), "#33ff33");
+    assert_eq!(settings.style.effect, PixelEffect::LcdGrid);
+    assert_eq!(settings.style.ghosting, DisplayStyle::MAX_GHOSTING);
+    let settings = RomSettings::parse("pad_a = 0xf\npad_b = 16");
+    assert_eq!(settings.gamepad[&PadButton::A], 0xf);
+    assert_eq!(settings.gamepad[&PadButton::B], RomSettings::default().gamepad[&PadButton::B]);
+    assert_eq!(RomSettings::parse("key_profile = two-player").key_profile, KeyProfile::TwoPlayer);
+    assert_eq!(RomSettings::parse("key_profile = three-player").key_profile, KeyProfile::Standard);
+    let settings = RomSettings::parse("remap_5 = up\nremap_g = DOWN\nremap_a =");
+    assert_eq!(settings.key_remap, BTreeMap::from([(5, "UP".to_string())]));
+    assert_eq!(RomSettings::parse(&settings.to_string()), settings);
+    let mut settings = RomSettings::parse("instructions_font_size = 24\nmemory_background = #000000\nquirk_wait_for_release = true\nquirk_load_store = true");
+    assert_eq!(settings.panels.get(crate::layout::Panel::Instructions).font_size, 24.0);
+    assert_eq!(settings.quirks, BTreeMap::from([(Quirk::WaitForRelease, true)]));
+    settings.shift_pitch(-12);
+    assert_eq!(RomSettings::parse(&settings.to_string()), settings);
+}
+
+#[test]
+fn test_usage_stats(){
+    let stats = UsageStats{ launches: 3, play_time: Duration::from_secs(3 * 60 * 60 + 5 * 60), last_played: Some(1_000_000) };
+    let mut settings = RomSettings{ stats, ..RomSettings::default() };
+    assert_eq!(RomSettings::parse(&settings.to_string()), settings);
+    settings.track_stats = false;
+    assert_eq!(RomSettings::parse(&settings.to_string()), settings);
+
+    let two_days = 2 * 24 * 60 * 60;
+    assert_eq!(stats.summary(1_000_000 + two_days + 10).unwrap(), "Last played 2 days ago, 3h 5m total over 3 launches");
+    let once = UsageStats{ launches: 1, play_time: Duration::from_secs(20), last_played: Some(1_000_000) };
+    assert_eq!(once.summary(1_000_000 + 60 * 60).unwrap(), "Last played 1 hour ago, under a minute total over 1 launch");
+    assert_eq!(UsageStats::default().summary(0), None);
+}
@@ -0,0 +1,328 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::{breakpoints::parse_number, errors::{Diagnostic, Span}, Addr, Chip8, Instruction, Reg};
+
+/// Label names for addresses in a ROM, as written by `chip8cc --symbols`.
+/// The file has one label per line, address first, and optionally a `.build` line saying what it was built from
+/// and `.var` lines naming the register or byte of memory a variable is kept in, with the addresses it's kept there:
+///
+/// ```text
+/// # comments start with '#'
+/// .build rom=0x1c2f0a9e3b4d5f60 source=0x0a9e3b4d5f601c2f origin=0x200
+/// .var score 0x300
+/// .var x v3 0x200-0x20a
+/// 0x200 start
+/// 0x20a loop
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols{
+    addresses: BTreeMap<String, Addr>,
+    /// The first label given for each address
+    names: BTreeMap<Addr, String>,
+    build: Option<BuildInfo>,
+    variables: Vec<Variable>
+}
+
+/// Where a variable is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location{
+    Register(Reg),
+    Memory(Addr)
+}
+
+impl Location{
+    /// The variable's value in `chip8`
+    pub fn value(&self, chip8: &Chip8) -> u8{
+        match self {
+            Location::Register(reg) => chip8.registers.vn[reg.index()],
+            Location::Memory(addr) => chip8.memory.ram[addr.index()]
+        }
+    }
+}
+
+impl Display for Location{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::Register(reg) => write!(f, "v{:x}", reg),
+            Location::Memory(addr) => write!(f, "{}", addr)
+        }
+    }
+}
+
+/// A named value, such as one of a compiled program's variables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable{
+    pub name: String,
+    pub location: Location,
+    /// The addresses, end excluded, of the code that keeps the variable at `location`, or `None` for all of it.
+    /// A variable that moves between registers has a `Variable` for each place
+    pub scope: Option<(Addr, Addr)>
+}
+
+impl Variable{
+    /// Parse the fields after `.var`
+    fn parse(fields: &str) -> Option<Self>{
+        let address = |text: &str| parse_number(text).and_then(|addr| Addr::try_from(addr).ok());
+        let (name, location, scope) = match fields.split_whitespace().collect::<Vec<_>>()[..] {
+            [name, location] => (name, location, None),
+            [name, location, scope] => (name, location, Some(scope)),
+            _ => return None
+        };
+        let location = match location.strip_prefix('v') {
+            Some(_) => Location::Register(location.parse().ok()?),
+            None => Location::Memory(address(location)?)
+        };
+        let scope = match scope {
+            Some(scope) => {
+                let (start, end) = scope.split_once('-')?;
+                Some((address(start)?, address(end)?))
+            },
+            None => None
+        };
+        Some(Self{ name: name.to_string(), location, scope })
+    }
+
+    /// Whether the variable is at its location while running the instruction at `pc`
+    pub fn is_live(&self, pc: usize) -> bool{
+        self.scope.is_none_or(|(start, end)| (start.index()..end.index()).contains(&pc))
+    }
+}
+
+impl Display for Variable{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, ".var {} {}", self.name, self.location)?;
+        if let Some((start, end)) = self.scope{
+            write!(f, " {}-{}", start, end)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a ROM came from: hashes of it and of its source, see `replay::rom_hash`, and the options it was built with.
+/// Assembling the same source with the same options always gives the same ROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildInfo{
+    pub rom: u64,
+    pub source: u64,
+    /// Address the ROM was assembled to be loaded at
+    pub origin: Addr
+}
+
+impl BuildInfo{
+    /// Parse the fields after `.build`
+    fn parse(fields: &str) -> Option<Self>{
+        let (mut rom, mut source, mut origin) = (None, None, None);
+        for field in fields.split_whitespace(){
+            let (key, value) = field.split_once('=')?;
+            let hash = || u64::from_str_radix(value.trim_start_matches("0x"), 16).ok();
+            match key {
+                "rom" => rom = hash(),
+                "source" => source = hash(),
+                "origin" => origin = parse_number(value).and_then(|addr| Addr::try_from(addr).ok()),
+                _ => return None
+            }
+        }
+        Some(Self{ rom: rom?, source: source?, origin: origin? })
+    }
+}
+
+impl Display for BuildInfo{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, ".build rom={:#018x} source={:#018x} origin={}", self.rom, self.source, self.origin)
+    }
+}
+
+impl Symbols{
+    pub fn parse(text: &str) -> Result<Self, Diagnostic>{
+        let mut symbols = Self::default();
+        for (lineno, source_line) in text.lines().enumerate(){
+            let error = |message: String| Diagnostic::error(message)
+                .with_span(Span::whole_line(lineno + 1, source_line), source_line);
+            let line = source_line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty(){
+                continue;
+            }
+            if let Some(fields) = line.strip_prefix(".build"){
+                symbols.build = Some(BuildInfo::parse(fields).ok_or_else(|| error(format!("invalid build info '{line}'")))?);
+                continue;
+            }
+            if let Some(fields) = line.strip_prefix(".var"){
+                symbols.variables.push(Variable::parse(fields).ok_or_else(|| error(format!("invalid variable '{line}'")))?);
+                continue;
+            }
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [addr, name] => {
+                    let addr = parse_number(addr)
+                        .and_then(|addr| Addr::try_from(addr).ok())
+                        .ok_or_else(|| error(format!("invalid address {addr}")))?;
+                    symbols.insert(name, addr);
+                },
+                _ => return Err(error(format!("expected an address and a label, found '{line}'")))
+            }
+        }
+        Ok(symbols)
+    }
+
+    pub fn insert(&mut self, name: &str, addr: Addr){
+        self.addresses.insert(name.to_string(), addr);
+        self.names.entry(addr).or_insert_with(|| name.to_string());
+    }
+
+    /// Address of the label `name`
+    pub fn addr(&self, name: &str) -> Option<Addr>{
+        self.addresses.get(name).copied()
+    }
+
+    /// The label at `addr`, if there is one
+    pub fn name(&self, addr: Addr) -> Option<&str>{
+        self.names.get(&addr).map(String::as_str)
+    }
+
+    /// The closest label at or before `addr` and how far past it `addr` is
+    pub fn nearest(&self, addr: Addr) -> Option<(&str, usize)>{
+        self.names.range(..=addr).next_back()
+            .map(|(label, name)| (name.as_str(), addr.index() - label.index()))
+    }
+
+    /// `addr` relative to the closest label before it, like `game_loop+0x4`,
+    /// or just the address if there is no label before it
+    pub fn location(&self, addr: Addr) -> String{
+        match self.nearest(addr) {
+            Some((name, 0)) => name.to_string(),
+            Some((name, offset)) => format!("{name}+{offset:#x}"),
+            None => format!("{addr:#05x}")
+        }
+    }
+
+    /// The end of the code starting at label `name`: the next label after it, or the end of memory
+    pub fn end_of(&self, name: &str) -> Option<usize>{
+        let addr = self.addr(name)?;
+        Some(self.names.range(addr..).nth(1).map_or(Addr::MAX.index() + 1, |(next, _)| next.index()))
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.addresses.is_empty()
+    }
+
+    /// What the ROM was built from, if the file says
+    pub fn build(&self) -> Option<BuildInfo>{
+        self.build
+    }
+
+    pub fn with_build(self, build: BuildInfo) -> Self{
+        Self { build: Some(build), ..self }
+    }
+
+    pub fn add_variable(&mut self, variable: Variable){
+        self.variables.push(variable);
+    }
+
+    pub fn variables(&self) -> &[Variable]{
+        &self.variables
+    }
+
+    /// The variables in their locations while running the instruction at `pc`
+    pub fn live_variables(&self, pc: usize) -> impl Iterator<Item = &Variable>{
+        self.variables.iter().filter(move |variable| variable.is_live(pc))
+    }
+
+    /// Whether these symbols could be for `rom`: true unless the file says it was built into a different ROM
+    pub fn matches(&self, rom: &[u8]) -> bool{
+        self.build.is_none_or(|build| build.rom == crate::replay::rom_hash(rom))
+    }
+
+    /// Every label and its address, in order of name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Addr)>{
+        self.addresses.iter().map(|(name, addr)| (name.as_str(), *addr))
+    }
+
+    /// Disassemble `instruction`, naming the address it uses if there is a label for it
+    pub fn instruction(&self, instruction: Instruction) -> String{
+        let named = |addr: Addr| self.name(addr).map_or(addr.to_string(), str::to_string);
+        match instruction {
+            Instruction::Jump(addr) => format!("JP {}", named(addr)),
+            Instruction::Call(addr) => format!("CALL {}", named(addr)),
+            Instruction::SetMemPtr(addr) => format!("LD I {}", named(addr)),
+            Instruction::JumpOffset(addr) => format!("JP V0 {}", named(addr)),
+            instruction => instruction.to_string()
+        }
+    }
+}
+
+impl<'a> FromIterator<(&'a str, Addr)> for Symbols{
+    fn from_iter<T: IntoIterator<Item = (&'a str, Addr)>>(iter: T) -> Self {
+        let mut symbols = Self::default();
+        for (name, addr) in iter{
+            symbols.insert(name, addr);
+        }
+        symbols
+    }
+}
+
+/// The symbol file, ordered by address
+impl Display for Symbols{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(build) = self.build{
+            writeln!(f, "{}", build)?;
+        }
+        for variable in self.variables.iter(){
+            writeln!(f, "{}", variable)?;
+        }
+        let mut labels: Vec<_> = self.iter().collect();
+        labels.sort_by_key(|&(name, addr)| (addr, name));
+        for (name, addr) in labels{
+            writeln!(f, "{} {}", addr, name)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_symbols(){
+    let symbols = Symbols::parse("# labels\n0x20a loop\n0x200 start\n\n0x200 main # same place\n").unwrap();
+    assert_eq!(symbols.addr("loop"), Addr::new(0x20a));
+    assert_eq!(symbols.name(Addr(0x200)), Some("start"));
+    assert_eq!(symbols.instruction(Instruction::Jump(Addr(0x20a))), "JP loop");
+    assert_eq!(symbols.instruction(Instruction::Call(Addr(0x300))), "CALL 0x300");
+    assert_eq!(symbols.to_string(), "0x200 main\n0x200 start\n0x20a loop\n");
+    assert_eq!(symbols.location(Addr(0x20e)), "loop+0x4");
+    assert_eq!(symbols.location(Addr(0x200)), "start");
+    assert_eq!(symbols.location(Addr(0x1fe)), "0x1fe");
+    assert_eq!(symbols.end_of("start"), Some(0x20a));
+    assert_eq!(symbols.end_of("loop"), Some(0x1000));
+    assert_eq!(Symbols::parse(&symbols.to_string()).unwrap().addr("main"), Addr::new(0x200));
+
+    let error = Symbols::parse("0x200 start\nloop\n").unwrap_err();
+    assert_eq!(error.span.map(|span| span.line), Some(2));
+    assert!(Symbols::parse("0x1000 far").is_err());
+
+    let rom = [0x12, 0x00];
+    let build = BuildInfo{ rom: crate::replay::rom_hash(&rom), source: 0x5eed, origin: Addr::ROM_START };
+    let symbols = symbols.with_build(build);
+    assert!(symbols.to_string().starts_with(".build rom=0x"));
+    let parsed = Symbols::parse(&symbols.to_string()).unwrap();
+    assert_eq!((parsed.build(), parsed.to_string()), (Some(build), symbols.to_string()));
+    assert!(parsed.matches(&rom));
+    assert!(!parsed.matches(&[0x12, 0x02]));
+    assert!(Symbols::default().matches(&[0x12, 0x02]));
+    assert!(Symbols::parse(".build rom=0x1 origin=0x200").is_err());
+}
+
+#[test]
+fn test_variables(){
+    let text = ".var score 0x300\n.var x v3 0x200-0x20a\n.var x v4 0x20a-0x220\n0x200 start\n";
+    let symbols = Symbols::parse(text).unwrap();
+    assert_eq!(symbols.to_string(), text);
+    assert_eq!(symbols.variables()[1].location, Location::Register(Reg::new(3).unwrap()));
+    let live = |pc: usize| symbols.live_variables(pc).map(|variable| variable.location.to_string()).collect::<Vec<_>>();
+    assert_eq!(live(0x208), vec!["0x300", "v3"]);
+    assert_eq!(live(0x20a), vec!["0x300", "v4"]);
+    assert_eq!(live(0x220), vec!["0x300"]);
+
+    let mut chip8 = Chip8::init(None);
+    chip8.registers.vn[3] = 7;
+    chip8.memory.ram[0x300] = 9;
+    assert_eq!((symbols.variables()[0].location.value(&chip8), symbols.variables()[1].location.value(&chip8)), (9, 7));
+    assert!(Symbols::parse(".var x vg").is_err());
+    assert!(Symbols::parse(".var x 0x300 0x200").is_err());
+}
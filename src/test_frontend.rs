@@ -0,0 +1,142 @@
+use std::{cell::RefCell, collections::{BTreeMap, VecDeque}, rc::Rc};
+
+use crate::{breakpoints::Breakpoints, clipboard::{self, Clipboard, LocalClipboard}, collision::Collision, errors::Diagnostic, frontend::{Audio, Chip8Frontend, InputSource, KeyInput, Point, Renderer}, Addr, Chip8, Display, screenshot::Screenshot};
+
+/// What the driver asked the frontend to draw
+pub(crate) struct Draw{
+    pub pc: usize,
+    pub show_current_instruction: bool,
+    pub display: Display
+}
+
+/// Everything a `TestFrontend` has seen, shared with the test that created it
+#[derive(Default)]
+pub(crate) struct Recording{
+    /// Inputs to hand out, one batch per call to `get_inputs`
+    pub inputs: VecDeque<Vec<KeyInput>>,
+    pub draws: Vec<Draw>,
+    pub debug_mode: bool,
+    pub show_grid: bool,
+    pub show_virtual_keypad: bool,
+    pub key_remap: BTreeMap<u8, String>,
+    /// Whether the prompt for remapping a key is up
+    pub remapping: bool,
+    pub buzzer: bool,
+    pub pitch: f32,
+    /// The last RAM snapshot given for the memory view to diff against
+    pub snapshot: Option<Vec<u8>>,
+    pub collisions: Vec<Collision>,
+    pub messages: Vec<String>,
+    /// Copying puts the display here, and pasting takes hex bytes from it
+    pub clipboard: LocalClipboard,
+    /// The byte selected in the memory view, where pasting writes to
+    pub paste_at: Option<Addr>,
+    /// Report the window as closed on the next update
+    pub closed: bool
+}
+
+/// A frontend with no window, for driving the driver from tests
+pub(crate) struct TestFrontend{
+    recording: Rc<RefCell<Recording>>
+}
+
+impl TestFrontend{
+    pub fn new() -> (Self, Rc<RefCell<Recording>>){
+        let recording = Rc::new(RefCell::new(Recording::default()));
+        (Self{ recording: recording.clone() }, recording)
+    }
+}
+
+impl Renderer for TestFrontend{
+    fn update(&mut self, chip8: &Chip8, _breakpoints: &Breakpoints, show_current_instruction: bool) -> bool {
+        let mut recording = self.recording.borrow_mut();
+        recording.draws.push(Draw{
+            pc: chip8.pc(),
+            show_current_instruction,
+            display: chip8.frame_buffer().clone()
+        });
+        recording.closed
+    }
+
+    fn set_memory_snapshot(&mut self, snapshot: Option<&[u8]>) {
+        self.recording.borrow_mut().snapshot = snapshot.map(<[u8]>::to_vec);
+    }
+
+    fn show_collision(&mut self, collision: &Collision) {
+        self.recording.borrow_mut().collisions.push(collision.clone());
+    }
+
+    fn show_message(&mut self, message: &str) {
+        self.recording.borrow_mut().messages.push(message.to_string());
+    }
+}
+
+impl InputSource for TestFrontend{
+    fn get_inputs(&mut self) -> Vec<KeyInput> {
+        self.recording.borrow_mut().inputs.pop_front().unwrap_or_default()
+    }
+}
+
+impl Chip8Frontend for TestFrontend{
+    fn toggle_debug(&mut self) {
+        let mut recording = self.recording.borrow_mut();
+        recording.debug_mode = !recording.debug_mode;
+    }
+
+    fn is_debug_view(&self) -> bool {
+        self.recording.borrow().debug_mode
+    }
+
+    fn toggle_grid(&mut self) {
+        let mut recording = self.recording.borrow_mut();
+        recording.show_grid = !recording.show_grid;
+    }
+
+    fn toggle_virtual_keypad(&mut self) {
+        let mut recording = self.recording.borrow_mut();
+        recording.show_virtual_keypad = !recording.show_virtual_keypad;
+    }
+
+    fn set_key_remap(&mut self, remap: &BTreeMap<u8, String>) {
+        self.recording.borrow_mut().key_remap = remap.clone();
+    }
+
+    fn toggle_key_remap(&mut self) {
+        let mut recording = self.recording.borrow_mut();
+        recording.remapping = !recording.remapping;
+    }
+
+    fn on_mouse_scroll(&mut self, _position: Point, _direction: isize) {}
+
+    fn audio(&mut self) -> &mut dyn Audio {
+        self
+    }
+
+    /// Clicking anywhere toggles a breakpoint at the address given by the y coordinate
+    fn on_mouse_click(&mut self, position: Point, breakpoints: &mut Breakpoints) {
+        if let Ok(addr) = Addr::try_from(position.y as usize){
+            breakpoints.toggle(addr);
+        }
+    }
+
+    fn copy(&mut self, chip8: &Chip8, _breakpoints: &Breakpoints) -> Result<(), Diagnostic> {
+        self.recording.borrow_mut().clipboard.copy(&Screenshot::of(chip8.frame_buffer()).to_string())
+    }
+
+    fn paste(&mut self) -> Result<Vec<(Addr, u8)>, Diagnostic> {
+        let mut recording = self.recording.borrow_mut();
+        let start = recording.paste_at.ok_or_else(|| Diagnostic::error("No byte is selected"))?;
+        let bytes = clipboard::parse_hex(&recording.clipboard.paste()?)?;
+        Ok(bytes.into_iter().enumerate().filter_map(|(offset, byte)| Some((start.offset(offset as isize)?, byte))).collect())
+    }
+}
+
+impl Audio for TestFrontend{
+    fn set_buzzer(&mut self, playing: bool) {
+        self.recording.borrow_mut().buzzer = playing;
+    }
+
+    fn set_pitch(&mut self, pitch: f32) {
+        self.recording.borrow_mut().pitch = pitch;
+    }
+}
@@ -0,0 +1,229 @@
+use std::{path::{Path, PathBuf}, time::Duration};
+
+use crate::{breakpoints::parse_number, errors::{Diagnostic, Span}, extensions::Extension, headless::{RunResult, Timeout, Watchdog}, screenshot::Screenshot, Addr, HeadlessDriver};
+
+/// A ROM to run headlessly and the screen it should end on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestRom{
+    pub rom: PathBuf,
+    /// The expected screen, `<ROM>.golden` unless the suite names one
+    pub golden: PathBuf,
+    /// How long to run before taking the screenshot, in 60ths of a second
+    pub frames: u64,
+    /// Bytes written to RAM after loading, e.g. the test number the Timendus suite reads from 0x1ff
+    pub pokes: Vec<(Addr, u8)>,
+    pub extensions: Vec<Extension>,
+    /// Seed for `RND`, so that ROMs using random numbers end on the same screen every run
+    pub seed: u64,
+    /// Limits that fail the test if the ROM runs past them, rather than hanging the suite
+    pub watchdog: Watchdog
+}
+
+/// How a test ROM's screen compared with its golden screenshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome{
+    Pass,
+    /// This many pixels differ
+    Fail(usize),
+    /// There is no golden screenshot yet
+    Missing,
+    /// The golden screenshot was written from this run
+    Updated,
+    /// The watchdog stopped the ROM before it ran all its frames
+    TimedOut(Timeout),
+    /// The ROM or golden screenshot couldn't be read
+    Error(String)
+}
+
+/// A list of test ROMs, one per line, with their settings as `key=value` words:
+///
+/// ```text
+/// # comments start with '#'
+/// 3-corax+.ch8 frames=120
+/// 5-quirks.ch8 frames=300 poke=0x1ff:1 golden=quirks-chip8.golden
+/// slow.ch8 frames=6000 max_instructions=2000000 timeout=2.5
+/// ```
+///
+/// Paths are relative to the suite file
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TestSuite{
+    pub roms: Vec<TestRom>
+}
+
+impl TestRom{
+    pub const DEFAULT_FRAMES: u64 = 120;
+    pub const DEFAULT_SEED: u64 = 0;
+
+    pub fn new(rom: PathBuf) -> Self{
+        Self{
+            golden: rom.with_extension("golden"),
+            rom,
+            frames: Self::DEFAULT_FRAMES,
+            pokes: vec![],
+            extensions: vec![],
+            seed: Self::DEFAULT_SEED,
+            watchdog: Watchdog::default()
+        }
+    }
+
+    /// The ROM's file name, to report results by
+    pub fn name(&self) -> String{
+        self.rom.file_stem().unwrap_or(self.rom.as_os_str()).to_string_lossy().into_owned()
+    }
+
+    /// Run the ROM and take a screenshot at the end, or wherever it halted or timed out
+    pub fn run(&self) -> Result<(Screenshot, RunResult), Diagnostic>{
+        let rom = std::fs::read(&self.rom)
+            .map_err(|err| Diagnostic::error(format!("could not read {}: {}", self.rom.display(), err)))?;
+        let mut driver = HeadlessDriver::new(None);
+        driver.seed_rng(self.seed);
+        driver.set_watchdog(self.watchdog);
+        for extension in self.extensions.iter(){
+            driver.set_extension(*extension, true);
+        }
+        driver.load_rom(&rom)?;
+        for (addr, value) in self.pokes.iter(){
+            driver.write_memory(addr.index(), &[*value])?;
+        }
+        let result = driver.run_frames(self.frames);
+        Ok((driver.screenshot(), result))
+    }
+
+    /// Run the ROM and compare its screen with the golden screenshot.
+    /// With `update`, a missing or different golden screenshot is written from this run instead,
+    /// unless the ROM timed out
+    pub fn check(&self, update: bool) -> (Outcome, Option<Screenshot>){
+        let screenshot = match self.run() {
+            Ok((screenshot, RunResult::TimedOut(timeout))) => return (Outcome::TimedOut(timeout), Some(screenshot)),
+            Ok((screenshot, _)) => screenshot,
+            Err(err) => return (Outcome::Error(err.message), None)
+        };
+        let outcome = match std::fs::read_to_string(&self.golden) {
+            Ok(text) => match Screenshot::parse(&text) {
+                Ok(golden) if golden == screenshot => Outcome::Pass,
+                Ok(_) | Err(_) if update => self.update(&screenshot),
+                Ok(golden) => Outcome::Fail(golden.diff(&screenshot)),
+                Err(err) => Outcome::Error(err.render(Some(&self.golden.display().to_string())))
+            },
+            Err(_) if update => self.update(&screenshot),
+            Err(_) => Outcome::Missing
+        };
+        (outcome, Some(screenshot))
+    }
+
+    fn update(&self, screenshot: &Screenshot) -> Outcome{
+        match std::fs::write(&self.golden, screenshot.to_string()) {
+            Ok(()) => Outcome::Updated,
+            Err(err) => Outcome::Error(format!("could not write {}: {}", self.golden.display(), err))
+        }
+    }
+}
+
+impl TestSuite{
+    /// Parse a suite whose paths are relative to `dir`
+    pub fn parse(text: &str, dir: &Path) -> Result<Self, Diagnostic>{
+        let mut suite = Self::default();
+        for (lineno, source_line) in text.lines().enumerate(){
+            let error = |message: String| Diagnostic::error(message)
+                .with_span(Span::whole_line(lineno + 1, source_line), source_line);
+            let line = source_line.split('#').next().unwrap_or_default().trim();
+            let mut words = line.split_whitespace();
+            let Some(rom) = words.next() else {continue};
+            let mut test = TestRom::new(dir.join(rom));
+            for word in words{
+                let Some((key, value)) = word.split_once('=') else {
+                    return Err(error(format!("expected 'key=value', found '{word}'")))
+                };
+                let invalid = || error(format!("invalid {key} '{value}'"));
+                match key {
+                    "frames" => test.frames = value.parse().map_err(|_| invalid())?,
+                    "golden" => test.golden = dir.join(value),
+                    "seed" => test.seed = parse_number(value).ok_or_else(invalid)? as u64,
+                    "max_instructions" => test.watchdog.max_instructions = Some(value.parse().map_err(|_| invalid())?),
+                    "timeout" => test.watchdog.max_time = Some(parse_seconds(value).ok_or_else(invalid)?),
+                    "extension" => test.extensions.push(value.parse().map_err(|_| invalid())?),
+                    "poke" => {
+                        let (addr, byte) = value.split_once(':').ok_or_else(invalid)?;
+                        let addr = addr.parse::<Addr>().map_err(|_| invalid())?;
+                        let byte = parse_number(byte).and_then(|byte| u8::try_from(byte).ok()).ok_or_else(invalid)?;
+                        test.pokes.push((addr, byte));
+                    },
+                    _ => return Err(error(format!("unknown key '{key}'")))
+                }
+            }
+            suite.roms.push(test);
+        }
+        Ok(suite)
+    }
+
+    /// Give every ROM without a time limit of its own this one
+    pub fn set_default_timeout(&mut self, timeout: Duration){
+        for test in self.roms.iter_mut(){
+            test.watchdog.max_time.get_or_insert(timeout);
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Diagnostic>{
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| Diagnostic::error(format!("could not read {}: {}", path.display(), err)))?;
+        Self::parse(&text, path.parent().unwrap_or(Path::new("")))
+    }
+}
+
+/// A duration written in seconds, e.g. `2.5`
+pub fn parse_seconds(text: &str) -> Option<Duration>{
+    text.parse::<f64>().ok().and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+}
+
+
+#[test]
+fn test_suite_parse(){
+    let suite = TestSuite::parse("# roms\n3-corax+.ch8\n\n5-quirks.ch8 frames=300 poke=0x1ff:1 extension=vsync seed=0x2a golden=q.golden # chip8\n",
+        Path::new("roms")).unwrap();
+    assert_eq!(suite.roms.len(), 2);
+    assert_eq!(suite.roms[0], TestRom::new(PathBuf::from("roms/3-corax+.ch8")));
+    assert_eq!(suite.roms[0].golden, Path::new("roms/3-corax+.golden"));
+    assert_eq!(suite.roms[0].name(), "3-corax+");
+    let quirks = &suite.roms[1];
+    assert_eq!((quirks.frames, quirks.pokes.clone()), (300, vec![(Addr(0x1ff), 1)]));
+    assert_eq!((quirks.extensions.clone(), quirks.seed), (vec![Extension::VSync], 0x2a));
+    assert_eq!(quirks.golden, Path::new("roms/q.golden"));
+
+    let error = TestSuite::parse("a.ch8\nb.ch8 poke=0x1ff", Path::new("")).unwrap_err();
+    assert_eq!(error.span.map(|span| span.line), Some(2));
+    assert!(TestSuite::parse("a.ch8 speed=10", Path::new("")).is_err());
+
+    let mut suite = TestSuite::parse("a.ch8 max_instructions=1000 timeout=2.5\nb.ch8", Path::new("")).unwrap();
+    assert_eq!(suite.roms[0].watchdog, Watchdog{ max_instructions: Some(1000), max_time: Some(Duration::from_millis(2500)) });
+    suite.set_default_timeout(Duration::from_secs(10));
+    assert_eq!(suite.roms[0].watchdog.max_time, Some(Duration::from_millis(2500)));
+    assert_eq!(suite.roms[1].watchdog.max_time, Some(Duration::from_secs(10)));
+    assert!(TestSuite::parse("a.ch8 timeout=-1", Path::new("")).is_err());
+}
+
+#[test]
+fn test_rom_check(){
+    let dir = std::env::temp_dir().join(format!("chip8-test-suite-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // ld v0 0xa; ld f v0; drw v1 v1 5; jp 0x206
+    std::fs::write(dir.join("a.ch8"), [0x60, 0x0a, 0xf0, 0x29, 0xd1, 0x15, 0x12, 0x06]).unwrap();
+    let mut test = TestRom::new(dir.join("a.ch8"));
+    test.frames = 2;
+    assert_eq!(test.check(false).0, Outcome::Missing);
+    let (outcome, screenshot) = test.check(true);
+    assert_eq!(outcome, Outcome::Updated);
+    assert_eq!(test.check(false), (Outcome::Pass, screenshot));
+
+    // drawing 'B' instead of 'A'
+    test.pokes = vec![(Addr(0x201), 0x0b)];
+    assert_eq!(test.check(false).0, Outcome::Fail(5));
+
+    // a timeout fails the test, and doesn't overwrite the golden screenshot
+    test.watchdog.max_instructions = Some(3);
+    let (outcome, _) = test.check(true);
+    assert!(matches!(outcome, Outcome::TimedOut(Timeout{ pc: 0x206, instructions: 3, .. })), "{outcome:?}");
+    test.pokes.clear();
+    test.watchdog = Watchdog::default();
+    assert_eq!(test.check(false).0, Outcome::Pass);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,288 @@
+use std::{collections::VecDeque, fmt::Display, str::FromStr};
+
+use ndarray::Array2;
+
+use crate::{display_events::DisplayEvents, errors::{parse_name, Diagnostic}, DISPLAY_COLUMNS, DISPLAY_ROWS};
+
+/// A colour, written as `#rrggbb`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb{
+    pub r: u8,
+    pub g: u8,
+    pub b: u8
+}
+
+impl Rgb{
+    pub const BLACK: Rgb = Rgb::new(0, 0, 0);
+    pub const WHITE: Rgb = Rgb::new(255, 255, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self{
+        Self { r, g, b }
+    }
+
+    /// The colour `amount` of the way from `self` to `other`
+    pub fn blend(&self, other: Rgb, amount: f32) -> Rgb{
+        let amount = amount.clamp(0.0, 1.0);
+        let mix = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount).round() as u8;
+        Rgb::new(mix(self.r, other.r), mix(self.g, other.g), mix(self.b, other.b))
+    }
+}
+
+impl FromStr for Rgb{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim().trim_start_matches('#');
+        let value = (hex.len() == 6).then(|| u32::from_str_radix(hex, 16).ok()).flatten()
+            .ok_or_else(|| format!("{} is not a colour, expected #rrggbb", s))?;
+        Ok(Rgb::new((value >> 16) as u8, (value >> 8) as u8, value as u8))
+    }
+}
+
+impl Display for Rgb{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+/// An effect drawn over the display to imitate a real screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum PixelEffect{
+    #[default]
+    None,
+    /// The bottom of each row of pixels is darkened, like a CRT
+    Scanlines,
+    /// Pixels are separated by thin lines of the background colour, like an LCD
+    LcdGrid
+}
+
+impl PixelEffect{
+    pub const ALL: [PixelEffect; 3] = [PixelEffect::None, PixelEffect::Scanlines, PixelEffect::LcdGrid];
+
+    /// How many screen pixels at the edge of a CHIP-8 pixel `size` screen pixels wide the effect covers
+    pub fn edge(&self, size: usize) -> usize{
+        match self {
+            PixelEffect::None => 0,
+            PixelEffect::Scanlines => (size / 4).max(1),
+            PixelEffect::LcdGrid => (size / 8).max(1)
+        }
+    }
+}
+
+impl Display for PixelEffect{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            PixelEffect::None => "none",
+            PixelEffect::Scanlines => "scanlines",
+            PixelEffect::LcdGrid => "lcd-grid"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Parses the names `--pixel-effect` takes, e.g. `lcd-grid`
+impl FromStr for PixelEffect{
+    type Err = Diagnostic;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_name(&Self::ALL, "pixel effect", s)
+    }
+}
+
+/// How the CHIP-8 display is drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayStyle{
+    pub foreground: Rgb,
+    pub background: Rgb,
+    pub effect: PixelEffect,
+    /// How much of its brightness a pixel keeps each frame after it is switched off,
+    /// from 0 (none) up to `MAX_GHOSTING`
+    pub ghosting: f32,
+    /// Ghosting for frames with a `CLS` in them, if more than `ghosting`, so that games which clear and redraw
+    /// every frame fade instead of flickering, while sprites erased by drawing over them still vanish
+    pub clear_fade: f32,
+    /// How many of the latest frames are averaged together, from 1 (no blending) up to `MAX_BLEND_FRAMES`
+    pub blend_frames: usize
+}
+
+impl Default for DisplayStyle{
+    fn default() -> Self {
+        Self {
+            foreground: Rgb::WHITE,
+            background: Rgb::BLACK,
+            effect: PixelEffect::None,
+            ghosting: 0.0,
+            clear_fade: 0.0,
+            blend_frames: 1
+        }
+    }
+}
+
+impl DisplayStyle{
+    /// Any more than this and pixels take seconds to fade out
+    pub const MAX_GHOSTING: f32 = 0.95;
+    pub const MAX_BLEND_FRAMES: usize = 8;
+
+    /// The ghosting for a frame with these display events
+    pub fn frame_ghosting(&self, events: &DisplayEvents) -> f32{
+        if events.cleared() {self.ghosting.max(self.clear_fade)} else {self.ghosting}
+    }
+
+    /// Colour of a pixel lit to `level`, between 0 (off) and 1 (on)
+    pub fn pixel_color(&self, level: f32) -> Rgb{
+        self.background.blend(self.foreground, level)
+    }
+
+    /// The display as RGBA bytes, row by row, with each CHIP-8 pixel
+    /// drawn as a `scale` by `scale` square with the effect applied
+    pub fn render_rgba(&self, phosphor: &Phosphor, scale: usize) -> Vec<u8>{
+        self.render_levels(|x, y| phosphor.level(x, y), scale)
+    }
+
+    /// Like `render_rgba`, with each pixel lit to `level(x, y)`
+    pub fn render_levels(&self, level: impl Fn(usize, usize) -> f32, scale: usize) -> Vec<u8>{
+        let width = DISPLAY_COLUMNS * scale;
+        let height = DISPLAY_ROWS * scale;
+        let edge = self.effect.edge(scale);
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        for y in 0..height{
+            for x in 0..width{
+                let color = self.pixel_color(level(x / scale, y / scale));
+                let (inner_x, inner_y) = (x % scale, y % scale);
+                let color = match self.effect {
+                    PixelEffect::Scanlines if inner_y >= scale - edge => color.blend(Rgb::BLACK, 0.5),
+                    PixelEffect::LcdGrid if inner_x >= scale - edge || inner_y >= scale - edge => self.background,
+                    _ => color
+                };
+                rgba.extend([color.r, color.g, color.b, 255]);
+            }
+        }
+        rgba
+    }
+}
+
+/// The last few frames drawn, so that sprites which are erased and redrawn
+/// every frame can be shown at a steady brightness instead of flickering
+#[derive(Debug, Clone)]
+pub struct FrameHistory{
+    frames: VecDeque<Array2<bool>>
+}
+
+impl Default for FrameHistory{
+    fn default() -> Self {
+        Self { frames: VecDeque::with_capacity(DisplayStyle::MAX_BLEND_FRAMES) }
+    }
+}
+
+impl FrameHistory{
+    /// Add the latest frame, keeping at most `depth` frames
+    pub fn push(&mut self, display: &Array2<bool>, depth: usize){
+        let depth = depth.clamp(1, DisplayStyle::MAX_BLEND_FRAMES);
+        while self.frames.len() >= depth{
+            self.frames.pop_front();
+        }
+        self.frames.push_back(display.clone());
+    }
+
+    /// The fraction of the kept frames the pixel at column `x`, row `y` is lit in
+    pub fn level(&self, x: usize, y: usize) -> f32{
+        if self.frames.is_empty(){
+            return 0.0;
+        }
+        let lit = self.frames.iter().filter(|frame| frame[[x, y]]).count();
+        lit as f32 / self.frames.len() as f32
+    }
+}
+
+/// Brightness of each pixel, which fades out after the pixel is switched off
+/// instead of vanishing, so sprites that are redrawn every frame don't flicker
+#[derive(Debug, Clone)]
+pub struct Phosphor{
+    levels: Array2<f32>
+}
+
+impl Default for Phosphor{
+    fn default() -> Self {
+        Self { levels: Array2::zeros([DISPLAY_COLUMNS, DISPLAY_ROWS]) }
+    }
+}
+
+impl Phosphor{
+    /// Light each pixel as brightly as it is in the blended `frames`,
+    /// or fade it by `ghosting` if it was brighter before. Called once for every frame drawn
+    pub fn update(&mut self, frames: &FrameHistory, ghosting: f32){
+        let ghosting = ghosting.clamp(0.0, DisplayStyle::MAX_GHOSTING);
+        for ((x, y), level) in self.levels.indexed_iter_mut(){
+            *level = frames.level(x, y).max(*level * ghosting);
+        }
+    }
+
+    /// Brightness of the pixel at column `x`, row `y`, between 0 and 1
+    pub fn level(&self, x: usize, y: usize) -> f32{
+        self.levels[[x, y]]
+    }
+}
+
+#[test]
+fn test_colors(){
+    assert_eq!("#ff8000".parse(), Ok(Rgb::new(255, 128, 0)));
+    assert_eq!("33ff33".parse::<Rgb>().map(|rgb| rgb.to_string()), Ok("#33ff33".to_string()));
+    assert!("#fff".parse::<Rgb>().is_err());
+    assert!("green".parse::<Rgb>().is_err());
+    for effect in PixelEffect::ALL{
+        assert_eq!(effect.to_string().parse(), Ok(effect));
+    }
+    assert_eq!(Rgb::BLACK.blend(Rgb::new(200, 100, 0), 0.5), Rgb::new(100, 50, 0));
+}
+
+#[test]
+fn test_phosphor(){
+    let mut display = Array2::from_elem([DISPLAY_COLUMNS, DISPLAY_ROWS], false);
+    display[[3, 4]] = true;
+    let mut frames = FrameHistory::default();
+    let mut phosphor = Phosphor::default();
+    frames.push(&display, 1);
+    phosphor.update(&frames, 0.5);
+    assert_eq!(phosphor.level(3, 4), 1.0);
+    display[[3, 4]] = false;
+    frames.push(&display, 1);
+    phosphor.update(&frames, 0.5);
+    assert_eq!(phosphor.level(3, 4), 0.5);
+    phosphor.update(&frames, 0.0);
+    assert_eq!(phosphor.level(3, 4), 0.0);
+
+    use crate::display_events::DisplayEvent;
+    let style = DisplayStyle{ ghosting: 0.2, clear_fade: 0.6, ..Default::default() };
+    let mut events = DisplayEvents::default();
+    events.push(DisplayEvent::sprite(0, 0, 5, false));
+    assert_eq!(style.frame_ghosting(&events), 0.2);
+    events.push(DisplayEvent::Clear);
+    assert_eq!(style.frame_ghosting(&events), 0.6);
+
+    let style = DisplayStyle{ effect: PixelEffect::LcdGrid, ..Default::default() };
+    display[[0, 0]] = true;
+    frames.push(&display, 1);
+    phosphor.update(&frames, 0.0);
+    let rgba = style.render_rgba(&phosphor, 4);
+    assert_eq!(rgba.len(), DISPLAY_COLUMNS * DISPLAY_ROWS * 16 * 4);
+    assert_eq!(rgba[0..4], [255, 255, 255, 255]);
+    // the right edge of the first pixel is grid
+    assert_eq!(rgba[12..16], [0, 0, 0, 255]);
+}
+
+#[test]
+fn test_frame_blending(){
+    let mut display = Array2::from_elem([DISPLAY_COLUMNS, DISPLAY_ROWS], false);
+    let mut frames = FrameHistory::default();
+    assert_eq!(frames.level(0, 0), 0.0);
+    // a sprite drawn every other frame
+    for frame in 0..6{
+        display[[0, 0]] = frame % 2 == 0;
+        frames.push(&display, 4);
+    }
+    assert_eq!(frames.level(0, 0), 0.5);
+    display[[0, 0]] = true;
+    frames.push(&display, 1);
+    assert_eq!(frames.level(0, 0), 1.0);
+}
@@ -0,0 +1,195 @@
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use crate::{errors::{parse_name, Diagnostic}, Chip8, Instruction};
+#[cfg(test)]
+use crate::Reg;
+
+/// How many instructions run in each 60 hz frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum TimingMode{
+    /// Roughly the speed of the COSMAC VIP interpreter, ~700 instructions per second,
+    /// with slow instructions like sprite drawing using up more of the frame
+    Original,
+    /// Each instruction takes about as many machine cycles as on the COSMAC VIP,
+    /// out of the cycles its display interrupt leaves free in each frame
+    Vip,
+    /// The same number of instructions every frame, set by the clock speed
+    #[default]
+    FixedIpf
+}
+
+/// Length of one 60 hz frame
+pub const FRAME_LENGTH: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+impl TimingMode{
+    pub const ALL: [TimingMode; 3] = [TimingMode::Original, TimingMode::Vip, TimingMode::FixedIpf];
+    /// Instructions per second in `Original` mode
+    pub const ORIGINAL_SPEED: u64 = 700;
+    /// The VIP's 1802 runs a machine cycle every 8 clocks of its 1.76 MHz crystal, about 3668 a frame
+    pub const VIP_CYCLES_PER_FRAME: u64 = 3668;
+    /// Machine cycles of each frame taken by the display interrupt and the 1861's DMA
+    pub const VIP_INTERRUPT_CYCLES: u64 = 1832;
+    /// Machine cycles the interpreter takes to fetch and decode each instruction
+    pub const VIP_FETCH_CYCLES: u64 = 40;
+
+    /// Whether the clock speed decides how many instructions run, rather than the timing mode
+    pub fn uses_speed(&self) -> bool{
+        *self == TimingMode::FixedIpf
+    }
+
+    /// How many cycles can be spent in a frame
+    pub fn frame_budget(&self, chip8: &Chip8) -> u64{
+        match self {
+            TimingMode::Original => Self::ORIGINAL_SPEED / 60,
+            TimingMode::Vip => Self::VIP_CYCLES_PER_FRAME - Self::VIP_INTERRUPT_CYCLES,
+            TimingMode::FixedIpf => chip8.cycles_per_frame()
+        }
+    }
+
+    /// How many cycles `instruction` takes
+    pub fn cost(&self, instruction: Instruction) -> u64{
+        match self {
+            TimingMode::Original => original_cost(instruction),
+            TimingMode::Vip => Self::VIP_FETCH_CYCLES + vip_cycles(instruction),
+            TimingMode::FixedIpf => 1
+        }
+    }
+}
+
+impl Display for TimingMode{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            TimingMode::Original => "original",
+            TimingMode::Vip => "vip",
+            TimingMode::FixedIpf => "fixed-ipf"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Parses the names `--timing` takes, e.g. `fixed-ipf`
+impl FromStr for TimingMode{
+    type Err = Diagnostic;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_name(&Self::ALL, "timing mode", s)
+    }
+}
+
+/// Approximate relative cost of an instruction on the original interpreter
+fn original_cost(instruction: Instruction) -> u64{
+    match instruction {
+        Instruction::Draw(_, _, rows) => 1 + rows as u64 / 2,
+        Instruction::ClearScreen | Instruction::BCD(_) => 3,
+        Instruction::Call(_) | Instruction::Ret => 2,
+        Instruction::RegDump(reg) | Instruction::RegLoad(reg) => 1 + reg.index() as u64 / 4,
+        _ => 1
+    }
+}
+
+/// Approximate machine cycles the VIP interpreter spends carrying out an instruction, once decoded.
+/// Skips, `BCD` and the sprite's position change the real count a little; this takes a typical case
+fn vip_cycles(instruction: Instruction) -> u64{
+    match instruction {
+        // 4 cycles for each of the display's 256 bytes
+        Instruction::ClearScreen => 24 + 256 * 4,
+        Instruction::Ret | Instruction::Nop | Instruction::VSync => 10,
+        Instruction::Jump(_) | Instruction::SetMemPtr(_) => 12,
+        Instruction::Call(_) => 26,
+        Instruction::SkipEqImm(..) | Instruction::SkipNeImm(..) => 10,
+        Instruction::SkipEqReg(..) | Instruction::SkipNeReg(..) => 14,
+        Instruction::SetImm(..) => 6,
+        Instruction::AddImm(..) => 10,
+        // the VIP runs the ALU instructions as a small routine it writes into RAM
+        Instruction::SetReg(..) | Instruction::OrReg(..) | Instruction::AndReg(..) | Instruction::XorReg(..)
+            | Instruction::AddReg(..) | Instruction::SubReg(..) | Instruction::Rsh(..) | Instruction::SubFrom(..)
+            | Instruction::Lsh(..) => 44,
+        Instruction::JumpOffset(_) => 22,
+        Instruction::Rand(..) => 36,
+        // each row is shifted into place across two bytes, then XORed onto the display
+        Instruction::Draw(_, _, rows) => 26 + rows as u64 * 68,
+        Instruction::SkipKeyPressed(_) | Instruction::SkipKeyNotPressed(_) | Instruction::WaitForKey(_) => 18,
+        Instruction::GetDelay(_) | Instruction::SetDelay(_) | Instruction::SetSound(_) => 10,
+        Instruction::AddMemPtr(_) => 16,
+        Instruction::SetChar(_) => 20,
+        // repeated subtraction of hundreds and tens
+        Instruction::BCD(_) => 150,
+        Instruction::RegDump(reg) | Instruction::RegLoad(reg) => 14 + 14 * (reg.index() as u64 + 1)
+    }
+}
+
+/// Decides how many frames to run, from the wall-clock time that has passed.
+/// Time left over from one call carries into the next, so frames don't drift
+pub struct FrameScheduler{
+    accumulator: Duration,
+    /// Most frames that are run back to back to catch up
+    max_frame_skip: u32
+}
+
+impl FrameScheduler{
+    pub const DEFAULT_MAX_FRAME_SKIP: u32 = 5;
+
+    pub fn new(max_frame_skip: u32) -> Self{
+        Self{
+            accumulator: Duration::ZERO,
+            max_frame_skip
+        }
+    }
+
+    /// Add `elapsed` time and return the number of frames that are due.
+    /// If more than `max_frame_skip` frames behind, the rest are dropped
+    pub fn advance(&mut self, elapsed: Duration) -> u32{
+        self.accumulator += elapsed;
+        let due = (self.accumulator.as_nanos() / FRAME_LENGTH.as_nanos()) as u32;
+        let frames = due.min(self.max_frame_skip + 1);
+        if due > frames{
+            self.accumulator = Duration::ZERO;
+        } else {
+            self.accumulator -= FRAME_LENGTH * frames;
+        }
+        frames
+    }
+
+    /// Time left until the next frame is due
+    pub fn until_next_frame(&self) -> Duration{
+        FRAME_LENGTH.saturating_sub(self.accumulator)
+    }
+
+    /// Forget any time owed, e.g. after the window stalled
+    pub fn reset(&mut self){
+        self.accumulator = Duration::ZERO;
+    }
+}
+
+impl Default for FrameScheduler{
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_MAX_FRAME_SKIP)
+    }
+}
+
+
+#[test]
+fn test_frame_scheduler(){
+    let mut scheduler = FrameScheduler::new(2);
+    assert_eq!(scheduler.advance(FRAME_LENGTH / 2), 0);
+    assert_eq!(scheduler.advance(FRAME_LENGTH), 1);
+    assert_eq!(scheduler.until_next_frame(), FRAME_LENGTH / 2);
+    // Too far behind: run the most frames allowed, and drop the rest
+    assert_eq!(scheduler.advance(FRAME_LENGTH * 10), 3);
+    assert_eq!(scheduler.until_next_frame(), FRAME_LENGTH);
+}
+
+#[test]
+fn test_frame_budget(){
+    let chip8 = Chip8::init(Some(600));
+    assert_eq!(TimingMode::FixedIpf.frame_budget(&chip8), 10);
+    assert_eq!(TimingMode::Original.frame_budget(&chip8), 11);
+    assert_eq!(TimingMode::Original.cost(Instruction::Draw(Reg(0), Reg(1), 15)), 8);
+    assert_eq!(TimingMode::FixedIpf.cost(Instruction::Draw(Reg(0), Reg(1), 15)), 1);
+    // a frame's free cycles fit a couple of dozen quick instructions, but only a few sprite rows
+    assert_eq!(TimingMode::Vip.frame_budget(&chip8), 1836);
+    assert_eq!(TimingMode::Vip.frame_budget(&chip8) / TimingMode::Vip.cost(Instruction::SetImm(Reg(0), 1)), 39);
+    assert_eq!(TimingMode::Vip.cost(Instruction::Draw(Reg(0), Reg(1), 15)), 40 + 26 + 15 * 68);
+    assert!(TimingMode::FixedIpf.uses_speed() && !TimingMode::Vip.uses_speed());
+}
@@ -0,0 +1,160 @@
+use std::{collections::VecDeque, fmt::Display};
+
+use crate::{emulator, symbols::Symbols, trace_filter::TraceFilter, Addr, Chip8, CpuState, Instruction, Reg};
+
+/// A register whose value was changed by an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterChange{
+    /// (register, before, after)
+    V(Reg, u8, u8),
+    /// (before, after)
+    I(usize, usize)
+}
+
+/// One executed instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry{
+    pub pc: usize,
+    pub opcode: u16,
+    pub instruction: Instruction,
+    pub changes: Vec<RegisterChange>
+}
+
+/// A ring buffer of the most recently executed instructions
+pub struct Trace{
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+    /// Only instructions that match are recorded
+    filter: Option<TraceFilter>
+}
+
+impl Trace{
+    pub const DEFAULT_CAPACITY: usize = 10_000;
+
+    pub fn new(capacity: usize) -> Self{
+        Self{
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            filter: None
+        }
+    }
+
+    /// Only record the instructions that match `filter`
+    pub fn with_filter(mut self, filter: Option<TraceFilter>) -> Self{
+        self.filter = filter;
+        self
+    }
+
+    /// Execute the next instruction, recording it in the trace
+    pub fn execute(&mut self, chip8: &mut Chip8){
+        if chip8.state() != CpuState::Running{
+            // Blocked or halted, nothing executes
+            return
+        }
+        let pc = chip8.pc();
+        let instruction = emulator::get_instruction(&chip8.memory, &chip8.registers);
+        let before = chip8.registers.clone();
+        chip8.do_instruction();
+        let after = &chip8.registers;
+        let mut changes: Vec<_> = before.vn.iter().zip(after.vn.iter()).enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(reg, (old, new))| RegisterChange::V(Reg(reg as u8), *old, *new))
+            .collect();
+        if before.i != after.i{
+            changes.push(RegisterChange::I(before.i, after.i));
+        }
+        self.push(TraceEntry{ pc, opcode: instruction.into(), instruction, changes });
+    }
+
+    fn push(&mut self, entry: TraceEntry){
+        if self.capacity == 0 || self.filter.as_ref().is_some_and(|filter| !filter.matches(&entry)){
+            return
+        }
+        while self.entries.len() >= self.capacity{
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Entries from oldest to newest
+    pub fn entries(&self) -> impl Iterator<Item=&TraceEntry>{
+        self.entries.iter()
+    }
+
+    /// Write the trace to `path`, with locations relative to the labels in `symbols`
+    pub fn dump(&self, path: &std::path::Path, symbols: &Symbols) -> std::io::Result<()>{
+        let text: String = self.entries().map(|entry| format!("{}\n", entry.display(symbols))).collect();
+        std::fs::write(path, text)
+    }
+}
+
+impl Display for RegisterChange{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegisterChange::V(reg, before, after) => write!(f, "V{reg:x}: {before:02x}->{after:02x}"),
+            RegisterChange::I(before, after) => write!(f, "I: {before:03x}->{after:03x}")
+        }
+    }
+}
+
+impl TraceEntry{
+    /// The entry with its address as `label+offset` and the label names of any address it uses,
+    /// or the same as `to_string` if `symbols` is empty
+    pub fn display(&self, symbols: &Symbols) -> String{
+        if symbols.is_empty(){
+            return self.to_string();
+        }
+        let location = Addr::try_from(self.pc).map_or(format!("{:#05x}", self.pc), |pc| symbols.location(pc));
+        let mut line = format!("{}\t{:04x}\t{}", location, self.opcode, symbols.instruction(self.instruction));
+        for change in self.changes.iter(){
+            line.push_str(&format!("\t{change}"));
+        }
+        line
+    }
+}
+
+impl Display for TraceEntry{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#05x}\t{:04x}\t{}", self.pc, self.opcode, self.instruction)?;
+        for change in self.changes.iter(){
+            write!(f, "\t{change}")?;
+        }
+        Ok(())
+    }
+}
+
+
+#[test]
+fn test_trace_changes(){
+    // ld v2 7; ld i 0x300; add v2 1
+    let mut chip8 = Chip8::init(None);
+    chip8.load_rom(&[0x62, 0x07, 0xa3, 0x00, 0x72, 0x01]).unwrap();
+    let mut trace = Trace::new(2);
+    for _ in 0..3{
+        trace.execute(&mut chip8);
+    }
+    let entries: Vec<_> = trace.entries().collect();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].pc, 0x202);
+    assert_eq!(entries[0].changes, vec![RegisterChange::I(0, 0x300)]);
+    assert_eq!(entries[1].instruction, Instruction::AddImm(Reg(2), 1));
+    assert_eq!(entries[1].changes, vec![RegisterChange::V(Reg(2), 7, 8)]);
+    assert_eq!(entries[1].to_string(), "0x204\t7201\tADD V2 1\tV2: 07->08");
+
+    let symbols: Symbols = [("start", Addr(0x200)), ("data", Addr(0x300))].into_iter().collect();
+    assert_eq!(entries[0].display(&symbols), "start+0x2\ta300\tLD I data\tI: 000->300");
+}
+
+#[test]
+fn test_trace_filter_records(){
+    // ld v2 7; ld i 0x300; add v2 1
+    let mut chip8 = Chip8::init(None);
+    chip8.load_rom(&[0x62, 0x07, 0xa3, 0x00, 0x72, 0x01]).unwrap();
+    let filter = TraceFilter::parse("reg:v2", &Symbols::default()).unwrap();
+    let mut trace = Trace::new(10).with_filter(Some(filter));
+    for _ in 0..3{
+        trace.execute(&mut chip8);
+    }
+    let pcs: Vec<_> = trace.entries().map(|entry| entry.pc).collect();
+    assert_eq!(pcs, vec![0x200, 0x204]);
+}
@@ -0,0 +1,212 @@
+use std::ops::Range;
+
+use crate::{errors::{Diagnostic, Span}, symbols::Symbols, trace::{RegisterChange, TraceEntry}, Addr, Instruction, Reg};
+
+/// Groups of instructions a trace can be filtered to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionClass{
+    /// Jumps, calls and returns
+    Flow,
+    Skip,
+    /// Arithmetic and logic on registers, including `RND`
+    Alu,
+    /// Loads into registers, other than from memory or timers
+    Load,
+    /// Anything that reads or writes memory or moves I
+    Memory,
+    Draw,
+    Key,
+    Timer
+}
+
+impl InstructionClass{
+    const NAMES: [(&'static str, InstructionClass); 8] = [
+        ("flow", InstructionClass::Flow), ("skip", InstructionClass::Skip), ("alu", InstructionClass::Alu),
+        ("load", InstructionClass::Load), ("memory", InstructionClass::Memory), ("draw", InstructionClass::Draw),
+        ("key", InstructionClass::Key), ("timer", InstructionClass::Timer)
+    ];
+
+    pub fn contains(self, instruction: Instruction) -> bool{
+        use Instruction::*;
+        match self {
+            InstructionClass::Flow => matches!(instruction, Ret | Jump(_) | Call(_) | JumpOffset(_)),
+            InstructionClass::Skip => matches!(instruction, SkipEqImm(..) | SkipNeImm(..) | SkipEqReg(..) | SkipNeReg(..)
+                | SkipKeyPressed(_) | SkipKeyNotPressed(_)),
+            InstructionClass::Alu => matches!(instruction, AddImm(..) | OrReg(..) | AndReg(..) | XorReg(..) | AddReg(..)
+                | SubReg(..) | Rsh(_) | SubFrom(..) | Lsh(_) | Rand(..)),
+            InstructionClass::Load => matches!(instruction, SetImm(..) | SetReg(..)),
+            InstructionClass::Memory => matches!(instruction, SetMemPtr(_) | AddMemPtr(_) | SetChar(_) | BCD(_)
+                | RegDump(_) | RegLoad(_)),
+            InstructionClass::Draw => matches!(instruction, ClearScreen | Draw(..)),
+            InstructionClass::Key => matches!(instruction, SkipKeyPressed(_) | SkipKeyNotPressed(_) | WaitForKey(_)),
+            InstructionClass::Timer => matches!(instruction, GetDelay(_) | SetDelay(_) | SetSound(_) | VSync)
+        }
+    }
+}
+
+/// A register an instruction reads or writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register{
+    V(Reg),
+    I
+}
+
+/// Registers `instruction` names, plus VF for the ones that set the flag
+fn registers(instruction: Instruction) -> Vec<Register>{
+    use Instruction::*;
+    let mut registers = match instruction {
+        SkipEqImm(x, _) | SkipNeImm(x, _) | SetImm(x, _) | AddImm(x, _) | Rand(x, _) | Rsh(x) | Lsh(x)
+            | SkipKeyPressed(x) | SkipKeyNotPressed(x) | GetDelay(x) | WaitForKey(x) | SetDelay(x) | SetSound(x) => vec![Register::V(x)],
+        SkipEqReg(x, y) | SkipNeReg(x, y) | SetReg(x, y) | OrReg(x, y) | AndReg(x, y) | XorReg(x, y) | AddReg(x, y)
+            | SubReg(x, y) | SubFrom(x, y) => vec![Register::V(x), Register::V(y)],
+        Draw(x, y, _) => vec![Register::V(x), Register::V(y), Register::I],
+        SetMemPtr(_) => vec![Register::I],
+        JumpOffset(_) => vec![Register::V(Reg::V0)],
+        AddMemPtr(x) | SetChar(x) | BCD(x) => vec![Register::V(x), Register::I],
+        RegDump(x) | RegLoad(x) => (0..=x.0).map(|reg| Register::V(Reg(reg))).chain([Register::I]).collect(),
+        ClearScreen | Ret | Nop | VSync | Jump(_) | Call(_) => vec![]
+    };
+    if matches!(instruction, AddReg(..) | SubReg(..) | SubFrom(..) | Rsh(_) | Lsh(_) | Draw(..)){
+        registers.push(Register::V(Reg::VF));
+    }
+    registers
+}
+
+/// One condition of a filter
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term{
+    /// The instruction is in this range of addresses
+    Pc(Range<usize>),
+    Class(InstructionClass),
+    /// The instruction uses or changes this register
+    Touches(Register),
+    Not(Box<Term>)
+}
+
+impl Term{
+    fn matches(&self, entry: &TraceEntry) -> bool{
+        match self {
+            Term::Pc(range) => range.contains(&entry.pc),
+            Term::Class(class) => class.contains(entry.instruction),
+            Term::Touches(register) => registers(entry.instruction).contains(register)
+                || entry.changes.iter().any(|change| match (change, register) {
+                    (RegisterChange::V(reg, ..), Register::V(other)) => reg == other,
+                    (RegisterChange::I(..), Register::I) => true,
+                    _ => false
+                }),
+            Term::Not(term) => !term.matches(entry)
+        }
+    }
+}
+
+/// Which executed instructions a trace records, e.g. `draw_player..game_loop class:draw, reg:vf`.
+///
+/// A filter is a comma-separated list of clauses, and records an instruction if any clause matches it.
+/// A clause matches if all of its space-separated terms do:
+///
+/// - `0x200..0x240`, `label..label+0x10` or a single location: the instruction's address
+/// - `in:label`: anywhere from `label` up to the next label
+/// - `class:draw`: the kind of instruction, one of flow, skip, alu, load, memory, draw, key or timer
+/// - `reg:v3` or `reg:i`: the instruction uses or changes the register
+/// - `!term`: the term doesn't match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFilter{
+    clauses: Vec<Vec<Term>>
+}
+
+impl TraceFilter{
+    pub fn parse(text: &str, symbols: &Symbols) -> Result<Self, Diagnostic>{
+        let mut clauses = vec![vec![]];
+        let mut column = 1;
+        for word in text.split_inclusive([' ', ',']){
+            let start = column;
+            column += word.len();
+            let ends_clause = word.ends_with(',');
+            let word = word.trim_end_matches([' ', ',']);
+            if !word.is_empty(){
+                let term = parse_term(word, symbols).map_err(|message| Diagnostic::error(message)
+                    .with_span(Span::new(1, start, 1, start + word.len()), text))?;
+                clauses.last_mut().expect("there is always a clause").push(term);
+            }
+            if ends_clause{
+                clauses.push(vec![]);
+            }
+        }
+        if clauses.iter().any(Vec::is_empty){
+            return Err(Diagnostic::error("Empty clause in trace filter").with_span(Span::whole_line(1, text), text));
+        }
+        Ok(Self { clauses })
+    }
+
+    pub fn matches(&self, entry: &TraceEntry) -> bool{
+        self.clauses.iter().any(|clause| clause.iter().all(|term| term.matches(entry)))
+    }
+}
+
+fn parse_term(word: &str, symbols: &Symbols) -> Result<Term, String>{
+    if let Some(rest) = word.strip_prefix('!'){
+        return Ok(Term::Not(Box::new(parse_term(rest, symbols)?)));
+    }
+    if let Some(name) = word.strip_prefix("in:"){
+        let start = symbols.addr(name).ok_or_else(|| format!("unknown label {name}"))?;
+        return Ok(Term::Pc(start.index()..symbols.end_of(name).unwrap_or(start.index() + 1)));
+    }
+    if let Some(name) = word.strip_prefix("class:"){
+        return InstructionClass::NAMES.iter().find(|(class, _)| *class == name)
+            .map(|(_, class)| Term::Class(*class))
+            .ok_or_else(|| format!("unknown instruction class {name}"));
+    }
+    if let Some(name) = word.strip_prefix("reg:"){
+        if name.eq_ignore_ascii_case("i"){
+            return Ok(Term::Touches(Register::I));
+        }
+        return name.parse::<Reg>().map(|reg| Term::Touches(Register::V(reg)))
+            .map_err(|err| err.message);
+    }
+    match word.split_once("..") {
+        Some((start, end)) => Ok(Term::Pc(location(start, symbols)?.index()..location(end, symbols)?.index())),
+        None => {
+            let addr = location(word, symbols)?.index();
+            Ok(Term::Pc(addr..addr + 1))
+        }
+    }
+}
+
+/// An address, label, or label with an offset like `game_loop+0x4`
+fn location(text: &str, symbols: &Symbols) -> Result<Addr, String>{
+    let (base, offset) = match text.split_once('+') {
+        Some((base, offset)) => (base, crate::breakpoints::parse_number(offset).ok_or_else(|| format!("invalid offset {offset}"))?),
+        None => (text, 0)
+    };
+    let base = match symbols.addr(base) {
+        Some(addr) => addr,
+        None => base.parse().map_err(|_| format!("unknown label or address {base}"))?
+    };
+    base.offset(offset as isize).ok_or_else(|| format!("address out of range: {text}"))
+}
+
+
+#[test]
+fn test_trace_filter(){
+    let symbols: Symbols = [("start", Addr(0x200)), ("game_loop", Addr(0x204)), ("draw", Addr(0x20c))].into_iter().collect();
+    let entry = |pc, instruction, changes| TraceEntry{ pc, opcode: u16::from(instruction), instruction, changes };
+    let draw = entry(0x20c, Instruction::Draw(Reg(1), Reg(2), 5), vec![RegisterChange::V(Reg::VF, 0, 1)]);
+    let add = entry(0x206, Instruction::AddImm(Reg(3), 1), vec![RegisterChange::V(Reg(3), 1, 2)]);
+    let jump = entry(0x20a, Instruction::Jump(Addr(0x204)), vec![]);
+
+    let filter = TraceFilter::parse("in:game_loop", &symbols).unwrap();
+    assert!(filter.matches(&add) && filter.matches(&jump) && !filter.matches(&draw));
+    let filter = TraceFilter::parse("game_loop+0x2..draw !class:flow", &symbols).unwrap();
+    assert!(filter.matches(&add) && !filter.matches(&jump) && !filter.matches(&draw));
+    let filter = TraceFilter::parse("class:draw, reg:v3", &symbols).unwrap();
+    assert!(filter.matches(&add) && !filter.matches(&jump) && filter.matches(&draw));
+    let filter = TraceFilter::parse("reg:vf 0x20c", &symbols).unwrap();
+    assert!(!filter.matches(&add) && filter.matches(&draw));
+
+    let error = TraceFilter::parse("class:draw, in:nowhere", &symbols).unwrap_err();
+    assert_eq!(error.message, "unknown label nowhere");
+    assert_eq!(error.span, Some(Span::new(1, 13, 1, 23)));
+    assert!(TraceFilter::parse("class:sound", &symbols).is_err());
+    assert!(TraceFilter::parse("reg:v10", &symbols).is_err());
+    assert!(TraceFilter::parse("class:draw,", &symbols).is_err());
+}
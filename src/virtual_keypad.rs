@@ -0,0 +1,52 @@
+use crate::layout::Rect;
+
+/// The keys of the original hex keypad, top row first
+pub const LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xc],
+    [0x4, 0x5, 0x6, 0xd],
+    [0x7, 0x8, 0x9, 0xe],
+    [0xa, 0x0, 0xb, 0xf]
+];
+
+/// Side of the keypad, as a fraction of the display's shorter side
+const SIZE: f32 = 0.5;
+/// Pixels between the keypad and the edge of the display
+const MARGIN: f32 = 8.0;
+/// Gap between buttons, as a fraction of a button's side
+const GAP: f32 = 0.1;
+
+/// Each key's button on an on-screen keypad drawn over the bottom right of `display`, for playing by mouse or touch
+pub fn buttons(display: Rect) -> impl Iterator<Item = (u8, Rect)>{
+    let side = display.width.min(display.height) * SIZE;
+    let left = display.x + display.width - side - MARGIN;
+    let top = display.y + display.height - side - MARGIN;
+    let pitch = side / 4.0;
+    let button = pitch * (1.0 - GAP);
+    LAYOUT.into_iter().enumerate().flat_map(move |(row, keys)| keys.into_iter().enumerate().map(move |(column, key)|
+        (key, Rect::new(left + column as f32 * pitch, top + row as f32 * pitch, button, button))))
+}
+
+/// The key whose button is under `x, y`, if any
+pub fn key_at(display: Rect, x: f32, y: f32) -> Option<u8>{
+    buttons(display).find(|(_, button)| button.contains(x, y)).map(|(key, _)| key)
+}
+
+
+#[test]
+fn test_virtual_keypad(){
+    let display = Rect::new(0.0, 0.0, 640.0, 320.0);
+    let buttons: Vec<_> = buttons(display).collect();
+    assert_eq!(buttons.len(), 16);
+    let mut keys: Vec<_> = buttons.iter().map(|(key, _)| *key).collect();
+    keys.sort();
+    assert_eq!(keys, (0..16).collect::<Vec<_>>());
+    // 160 pixels square in the bottom right corner
+    assert_eq!(buttons[0].1.x, 640.0 - 160.0 - MARGIN);
+    assert!((buttons[15].1.y + 40.0 * GAP + buttons[15].1.height - (320.0 - MARGIN)).abs() < 0.01);
+    for (key, button) in buttons{
+        assert_eq!(key_at(display, button.x + 1.0, button.y + 1.0), Some(key));
+    }
+    // the gaps between buttons and the rest of the display press nothing
+    assert_eq!(key_at(display, 10.0, 10.0), None);
+    assert_eq!(key_at(display, 640.0 - MARGIN - 2.0, 320.0 - MARGIN - 2.0), None);
+}
@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{audio::SquareWave, clipboard::{Clipboard, WebClipboard}, errors::Diagnostic, extensions::Extension, quirks::Quirk, recording::Recording, rom_check, rom_inspect, screenshot::Screenshot, theme::{DisplayStyle, FrameHistory, Phosphor, PixelEffect}, Chip8, DISPLAY_COLUMNS, DISPLAY_ROWS};
+
+/// A handle to the emulator for use from JavaScript.
+/// The page is responsible for calling `tick` 60 times a second
+/// and drawing the result of `get_display`
+#[wasm_bindgen]
+pub struct Chip8Handle{
+    chip8: Chip8,
+    style: DisplayStyle,
+    frames: FrameHistory,
+    phosphor: Phosphor,
+    /// The display of every frame since `start_recording`
+    recording: Option<Recording>,
+    /// The quirks switched since `take_quirk_changes`, kept when `render` clears the display events
+    quirk_changes: BTreeMap<Quirk, bool>,
+    clipboard: WebClipboard
+}
+
+#[wasm_bindgen]
+impl Chip8Handle{
+
+    #[wasm_bindgen(constructor)]
+    pub fn new(speed: Option<u64>) -> Self{
+        Self{
+            chip8: Chip8::init(speed),
+            style: DisplayStyle::default(),
+            frames: FrameHistory::default(),
+            phosphor: Phosphor::default(),
+            recording: None,
+            quirk_changes: BTreeMap::new(),
+            clipboard: WebClipboard::default()
+        }
+    }
+
+    /// Reasons to think `rom` isn't a CHIP-8 program, as a JSON array of diagnostics.
+    /// Any with severity "error" mean it can't be loaded
+    pub fn check_rom(rom: &[u8]) -> String{
+        format!("[{}]", rom_check::check_rom(rom).iter().map(Diagnostic::to_json).collect::<Vec<_>>().join(","))
+    }
+
+    /// Reset the emulator and load `rom` at 0x200, keeping the speed, quirks, seed and other settings,
+    /// with only the extensions its code uses switched on.
+    /// The emulator is left as it was if the ROM doesn't fit in memory
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsValue>{
+        let mut chip8 = self.chip8.clone();
+        chip8.reset();
+        let extensions = rom_inspect::inspect(rom).extensions;
+        for extension in Extension::ALL{
+            chip8.set_extension(extension, extensions.contains_key(&extension));
+        }
+        chip8.load_rom(rom)?;
+        self.chip8 = chip8;
+        Ok(())
+    }
+
+    /// The extensions switched on for the ROM, e.g. `vsync`, or `none`
+    pub fn extensions(&self) -> String{
+        let extensions: Vec<_> = Extension::ALL.into_iter().filter(|extension| self.chip8.has_extension(*extension))
+            .map(|extension| extension.to_string()).collect();
+        if extensions.is_empty() {"none".to_string()} else {extensions.join(", ")}
+    }
+
+    /// Switch a quirk (`display-wait`, `wait-for-release` or `protect-low-memory`) on or off, even mid-run
+    pub fn set_quirk(&mut self, quirk: &str, enabled: bool) -> Result<(), JsValue>{
+        self.chip8.set_quirk(quirk.parse::<Quirk>()?, enabled);
+        Ok(())
+    }
+
+    /// The quirks switched on, e.g. `display-wait, protect-low-memory`, or `none`
+    pub fn quirks(&self) -> String{
+        self.chip8.quirks().to_string()
+    }
+
+    /// The quirks switched since this was last called, as a JSON object like `{"display-wait":false}`,
+    /// so the page can keep anything showing them in step
+    pub fn take_quirk_changes(&mut self) -> String{
+        self.quirk_changes.extend(self.chip8.take_quirk_changes());
+        let changes: Vec<_> = std::mem::take(&mut self.quirk_changes).into_iter()
+            .map(|(quirk, enabled)| format!("\"{}\":{}", quirk, enabled))
+            .collect();
+        format!("{{{}}}", changes.join(","))
+    }
+
+    /// Start again from 0x200 without reloading the ROM, keeping RAM as it is
+    pub fn soft_reset(&mut self){
+        self.chip8.soft_reset();
+    }
+
+    /// Run one 60 hz frame
+    pub fn tick(&mut self){
+        self.chip8.run_frame();
+        if let Some(recording) = &mut self.recording{
+            recording.push(Screenshot::of(self.chip8.frame_buffer()));
+        }
+    }
+
+    /// Why the CPU halted, with the address of the faulting instruction, or nothing while it runs.
+    /// Check after `tick` and stop ticking once there is one
+    pub fn fault(&self) -> Option<String>{
+        self.chip8.fault().map(|fault| fault.diagnostic(self.chip8.pc()).render(None))
+    }
+
+    /// Carry on after a fault, skipping the instruction that caused it
+    pub fn resume(&mut self){
+        self.chip8.resume();
+    }
+
+    /// The display as one byte per pixel (0 or 1), row by row
+    pub fn get_display(&self) -> Vec<u8>{
+        let display = self.chip8.frame_buffer();
+        let mut pixels = Vec::with_capacity(DISPLAY_COLUMNS * DISPLAY_ROWS);
+        for y in 0..DISPLAY_ROWS{
+            for x in 0..DISPLAY_COLUMNS{
+                pixels.push(display[[x, y]] as u8);
+            }
+        }
+        pixels
+    }
+
+    /// Set the colours (as `#rrggbb`), pixel effect (`none`, `scanlines` or `lcd-grid`),
+    /// ghosting and number of blended frames used by `render`
+    pub fn set_style(&mut self, foreground: &str, background: &str, effect: &str, ghosting: f32, blend_frames: usize) -> Result<(), JsValue>{
+        self.style = DisplayStyle{
+            foreground: foreground.parse().map_err(|err: String| JsValue::from_str(&err))?,
+            background: background.parse().map_err(|err: String| JsValue::from_str(&err))?,
+            effect: effect.parse::<PixelEffect>()?,
+            ghosting: ghosting.clamp(0.0, DisplayStyle::MAX_GHOSTING),
+            clear_fade: self.style.clear_fade,
+            blend_frames: blend_frames.clamp(1, DisplayStyle::MAX_BLEND_FRAMES)
+        };
+        Ok(())
+    }
+
+    /// Ghosting for frames that clear the screen, from 0 to 0.95, for games that clear and redraw every frame
+    pub fn set_clear_fade(&mut self, fade: f32){
+        self.style.clear_fade = fade.clamp(0.0, DisplayStyle::MAX_GHOSTING);
+    }
+
+    /// The display drawn with the current style as RGBA bytes, row by row,
+    /// `scale` times the size of the CHIP-8 display. Call once per drawn frame
+    pub fn render(&mut self, scale: usize) -> Vec<u8>{
+        self.frames.push(self.chip8.frame_buffer(), self.style.blend_frames);
+        self.phosphor.update(&self.frames, self.style.frame_ghosting(self.chip8.display_events()));
+        self.quirk_changes.extend(self.chip8.take_quirk_changes());
+        self.chip8.clear_display_events();
+        self.style.render_rgba(&self.phosphor, scale.max(1))
+    }
+
+    /// The display as a PNG image in the current style, each pixel `scale` image pixels wide
+    pub fn screenshot_png(&self, scale: usize) -> Vec<u8>{
+        Screenshot::of(self.chip8.frame_buffer()).to_png(&self.style, scale)
+    }
+
+    /// Record the display every frame until `stop_recording`
+    pub fn start_recording(&mut self){
+        self.recording = Some(Recording::default());
+    }
+
+    /// Stop recording, returning an animated GIF with each pixel `scale` image pixels wide,
+    /// or nothing if there was no recording
+    pub fn stop_recording(&mut self, scale: usize) -> Option<Vec<u8>>{
+        self.recording.take().map(|recording| recording.to_gif(&self.style, scale))
+    }
+
+    /// The display as a plain PBM image
+    pub fn screenshot_pbm(&self) -> String{
+        Screenshot::of(self.chip8.frame_buffer()).to_pbm()
+    }
+
+    /// Start copying the display to the clipboard as text, `#` for a lit pixel and `.` for an unlit one.
+    /// Whether the browser allowed it comes later, from `take_copy_status`
+    pub fn copy_display(&mut self) -> Result<(), JsValue>{
+        self.clipboard.copy(&Screenshot::of(self.chip8.frame_buffer()).to_string())?;
+        Ok(())
+    }
+
+    /// How the last `copy_display` went once the browser has finished it, as a message for the page to show,
+    /// e.g. why it refused. Nothing before then, or once it has been taken
+    pub fn take_copy_status(&self) -> Option<String>{
+        self.clipboard.take_status()
+    }
+
+    pub fn display_width(&self) -> usize{
+        DISPLAY_COLUMNS
+    }
+
+    pub fn display_height(&self) -> usize{
+        DISPLAY_ROWS
+    }
+
+    pub fn key_down(&mut self, key: u8){
+        self.chip8.press_key(key);
+    }
+
+    pub fn key_up(&mut self, key: u8){
+        self.chip8.release_key(key);
+    }
+
+    /// Whether the buzzer should be sounding
+    pub fn sound(&self) -> bool{
+        self.chip8.sound()
+    }
+
+    /// Pitch of the buzzer in Hz, the same tone the native frontend plays
+    pub fn buzzer_frequency(&self) -> f32{
+        SquareWave::DEFAULT_FREQUENCY
+    }
+}
@@ -0,0 +1,182 @@
+// Build the bindings first with:
+//     wasm-pack build --target web --out-dir web/pkg -- --features wasm
+// then serve this directory with any static file server.
+import init, { Chip8Handle } from "./pkg/chip8.js";
+import { KEYMAP, KEYPAD } from "./keymap.js";
+
+await init();
+const chip8 = new Chip8Handle();
+// Each CHIP-8 pixel is drawn this many canvas pixels wide, leaving room for the pixel effects
+const SCALE = 8;
+const canvas = document.getElementById("screen");
+canvas.width = chip8.display_width() * SCALE;
+canvas.height = chip8.display_height() * SCALE;
+const context = canvas.getContext("2d");
+const image = context.createImageData(canvas.width, canvas.height);
+
+// Display options, the same as the native frontend's --foreground, --background,
+// --pixel-effect, --ghosting and --blend-frames flags
+const styleInputs = ["foreground", "background", "pixel-effect", "ghosting", "blend-frames"]
+    .map((id) => document.getElementById(id));
+function updateStyle() {
+    const [foreground, background, effect, ghosting, blendFrames] = styleInputs.map((input) => input.value);
+    chip8.set_style(foreground, background, effect, parseFloat(ghosting), parseInt(blendFrames));
+}
+styleInputs.forEach((input) => input.addEventListener("input", updateStyle));
+updateStyle();
+// --clear-fade
+const clearFade = document.getElementById("clear-fade");
+clearFade.addEventListener("input", () => chip8.set_clear_fade(parseFloat(clearFade.value)));
+let running = false;
+// The ROM last loaded, for restarting after a fault
+let loadedRom = null;
+
+// Browsers only allow audio to start after a user gesture,
+// so the buzzer is created when the first ROM is picked
+let buzzer = null;
+function createBuzzer() {
+    const audio = new AudioContext();
+    const oscillator = audio.createOscillator();
+    oscillator.type = "square";
+    oscillator.frequency.value = chip8.buzzer_frequency();
+    const gain = audio.createGain();
+    gain.gain.value = 0;
+    oscillator.connect(gain).connect(audio.destination);
+    oscillator.start();
+    return { audio, gain };
+}
+
+function updateBuzzer() {
+    if (buzzer === null) return;
+    const volume = running && chip8.sound() ? 0.25 : 0;
+    buzzer.gain.gain.setTargetAtTime(volume, buzzer.audio.currentTime, 0.005);
+}
+
+document.getElementById("rom").addEventListener("change", async (event) => {
+    if (buzzer === null) buzzer = createBuzzer();
+    const file = event.target.files[0];
+    const rom = new Uint8Array(await file.arrayBuffer());
+    const problems = JSON.parse(Chip8Handle.check_rom(rom));
+    const reasons = problems.map((problem) => problem.message).join("\n");
+    if (problems.some((problem) => problem.severity === "error")) {
+        alert(`Can't load ${file.name}:\n${reasons}`);
+        return;
+    }
+    if (problems.length > 0 && !confirm(`${file.name} doesn't look like a CHIP-8 ROM:\n${reasons}\n\nLoad it anyway?`)) return;
+    try {
+        chip8.load_rom(rom);
+    } catch (err) {
+        alert(`Can't load ${file.name}:\n${err}`);
+        return;
+    }
+    loadedRom = rom;
+    document.getElementById("extensions").textContent = chip8.extensions();
+    running = true;
+});
+
+function download(bytes, type, name) {
+    const link = document.createElement("a");
+    link.href = URL.createObjectURL(new Blob([bytes], { type }));
+    link.download = name;
+    link.click();
+    URL.revokeObjectURL(link.href);
+}
+
+document.getElementById("reset").addEventListener("click", () => {
+    if (loadedRom !== null) chip8.load_rom(loadedRom);
+});
+
+document.getElementById("soft-reset").addEventListener("click", () => {
+    if (loadedRom !== null) chip8.soft_reset();
+});
+
+document.getElementById("screenshot").addEventListener("click", () => {
+    download(chip8.screenshot_png(SCALE), "image/png", "screenshot.png");
+});
+
+const status = document.getElementById("status");
+document.getElementById("copy").addEventListener("click", () => {
+    try {
+        chip8.copy_display();
+        status.textContent = "Copying…";
+    } catch (err) {
+        status.textContent = err;
+    }
+});
+
+const record = document.getElementById("record");
+record.addEventListener("click", () => {
+    const gif = chip8.stop_recording(SCALE / 2);
+    if (gif === undefined) {
+        chip8.start_recording();
+        record.textContent = "Stop";
+    } else {
+        download(gif, "image/gif", "recording.gif");
+        record.textContent = "Record";
+    }
+});
+
+document.addEventListener("keydown", (event) => {
+    const key = KEYMAP[event.key.toLowerCase()];
+    if (key !== undefined) chip8.key_down(key);
+});
+
+document.addEventListener("keyup", (event) => {
+    const key = KEYMAP[event.key.toLowerCase()];
+    if (key !== undefined) chip8.key_up(key);
+});
+
+// Buttons for touch screens, held for as long as the finger or mouse button stays on them
+const keypad = document.getElementById("keypad");
+for (const key of KEYPAD.flat()) {
+    const button = document.createElement("button");
+    button.textContent = key.toString(16).toUpperCase();
+    button.addEventListener("pointerdown", (event) => {
+        button.setPointerCapture(event.pointerId);
+        chip8.key_down(key);
+    });
+    for (const release of ["pointerup", "pointercancel"]) {
+        button.addEventListener(release, () => chip8.key_up(key));
+    }
+    keypad.appendChild(button);
+}
+document.getElementById("show-keypad").addEventListener("change", (event) => {
+    keypad.hidden = !event.target.checked;
+});
+
+function draw() {
+    image.data.set(chip8.render(SCALE));
+    context.putImageData(image, 0, 0);
+}
+
+// requestAnimationFrame runs at the display refresh rate, so run
+// however many 60 hz frames have elapsed since the last callback
+const FRAME_MS = 1000 / 60;
+let last = performance.now();
+function frame(now) {
+    // the browser finishes a copy later, allowing it or not
+    const copied = chip8.take_copy_status();
+    if (copied !== undefined) status.textContent = copied;
+    if (running) {
+        while (now - last >= FRAME_MS) {
+            chip8.tick();
+            last += FRAME_MS;
+            const fault = chip8.fault();
+            if (fault !== undefined) {
+                if (confirm(`The program stopped:\n${fault}\n\nOK skips it and carries on, Cancel restarts the ROM`)) {
+                    chip8.resume();
+                } else {
+                    chip8.load_rom(loadedRom);
+                }
+                last = performance.now();
+                break;
+            }
+        }
+        draw();
+        updateBuzzer();
+    } else {
+        last = now;
+    }
+    requestAnimationFrame(frame);
+}
+requestAnimationFrame(frame);
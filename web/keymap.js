@@ -0,0 +1,17 @@
+// Same keypad layout as the native frontend, see src/frontend/keymap.rs.
+// Only the keypad is bound: the page copies with its button, and [shift]+[insert] isn't bound
+// because WebClipboard can't paste, see src/clipboard.rs
+export const KEYMAP = {
+    "1": 0x1, "2": 0x2, "3": 0x3, "4": 0xc,
+    "q": 0x4, "w": 0x5, "e": 0x6, "r": 0xd,
+    "a": 0x7, "s": 0x8, "d": 0x9, "f": 0xe,
+    "z": 0xa, "x": 0x0, "c": 0xb, "v": 0xf,
+};
+
+// The on-screen keypad's buttons, top row first, as in src/virtual_keypad.rs
+export const KEYPAD = [
+    [0x1, 0x2, 0x3, 0xc],
+    [0x4, 0x5, 0x6, 0xd],
+    [0x7, 0x8, 0x9, 0xe],
+    [0xa, 0x0, 0xb, 0xf],
+];
@@ -0,0 +1,97 @@
+// Build the bindings first with:
+//     wasm-pack build chip8cc --target web --out-dir ../web/playground-pkg -- --no-default-features --features wasm
+// then serve this directory with any static file server.
+import init, { Chip8Handle, assemble, disassemble } from "./playground-pkg/chip8cc.js";
+import { KEYMAP } from "./keymap.js";
+
+await init();
+const chip8 = new Chip8Handle();
+const source = document.getElementById("source");
+const diagnostics = document.getElementById("diagnostics");
+const listing = document.getElementById("listing");
+const canvas = document.getElementById("screen");
+const context = canvas.getContext("2d");
+const image = context.createImageData(chip8.display_width(), chip8.display_height());
+let running = false;
+
+// Offset into the textarea of a 1-based line and column
+function offset(line, column) {
+    const lines = source.value.split("\n").slice(0, line - 1);
+    return lines.reduce((total, text) => total + text.length + 1, 0) + column - 1;
+}
+
+function showDiagnostics(list) {
+    diagnostics.replaceChildren(...list.map((diagnostic) => {
+        const item = document.createElement("li");
+        const span = diagnostic.span;
+        item.textContent = span
+            ? `${span.line}:${span.column}: ${diagnostic.severity}: ${diagnostic.message}`
+            : `${diagnostic.severity}: ${diagnostic.message}`;
+        if (!span) return item;
+        // select the offending text, or at least one character of it
+        item.addEventListener("click", () => {
+            const start = offset(span.line, span.column);
+            const end = Math.max(offset(span.end_line, span.end_column), start + 1);
+            source.focus();
+            source.setSelectionRange(start, end);
+        });
+        return item;
+    }));
+}
+
+function build() {
+    const assembly = assemble(source.value);
+    showDiagnostics(JSON.parse(assembly.diagnostics()));
+    if (!assembly.ok()) return;
+    const rom = assembly.bytes();
+    listing.textContent = disassemble(rom);
+    chip8.load_rom(rom);
+    for (const [quirk, enabled] of Object.entries(JSON.parse(assembly.quirks()))) {
+        chip8.set_quirk(quirk, enabled);
+    }
+    running = true;
+}
+
+document.getElementById("assemble").addEventListener("click", build);
+source.addEventListener("keydown", (event) => {
+    if (event.key === "Enter" && event.ctrlKey) {
+        event.preventDefault();
+        build();
+    }
+});
+
+// Keys only reach the emulator while the editor isn't focused
+document.addEventListener("keydown", (event) => {
+    const key = KEYMAP[event.key.toLowerCase()];
+    if (key !== undefined && event.target !== source) chip8.key_down(key);
+});
+
+document.addEventListener("keyup", (event) => {
+    const key = KEYMAP[event.key.toLowerCase()];
+    if (key !== undefined) chip8.key_up(key);
+});
+
+function draw() {
+    const pixels = chip8.get_display();
+    for (let i = 0; i < pixels.length; i++) {
+        const value = pixels[i] ? 255 : 0;
+        image.data.set([value, value, value, 255], i * 4);
+    }
+    context.putImageData(image, 0, 0);
+}
+
+const FRAME_MS = 1000 / 60;
+let last = performance.now();
+function frame(now) {
+    if (running) {
+        while (now - last >= FRAME_MS) {
+            chip8.tick();
+            last += FRAME_MS;
+        }
+        draw();
+    } else {
+        last = now;
+    }
+    requestAnimationFrame(frame);
+}
+requestAnimationFrame(frame);